@@ -0,0 +1,443 @@
+//! ## Admin API for support staff
+//!
+//! JSON endpoints to look up and fix customer records without opening Supabase, every route
+//! needs an API key with the scope listed below, see [auth](../../auth/index.html).
+//!
+//! ### Routes
+//! - `GET /admin/customers?email=<email>&customer_id=<id>&limit=<n>` - `customers:read`, search customer records
+//! - `GET /admin/customers/<customer_id>` - `customers:read`, the record, its audit trail, webhook events and emails
+//! - `PUT /admin/customers/<customer_id>/paid` - `customers:write`, set the paid status, body `{"paid": true}`
+//! - `POST /admin/customers/<customer_id>/welcome-email` - `customers:write`, send the welcome email again
+//! - `POST /admin/customers/<customer_id>/discord-sync` - `customers:write`, grant or revoke the Discord role now
+//! - `POST /admin/events/<event_id>/replay` - `events:replay`, process a stored webhook event again
+//!
+//! ### Errors
+//! Errors answer `{"error": "<message>"}` with the matching status, `404` when there is no record
+//! for the customer id and `422` when the record lacks what the action needs.
+//!
+//! ### Usage example
+//! ```text
+//! $ curl -H "Authorization: Bearer sdk_.." "localhost:4242/admin/customers?email=jenny.rosen@example.com"
+//! ```
+
+use crate::auth::{ReadCustomers, ReplayEvents, WriteCustomers};
+use crate::db::operations::audit::AuditEntry;
+use crate::db::operations::email_log::EmailLog;
+use crate::db::operations::webhook_event::{WebhookEvent, WebhookOutcome};
+use crate::discord::access::granted_by;
+use crate::discord::roles::{DiscordRoles, RoleSync};
+use crate::email::client::{customer_placeholders, send_welcome_email};
+use crate::email::locale::Locale;
+use crate::events::pipeline::process_event;
+use crate::overwrite::{
+    overwrite_stripe_customer_country_column_name,
+    overwrite_stripe_customer_name_column_name,
+    overwrite_stripe_customer_paid_column_name,
+    overwrite_stripe_email_column_name,
+};
+use crate::secrets;
+use crate::CustomerId;
+use crate::Organization;
+
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{get, post, put, routes, Route, State};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use supabase_rs::SupabaseClient;
+use tracing::{error, info};
+
+
+/// How many webhook events are returned with a customer
+const EVENT_HISTORY_LIMIT: usize = 50;
+
+/// How many logged emails are returned with a customer
+const EMAIL_HISTORY_LIMIT: usize = 50;
+
+
+/// A JSON answer with a status
+pub type AdminResponse = status::Custom<Json<Value>>;
+
+
+/// # admin_routes
+/// Every admin route, mounted under `/admin` by `build_rocket`.
+pub fn admin_routes() -> Vec<Route> {
+    routes![
+        search_customers,
+        get_customer,
+        set_paid,
+        resend_welcome_email,
+        sync_discord_role,
+        replay_event
+    ]
+}
+
+
+/// ## PaidUpdate
+/// The body of `PUT /admin/customers/<customer_id>/paid`
+#[derive(Debug, Deserialize)]
+pub struct PaidUpdate {
+    pub paid: bool,
+}
+
+
+/// # search_customers
+/// Searches customer records by email and/or customer id.
+#[get("/customers?<email>&<customer_id>&<limit>")]
+pub async fn search_customers(
+    _key: ReadCustomers,
+    email: Option<String>,
+    customer_id: Option<String>,
+    limit: Option<usize>,
+) -> AdminResponse {
+    if email.is_none() && customer_id.is_none() {
+        return error_response(Status::BadRequest, "search by `email` or `customer_id`");
+    }
+
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(response) => return response,
+    };
+
+    let customers: Result<Vec<Value>, String> = CustomerId::search(
+        email.as_deref(),
+        customer_id.as_deref(),
+        limit.unwrap_or(50),
+        supabase
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    match customers {
+        Ok(customers) => status::Custom(Status::Ok, Json(json!({ "customers": customers }))),
+        Err(error) => database_error(error),
+    }
+}
+
+
+/// # get_customer
+/// The record of a customer with its refund and dispute audit trail, the webhook events about
+/// its charge or email and the emails sent to it, newest first.
+#[get("/customers/<customer_id>")]
+pub async fn get_customer(_key: ReadCustomers, customer_id: String) -> AdminResponse {
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(response) => return response,
+    };
+
+    let customer: Value = match find_customer(&customer_id, supabase.clone()).await {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+
+    let email: String = record_email(&customer).unwrap_or_default();
+
+    let audit: Result<Vec<AuditEntry>, String> = AuditEntry::list_by_customer_id(
+        CustomerId { id: customer_id.clone() },
+        supabase.clone()
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    let events: Result<Vec<WebhookEvent>, String> = WebhookEvent::list_for_customer(
+        &customer_id,
+        &email,
+        EVENT_HISTORY_LIMIT,
+        supabase.clone()
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    let emails: Result<Vec<EmailLog>, String> = EmailLog::list_by_customer_id(
+        &customer_id,
+        EMAIL_HISTORY_LIMIT,
+        supabase
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    let (audit, events, emails): (Vec<AuditEntry>, Vec<WebhookEvent>, Vec<EmailLog>) = match (audit, events, emails) {
+        (Ok(audit), Ok(events), Ok(emails)) => (audit, events, emails),
+        (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => return database_error(error),
+    };
+
+    // the raw bodies are left out, `events show` prints them
+    let events: Vec<Value> = events
+        .into_iter()
+        .map(|event| json!({
+            "event_id": event.event_id,
+            "event_type": event.event_type,
+            "received_at": event.received_at,
+            "outcome": event.outcome,
+            "attempts": event.attempts,
+            "last_error": event.last_error,
+        }))
+        .collect();
+
+    status::Custom(Status::Ok, Json(json!({
+        "customer": customer,
+        "audit": audit,
+        "events": events,
+        "emails": emails,
+    })))
+}
+
+
+/// # set_paid
+/// Sets the paid status of a customer, the Discord role is not synced until asked to.
+#[put("/customers/<customer_id>/paid", format = "json", data = "<update>")]
+pub async fn set_paid(
+    _key: WriteCustomers,
+    customer_id: String,
+    update: Json<PaidUpdate>,
+) -> AdminResponse {
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(response) => return response,
+    };
+
+    let customer: Value = match find_customer(&customer_id, supabase.clone()).await {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+
+    // `update_paid` finds the row through the email of the customer
+    if record_email(&customer).is_none() {
+        return error_response(Status::UnprocessableEntity, "the customer has no email");
+    }
+
+    let updated: Result<String, String> = CustomerId::update_paid(
+        CustomerId { id: customer_id.clone() },
+        update.paid,
+        supabase.clone()
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    if let Err(error) = updated {
+        return database_error(error);
+    }
+
+    info!(customer_id = %customer_id, paid = update.paid, "Paid status set through the admin API");
+
+    match find_customer(&customer_id, supabase).await {
+        Ok(customer) => status::Custom(Status::Ok, Json(json!({ "customer": customer }))),
+        Err(response) => response,
+    }
+}
+
+
+/// # resend_welcome_email
+/// Sends the welcome email of the Organization to the customer again and records whether it was sent.
+#[post("/customers/<customer_id>/welcome-email")]
+pub async fn resend_welcome_email(
+    _key: WriteCustomers,
+    customer_id: String,
+    organization: &State<Organization>,
+) -> AdminResponse {
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(response) => return response,
+    };
+
+    let customer: Value = match find_customer(&customer_id, supabase.clone()).await {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+
+    let Some(email) = record_email(&customer) else {
+        return error_response(Status::UnprocessableEntity, "the customer has no email");
+    };
+
+    // the stored billing country picks the locale, there is no session to take it from
+    let locale: Locale = organization.locale(None, customer[overwrite_stripe_customer_country_column_name()].as_str());
+    let name: &str = customer[overwrite_stripe_customer_name_column_name()].as_str().unwrap_or_default();
+
+    let sent: Result<String, String> = send_welcome_email(
+        organization.inner().clone(),
+        email.clone(),
+        &locale,
+        &customer_placeholders(name, &email),
+        Vec::new()
+    ).await;
+
+    let recorded: Result<(), String> = CustomerId::update_email_sent_status_by_email(
+        email,
+        sent.is_ok(),
+        sent.as_ref().err().cloned(),
+        supabase
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    if let Err(error) = recorded {
+        return database_error(error);
+    }
+
+    match sent {
+        Ok(message_id) => {
+            info!(customer_id = %customer_id, %message_id, "Welcome email resent through the admin API");
+            status::Custom(Status::Ok, Json(json!({ "message_id": message_id })))
+        },
+        Err(error) => error_response(Status::BadGateway, &error),
+    }
+}
+
+
+/// # sync_discord_role
+/// Grants the Discord role to a paid customer or revokes it from an unpaid one right away, with
+/// the Discord settings of the Organization that granted its time-boxed access or of the default
+/// Organization.
+#[post("/customers/<customer_id>/discord-sync")]
+pub async fn sync_discord_role(
+    _key: WriteCustomers,
+    customer_id: String,
+    organizations: &State<Vec<Organization>>,
+) -> AdminResponse {
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(response) => return response,
+    };
+
+    let customer: Value = match find_customer(&customer_id, supabase.clone()).await {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+
+    let organization: Option<&Organization> = granted_by(&customer, organizations).or(organizations.first());
+
+    let roles: DiscordRoles = match organization.map_or_else(DiscordRoles::from_env, Organization::discord_roles) {
+        Ok(roles) => roles,
+        Err(error) => return error_response(Status::ServiceUnavailable, &error),
+    };
+
+    let discord_user_id: Result<Option<String>, String> = CustomerId::get_discord_user_id(
+        CustomerId { id: customer_id.clone() },
+        supabase
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    let discord_user_id: String = match discord_user_id {
+        Ok(Some(discord_user_id)) => discord_user_id,
+        Ok(None) => return error_response(Status::UnprocessableEntity, "the customer has not linked a Discord account"),
+        Err(error) => return database_error(error),
+    };
+
+    let paid: bool = customer
+        .get(overwrite_stripe_customer_paid_column_name())
+        .and_then(|paid| paid.as_bool())
+        .unwrap_or(false);
+
+    let synced: Result<RoleSync, String> = roles.sync_member(&discord_user_id, paid).await;
+
+    match synced {
+        Ok(role) => {
+            info!(customer_id = %customer_id, ?role, "Discord role synced through the admin API");
+            status::Custom(Status::Ok, Json(json!({
+                "discord_user_id": discord_user_id,
+                "paid": paid,
+                "role": role,
+            })))
+        },
+        Err(error) => {
+            error!(customer_id = %customer_id, %error, "Discord role sync failed");
+            error_response(Status::BadGateway, &error)
+        },
+    }
+}
+
+
+/// # replay_event
+/// Processes a stored webhook event again, exactly like `stripe_discord events replay`.
+#[post("/events/<event_id>/replay")]
+pub async fn replay_event(
+    key: ReplayEvents,
+    event_id: String,
+    organization: &State<Organization>,
+) -> AdminResponse {
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(response) => return response,
+    };
+
+    let stored: Result<Option<WebhookEvent>, String> = WebhookEvent::get_by_event_id(&event_id, supabase.clone())
+        .await
+        .map_err(|error| error.to_string());
+
+    let mut event: WebhookEvent = match stored {
+        Ok(Some(event)) => event,
+        Ok(None) => return error_response(Status::NotFound, "event not found"),
+        Err(error) => return database_error(error),
+    };
+
+    let outcome: WebhookOutcome = process_event(&mut event, organization.inner().clone(), supabase).await;
+
+    info!(event_id = %event_id, outcome = %outcome, key = %key.0.name, "Webhook event replayed through the admin API");
+
+    status::Custom(Status::Ok, Json(json!({
+        "event_id": event.event_id,
+        "event_type": event.event_type,
+        "outcome": event.outcome,
+        "attempts": event.attempts,
+        "last_error": event.last_error,
+    })))
+}
+
+
+/// # find_customer
+/// The record of a customer, or the `404` or database error response.
+async fn find_customer(customer_id: &str, supabase: SupabaseClient) -> Result<Value, AdminResponse> {
+    let record: Result<Option<Value>, String> = CustomerId::get_record(
+        CustomerId { id: customer_id.to_string() },
+        supabase
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    match record {
+        Ok(Some(customer)) => Ok(customer),
+        Ok(None) => Err(error_response(Status::NotFound, "customer not found")),
+        Err(error) => Err(database_error(error)),
+    }
+}
+
+
+/// # record_email
+/// The non-empty email of a customer record.
+fn record_email(customer: &Value) -> Option<String> {
+    customer
+        .get(overwrite_stripe_email_column_name())
+        .and_then(|email| email.as_str())
+        .filter(|email| !email.is_empty() && *email != "unknown")
+        .map(|email| email.to_string())
+}
+
+
+/// # supabase_client
+/// The Supabase client from the `SUPABASE_URL` and `SUPABASE_KEY` secrets, `503` when they are
+/// not set.
+fn supabase_client() -> Result<SupabaseClient, AdminResponse> {
+    secrets::supabase_client().map_err(|error| {
+        error!(%error, "Admin API has no database");
+
+        error_response(Status::ServiceUnavailable, "database is not configured")
+    })
+}
+
+
+/// # error_response
+/// An `{"error": ...}` answer.
+fn error_response(status: Status, message: &str) -> AdminResponse {
+    status::Custom(status, Json(json!({ "error": message })))
+}
+
+
+/// # database_error
+/// Logs a database error and answers `500`.
+fn database_error(error: String) -> AdminResponse {
+    error!(%error, "Admin API database operation failed");
+
+    error_response(Status::InternalServerError, "database operation failed")
+}
@@ -2,3 +2,190 @@
 //!
 //! This module contains all the api client that are used in the application
 //!
+//! ### Stripe
+//! - [`stripe_api_url`] - The base url of the Stripe API, `STRIPE_API_URL` overrides it e.g. to
+//!   point at a local fake in tests
+//! - [`fetch_invoice`] - Retrieves an invoice, for its `invoice_pdf`
+//! - [`fetch_customer`] - Retrieves a customer, for the email of events that only carry its id
+//! - [`fetch_line_items`] - Retrieves the line items of a checkout session, for the purchased prices
+//! - [`fetch_invoice_lines`] - Retrieves the line items of an invoice, for the prices a charge paid
+//! - [`fetch_checkout_sessions`] - Lists the checkout sessions of a payment intent, to tell
+//!   charges made through Checkout apart
+//! - [`download`] - Downloads a file like an invoice PDF
+
+use crate::secrets::Secret;
+
+use dotenv::dotenv;
+use reqwest::{Client, Response};
+use serde_json::Value;
+use std::env::var;
+use std::time::Duration;
+
+
+/// The Stripe API invoices are retrieved from by default
+pub const STRIPE_API_URL: &str = "https://api.stripe.com";
+
+/// How long a Stripe request or download may take
+pub const STRIPE_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// # stripe_api_url
+/// The base url of the Stripe API, `STRIPE_API_URL` overrides it.
+pub fn stripe_api_url() -> String {
+    dotenv().ok();
+
+    var("STRIPE_API_URL").unwrap_or(STRIPE_API_URL.to_string())
+}
+
+
+/// # fetch_invoice
+/// Retrieves an invoice from the Stripe API.
+///
+/// ## Arguments
+/// - `invoice_id`: `&str` - The id of the invoice, e.g. `in_1P...`
+/// - `api_key`: `&Secret` - The private API key of the Stripe account
+///
+/// ## Errors
+/// - `String` - The request failed or Stripe answered with an error
+pub async fn fetch_invoice(invoice_id: &str, api_key: &Secret) -> Result<Value, String> {
+    let response: Response = Client::new()
+        .get(format!("{}/v1/invoices/{}", stripe_api_url(), invoice_id))
+        .bearer_auth(api_key.expose())
+        .timeout(STRIPE_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("stripe answered {} for invoice {}", response.status(), invoice_id));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+
+/// # fetch_customer
+/// Retrieves a customer from the Stripe API.
+///
+/// ## Arguments
+/// - `customer_id`: `&str` - The id of the customer, e.g. `cus_P...`
+/// - `api_key`: `&Secret` - The private API key of the Stripe account
+///
+/// ## Errors
+/// - `String` - The request failed or Stripe answered with an error
+pub async fn fetch_customer(customer_id: &str, api_key: &Secret) -> Result<Value, String> {
+    let response: Response = Client::new()
+        .get(format!("{}/v1/customers/{}", stripe_api_url(), customer_id))
+        .bearer_auth(api_key.expose())
+        .timeout(STRIPE_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("stripe answered {} for customer {}", response.status(), customer_id));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+
+/// # fetch_line_items
+/// Retrieves the line items of a checkout session from the Stripe API.
+///
+/// ## Arguments
+/// - `session_id`: `&str` - The id of the checkout session, e.g. `cs_live_...`
+/// - `api_key`: `&Secret` - The private API key of the Stripe account
+///
+/// ## Errors
+/// - `String` - The request failed or Stripe answered with an error
+pub async fn fetch_line_items(session_id: &str, api_key: &Secret) -> Result<Value, String> {
+    let response: Response = Client::new()
+        .get(format!("{}/v1/checkout/sessions/{}/line_items?limit=100", stripe_api_url(), session_id))
+        .bearer_auth(api_key.expose())
+        .timeout(STRIPE_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("stripe answered {} for the line items of {}", response.status(), session_id));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+
+/// # fetch_invoice_lines
+/// Retrieves the line items of an invoice from the Stripe API.
+///
+/// ## Arguments
+/// - `invoice_id`: `&str` - The id of the invoice, e.g. `in_1P...`
+/// - `api_key`: `&Secret` - The private API key of the Stripe account
+///
+/// ## Errors
+/// - `String` - The request failed or Stripe answered with an error
+pub async fn fetch_invoice_lines(invoice_id: &str, api_key: &Secret) -> Result<Value, String> {
+    let response: Response = Client::new()
+        .get(format!("{}/v1/invoices/{}/lines?limit=100", stripe_api_url(), invoice_id))
+        .bearer_auth(api_key.expose())
+        .timeout(STRIPE_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("stripe answered {} for the lines of invoice {}", response.status(), invoice_id));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+
+/// # fetch_checkout_sessions
+/// Lists the checkout sessions of a payment intent from the Stripe API.
+///
+/// ## Arguments
+/// - `payment_intent`: `&str` - The id of the payment intent, e.g. `pi_3P...`
+/// - `api_key`: `&Secret` - The private API key of the Stripe account
+///
+/// ## Errors
+/// - `String` - The request failed or Stripe answered with an error
+pub async fn fetch_checkout_sessions(payment_intent: &str, api_key: &Secret) -> Result<Value, String> {
+    let response: Response = Client::new()
+        .get(format!("{}/v1/checkout/sessions?payment_intent={}&limit=1", stripe_api_url(), payment_intent))
+        .bearer_auth(api_key.expose())
+        .timeout(STRIPE_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("stripe answered {} for the checkout sessions of {}", response.status(), payment_intent));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+
+/// # download
+/// Downloads a file, e.g. the `invoice_pdf` of an invoice.
+///
+/// ## Errors
+/// - `String` - The request failed or was not answered with a success status
+pub async fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response: Response = Client::new()
+        .get(url)
+        .timeout(STRIPE_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", url, response.status()));
+    }
+
+    let bytes = response.bytes().await.map_err(|error| error.to_string())?;
+
+    Ok(bytes.to_vec())
+}
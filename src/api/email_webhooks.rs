@@ -0,0 +1,321 @@
+//! ## Email delivery webhooks
+//!
+//! The email providers report what happened to our emails here. The status of the email is
+//! updated in the email log, see [delivery](../../email/delivery/index.html), and the addresses of
+//! hard bounces and complaints are added to the suppression list, see
+//! [suppression](../../email/suppression/index.html).
+//!
+//! ### Routes
+//! - `POST /email_webhooks/resend` - Resend events, verified with `RESEND_WEBHOOK_SECRET`
+//! - `POST /email_webhooks/ses` - SES notifications delivered by SNS, verified with the SNS
+//!   signing certificate. Only `SES_SNS_TOPIC_ARN` is accepted and its subscription confirmed
+//!
+//! Webhooks are never accepted without their secret, a suppression list anyone can write to
+//! would let them stop the emails of any customer. Any AWS account can have SNS sign its
+//! messages, so for SES the topic is what stands in for the secret.
+
+use crate::api::events::WebhookHeaders;
+use crate::api::routes::read_webhook_body;
+use crate::db::operations::email_log::EmailLog;
+use crate::db::operations::suppression::Suppression;
+use crate::email::delivery::{resend_delivery, ses_delivery, DeliveryUpdate};
+use crate::email::suppression::{is_sns_url, resend_suppressions, ses_suppressions, verify_resend_signature, verify_sns_signature, EmailWebhookError};
+use crate::events::signature::unix_now;
+use crate::log::redact::redact_email;
+use crate::secrets::{secret, supabase_client, Secret};
+
+use dotenv::dotenv;
+use reqwest::Client;
+use rocket::data::Data;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{post, routes, Route};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env::var;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use supabase_rs::SupabaseClient;
+use tracing::{debug, error, info, warn};
+
+
+/// How long confirming an SNS subscription or downloading a signing certificate may take
+pub const SUBSCRIPTION_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// The SNS signing certificates by their `SigningCertURL`, SNS rotates them rarely so each is
+/// downloaded once
+pub(crate) static SIGNING_CERTIFICATES: LazyLock<Mutex<HashMap<String, Vec<u8>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+
+/// # email_webhook_routes
+/// The delivery, bounce and complaint webhooks, mounted under `/email_webhooks`.
+pub fn email_webhook_routes() -> Vec<Route> {
+    routes![
+        resend_webhook,
+        ses_webhook
+    ]
+}
+
+
+/// # resend_webhook
+/// Verifies a Resend webhook, updates the status of the email and suppresses the addresses of
+/// a bounce or complaint.
+#[post("/resend", data = "<webhook_data>")]
+pub async fn resend_webhook(
+    webhook_data: Data<'_>,
+    headers: WebhookHeaders,
+) -> status::Custom<String> {
+    let raw_body: String = match read_webhook_body(webhook_data).await {
+        Ok(raw_body) => raw_body,
+        Err(response) => return response,
+    };
+
+    let Ok(webhook_secret) = secret("RESEND_WEBHOOK_SECRET") else {
+        warn!("Rejected a Resend webhook, RESEND_WEBHOOK_SECRET is not set");
+        return status::Custom(Status::Unauthorized, "RESEND_WEBHOOK_SECRET is not set".to_string());
+    };
+
+    if let Err(error) = verify_resend_headers(&raw_body, &headers, &webhook_secret) {
+        warn!(%error, "Rejected Resend webhook");
+        return status::Custom(Status::BadRequest, error.to_string());
+    }
+
+    let event: Value = match serde_json::from_str(&raw_body) {
+        Ok(event) => event,
+        Err(error) => return status::Custom(Status::BadRequest, EmailWebhookError::Malformed(error.to_string()).to_string()),
+    };
+
+    record(resend_delivery(&event), resend_suppressions(&event, unix_now())).await
+}
+
+
+/// # verify_resend_headers
+/// Verifies the `svix-id`, `svix-timestamp` and `svix-signature` headers of a Resend webhook.
+fn verify_resend_headers(raw_body: &str, headers: &WebhookHeaders, webhook_secret: &Secret) -> Result<(), EmailWebhookError> {
+    let header = |name: &str| -> Result<&str, EmailWebhookError> {
+        headers.get(name).ok_or(EmailWebhookError::MissingHeader(name.to_string()))
+    };
+
+    verify_resend_signature(
+        raw_body,
+        header("svix-id")?,
+        header("svix-timestamp")?,
+        header("svix-signature")?,
+        webhook_secret.expose(),
+        unix_now()
+    )
+}
+
+
+/// # ses_webhook
+/// Verifies the signature and topic of an SNS delivery, confirms the subscription of the topic,
+/// updates the status of the email and suppresses the addresses of a bounce or complaint
+/// notification. SNS posts as `text/plain`.
+#[post("/ses", data = "<webhook_data>")]
+pub async fn ses_webhook(
+    webhook_data: Data<'_>,
+) -> status::Custom<String> {
+    let raw_body: String = match read_webhook_body(webhook_data).await {
+        Ok(raw_body) => raw_body,
+        Err(response) => return response,
+    };
+
+    let Some(topics) = configured_topics() else {
+        warn!("Rejected an SES webhook, SES_SNS_TOPIC_ARN is not set");
+        return status::Custom(Status::Unauthorized, "SES_SNS_TOPIC_ARN is not set".to_string());
+    };
+
+    let envelope: Value = match serde_json::from_str(&raw_body) {
+        Ok(envelope) => envelope,
+        Err(error) => return status::Custom(Status::BadRequest, EmailWebhookError::Malformed(error.to_string()).to_string()),
+    };
+
+    if let Err(error) = verify_sns_envelope(&envelope).await {
+        warn!(%error, "Rejected SES webhook");
+        return status::Custom(Status::Unauthorized, error.to_string());
+    }
+
+    let topic: &str = envelope["TopicArn"].as_str().unwrap_or_default();
+
+    if !topics.iter().any(|configured| configured == topic) {
+        warn!(topic, "Rejected an SES webhook from another SNS topic");
+        return status::Custom(Status::Forbidden, EmailWebhookError::UnknownTopic(topic.to_string()).to_string());
+    }
+
+    match envelope["Type"].as_str() {
+        Some("SubscriptionConfirmation") => confirm_subscription(envelope["SubscribeURL"].as_str().unwrap_or_default(), topic).await,
+        Some("Notification") => {
+            let message: Value = match envelope["Message"].as_str().map(serde_json::from_str) {
+                Some(Ok(message)) => message,
+                _ => return status::Custom(Status::BadRequest, EmailWebhookError::Malformed("Message is not JSON".to_string()).to_string()),
+            };
+
+            record(ses_delivery(&message), ses_suppressions(&message, unix_now())).await
+        },
+        _ => status::Custom(Status::Ok, "Received webhook".to_string()),
+    }
+}
+
+
+/// # configured_topics
+/// The comma separated `SES_SNS_TOPIC_ARN`, `None` when it is not set.
+fn configured_topics() -> Option<Vec<String>> {
+    dotenv().ok();
+
+    let topics: Vec<String> = var("SES_SNS_TOPIC_ARN")
+        .ok()?
+        .split(',')
+        .map(|topic| topic.trim().to_string())
+        .filter(|topic| !topic.is_empty())
+        .collect();
+
+    (!topics.is_empty()).then_some(topics)
+}
+
+
+/// # verify_sns_envelope
+/// Verifies the signature of an SNS message with the certificate at its `SigningCertURL`, which
+/// has to be a url of SNS itself.
+async fn verify_sns_envelope(envelope: &Value) -> Result<(), EmailWebhookError> {
+    let certificate_url: &str = envelope["SigningCertURL"].as_str().unwrap_or_default();
+
+    if !is_sns_url(certificate_url) {
+        return Err(EmailWebhookError::InvalidSignature("SigningCertURL is not an SNS url".to_string()));
+    }
+
+    let certificate: Vec<u8> = signing_certificate(certificate_url)
+        .await
+        .map_err(|error| EmailWebhookError::InvalidSignature(format!("the signing certificate could not be downloaded: {}", error)))?;
+
+    verify_sns_signature(envelope, &certificate)
+}
+
+
+/// # signing_certificate
+/// The PEM certificate at an SNS `SigningCertURL`, downloaded once and then read from
+/// [`SIGNING_CERTIFICATES`].
+async fn signing_certificate(certificate_url: &str) -> Result<Vec<u8>, reqwest::Error> {
+    if let Some(certificate) = SIGNING_CERTIFICATES.lock().unwrap().get(certificate_url) {
+        return Ok(certificate.clone());
+    }
+
+    let certificate: Vec<u8> = Client::new()
+        .get(certificate_url)
+        .timeout(SUBSCRIPTION_CONFIRMATION_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec();
+
+    SIGNING_CERTIFICATES.lock().unwrap().insert(certificate_url.to_string(), certificate.clone());
+
+    Ok(certificate)
+}
+
+
+/// # confirm_subscription
+/// Confirms an SNS subscription by visiting its `SubscribeURL`, only URLs of SNS itself are
+/// visited.
+async fn confirm_subscription(subscribe_url: &str, topic: &str) -> status::Custom<String> {
+    if !is_sns_url(subscribe_url) {
+        warn!(topic, "Rejected an SNS subscription confirmation that is not from SNS");
+        return status::Custom(Status::BadRequest, EmailWebhookError::Malformed("SubscribeURL is not an SNS url".to_string()).to_string());
+    }
+
+    let confirmed: Result<(), String> = Client::new()
+        .get(subscribe_url)
+        .timeout(SUBSCRIPTION_CONFIRMATION_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map(|_| ())
+        .map_err(|error| error.to_string());
+
+    match confirmed {
+        Ok(()) => {
+            info!(topic, "Confirmed the SNS subscription");
+            status::Custom(Status::Ok, "Subscription confirmed".to_string())
+        },
+        Err(error) => {
+            error!(topic, %error, "Failed to confirm the SNS subscription");
+            status::Custom(Status::BadGateway, "Failed to confirm subscription".to_string())
+        },
+    }
+}
+
+
+/// # record
+/// Stores the delivery status and the suppressions a provider reported, answers `500` when they
+/// could not be stored so the provider retries.
+async fn record(delivery: Option<DeliveryUpdate>, suppressions: Vec<Suppression>) -> status::Custom<String> {
+    if let Some(delivery) = delivery {
+        if let Err(response) = track_delivery(delivery).await {
+            return response;
+        }
+    }
+
+    suppress(suppressions).await
+}
+
+
+/// # track_delivery
+/// Updates the status of a logged email, emails that are not in the log are skipped.
+async fn track_delivery(delivery: DeliveryUpdate) -> Result<(), status::Custom<String>> {
+    let failed = |error: String| {
+        error!(message_id = %delivery.message_id, %error, "Failed to store the email delivery status");
+        status::Custom(Status::InternalServerError, "Failed to store the delivery status".to_string())
+    };
+
+    let supabase: SupabaseClient = supabase_client().map_err(|error| failed(error.to_string()))?;
+
+    let recorded: bool = EmailLog::record_status(&delivery.message_id, delivery.status, unix_now(), supabase)
+        .await
+        .map_err(|error| failed(error.to_string()))?;
+
+    if recorded {
+        info!(message_id = %delivery.message_id, status = %delivery.status, "Email delivery status updated");
+    } else {
+        debug!(message_id = %delivery.message_id, "The email is not in the email log");
+    }
+
+    Ok(())
+}
+
+
+/// # suppress
+/// Adds the addresses to the suppression list, answers `500` when they could not be stored so
+/// the provider retries.
+async fn suppress(suppressions: Vec<Suppression>) -> status::Custom<String> {
+    if suppressions.is_empty() {
+        return status::Custom(Status::Ok, "Received webhook".to_string());
+    }
+
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(error) => {
+            error!(%error, "Failed to store the suppressions");
+            return status::Custom(Status::InternalServerError, "Failed to store suppressions".to_string());
+        }
+    };
+
+    for suppression in suppressions {
+        let inserted: Result<bool, String> = suppression
+            .insert(supabase.clone())
+            .await
+            .map_err(|error| error.to_string());
+
+        match inserted {
+            Ok(true) => info!(email = %redact_email(&suppression.email), reason = %suppression.reason, source = %suppression.source, "Suppressed email"),
+            Ok(false) => info!(email = %redact_email(&suppression.email), "Email was already suppressed"),
+            Err(error) => {
+                error!(email = %redact_email(&suppression.email), %error, "Failed to store the suppression");
+                return status::Custom(Status::InternalServerError, "Failed to store suppressions".to_string());
+            },
+        }
+    }
+
+    status::Custom(Status::Ok, "Received webhook".to_string())
+}
@@ -0,0 +1,119 @@
+//! ## Webhook endpoints from the config
+//!
+//! Every entry under `Endpoints` in `stripe_discord.yaml` is mounted as its own `POST` route at
+//! its `Route`. Its webhooks are verified with its own webhook secret, unsigned webhooks are
+//! rejected, and handled for the Organization built by
+//! [`organization_for_endpoint`](../../organization/router/fn.organization_for_endpoint.html).
+//! Storing and handling is shared with `/stripe_webhooks`, see [routes](../routes/index.html).
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let rocket: Rocket<Build> = mount_endpoints(rocket, config.endpoints, &organization);
+//! ```
+
+use crate::api::events::{verify_webhook_with_secret, WebhookHeaders};
+use crate::api::routes::{read_webhook_body, receive_webhook};
+use crate::organization::router::organization_for_endpoint;
+use crate::secrets::Secret;
+use crate::{EndpointConfigStripe, Organization};
+
+use rocket::data::Data;
+use rocket::http::{MediaType, Method, Status};
+use rocket::response::status;
+use rocket::route::{Handler, Outcome, Route};
+use rocket::{Build, Request, Rocket};
+use tracing::{error, info, warn};
+
+
+/// ## EndpointWebhook
+/// The route handler of an endpoint from the config
+///
+/// ### Fields
+/// - `endpoint` - The endpoint, its secret is resolved per request so rotated secret files apply
+/// - `organization` - The Organization its webhooks are handled for
+#[derive(Clone)]
+pub struct EndpointWebhook {
+    pub endpoint: EndpointConfigStripe,
+    pub organization: Organization,
+}
+
+
+impl EndpointWebhook {
+    /// # new
+    /// Creates the handler of an endpoint, its Organization is derived from `base`.
+    pub fn new(endpoint: EndpointConfigStripe, base: &Organization) -> Self {
+        let organization: Organization = organization_for_endpoint(base, &endpoint);
+
+        EndpointWebhook { endpoint, organization }
+    }
+
+    /// # route
+    /// The `POST` route at the route of the endpoint, accepting JSON bodies.
+    pub fn route(self) -> Route {
+        let mut route: Route = Route::new(Method::Post, &self.endpoint.endpoint_route.clone(), self);
+        route.format = Some(MediaType::JSON);
+
+        route
+    }
+
+    /// # receive
+    /// Verifies a webhook with the secret of the endpoint, then stores and handles it.
+    async fn receive(&self, data: Data<'_>, headers: WebhookHeaders) -> status::Custom<String> {
+        let raw_body: String = match read_webhook_body(data).await {
+            Ok(raw_body) => raw_body,
+            Err(response) => return response,
+        };
+
+        let secret: Secret = match self.endpoint.stripe_webhook_secret() {
+            Ok(secret) => secret,
+            Err(error) => {
+                error!(endpoint = %self.endpoint.endpoint_route, %error, "Webhook secret of the endpoint is unavailable");
+                return status::Custom(Status::InternalServerError, "Webhook secret is unavailable".to_string());
+            }
+        };
+
+        if let Err(error) = verify_webhook_with_secret(&raw_body, &headers, &secret) {
+            warn!(endpoint = %self.endpoint.endpoint_route, %error, "Rejected webhook");
+            return status::Custom(Status::BadRequest, error.to_string());
+        }
+
+        receive_webhook(raw_body, headers, self.organization.clone()).await
+    }
+}
+
+
+#[rocket::async_trait]
+impl Handler for EndpointWebhook {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let headers: WebhookHeaders = match request.guard::<WebhookHeaders>().await.succeeded() {
+            Some(headers) => headers,
+            None => return Outcome::Error(Status::BadRequest),
+        };
+
+        Outcome::from(request, self.receive(data, headers).await)
+    }
+}
+
+
+/// # mount_endpoints
+/// Mounts the route of every endpoint.
+///
+/// ## Arguments
+/// - `rocket`: `Rocket<Build>` - The Rocket instance
+/// - `endpoints`: `Vec<EndpointConfigStripe>` - The validated endpoints from the config
+/// - `base`: `&Organization` - The Organization the endpoint Organizations are derived from
+pub fn mount_endpoints(
+    rocket: Rocket<Build>,
+    endpoints: Vec<EndpointConfigStripe>,
+    base: &Organization,
+) -> Rocket<Build> {
+    let routes: Vec<Route> = endpoints
+        .into_iter()
+        .map(|endpoint| {
+            info!(endpoint = %endpoint.endpoint_route, organization = %endpoint.name, "Mounting webhook endpoint");
+            EndpointWebhook::new(endpoint, base).route()
+        })
+        .collect();
+
+    rocket.mount("/", routes)
+}
@@ -3,3 +3,64 @@
 //! This module contains all the events that are used in the application
 //! And it will unwrap all the bigger events into smaller bits
 //!
+//! ### Receiving a webhook
+//! - [`WebhookHeaders`] collects the request headers so they can be stored with the event
+//! - [`verify_webhook_with_secret`] checks the `Stripe-Signature` header with the
+//!   `STRIPE_WEBHOOK_SECRET` or the secret of an endpoint from the config
+
+use crate::events::signature::{unix_now, verify_signature, SignatureError};
+use crate::secrets::Secret;
+
+use rocket::request::{FromRequest, Outcome, Request};
+use serde_json::{Map, Value};
+
+
+/// ## WebhookHeaders
+/// The headers of a webhook request as a JSON object, header names are lowercase
+#[derive(Debug, Clone)]
+pub struct WebhookHeaders(pub Value);
+
+
+impl WebhookHeaders {
+    /// # get
+    /// Returns the value of a header by its lowercase name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).and_then(|value| value.as_str())
+    }
+}
+
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebhookHeaders {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers: Map<String, Value> = request
+            .headers()
+            .iter()
+            .map(|header| (header.name().as_str().to_lowercase(), Value::String(header.value().to_string())))
+            .collect();
+
+        Outcome::Success(WebhookHeaders(Value::Object(headers)))
+    }
+}
+
+
+/// # verify_webhook_with_secret
+/// Verifies the `Stripe-Signature` of a webhook with the `STRIPE_WEBHOOK_SECRET` or the secret of
+/// an endpoint, webhooks are never accepted unverified.
+///
+/// ## Arguments
+/// - `raw_body`: `&str` - The request body exactly as received
+/// - `headers`: `&WebhookHeaders` - The request headers
+/// - `secret`: `&Secret` - The `whsec_` webhook secret
+///
+/// ## Errors
+/// - [`SignatureError`] - When the signature is missing or does not match
+pub fn verify_webhook_with_secret(raw_body: &str, headers: &WebhookHeaders, secret: &Secret) -> Result<(), SignatureError> {
+    let signature: &str = headers
+        .get("stripe-signature")
+        .ok_or(SignatureError::MissingHeader)?;
+
+    verify_signature(raw_body, signature, secret.expose(), unix_now())
+}
@@ -7,6 +7,13 @@
 use crate::api::Api;
 use crate::ConfigSetup;
 
+impl Default for Api {
+    /// Creates an `Api` from the host and port in `stripe_discord.yaml`, same as [`Api::new`].
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Implementation of the `Api` struct.
 impl Api {
     /// Constructs a new `Api` instance.
@@ -0,0 +1,316 @@
+//! ## Health and readiness checks
+//!
+//! `/healthz` only tells the orchestrator the process is alive, `/readyz` checks every configured
+//! dependency so traffic is only routed here once webhooks can actually be handled.
+//!
+//! ### Checks
+//! - `config` - `stripe_discord.yaml` loaded
+//! - `database` - Supabase is reachable with `SUPABASE_URL` and `SUPABASE_KEY`, or the Sled
+//!   database at `Db.SledPath` opens, it is opened once and its handle kept for later probes
+//! - `email` - The credentials of the configured email provider are accepted
+//! - `discord` - `DISCORD_BOT_TOKEN` is valid, skipped when it is not set
+//!
+//! Every check runs concurrently and is bounded by `Health.TimeoutMs` (2000 by default).
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let readiness: Readiness = check_readiness().await;
+//!
+//! if !readiness.ready {
+//!     // answer with 503 Service Unavailable
+//! }
+//! ```
+//!
+//! ### Response example
+//! ```json
+//! {
+//!   "ready": false,
+//!   "checks": [
+//!     { "name": "config", "status": "up", "latency_ms": 0, "detail": null },
+//!     { "name": "database", "status": "up", "latency_ms": 84, "detail": "supabase" },
+//!     { "name": "email", "status": "down", "latency_ms": 2000, "detail": "timed out after 2000ms" },
+//!     { "name": "discord", "status": "skipped", "latency_ms": 0, "detail": "DISCORD_BOT_TOKEN is not set" }
+//!   ]
+//! }
+//! ```
+
+use crate::discord::client::DiscordClient;
+use crate::email::resend::resend_api_url;
+use crate::email::smtp::check_smtp;
+use crate::email::EmailProvider;
+use crate::overwrite::overwrite_stripe_customer_table_name;
+use crate::secrets::{secret, Secret};
+use crate::ConfigSetup;
+
+use dotenv::dotenv;
+use reqwest::{Client, StatusCode};
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+
+/// The Sled databases opened by the readiness check by path, sled locks its directory so it is
+/// opened once and the handle reused on every probe
+static SLED_DATABASES: LazyLock<Mutex<HashMap<String, sled::Db>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+
+/// ## CheckStatus
+/// The outcome of a single dependency check
+///
+/// ### Variants
+/// - `Up` - The dependency is usable
+/// - `Down` - The dependency is not usable, the service is not ready
+/// - `Skipped` - The dependency is not configured and does not count towards readiness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Up,
+    Down,
+    Skipped,
+}
+
+
+/// ## DependencyCheck
+/// The result of checking one dependency
+///
+/// ### Fields
+/// - `name` - The dependency, e.g. `database`
+/// - `status` - Whether it is up, down or skipped
+/// - `latency_ms` - How long the check took
+/// - `detail` - What was checked or why it failed
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub latency_ms: u128,
+    pub detail: Option<String>,
+}
+
+
+/// ## Readiness
+/// The breakdown served by `/readyz`
+///
+/// ### Fields
+/// - `ready` - `true` when no check is down
+/// - `checks` - The result per dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<DependencyCheck>,
+}
+
+
+impl Readiness {
+    /// # from_checks
+    /// Builds the readiness from the individual checks, any check that is down makes it not ready.
+    pub fn from_checks(checks: Vec<DependencyCheck>) -> Self {
+        let ready: bool = checks.iter().all(|check| check.status != CheckStatus::Down);
+
+        Readiness { ready, checks }
+    }
+}
+
+
+/// # check_readiness
+/// Loads the config and checks the database, email provider and Discord concurrently.
+///
+/// ## Returns
+/// The [`Readiness`] breakdown, when the config cannot be loaded every other check is skipped.
+pub async fn check_readiness() -> Readiness {
+    dotenv().ok();
+
+    let started: Instant = Instant::now();
+
+    let config: ConfigSetup = match ConfigSetup::try_new() {
+        Ok(config) => config,
+        Err(error) => {
+            let config_check: DependencyCheck = DependencyCheck {
+                name: "config".to_string(),
+                status: CheckStatus::Down,
+                latency_ms: started.elapsed().as_millis(),
+                detail: Some(error.to_string()),
+            };
+
+            let skipped: Vec<DependencyCheck> = ["database", "email", "discord"]
+                .iter()
+                .map(|name| DependencyCheck {
+                    name: name.to_string(),
+                    status: CheckStatus::Skipped,
+                    latency_ms: 0,
+                    detail: Some("config not loaded".to_string()),
+                })
+                .collect();
+
+            return Readiness::from_checks([vec![config_check], skipped].concat());
+        }
+    };
+
+    let config_check: DependencyCheck = DependencyCheck {
+        name: "config".to_string(),
+        status: CheckStatus::Up,
+        latency_ms: started.elapsed().as_millis(),
+        detail: None,
+    };
+
+    let limit: Duration = Duration::from_millis(config.health_timeout_ms);
+
+    let (database, email, discord) = tokio::join!(
+        run_check("database", limit, check_database(&config)),
+        run_check("email", limit, check_email(&config)),
+        run_check("discord", limit, check_discord()),
+    );
+
+    Readiness::from_checks(vec![config_check, database, email, discord])
+}
+
+
+/// # run_check
+/// Runs a check within the time limit and records how long it took.
+///
+/// ## Arguments
+/// - `name`: `&str` - The name of the dependency
+/// - `limit`: `Duration` - The timeout, the check is reported down once it passes
+/// - `check`: `impl Future` - Resolves to the status and detail, or to the reason the dependency is down
+pub async fn run_check(
+    name: &str,
+    limit: Duration,
+    check: impl Future<Output = Result<(CheckStatus, Option<String>), String>>,
+) -> DependencyCheck {
+    let started: Instant = Instant::now();
+
+    let (status, detail): (CheckStatus, Option<String>) = match timeout(limit, check).await {
+        Ok(Ok((status, detail))) => (status, detail),
+        Ok(Err(error)) => (CheckStatus::Down, Some(error)),
+        Err(_) => (CheckStatus::Down, Some(format!("timed out after {}ms", limit.as_millis()))),
+    };
+
+    DependencyCheck {
+        name: name.to_string(),
+        status,
+        latency_ms: started.elapsed().as_millis(),
+        detail,
+    }
+}
+
+
+/// # sled_database
+/// The handle of the Sled database at a path, opened on the first call and shared afterwards.
+///
+/// ## Arguments
+/// - `path`: `&str` - The directory of the database, `Db.SledPath`
+///
+/// ## Errors
+/// - `sled::Error` - The database could not be opened, e.g. the directory is not writable
+pub fn sled_database(path: &str) -> Result<sled::Db, sled::Error> {
+    let mut databases = SLED_DATABASES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if let Some(database) = databases.get(path) {
+        return Ok(database.clone());
+    }
+
+    let database: sled::Db = sled::open(path)?;
+    databases.insert(path.to_string(), database.clone());
+
+    Ok(database)
+}
+
+
+/// # check_database
+/// Queries one row of the customer table on Supabase, or checks the Sled database through its
+/// shared handle.
+async fn check_database(config: &ConfigSetup) -> Result<(CheckStatus, Option<String>), String> {
+    match config.db_provider.as_str() {
+        "sled" => {
+            let path: String = config.sled_path.clone();
+
+            // sled opens the database and reads its size with blocking io
+            tokio::task::spawn_blocking(move || sled_database(&path)?.size_on_disk())
+                .await
+                .map_err(|error| error.to_string())?
+                .map_err(|error| format!("failed to open sled at {}: {}", config.sled_path, error))?;
+
+            Ok((CheckStatus::Up, Some("sled".to_string())))
+        },
+        "supabase" => {
+            let supabase_url: String = secret("SUPABASE_URL")
+                .map(|url| url.expose().to_string())
+                .unwrap_or(config.supabase_url.clone());
+            let supabase_key: Secret = secret("SUPABASE_KEY").map_err(|error| error.to_string())?;
+
+            let url: String = format!(
+                "{}/rest/v1/{}?select=*&limit=1",
+                supabase_url.trim_end_matches('/'),
+                overwrite_stripe_customer_table_name()
+            );
+
+            let status: StatusCode = Client::new()
+                .get(url)
+                .header("apikey", supabase_key.expose())
+                .header("Authorization", format!("Bearer {}", supabase_key.expose()))
+                .send()
+                .await
+                .map_err(|error| error.to_string())?
+                .status();
+
+            if !status.is_success() {
+                return Err(format!("supabase answered {}", status));
+            }
+
+            Ok((CheckStatus::Up, Some("supabase".to_string())))
+        },
+        provider => Err(format!("unknown database provider `{}`", provider)),
+    }
+}
+
+
+/// # check_email
+/// Verifies the credentials of the configured email provider.
+async fn check_email(config: &ConfigSetup) -> Result<(CheckStatus, Option<String>), String> {
+    match EmailProvider::from_str(&config.email_provider) {
+        EmailProvider::Resend => {
+            let api_key: Secret = secret("RESEND_API_KEY").map_err(|error| error.to_string())?;
+            let base_url: String = resend_api_url();
+
+            let response: reqwest::Response = Client::new()
+                .get(format!("{}/domains", base_url))
+                .bearer_auth(api_key.expose())
+                .send()
+                .await
+                .map_err(|error| error.to_string())?;
+
+            let status: StatusCode = response.status();
+            let body: Value = response.json().await.unwrap_or(Value::Null);
+
+            // a key restricted to sending is valid but may not list the domains
+            if status.is_success() || body["name"] == "restricted_api_key" {
+                return Ok((CheckStatus::Up, Some("resend".to_string())));
+            }
+
+            Err(format!("resend answered {}", status))
+        },
+        EmailProvider::Smtp => {
+            check_smtp().await?;
+
+            Ok((CheckStatus::Up, Some("smtp".to_string())))
+        },
+    }
+}
+
+
+/// # check_discord
+/// Verifies the bot token by fetching the bot user, skipped when no bot token is configured.
+async fn check_discord() -> Result<(CheckStatus, Option<String>), String> {
+    let client: DiscordClient = match DiscordClient::from_env() {
+        Ok(client) => client,
+        Err(reason) => return Ok((CheckStatus::Skipped, Some(reason))),
+    };
+
+    let bot: Value = client.get_current_user().await?;
+    let username: String = bot["username"].as_str().unwrap_or("unknown").to_string();
+
+    Ok((CheckStatus::Up, Some(username)))
+}
@@ -1,11 +1,21 @@
 //! ## API endpoints to expose for Stripe
 //!
 //! ### Table of contents
+//! - [admin](admin/index.html) - The authenticated admin API for customer records
+//! - [email_webhooks](email_webhooks/index.html) - The bounce and complaint webhooks of the email providers
+//! - [endpoints](endpoints/index.html) - The webhook endpoints from the config
+//! - [health](health/index.html) - The `/healthz` and `/readyz` checks
+//! - [routes](routes/index.html) - The Rocket routes and `build_rocket`
 
+pub mod admin;
 pub mod client;
+pub mod email_webhooks;
+pub mod endpoints;
 pub mod errors;
 pub mod events;
 pub mod format;
+pub mod health;
+pub mod routes;
 pub mod success;
 
 /// ## Base construction for the `Api`
@@ -15,7 +25,7 @@ pub mod success;
 /// - `port` - This is the port that the the api is exposed under
 ///
 /// ## Usage example
-/// ```
+/// ```rust,ignore
 /// let api = Api::new("localhost", 8080);
 /// ```
 #[derive(Debug, Clone)]
@@ -0,0 +1,254 @@
+//! ## Rocket routes
+//!
+//! ### Routes
+//! - `POST /stripe_webhooks` - Verifies and stores a Stripe webhook, it is handled in the background
+//! - `POST <Route>` - The same for every endpoint under `Endpoints`, see [endpoints](../endpoints/index.html)
+//! - `GET /metrics` - Prometheus metrics, see [metrics](../../metrics/index.html)
+//! - `GET /healthz` - Liveness
+//! - `GET /readyz` - Readiness, see [health](../health/index.html)
+//! - `/admin/...` - The authenticated admin API, see [admin](../admin/index.html)
+//! - `POST /email_webhooks/...` - Bounces and complaints, see [email_webhooks](../email_webhooks/index.html)
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let config: ConfigSetup = ConfigSetup::try_new()?;
+//! let organization: Organization = organization_from_config(&config);
+//!
+//! build_rocket(organization, &config).launch().await?;
+//! ```
+
+use crate::api::admin::admin_routes;
+use crate::api::email_webhooks::email_webhook_routes;
+use crate::api::endpoints::mount_endpoints;
+use crate::auth::ConfiguredApiKey;
+use crate::api::events::{verify_webhook_with_secret, WebhookHeaders};
+use crate::api::health::{check_readiness, Readiness};
+use crate::background::scheduler::start_scheduler;
+use crate::background::spawn_background;
+use crate::db::operations::webhook_event::WebhookEvent;
+use crate::events::pipeline::process_event;
+use crate::events::signature::unix_now;
+use crate::metrics::{render_metrics, CONTENT_TYPE};
+use crate::organization::router::organization_for_endpoint;
+use crate::secrets::{secret, supabase_client, Secret};
+use crate::{ConfigSetup, EndpointConfigStripe, Organization};
+
+use rocket::data::{Capped, Data, ToByteUnit};
+use rocket::fairing::AdHoc;
+use rocket::http::{ContentType, Status};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{get, post, routes, Build, Rocket, State};
+use serde_json::Value;
+use supabase_rs::SupabaseClient;
+use tracing::{error, info, warn};
+
+
+/// # build_rocket
+/// Builds the Rocket instance with every route mounted, webhooks are handled for the given
+/// Organization. The scheduler starts on liftoff when an Organization has a drip sequence, a
+/// reminder window or time-boxed products, see [scheduler](../../background/scheduler/index.html).
+///
+/// The config is loaded by the caller, so a `stripe_discord.yaml` that can not be loaded stops
+/// the startup instead of leaving out its endpoints and API keys.
+///
+/// ## Arguments
+/// - `organization`: `Organization` - The Organization webhooks are handled for
+/// - `config`: `&ConfigSetup` - The loaded config, its `Endpoints` are mounted and its `ApiKeys`
+///   accepted by the admin API
+///
+/// ## Returns
+/// - `Rocket<Build>`: The Rocket instance, configure the address and port before launching it
+pub fn build_rocket(organization: Organization, config: &ConfigSetup) -> Rocket<Build> {
+    // API keys listed in the config, keys issued through the CLI are looked up in the database
+    let configured_api_keys: Vec<ConfiguredApiKey> = config.api_keys.clone();
+
+    let endpoints: Vec<EndpointConfigStripe> = config.endpoints.clone();
+
+    // every Organization, the default one first, the admin API syncs roles with their settings
+    let organizations: Vec<Organization> = std::iter::once(organization.clone())
+        .chain(endpoints.iter().map(|endpoint| organization_for_endpoint(&organization, endpoint)))
+        .collect();
+
+    // the Organizations with a drip sequence, reminder window or time-boxed products, their
+    // scheduled emails are sent and their access expired once the server is up
+    let scheduled: Vec<Organization> = organizations
+        .iter()
+        .filter(|organization| {
+            !organization.drip_sequence.is_empty()
+                || organization.reminder_window.is_some()
+                || !organization.access_products.is_empty()
+        })
+        .cloned()
+        .collect();
+
+    let rocket: Rocket<Build> = mount_endpoints(rocket::build(), endpoints, &organization);
+
+    rocket
+        .manage(organization)
+        .manage(organizations)
+        .manage(configured_api_keys)
+        .mount("/", routes![
+            stripe_webhook,
+            metrics,
+            healthz,
+            readyz
+        ])
+        .mount("/admin", admin_routes())
+        .mount("/email_webhooks", email_webhook_routes())
+        .attach(AdHoc::on_liftoff("Scheduler", |_| Box::pin(async move {
+            if !scheduled.is_empty() {
+                start_scheduler(scheduled);
+            }
+        })))
+}
+
+
+/// # stripe_webhook
+/// Verifies and stores a Stripe webhook, it is handled once the response is sent.
+#[post("/stripe_webhooks", format = "json", data = "<webhook_data>")]
+pub async fn stripe_webhook(
+    webhook_data: Data<'_>,
+    headers: WebhookHeaders,
+    organization: &State<Organization>,
+) -> status::Custom<String> {
+    let raw_body: String = match read_webhook_body(webhook_data).await {
+        Ok(raw_body) => raw_body,
+        Err(response) => return response,
+    };
+
+    // without a secret nothing can be verified, so nothing is stored or handled
+    let secret: Secret = match secret("STRIPE_WEBHOOK_SECRET") {
+        Ok(secret) => secret,
+        Err(error) => {
+            error!(%error, "STRIPE_WEBHOOK_SECRET is unavailable, rejecting the webhook");
+            return status::Custom(Status::InternalServerError, "Webhook secret is unavailable".to_string());
+        }
+    };
+
+    // only events with a valid `Stripe-Signature` are stored and handled
+    if let Err(error) = verify_webhook_with_secret(&raw_body, &headers, &secret) {
+        warn!(%error, "Rejected webhook");
+        return status::Custom(Status::BadRequest, error.to_string());
+    }
+
+    receive_webhook(raw_body, headers, organization.inner().clone()).await
+}
+
+
+/// # read_webhook_body
+/// Reads the raw body of a webhook, the signature is computed over it exactly as received.
+///
+/// ## Errors
+/// - `400` when the body is not UTF-8, `413` when it is larger than 1 MiB
+pub async fn read_webhook_body(webhook_data: Data<'_>) -> Result<String, status::Custom<String>> {
+    let raw_body: Capped<String> = webhook_data
+        .open(1.mebibytes())
+        .into_string()
+        .await
+        .map_err(|error| status::Custom(Status::BadRequest, error.to_string()))?;
+
+    if !raw_body.is_complete() {
+        return Err(status::Custom(Status::PayloadTooLarge, "Webhook body is too large".to_string()));
+    }
+
+    Ok(raw_body.into_inner())
+}
+
+
+/// # receive_webhook
+/// Stores a verified webhook and acknowledges it, it is handled for the Organization in the
+/// background so slow handlers and email retries never run into the response timeout of Stripe.
+/// Shared by `/stripe_webhooks` and the endpoints from the config.
+///
+/// Redeliveries of a stored event are acknowledged without handling them again, events that
+/// failed are replayed with `stripe_discord events replay`.
+///
+/// ## Arguments
+/// - `raw_body`: `String` - The request body exactly as received
+/// - `headers`: `WebhookHeaders` - The request headers
+/// - `organization`: `Organization` - The Organization the webhook is handled for
+pub async fn receive_webhook(
+    raw_body: String,
+    headers: WebhookHeaders,
+    organization: Organization,
+) -> status::Custom<String> {
+    let supabase: SupabaseClient = match supabase_client() {
+        Ok(supabase) => supabase,
+        Err(error) => {
+            error!(%error, "Failed to store the webhook");
+            return status::Custom(Status::InternalServerError, "Failed to store webhook".to_string());
+        }
+    };
+
+    // events without an id cannot be deduplicated, they are rejected before they are stored
+    let event: WebhookEvent = match WebhookEvent::new(raw_body, headers.0, unix_now()) {
+        Ok(event) => event,
+        Err(error) => {
+            warn!(%error, "Rejected webhook");
+            return status::Custom(Status::BadRequest, error);
+        }
+    };
+
+    let event_id: String = event.event_id.clone();
+
+    // store the event before handling it so it can be replayed, Stripe retries when this fails
+    let stored: Result<Option<WebhookEvent>, String> = event
+        .insert(supabase.clone())
+        .await
+        .map_err(|error| error.to_string());
+
+    let mut event: WebhookEvent = match stored {
+        Ok(Some(event)) => event,
+        Ok(None) => {
+            info!(%event_id, "Webhook was already received");
+            return status::Custom(Status::Ok, "Received webhook".to_string());
+        },
+        Err(error) => {
+            error!(%error, "Failed to store the webhook");
+            return status::Custom(Status::InternalServerError, "Failed to store webhook".to_string());
+        }
+    };
+
+    // the outcome and any error are recorded on the stored event
+    spawn_background(async move {
+        process_event(&mut event, organization, supabase).await;
+    });
+
+    status::Custom(Status::Ok, "Received webhook".to_string())
+}
+
+
+/// # metrics
+/// Serves every metric in the Prometheus text format for scraping.
+#[get("/metrics")]
+pub async fn metrics() -> (ContentType, String) {
+    let content_type: ContentType = ContentType::parse_flexible(CONTENT_TYPE)
+        .unwrap_or(ContentType::Plain);
+
+    (content_type, render_metrics())
+}
+
+
+/// # healthz
+/// Liveness, answers as long as the process is able to serve requests.
+#[get("/healthz")]
+pub async fn healthz() -> Json<Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+
+/// # readyz
+/// Readiness, checks every configured dependency and answers `503` when one of them is down.
+#[get("/readyz")]
+pub async fn readyz() -> status::Custom<Json<Readiness>> {
+    let readiness: Readiness = check_readiness().await;
+
+    let status: Status = if readiness.ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    status::Custom(status, Json(readiness))
+}
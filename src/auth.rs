@@ -1,26 +1,384 @@
-//! ## Authenticating of `stripe`, `discord`, `emailing` and `databasing` keys
+//! ## Authenticating requests to the non-webhook routes
+//!
+//! Webhooks are authenticated by their `Stripe-Signature`, every other route that reads or
+//! changes data needs an API key sent as `Authorization: Bearer <key>`.
 //!
 //! ### Table of contents
-//! - Authenticating all `stripe` keys & secrets
-//! - Authenticating all `discord` keys & id's
-//! - How to secure `.env` vars or vars being passed
+//! - [`Scope`] - What a key may do
+//! - [`ApiKey`](../db/operations/api_key/struct.ApiKey.html) - Keys issued through the CLI, stored in the database
+//! - [`ConfiguredApiKey`] - Keys listed under `Auth.ApiKeys` in `stripe_discord.yaml`
+//! - [`ReadCustomers`], [`WriteCustomers`], [`ReplayEvents`] - Rocket request guards per scope
 //!
-//! ### How to switch between `.env` or custom implementation
-//! You can route either the `.env` name of the keys or you can pass them directly as a String
+//! ### How keys are stored
+//! Keys look like `sdk_<key id>_<secret>` and are shown once when they are issued. Only the
+//! SHA-256 hash of the whole key is stored, the key id is kept in the clear so a key can be found
+//! and revoked without knowing it. Keys are long random strings, so a fast unsalted hash is enough.
 //!
+//! ### Keys in the config
+//! Deployments without a database for keys can list hashes in the config, print one with
+//! `stripe_discord keys issue <name> --scope <scope> --config`:
+//! ```yaml
+//! Auth:
+//!   ApiKeys:
+//!     - Name: support
+//!       Hash: 3f1c...
+//!       Scopes: [customers:read, customers:write]
+//! ```
 //!
 //! ### Usage example
-//! ```rust
-//!
-//!
+//! ```rust,ignore
+//! #[get("/customers/<customer_id>")]
+//! pub async fn get_customer(_key: ReadCustomers, customer_id: String) -> AdminResponse {
+//!     // only keys with the `customers:read` scope get here
+//! }
 //! ```
 //!
-//! ### Arguments
-//!
-//! ### Returns
-//!
 //! ### Errors
-//!
-//! ### Notes
-//! Not implemented yet
-//!
+//! - `401` - No key, an unknown key or a revoked key
+//! - `403` - The key lacks the scope of the route
+//! - `500` - The database of keys could not be reached
+
+use crate::db::operations::api_key::ApiKey;
+use crate::events::signature::unix_now;
+use crate::secrets::supabase_client;
+
+use rand::RngCore;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use supabase_rs::SupabaseClient;
+use tracing::{error, warn};
+
+
+/// Every issued key starts with this prefix so leaked keys are easy to recognise
+pub const API_KEY_PREFIX: &str = "sdk_";
+
+
+/// ## Scope
+/// What an API key may do
+///
+/// ### Variants
+/// - `ReadCustomers` - `customers:read`, search and view customer records
+/// - `WriteCustomers` - `customers:write`, change customer records, resend emails and sync roles
+/// - `ReplayEvents` - `events:replay`, process stored webhook events again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    ReadCustomers,
+    WriteCustomers,
+    ReplayEvents,
+}
+
+
+/// Every scope, in the order they are listed
+pub const ALL_SCOPES: [Scope; 3] = [Scope::ReadCustomers, Scope::WriteCustomers, Scope::ReplayEvents];
+
+
+impl FromStr for Scope {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a scope name into a scope
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::auth::Scope;
+    /// assert_eq!("events:replay".parse(), Ok(Scope::ReplayEvents));
+    /// assert!("customers:delete".parse::<Scope>().is_err());
+    /// ```
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "customers:read" => Ok(Scope::ReadCustomers),
+            "customers:write" => Ok(Scope::WriteCustomers),
+            "events:replay" => Ok(Scope::ReplayEvents),
+            _ => Err(format!(
+                "unknown scope `{}`, scopes are {}",
+                scope,
+                ALL_SCOPES.iter().map(|scope| scope.to_string()).collect::<Vec<String>>().join(", ")
+            )),
+        }
+    }
+}
+
+
+impl fmt::Display for Scope {
+    /// ## To String
+    /// This will write the name of the scope
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::ReadCustomers => "customers:read",
+            Scope::WriteCustomers => "customers:write",
+            Scope::ReplayEvents => "events:replay",
+        })
+    }
+}
+
+
+/// ## ConfiguredApiKey
+/// A key listed under `Auth.ApiKeys` in `stripe_discord.yaml`
+///
+/// ### Fields
+/// - `name` - Who or what the key is for
+/// - `key_hash` - The hex encoded SHA-256 of the key
+/// - `scopes` - What the key may do, unknown scope names are dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfiguredApiKey {
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<Scope>,
+}
+
+
+impl ConfiguredApiKey {
+    /// # from_config
+    /// Reads the keys under `Auth.ApiKeys`, entries without a name or hash are skipped.
+    ///
+    /// ## Arguments
+    /// - `value`: `&Value` - The whole config file
+    pub fn from_config(value: &Value) -> Vec<Self> {
+        let Some(keys) = value["Auth"]["ApiKeys"].as_array() else {
+            return Vec::new();
+        };
+
+        keys.iter()
+            .filter_map(|key| {
+                let name: &str = key["Name"].as_str()?;
+                let key_hash: &str = key["Hash"].as_str()?;
+
+                let scopes: Vec<Scope> = key["Scopes"]
+                    .as_array()
+                    .map(|scopes| scopes.iter().filter_map(|scope| scope.as_str()?.parse().ok()).collect())
+                    .unwrap_or_default();
+
+                Some(ConfiguredApiKey {
+                    name: name.to_string(),
+                    key_hash: key_hash.to_lowercase(),
+                    scopes,
+                })
+            })
+            .collect()
+    }
+}
+
+
+/// ## AuthenticatedKey
+/// The key a request was authenticated with
+///
+/// ### Fields
+/// - `key_id` - The id of the key, `None` for keys from the config
+/// - `name` - Who or what the key is for
+/// - `scopes` - What the key may do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedKey {
+    pub key_id: Option<String>,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+
+/// # generate_api_key
+/// Generates a new random key.
+///
+/// ## Returns
+/// - `(String, String)`: The key id and the full key, `sdk_<key id>_<secret>`
+pub fn generate_api_key() -> (String, String) {
+    let mut key_id: [u8; 6] = [0; 6];
+    let mut secret: [u8; 24] = [0; 24];
+
+    rand::thread_rng().fill_bytes(&mut key_id);
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    let key_id: String = hex::encode(key_id);
+    let key: String = format!("{}{}_{}", API_KEY_PREFIX, key_id, hex::encode(secret));
+
+    (key_id, key)
+}
+
+
+/// # hash_api_key
+/// The hex encoded SHA-256 of a key, the only form keys are stored in.
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+
+/// # key_id_of
+/// The key id of a key, `None` when the key is not shaped like an issued key.
+pub fn key_id_of(key: &str) -> Option<&str> {
+    key.strip_prefix(API_KEY_PREFIX)
+        .and_then(|rest| rest.split_once('_'))
+        .map(|(key_id, _)| key_id)
+        .filter(|key_id| !key_id.is_empty())
+}
+
+
+/// # issue_api_key
+/// Generates a key and stores its hash in the database.
+///
+/// ## Arguments
+/// - `name`: `String` - Who or what the key is for
+/// - `scopes`: `Vec<Scope>` - What the key may do
+/// - `supabase`: `SupabaseClient` - The client used to store the key
+///
+/// ## Returns
+/// - `(ApiKey, String)`: The stored key and the full key, which can not be recovered later
+pub async fn issue_api_key(
+    name: String,
+    scopes: Vec<Scope>,
+    supabase: SupabaseClient,
+) -> Result<(ApiKey, String), Box<dyn Error>> {
+    let (key_id, key): (String, String) = generate_api_key();
+
+    let api_key: ApiKey = ApiKey::new(key_id, name, hash_api_key(&key), &scopes, unix_now());
+    api_key.insert(supabase).await?;
+
+    Ok((api_key, key))
+}
+
+
+/// # authenticate
+/// Finds the key a request was sent with, in the config first and in the database second.
+///
+/// ## Arguments
+/// - `key`: `&str` - The key from the `Authorization` header
+/// - `configured`: `&[ConfiguredApiKey]` - The keys from the config
+/// - `supabase`: `Option<SupabaseClient>` - The client used to look up issued keys, `None` when
+///   there is no database and only the config keys are accepted
+///
+/// ## Returns
+/// - `Ok(Some(AuthenticatedKey))` - The key is known and not revoked
+/// - `Ok(None)` - The key is unknown or revoked
+///
+/// ## Errors
+/// - `String` - When the database could not be reached
+pub async fn authenticate(
+    key: &str,
+    configured: &[ConfiguredApiKey],
+    supabase: Option<SupabaseClient>,
+) -> Result<Option<AuthenticatedKey>, String> {
+    let key_hash: String = hash_api_key(key);
+
+    if let Some(configured) = configured.iter().find(|configured| hashes_match(&configured.key_hash, &key_hash)) {
+        return Ok(Some(AuthenticatedKey {
+            key_id: None,
+            name: configured.name.clone(),
+            scopes: configured.scopes.clone(),
+        }));
+    }
+
+    let (Some(key_id), Some(supabase)) = (key_id_of(key), supabase) else {
+        return Ok(None);
+    };
+
+    let stored: Option<ApiKey> = ApiKey::get_by_key_id(key_id, supabase)
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let authenticated: Option<AuthenticatedKey> = stored
+        .filter(|stored| stored.revoked_at.is_none() && hashes_match(&stored.key_hash, &key_hash))
+        .map(|stored| AuthenticatedKey {
+            scopes: stored.scopes(),
+            key_id: Some(stored.key_id),
+            name: stored.name,
+        });
+
+    Ok(authenticated)
+}
+
+
+/// # hashes_match
+/// Compares two hex hashes in constant time.
+fn hashes_match(expected: &str, actual: &str) -> bool {
+    if expected.len() != actual.len() {
+        return false;
+    }
+
+    expected
+        .bytes()
+        .zip(actual.bytes())
+        .fold(0u8, |difference, (expected, actual)| difference | (expected ^ actual))
+        == 0
+}
+
+
+/// # authorize
+/// Authenticates the bearer key of a request and checks it has the scope of the route.
+async fn authorize(request: &Request<'_>, scope: Scope) -> Outcome<AuthenticatedKey, &'static str> {
+    let key: Option<&str> = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|authorization| authorization.strip_prefix("Bearer "));
+
+    let Some(key) = key else {
+        return Outcome::Error((Status::Unauthorized, "missing API key"));
+    };
+
+    let configured: &[ConfiguredApiKey] = request
+        .rocket()
+        .state::<Vec<ConfiguredApiKey>>()
+        .map(|configured| configured.as_slice())
+        .unwrap_or_default();
+
+    // without a database only the config keys are accepted
+    let supabase: Option<SupabaseClient> = supabase_client().ok();
+
+    match authenticate(key, configured, supabase).await {
+        Ok(Some(authenticated)) if authenticated.scopes.contains(&scope) => Outcome::Success(authenticated),
+        Ok(Some(authenticated)) => {
+            warn!(key = %authenticated.name, scope = %scope, "API key lacks the scope of the route");
+            Outcome::Error((Status::Forbidden, "API key lacks the scope of the route"))
+        },
+        Ok(None) => Outcome::Error((Status::Unauthorized, "invalid API key")),
+        Err(error) => {
+            error!(%error, "Failed to look up the API key");
+            Outcome::Error((Status::InternalServerError, "failed to look up the API key"))
+        },
+    }
+}
+
+
+/// ## ReadCustomers
+/// Request guard for keys with the `customers:read` scope
+#[derive(Debug)]
+pub struct ReadCustomers(pub AuthenticatedKey);
+
+/// ## WriteCustomers
+/// Request guard for keys with the `customers:write` scope
+#[derive(Debug)]
+pub struct WriteCustomers(pub AuthenticatedKey);
+
+/// ## ReplayEvents
+/// Request guard for keys with the `events:replay` scope
+#[derive(Debug)]
+pub struct ReplayEvents(pub AuthenticatedKey);
+
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReadCustomers {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::ReadCustomers).await.map(ReadCustomers)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WriteCustomers {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::WriteCustomers).await.map(WriteCustomers)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReplayEvents {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::ReplayEvents).await.map(ReplayEvents)
+    }
+}
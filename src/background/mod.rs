@@ -0,0 +1,48 @@
+//! ## Background tasks
+//!
+//! Work that should not hold up the webhook response is spawned here, every task is counted in
+//! the `background_tasks_in_flight` gauge while it is queued or running.
+//!
+//! ### Table of contents
+//! - `scheduler` - Sends the scheduled emails of drip sequences and renewal reminders once they
+//!   are due and expires the time-boxed access that has ended
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! spawn_background(async move {
+//!     sleep(Duration::from_secs(5)).await;
+//!     // attach the payment link
+//! });
+//! ```
+
+use crate::metrics::BACKGROUND_TASKS_IN_FLIGHT;
+
+use std::future::Future;
+use tokio::spawn;
+use tokio::task::JoinHandle;
+
+pub mod scheduler;
+
+
+/// # spawn_background
+/// Spawns a task on the tokio runtime and tracks it in `background_tasks_in_flight`.
+///
+/// ## Arguments
+/// - `task`: `impl Future` - The work to run in the background
+///
+/// ## Returns
+/// The `JoinHandle` of the spawned task.
+pub fn spawn_background<F>(task: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    BACKGROUND_TASKS_IN_FLIGHT.inc();
+
+    spawn(async move {
+        let output: F::Output = task.await;
+
+        BACKGROUND_TASKS_IN_FLIGHT.dec();
+        output
+    })
+}
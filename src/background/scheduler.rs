@@ -0,0 +1,194 @@
+//! ## Scheduled email and access scheduler
+//!
+//! Sends the steps of drip sequences once they are due, see [drip](../../email/drip/index.html),
+//! and every [`REMINDER_SCAN_INTERVAL`] the renewal reminders of customers whose `end_time` is
+//! within the reminder window, see [reminder](../../email/reminder/index.html). Every run also
+//! expires the time-boxed access that has ended, see [access](../../discord/access/index.html).
+//! The steps are stored in the [scheduled emails](../../db/operations/scheduled_email/index.html)
+//! so nothing is lost on a restart, every [`SCHEDULER_INTERVAL`] the pending steps that are due
+//! are sent, steps that came due while the server was down included.
+//!
+//! Only the steps of the Organizations the scheduler is started with are touched. A step whose
+//! name is not configured anymore is `skipped`, one that fails is `failed` with the error, after
+//! the email itself was retried like every other email.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! start_scheduler(vec![organization_from_config(&ConfigSetup::new())]);
+//! ```
+
+use crate::db::operations::scheduled_email::{ScheduledEmail, ScheduledStatus};
+use crate::discord::access::expire_ended_access;
+use crate::email::drip::{send_drip_email, DripStep};
+use crate::email::reminder::remind_ending_customers;
+use crate::events::signature::unix_now;
+use crate::log::redact::redact_email;
+use crate::secrets::supabase_client;
+use crate::Organization;
+
+use std::time::Duration;
+use supabase_rs::SupabaseClient;
+use tokio::spawn;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+
+/// How often the scheduler looks for due emails
+pub const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The most emails an Organization sends per run, the rest follow in the next run
+pub const SCHEDULER_BATCH: usize = 100;
+
+/// How often the customers are scanned for renewals within the reminder window
+pub const REMINDER_SCAN_INTERVAL: Duration = Duration::from_secs(3600);
+
+
+/// # start_scheduler
+/// Runs [`run_due_emails`] and [`run_access_expiry`] every [`SCHEDULER_INTERVAL`] and
+/// [`run_reminder_scan`] every [`REMINDER_SCAN_INTERVAL`] for as long as the server runs,
+/// starting right away so steps that came due during a restart go out first.
+///
+/// ## Arguments
+/// - `organizations`: `Vec<Organization>` - The Organizations whose drip sequences and renewal
+///   reminders are sent and whose time-boxed access expires
+///
+/// ## Returns
+/// The `JoinHandle` of the scheduler task.
+pub fn start_scheduler(organizations: Vec<Organization>) -> JoinHandle<()> {
+    info!(organizations = organizations.len(), "Starting the email scheduler");
+
+    spawn(async move {
+        let mut last_scan: Option<i64> = None;
+
+        loop {
+            let now: i64 = unix_now();
+
+            if let Err(error) = run_due_emails(&organizations, now).await {
+                error!(%error, "Failed to send the scheduled emails");
+            }
+
+            if let Err(error) = run_access_expiry(&organizations, now).await {
+                error!(%error, "Failed to expire the ended access");
+            }
+
+            if last_scan.is_none_or(|last_scan| now - last_scan >= REMINDER_SCAN_INTERVAL.as_secs() as i64) {
+                last_scan = Some(now);
+
+                if let Err(error) = run_reminder_scan(&organizations, now).await {
+                    error!(%error, "Failed to send the renewal reminders");
+                }
+            }
+
+            sleep(SCHEDULER_INTERVAL).await;
+        }
+    })
+}
+
+
+/// # run_due_emails
+/// Sends the pending steps of every Organization that are due at `now`.
+///
+/// ## Arguments
+/// - `organizations`: `&[Organization]` - The Organizations whose drip sequences are sent
+/// - `now`: `i64` - The unix timestamp steps are due at
+///
+/// ## Returns
+/// - `Result<usize, String>`: The number of sent emails, or why the scheduled emails could not be
+///   read. Emails that fail are stored as `failed` and do not stop the run.
+pub async fn run_due_emails(organizations: &[Organization], now: i64) -> Result<usize, String> {
+    let supabase: SupabaseClient = supabase_client().map_err(|error| error.to_string())?;
+    let mut sent: usize = 0;
+
+    for organization in organizations.iter().filter(|organization| !organization.drip_sequence.is_empty()) {
+        let due: Vec<ScheduledEmail> = ScheduledEmail::list_due(&organization.name, now, SCHEDULER_BATCH, supabase.clone())
+            .await
+            .map_err(|error| error.to_string())?;
+
+        for scheduled in due {
+            if send_scheduled(organization, scheduled, supabase.clone()).await {
+                sent += 1;
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+
+/// # run_reminder_scan
+/// Sends the renewal reminders of every Organization with a reminder window to the customers
+/// whose `end_time` is between `now` and the end of the window.
+///
+/// ## Arguments
+/// - `organizations`: `&[Organization]` - The Organizations whose customers are reminded
+/// - `now`: `i64` - The unix timestamp the window starts at
+///
+/// ## Returns
+/// - `Result<usize, String>`: The number of sent reminders, or why the customers could not be
+///   read. Reminders that fail are attempted again by the next scan.
+pub async fn run_reminder_scan(organizations: &[Organization], now: i64) -> Result<usize, String> {
+    let supabase: SupabaseClient = supabase_client().map_err(|error| error.to_string())?;
+    let mut sent: usize = 0;
+
+    for organization in organizations.iter().filter(|organization| organization.reminder_window.is_some()) {
+        sent += remind_ending_customers(organization, now, &supabase).await?;
+    }
+
+    Ok(sent)
+}
+
+
+/// # run_access_expiry
+/// Expires the time-boxed access that ended at or before `now` when any Organization sells a
+/// time-boxed product. The customers are shared, so they are expired once for all of them, each
+/// with the Discord role of the Organization that granted the access.
+///
+/// ## Arguments
+/// - `organizations`: `&[Organization]` - The Organizations whose time-boxed access expires
+/// - `now`: `i64` - The unix timestamp access has ended by
+///
+/// ## Returns
+/// - `Result<usize, String>`: The number of customers whose access expired, or why the customers
+///   could not be read. Customers whose role could not be revoked are attempted again next run.
+pub async fn run_access_expiry(organizations: &[Organization], now: i64) -> Result<usize, String> {
+    if organizations.iter().all(|organization| organization.access_products.is_empty()) {
+        return Ok(0);
+    }
+
+    let supabase: SupabaseClient = supabase_client().map_err(|error| error.to_string())?;
+
+    expire_ended_access(organizations, now, &supabase).await
+}
+
+
+/// # send_scheduled
+/// Sends one scheduled step and stores how it went, returns whether it was sent.
+async fn send_scheduled(organization: &Organization, mut scheduled: ScheduledEmail, supabase: SupabaseClient) -> bool {
+    let step: Option<&DripStep> = organization.drip_step(&scheduled.step);
+
+    let (status, message_id, reason) = match step {
+        None => (ScheduledStatus::Skipped, None, Some(format!("the drip step {} is not configured", scheduled.step))),
+        Some(step) => match send_drip_email(organization, step, &scheduled).await {
+            Ok(message_id) => (ScheduledStatus::Sent, Some(message_id), None),
+            Err(error) => (ScheduledStatus::Failed, None, Some(error)),
+        },
+    };
+
+    match (&status, &reason) {
+        (ScheduledStatus::Sent, _) => info!(email = %redact_email(&scheduled.email), step = %scheduled.step, "Drip email sent"),
+        (_, Some(reason)) => warn!(email = %redact_email(&scheduled.email), step = %scheduled.step, %reason, "Drip email not sent"),
+        _ => {},
+    }
+
+    let recorded: Result<(), String> = scheduled
+        .record(status, message_id, reason, unix_now(), supabase)
+        .await
+        .map_err(|error| error.to_string());
+
+    if let Err(error) = recorded {
+        error!(email = %redact_email(&scheduled.email), step = %scheduled.step, %error, "Failed to record the scheduled email");
+    }
+
+    status == ScheduledStatus::Sent
+}
@@ -0,0 +1,99 @@
+//! ## Webhook event log commands
+//!
+//! ### Usage example
+//! ```text
+//! $ stripe_discord events list --outcome failed
+//! evt_1P...  charge.succeeded  failed  attempts=1  received_at=1714000000
+//!
+//! $ stripe_discord events replay evt_1P...
+//! evt_1P...  charge.succeeded  handled  attempts=2
+//! ```
+//! Replays go through the same pipeline as a received webhook, the signature is not checked again
+//! as it was verified when the event was first received.
+
+use crate::db::operations::webhook_event::{WebhookEvent, WebhookOutcome};
+use crate::events::pipeline::process_event;
+use crate::organization::router::organization_from_config;
+use crate::ConfigSetup;
+
+use serde_json::{json, Value};
+use supabase_rs::SupabaseClient;
+
+
+/// # list
+/// Prints the most recently received events, one per line.
+pub async fn list(
+    event_type: Option<String>,
+    outcome: Option<WebhookOutcome>,
+    limit: usize,
+    supabase: SupabaseClient,
+) -> Result<(), String> {
+    let events: Vec<WebhookEvent> = WebhookEvent::list(event_type.as_deref(), outcome, limit, supabase)
+        .await
+        .map_err(|error| error.to_string())?;
+
+    for event in events {
+        println!(
+            "{}  {}  {}  attempts={}  received_at={}",
+            event.event_id, event.event_type, event.outcome, event.attempts, event.received_at
+        );
+    }
+
+    Ok(())
+}
+
+
+/// # show
+/// Prints a stored event with its headers and parsed body.
+pub async fn show(event_id: &str, supabase: SupabaseClient) -> Result<(), String> {
+    let event: WebhookEvent = find(event_id, supabase).await?;
+
+    let body: Value = event.payload().unwrap_or(Value::String(event.raw_body.clone()));
+
+    let printed: Value = json!({
+        "event_id": event.event_id,
+        "event_type": event.event_type,
+        "received_at": event.received_at,
+        "verified": event.verified,
+        "attempts": event.attempts,
+        "outcome": event.outcome,
+        "last_error": event.last_error,
+        "processed_at": event.processed_at,
+        "headers": event.headers,
+        "body": body,
+    });
+
+    println!("{}", serde_json::to_string_pretty(&printed).map_err(|error| error.to_string())?);
+
+    Ok(())
+}
+
+
+/// # replay
+/// Processes a stored event again through the normal `EventHandler` pipeline.
+///
+/// ## Errors
+/// - The event is not stored, or the replay failed
+pub async fn replay(event_id: &str, supabase: SupabaseClient) -> Result<(), String> {
+    let mut event: WebhookEvent = find(event_id, supabase.clone()).await?;
+
+    let organization = organization_from_config(&ConfigSetup::new());
+    let outcome: WebhookOutcome = process_event(&mut event, organization, supabase).await;
+
+    println!("{}  {}  {}  attempts={}", event.event_id, event.event_type, outcome, event.attempts);
+
+    match outcome {
+        WebhookOutcome::Failed => Err(event.last_error.unwrap_or("replay failed".to_string())),
+        _ => Ok(()),
+    }
+}
+
+
+/// # find
+/// Retrieves a stored event or explains that it was never stored.
+async fn find(event_id: &str, supabase: SupabaseClient) -> Result<WebhookEvent, String> {
+    WebhookEvent::get_by_event_id(event_id, supabase)
+        .await
+        .map_err(|error| error.to_string())?
+        .ok_or(format!("no stored event with id `{}`", event_id))
+}
@@ -0,0 +1,94 @@
+//! ## API key commands
+//!
+//! ### Usage example
+//! ```text
+//! $ stripe_discord keys issue support --scope customers:read --scope customers:write
+//! 3f9a1c0b7d2e  support  customers:read,customers:write
+//! sdk_3f9a1c0b7d2e_9c4f...
+//! Store the key now, it can not be shown again.
+//!
+//! $ stripe_discord keys revoke 3f9a1c0b7d2e
+//! 3f9a1c0b7d2e  support  revoked_at=1714000000
+//! ```
+//! `--config` prints a `stripe_discord.yaml` entry with the hash instead of storing the key in the
+//! database, such keys are revoked by removing the entry.
+
+use crate::auth::{generate_api_key, hash_api_key, issue_api_key, Scope};
+use crate::db::operations::api_key::ApiKey;
+use crate::events::signature::unix_now;
+use crate::ConfigSetup;
+
+use supabase_rs::SupabaseClient;
+
+
+/// # issue
+/// Issues a key, stores its hash in the database and prints the key once.
+pub async fn issue(name: String, scopes: Vec<Scope>, supabase: SupabaseClient) -> Result<(), String> {
+    let (api_key, key): (ApiKey, String) = issue_api_key(name, scopes, supabase)
+        .await
+        .map_err(|error| error.to_string())?;
+
+    println!("{}  {}  {}", api_key.key_id, api_key.name, api_key.scopes.join(","));
+    println!("{}", key);
+    println!("Store the key now, it can not be shown again.");
+
+    Ok(())
+}
+
+
+/// # issue_config
+/// Generates a key and prints it with the `stripe_discord.yaml` entry of its hash.
+pub fn issue_config(name: String, scopes: Vec<Scope>) -> Result<(), String> {
+    let scope_names: Vec<String> = scopes.iter().map(|scope| scope.to_string()).collect();
+    let (_, key): (String, String) = generate_api_key();
+
+    println!("{}", key);
+    println!("Store the key now, it can not be shown again. Add its hash to stripe_discord.yaml:\n");
+    println!("Auth:\n  ApiKeys:\n    - Name: {}\n      Hash: {}\n      Scopes: [{}]", name, hash_api_key(&key), scope_names.join(", "));
+
+    Ok(())
+}
+
+
+/// # list
+/// Prints the keys from the config and the database, one per line.
+pub async fn list(supabase: SupabaseClient) -> Result<(), String> {
+    for configured in ConfigSetup::try_new().map(|config| config.api_keys).unwrap_or_default() {
+        let scope_names: Vec<String> = configured.scopes.iter().map(|scope| scope.to_string()).collect();
+
+        println!("(config)  {}  {}", configured.name, scope_names.join(","));
+    }
+
+    let api_keys: Vec<ApiKey> = ApiKey::list(supabase)
+        .await
+        .map_err(|error| error.to_string())?;
+
+    for api_key in api_keys {
+        let state: String = match api_key.revoked_at {
+            Some(revoked_at) => format!("revoked_at={}", revoked_at),
+            None => "active".to_string(),
+        };
+
+        println!(
+            "{}  {}  {}  created_at={}  {}",
+            api_key.key_id, api_key.name, api_key.scopes.join(","), api_key.created_at, state
+        );
+    }
+
+    Ok(())
+}
+
+
+/// # revoke
+/// Revokes a key by its key id.
+pub async fn revoke(key_id: &str, supabase: SupabaseClient) -> Result<(), String> {
+    let revoked: Option<ApiKey> = ApiKey::revoke(key_id, unix_now(), supabase)
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let api_key: ApiKey = revoked.ok_or(format!("no API key with id `{}`", key_id))?;
+
+    println!("{}  {}  revoked_at={}", api_key.key_id, api_key.name, api_key.revoked_at.unwrap_or_default());
+
+    Ok(())
+}
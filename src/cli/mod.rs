@@ -0,0 +1,242 @@
+//! ## Command line interface
+//!
+//! The `stripe_discord` binary serves the webhook API by default, the subcommands below operate
+//! on the same config and database without going through the API.
+//!
+//! ### Commands
+//! ```text
+//! stripe_discord [serve]
+//! stripe_discord events list [--type <event_type>] [--outcome <outcome>] [--limit <n>]
+//! stripe_discord events show <event_id>
+//! stripe_discord events replay <event_id>
+//! stripe_discord send-test-event <event_type> [--email <email>] [--amount <minor units>]
+//!     [--currency <currency>] [--price <price_id>] [--url <webhook_url>]
+//! stripe_discord keys issue <name> --scope <scope> [--scope <scope>...] [--config]
+//! stripe_discord keys list
+//! stripe_discord keys revoke <key_id>
+//! ```
+//!
+//! ### Table of contents
+//! - [events](events/index.html) - Listing, inspecting and replaying stored webhook events
+//! - [keys](keys/index.html) - Issuing, listing and revoking API keys
+//! - [test_event](test_event/index.html) - Sending signed synthetic events to a running instance
+
+pub mod events;
+pub mod keys;
+pub mod test_event;
+
+use crate::auth::Scope;
+use crate::db::operations::webhook_event::WebhookOutcome;
+use crate::events::test_event::TestEvent;
+use crate::secrets;
+
+use supabase_rs::SupabaseClient;
+
+
+/// Printed when the arguments cannot be parsed
+pub const USAGE: &str = "Usage:
+  stripe_discord [serve]
+  stripe_discord events list [--type <event_type>] [--outcome <outcome>] [--limit <n>]
+  stripe_discord events show <event_id>
+  stripe_discord events replay <event_id>
+  stripe_discord send-test-event <event_type> [--email <email>] [--amount <minor units>]
+      [--currency <currency>] [--price <price_id>] [--url <webhook_url>]
+  stripe_discord keys issue <name> --scope <scope> [--scope <scope>...] [--config]
+  stripe_discord keys list
+  stripe_discord keys revoke <key_id>
+
+Scopes: customers:read, customers:write, events:replay";
+
+
+/// ## Command
+/// A parsed command line
+///
+/// ### Variants
+/// - `Serve` - Serve the webhook API, the default
+/// - `EventsList` - List stored webhook events, newest first
+/// - `EventsShow` - Print a stored webhook event
+/// - `EventsReplay` - Process a stored webhook event again
+/// - `SendTestEvent` - Sign a synthetic event and post it to the webhook API
+/// - `KeysIssue` - Issue an API key, `config` prints a config entry instead of storing it
+/// - `KeysList` - List the API keys
+/// - `KeysRevoke` - Revoke an API key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    EventsList {
+        event_type: Option<String>,
+        outcome: Option<WebhookOutcome>,
+        limit: usize,
+    },
+    EventsShow {
+        event_id: String,
+    },
+    EventsReplay {
+        event_id: String,
+    },
+    SendTestEvent {
+        test_event: TestEvent,
+        url: Option<String>,
+    },
+    KeysIssue {
+        name: String,
+        scopes: Vec<Scope>,
+        config: bool,
+    },
+    KeysList,
+    KeysRevoke {
+        key_id: String,
+    },
+}
+
+
+impl Command {
+    /// # parse
+    /// Parses the arguments that follow the binary name.
+    ///
+    /// ## Arguments
+    /// - `args`: `&[String]` - The arguments, e.g. `["events", "replay", "evt_1P..."]`
+    ///
+    /// ## Errors
+    /// - `String` - What is wrong with the arguments
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let args: Vec<String> = std::env::args().skip(1).collect();
+    /// let command: Command = Command::parse(&args)?;
+    /// ```
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+        match args.as_slice() {
+            [] | ["serve"] => Ok(Command::Serve),
+            ["events", "list", flags @ ..] => parse_events_list(flags),
+            ["events", "show", event_id] => Ok(Command::EventsShow { event_id: event_id.to_string() }),
+            ["events", "replay", event_id] => Ok(Command::EventsReplay { event_id: event_id.to_string() }),
+            ["send-test-event", event_type, flags @ ..] => parse_send_test_event(event_type, flags),
+            ["keys", "issue", name, flags @ ..] => parse_keys_issue(name, flags),
+            ["keys", "list"] => Ok(Command::KeysList),
+            ["keys", "revoke", key_id] => Ok(Command::KeysRevoke { key_id: key_id.to_string() }),
+            _ => Err(format!("unknown command `{}`", args.join(" "))),
+        }
+    }
+}
+
+
+/// # run
+/// Runs every command but `serve`, which is handled by the binary.
+///
+/// ## Errors
+/// - `String` - Why the command failed, printed to stderr by the binary
+pub async fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Serve => Err("`serve` is handled by the binary".to_string()),
+        Command::EventsList { event_type, outcome, limit } => {
+            events::list(event_type, outcome, limit, supabase_client()?).await
+        },
+        Command::EventsShow { event_id } => events::show(&event_id, supabase_client()?).await,
+        Command::EventsReplay { event_id } => events::replay(&event_id, supabase_client()?).await,
+        Command::SendTestEvent { test_event, url } => test_event::send(test_event, url).await,
+        Command::KeysIssue { name, scopes, config: true } => keys::issue_config(name, scopes),
+        Command::KeysIssue { name, scopes, config: false } => keys::issue(name, scopes, supabase_client()?).await,
+        Command::KeysList => keys::list(supabase_client()?).await,
+        Command::KeysRevoke { key_id } => keys::revoke(&key_id, supabase_client()?).await,
+    }
+}
+
+
+/// # supabase_client
+/// The Supabase client from the `SUPABASE_URL` and `SUPABASE_KEY` secrets.
+fn supabase_client() -> Result<SupabaseClient, String> {
+    secrets::supabase_client().map_err(|error| error.to_string())
+}
+
+
+/// # parse_events_list
+/// Parses the flags of `events list`.
+fn parse_events_list(flags: &[&str]) -> Result<Command, String> {
+    let mut event_type: Option<String> = None;
+    let mut outcome: Option<WebhookOutcome> = None;
+    let mut limit: usize = 20;
+
+    let mut flags = flags.iter();
+
+    while let Some(flag) = flags.next() {
+        let value: &str = flags
+            .next()
+            .ok_or(format!("`{}` needs a value", flag))?;
+
+        match *flag {
+            "--type" => event_type = Some(value.to_string()),
+            "--outcome" => outcome = Some(value.parse()?),
+            "--limit" => limit = value.parse().map_err(|_| format!("`{}` is not a number", value))?,
+            _ => return Err(format!("unknown flag `{}`", flag)),
+        }
+    }
+
+    Ok(Command::EventsList { event_type, outcome, limit })
+}
+
+
+/// # parse_send_test_event
+/// Parses the event type and flags of `send-test-event`.
+fn parse_send_test_event(event_type: &str, flags: &[&str]) -> Result<Command, String> {
+    let mut test_event: TestEvent = TestEvent::new(event_type)?;
+    let mut url: Option<String> = None;
+
+    let mut flags = flags.iter();
+
+    while let Some(flag) = flags.next() {
+        let value: &str = flags
+            .next()
+            .ok_or(format!("`{}` needs a value", flag))?;
+
+        test_event = match *flag {
+            "--email" => test_event.with_email(value.to_string()),
+            "--amount" => test_event.with_amount(value.parse().map_err(|_| format!("`{}` is not an amount in minor units", value))?),
+            "--currency" => test_event.with_currency(value.to_string()),
+            "--price" => test_event.with_price(value.to_string()),
+            "--url" => {
+                url = Some(value.to_string());
+                test_event
+            },
+            _ => return Err(format!("unknown flag `{}`", flag)),
+        };
+    }
+
+    Ok(Command::SendTestEvent { test_event, url })
+}
+
+
+/// # parse_keys_issue
+/// Parses the name and flags of `keys issue`, at least one scope is required.
+fn parse_keys_issue(name: &str, flags: &[&str]) -> Result<Command, String> {
+    let mut scopes: Vec<Scope> = Vec::new();
+    let mut config: bool = false;
+
+    let mut flags = flags.iter();
+
+    while let Some(flag) = flags.next() {
+        match *flag {
+            "--config" => config = true,
+            "--scope" => {
+                let value: &str = flags
+                    .next()
+                    .ok_or(format!("`{}` needs a value", flag))?;
+
+                let scope: Scope = value.parse()?;
+
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            },
+            _ => return Err(format!("unknown flag `{}`", flag)),
+        }
+    }
+
+    if scopes.is_empty() {
+        return Err("`keys issue` needs at least one `--scope`".to_string());
+    }
+
+    Ok(Command::KeysIssue { name: name.to_string(), scopes, config })
+}
@@ -0,0 +1,97 @@
+//! ## Sending synthetic test events
+//!
+//! ### Usage example
+//! ```text
+//! $ stripe_discord send-test-event checkout.session.completed --email jane@example.com --amount 1999
+//! evt_test_17c9...  checkout.session.completed  -> http://127.0.0.1:8080/stripe_webhooks  200 OK
+//! ```
+//! The event is signed with `STRIPE_WEBHOOK_SECRET` exactly like Stripe signs it, so the running
+//! instance verifies, stores and handles it like any other webhook. Without a secret the event is
+//! sent unsigned, which only an instance without a secret accepts.
+
+use crate::events::signature::{signature_header, unix_now};
+use crate::events::test_event::TestEvent;
+use crate::secrets::secret;
+use crate::ConfigSetup;
+
+use dotenv::dotenv;
+use reqwest::{Client, Response};
+use serde_json::Value;
+use std::env::var;
+
+
+/// The path the webhook API receives Stripe events on
+pub const WEBHOOK_PATH: &str = "/stripe_webhooks";
+
+
+/// # send
+/// Builds a test event, signs it and posts it to the webhook API.
+///
+/// ## Arguments
+/// - `test_event`: `TestEvent` - The options of the event
+/// - `url`: `Option<String>` - The webhook url, defaults to [`webhook_url`] of the config
+///
+/// ## Errors
+/// - `String` - When the request fails or the API does not answer with a success status
+pub async fn send(test_event: TestEvent, url: Option<String>) -> Result<(), String> {
+    dotenv().ok();
+
+    let url: String = url.unwrap_or_else(|| webhook_url(&ConfigSetup::new()));
+    let event: Value = test_event.build();
+    let body: String = event.to_string();
+
+    let mut request = Client::new()
+        .post(&url)
+        .header("Content-Type", "application/json");
+
+    match secret("STRIPE_WEBHOOK_SECRET") {
+        Ok(secret) => {
+            request = request.header("Stripe-Signature", signature_header(&body, unix_now(), secret.expose()));
+        },
+        Err(error) => eprintln!("{}, sending the event unsigned", error),
+    }
+
+    let response: Response = request
+        .body(body)
+        .send()
+        .await
+        .map_err(|error| format!("failed to send the event to {}: {}", url, error))?;
+
+    let status = response.status();
+
+    println!("{}  {}  -> {}  {}", event["id"].as_str().unwrap_or_default(), test_event.event_type, url, status);
+
+    if !status.is_success() {
+        let reply: String = response.text().await.unwrap_or_default();
+        return Err(format!("the webhook API answered {}: {}", status, reply));
+    }
+
+    Ok(())
+}
+
+
+/// # webhook_url
+/// The webhook url of the configured `Api` address.
+///
+/// `PORT` overrides the configured port the same way it does for `serve`, and an `Api.Host` of
+/// `0.0.0.0` is reached through `127.0.0.1`.
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::cli::test_event::webhook_url;
+/// # use stripe_discord::ConfigSetup;
+/// assert_eq!(webhook_url(&ConfigSetup::default()), "http://127.0.0.1:8080/stripe_webhooks");
+/// ```
+pub fn webhook_url(config: &ConfigSetup) -> String {
+    let host: &str = match config.host.as_str() {
+        "0.0.0.0" | "" => "127.0.0.1",
+        host => host,
+    };
+
+    let port: u64 = var("PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(config.port);
+
+    format!("http://{}:{}{}", host, port, WEBHOOK_PATH)
+}
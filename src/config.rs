@@ -15,9 +15,27 @@
 
 use serde_json::Value;
 use serde_yaml;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
 use std::{error::Error, fs, fs::File, io::BufReader};
 
-use crate::ConfigSetup;
+use crate::auth::ConfiguredApiKey;
+use crate::discord::snowflake::parse_snowflake;
+use crate::email::locale::{EmailTranslation, Locale, DEFAULT_LOCALE};
+use crate::email::templates::source::DEFAULT_TEMPLATES_DIR;
+use crate::secrets::{secret, Secret};
+use crate::organization::model::{DisputePolicy, EmailEvent, RefundPolicy};
+use crate::discord::access::AccessProduct;
+use crate::email::drip::DripStep;
+use crate::{ConfigError, ConfigSetup, DripStepConfig, EmailConfig, EmailEventConfig, EndpointConfigStripe};
+
+
+/// The config file loaded from the working directory
+const CONFIG_PATH: &str = "stripe_discord.yaml";
+
+/// Routes mounted by `build_rocket` that endpoints can not be mounted at
+const RESERVED_ROUTES: [&str; 5] = ["/stripe_webhooks", "/metrics", "/healthz", "/readyz", "/admin"];
 
 impl Default for ConfigSetup {
     /// # default
@@ -35,9 +53,32 @@ impl Default for ConfigSetup {
     /// - `port`: 8080 - Default port number.
     /// - `supabase_url`: "https://xxx.supabase.co" - Default Supabase URL.
     /// - `supabase_key`: "xxx" - Default Supabase API key.
+    /// - `emails`: Only the welcome email is enabled by default.
+    /// - `operator_email`: None - Operator notifications are disabled by default.
+    /// - `refund_policy`: "revoke_on_full_refund" - Default refund policy.
+    /// - `dispute_policy`: "revoke_on_lost" - Default dispute policy.
+    /// - `log_level`: "info" - Default log level.
+    /// - `log_format`: "pretty" - Default log format.
+    /// - `sled_path`: "stripe_discord_db" - Default Sled database directory.
+    /// - `health_timeout_ms`: 2000 - Default timeout of each readiness check.
+    /// - `api_keys`: empty - No API keys are configured by default.
+    /// - `endpoints`: empty - Only `/stripe_webhooks` is mounted by default.
+    /// - `templates_dir`: "./email/templates" - Default directory of named email templates.
+    /// - `attach_receipts`: false - Welcome emails carry no receipt by default.
+    /// - `default_locale`: "en" - Emails are rendered in English without a customer locale.
+    /// - `invalid_email_policy`: "quarantine" - Invalid customer addresses are quarantined.
+    /// - `check_mx`: false - The mail servers of customer domains are not looked up.
+    /// - `email_max_attempts`: 4 - Every email is attempted up to four times.
+    /// - `email_backoff_ms`: 500 - The wait after the first failed attempt.
+    /// - `email_max_backoff_ms`: 5000 - The longest wait between attempts.
+    /// - `email_fallback_provider`: None - Failed emails are not sent through another provider.
+    /// - `email_fallback_after`: 2 - The failures before the fallback provider is used.
+    /// - `drips`: empty - No drip sequence follows a purchase by default.
+    /// - `renewal_reminder_window_days`: None - Customers are not scanned for renewals by default.
+    /// - `access_products`: empty - Purchases grant access for good by default.
     ///
     /// ## Examples
-    /// ```rust
+    /// ```rust,ignore
     /// let config = ConfigSetup::default();
     /// assert_eq!(config.db_provider, "supabase");
     /// assert_eq!(config.email_provider, "resend");
@@ -45,7 +86,7 @@ impl Default for ConfigSetup {
     /// assert_eq!(config.host, "0.0.0.0");
     /// assert_eq!(config.port, 8080);
     /// assert_eq!(config.supabase_url, "https://xxx.supabase.co");
-    /// assert_eq!(config.supabase_key, "xxx");
+    /// assert_eq!(config.supabase_key.expose(), "xxx");
     /// ```
     fn default() -> Self {
         ConfigSetup {
@@ -55,7 +96,30 @@ impl Default for ConfigSetup {
             host: "0.0.0.0".to_string(),
             port: 8080,
             supabase_url: "https://xxx.supabase.co".to_string(),
-            supabase_key: "xxx".to_string(),
+            supabase_key: Secret::new("xxx".to_string()),
+            emails: EmailEventConfig::from_config(&Value::Null),
+            operator_email: None,
+            refund_policy: "revoke_on_full_refund".to_string(),
+            dispute_policy: "revoke_on_lost".to_string(),
+            log_level: "info".to_string(),
+            log_format: "pretty".to_string(),
+            sled_path: "stripe_discord_db".to_string(),
+            health_timeout_ms: 2000,
+            api_keys: Vec::new(),
+            endpoints: Vec::new(),
+            templates_dir: DEFAULT_TEMPLATES_DIR.to_string(),
+            attach_receipts: false,
+            default_locale: DEFAULT_LOCALE.to_string(),
+            invalid_email_policy: "quarantine".to_string(),
+            check_mx: false,
+            email_max_attempts: 4,
+            email_backoff_ms: 500,
+            email_max_backoff_ms: 5000,
+            email_fallback_provider: None,
+            email_fallback_after: 2,
+            drips: Vec::new(),
+            renewal_reminder_window_days: None,
+            access_products: Vec::new(),
         }
     }
 }
@@ -69,7 +133,7 @@ impl ConfigSetup {
     /// It will rely on the file path of the `stripe_discord.yaml` file, to offer more flexibility.
     /// You can supply the path to the file as a string yourself.
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// // Declaring a file called stripe_discord.yaml as config file in the root directory
     ///
     /// let config = Config::new("stripe_discord.yaml").unwrap();
@@ -82,7 +146,7 @@ impl ConfigSetup {
     /// A Result containing the Config object or a Boxed Error
     ///
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// let config = Config::new("stripe_discord.yaml").unwrap();
     /// ```
     ///
@@ -96,7 +160,30 @@ impl ConfigSetup {
             host: String::new(),
             port: 0,
             supabase_url: String::new(),
-            supabase_key: String::new(),
+            supabase_key: Secret::new(String::new()),
+            emails: EmailEventConfig::from_config(&Value::Null),
+            operator_email: None,
+            refund_policy: String::new(),
+            dispute_policy: String::new(),
+            log_level: String::new(),
+            log_format: String::new(),
+            sled_path: String::new(),
+            health_timeout_ms: 0,
+            api_keys: Vec::new(),
+            endpoints: Vec::new(),
+            templates_dir: DEFAULT_TEMPLATES_DIR.to_string(),
+            attach_receipts: false,
+            default_locale: DEFAULT_LOCALE.to_string(),
+            invalid_email_policy: String::new(),
+            check_mx: false,
+            email_max_attempts: 0,
+            email_backoff_ms: 0,
+            email_max_backoff_ms: 0,
+            email_fallback_provider: None,
+            email_fallback_after: 0,
+            drips: Vec::new(),
+            renewal_reminder_window_days: None,
+            access_products: Vec::new(),
         };
 
         config.load();
@@ -104,15 +191,40 @@ impl ConfigSetup {
         config
     }
 
+    /// ## Try to create a new Config object
+    ///
+    /// Same as [`ConfigSetup::new`] but returns the error instead of panicking when the
+    /// `stripe_discord.yaml` file is missing or not valid YAML, used by the `/readyz` check.
+    ///
+    /// ### Errors
+    /// - `ConfigError::FileNotFound` - The `stripe_discord.yaml` file could not be opened
+    /// - `ConfigError::InvalidFileType` - The file is not valid YAML
+    /// - `ConfigError::InvalidEndpoint` - An entry under `Endpoints` is invalid
+    /// - `ConfigError::InvalidValue` - `Entitlement.Refund` or `Entitlement.Dispute` is not a known
+    ///   policy
+    pub fn try_new() -> Result<Self, ConfigError> {
+        let mut config: ConfigSetup = ConfigSetup::default();
+
+        config.try_load()?;
+
+        Ok(config)
+    }
+
     fn load(&mut self) {
+        if let Err(error) = self.try_load() {
+            panic!("Failed to load stripe_discord.yaml: {}", error);
+        }
+    }
+
+    fn try_load(&mut self) -> Result<(), ConfigError> {
         // open and read our .yaml file
-        let file: File =
-            File::open("stripe_discord.yaml").expect("Failed to open the stripe_discord.yaml file");
+        let file: File = File::open(CONFIG_PATH)
+            .map_err(|_| ConfigError::FileNotFound(CONFIG_PATH.to_string()))?;
         let reader: BufReader<File> = BufReader::new(file);
 
         // read and iterate over the value keys
-        let value: Value =
-            serde_yaml::from_reader(reader).expect("Failed to read stripe_discord.yaml");
+        let value: Value = serde_yaml::from_reader(reader)
+            .map_err(|_| ConfigError::InvalidFileType(CONFIG_PATH.to_string()))?;
 
         // load our pre-configed yaml objects from the reader
         self.db_provider = value["Db"]["Provider"]
@@ -136,11 +248,352 @@ impl ConfigSetup {
             .as_str()
             .unwrap_or("https://xxx.supabase.co")
             .to_string();
+        self.emails = EmailEventConfig::from_config(&value);
+        self.operator_email = value["Email"]["Operator"]
+            .as_str()
+            .map(|operator_email| operator_email.to_string());
+        self.refund_policy = policy::<RefundPolicy>(&value, "Refund", "revoke_on_full_refund")?;
+        self.dispute_policy = policy::<DisputePolicy>(&value, "Dispute", "revoke_on_lost")?;
+        self.log_level = value["Log"]["Level"]
+            .as_str()
+            .unwrap_or("info")
+            .to_string();
+        self.log_format = value["Log"]["Format"]
+            .as_str()
+            .unwrap_or("pretty")
+            .to_string();
+        self.sled_path = value["Db"]["SledPath"]
+            .as_str()
+            .unwrap_or("stripe_discord_db")
+            .to_string();
+        self.health_timeout_ms = value["Health"]["TimeoutMs"].as_u64().unwrap_or(2000);
+        self.api_keys = ConfiguredApiKey::from_config(&value);
+        self.endpoints = EndpointConfigStripe::from_config(&value)?;
 
-        // load env vars
-        self.supabase_key = std::env::var("SUPABASE_KEY")
-            .unwrap_or("xxx".to_string())
+        self.templates_dir = value["Email"]["TemplatesDir"]
+            .as_str()
+            .unwrap_or(DEFAULT_TEMPLATES_DIR)
             .to_string();
+
+        self.attach_receipts = value["Email"]["AttachReceipts"].as_bool().unwrap_or(false);
+        self.default_locale = value["Email"]["DefaultLocale"]
+            .as_str()
+            .and_then(Locale::parse)
+            .map(|locale| locale.tag)
+            .unwrap_or(DEFAULT_LOCALE.to_string());
+        self.invalid_email_policy = value["Email"]["Validation"]["InvalidAddress"]
+            .as_str()
+            .unwrap_or("quarantine")
+            .to_string();
+        self.check_mx = value["Email"]["Validation"]["CheckMx"].as_bool().unwrap_or(false);
+        self.email_max_attempts = value["Email"]["Retry"]["MaxAttempts"].as_u64().unwrap_or(4) as u32;
+        self.email_backoff_ms = value["Email"]["Retry"]["BackoffMs"].as_u64().unwrap_or(500);
+        self.email_max_backoff_ms = value["Email"]["Retry"]["MaxBackoffMs"].as_u64().unwrap_or(5000);
+        self.email_fallback_provider = value["Email"]["Retry"]["Fallback"]
+            .as_str()
+            .map(|fallback| fallback.to_string());
+        self.email_fallback_after = value["Email"]["Retry"]["FallbackAfter"].as_u64().unwrap_or(2) as u32;
+        self.drips = DripStepConfig::from_config(&value);
+        self.renewal_reminder_window_days = value["Email"]["RenewalReminder"]["WindowDays"].as_u64();
+        self.access_products = AccessProduct::from_config(&value);
+
+        // load env vars
+        self.supabase_key = secret("SUPABASE_KEY").unwrap_or(Secret::new("xxx".to_string()));
+        if let Ok(templates_dir) = std::env::var("EMAIL_TEMPLATES_DIR") {
+            self.templates_dir = templates_dir;
+        }
+
+        Ok(())
+    }
+}
+
+
+/// # policy
+/// Reads the name of a policy under `Entitlement`, `default` when it is not set.
+///
+/// ## Errors
+/// - `ConfigError::InvalidValue` - The name is not one of the policy, e.g. `revoke_on_full`
+pub(crate) fn policy<P: FromStr<Err = String>>(value: &Value, key: &str, default: &str) -> Result<String, ConfigError> {
+    let name: &str = value["Entitlement"][key].as_str().unwrap_or(default);
+
+    match P::from_str(name) {
+        Ok(_) => Ok(name.to_string()),
+        Err(reason) => Err(ConfigError::InvalidValue(format!("Entitlement.{}", key), reason)),
+    }
+}
+
+
+impl EmailEventConfig {
+    /// # from_config
+    /// Reads the email of every [`EmailEvent`] under `Email`, e.g. `Email.Receipt`.
+    ///
+    /// An email is enabled by its `Enabled` key. Without one the welcome email is enabled and the
+    /// others are enabled when they have a `TemplateUrl`.
+    pub fn from_config(value: &Value) -> Vec<EmailEventConfig> {
+        let text = |value: &Value| -> Option<String> { value.as_str().map(|text| text.to_string()) };
+
+        EmailEvent::ALL
+            .into_iter()
+            .map(|event| {
+                let entry: &Value = &value["Email"][event.config_key()];
+                let template_url: Option<String> = text(&entry["TemplateUrl"]);
+
+                let enabled: bool = entry["Enabled"]
+                    .as_bool()
+                    .unwrap_or(event == EmailEvent::Welcome || template_url.is_some());
+
+                EmailEventConfig {
+                    event,
+                    enabled,
+                    sender_email: text(&entry["Sender"]),
+                    subject: text(&entry["Subject"]),
+                    template_url,
+                    text_template_url: text(&entry["TextTemplateUrl"]),
+                    preheader: text(&entry["Preheader"]),
+                    translations: translations(&entry["Locales"]),
+                }
+            })
+            .collect()
+    }
+
+    /// # to_email_config
+    /// The `EmailConfig` of the email, unset fields are taken from `default`.
+    pub fn to_email_config(&self, default: &EmailConfig) -> EmailConfig {
+        let mut email_config: EmailConfig = EmailConfig::new(
+            self.sender_email.clone().unwrap_or(default.sender_email.clone()),
+            self.subject.clone().unwrap_or(default.subject.clone()),
+            self.template_url.clone().unwrap_or(default.template_url.clone()),
+        );
+
+        email_config.text_template_url = self.text_template_url.clone().or(default.text_template_url.clone());
+        email_config.preheader = self.preheader.clone().or(default.preheader.clone());
+        email_config.translations = default.translations.clone();
+        email_config.translations.extend(self.translations.clone());
+
+        email_config
+    }
+}
+
+
+impl DripStepConfig {
+    /// # from_config
+    /// Reads the steps of the drip sequence under `Email.Drip`, in order. Entries without a
+    /// `Subject` or `TemplateUrl` are skipped.
+    pub fn from_config(value: &Value) -> Vec<DripStepConfig> {
+        let text = |value: &Value| -> Option<String> { value.as_str().map(|text| text.to_string()) };
+
+        value["Email"]["Drip"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let subject: String = text(&entry["Subject"])?;
+                let template_url: String = text(&entry["TemplateUrl"])?;
+
+                let delay_secs: u64 = entry["DelayDays"].as_u64().unwrap_or(0) * 86400
+                    + entry["DelayHours"].as_u64().unwrap_or(0) * 3600;
+
+                Some(DripStepConfig {
+                    name: text(&entry["Name"]).unwrap_or(template_url.clone()),
+                    delay_secs,
+                    sender_email: text(&entry["Sender"]),
+                    subject,
+                    template_url,
+                    text_template_url: text(&entry["TextTemplateUrl"]),
+                    preheader: text(&entry["Preheader"]),
+                    translations: translations(&entry["Locales"]),
+                })
+            })
+            .collect()
+    }
+
+    /// # to_drip_step
+    /// The `DripStep` of the entry, sent from `sender_email` when it has no `Sender`.
+    pub fn to_drip_step(&self, sender_email: &str) -> DripStep {
+        let mut email_config: EmailConfig = EmailConfig::new(
+            self.sender_email.clone().unwrap_or(sender_email.to_string()),
+            self.subject.clone(),
+            self.template_url.clone(),
+        );
+
+        email_config.text_template_url = self.text_template_url.clone();
+        email_config.preheader = self.preheader.clone();
+        email_config.translations = self.translations.clone();
+
+        DripStep::new(&self.name, Duration::from_secs(self.delay_secs), email_config)
+    }
+}
+
+
+impl AccessProduct {
+    /// # from_config
+    /// Reads the products under `Entitlement.Products` that grant access for `AccessDays`, by their
+    /// `Price` or `Product` id. Entries without an id or `AccessDays` are skipped.
+    pub fn from_config(value: &Value) -> Vec<AccessProduct> {
+        value["Entitlement"]["Products"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let id: &str = entry["Price"].as_str().or(entry["Product"].as_str())?;
+                let access_days: u64 = entry["AccessDays"].as_u64().filter(|access_days| *access_days > 0)?;
+
+                Some(AccessProduct::new(id, Duration::from_secs(access_days * 86400)))
+            })
+            .collect()
     }
 }
 
+
+/// # translations
+/// Reads the translations of an email under its `Locales`, by locale. Keys that are not a
+/// locale are skipped.
+fn translations(locales: &Value) -> HashMap<String, EmailTranslation> {
+    let text = |value: &Value| -> Option<String> { value.as_str().map(|text| text.to_string()) };
+
+    locales
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(locale, entry)| {
+            let translation: EmailTranslation = EmailTranslation {
+                subject: text(&entry["Subject"]),
+                template_url: text(&entry["TemplateUrl"]),
+                text_template_url: text(&entry["TextTemplateUrl"]),
+                preheader: text(&entry["Preheader"]),
+            };
+
+            Locale::parse(locale).map(|locale| (locale.tag, translation))
+        })
+        .collect()
+}
+
+
+impl EndpointConfigStripe {
+    /// # from_config
+    /// Reads and validates every endpoint listed under `Endpoints`.
+    ///
+    /// ## Arguments
+    /// - `value`: `&Value` - The parsed `stripe_discord.yaml`
+    ///
+    /// ## Errors
+    /// - `ConfigError::InvalidEndpoint` - The first entry that is missing a field, fails
+    ///   [`EndpointConfigStripe::validate`] or reuses the route of an earlier entry
+    pub fn from_config(value: &Value) -> Result<Vec<EndpointConfigStripe>, ConfigError> {
+        let entries: &[Value] = value["Endpoints"]
+            .as_array()
+            .map(|entries| entries.as_slice())
+            .unwrap_or_default();
+
+        let mut endpoints: Vec<EndpointConfigStripe> = Vec::new();
+
+        for entry in entries {
+            let endpoint: EndpointConfigStripe = EndpointConfigStripe::from_entry(entry)?;
+
+            if endpoints.iter().any(|other| other.endpoint_route == endpoint.endpoint_route) {
+                return Err(ConfigError::InvalidEndpoint(
+                    endpoint.endpoint_route,
+                    "the route is used by another endpoint".to_string(),
+                ));
+            }
+
+            endpoints.push(endpoint);
+        }
+
+        Ok(endpoints)
+    }
+
+    /// # from_entry
+    /// Reads one entry under `Endpoints`.
+    fn from_entry(entry: &Value) -> Result<EndpointConfigStripe, ConfigError> {
+        let text = |value: &Value| -> String { value.as_str().unwrap_or_default().to_string() };
+
+        let endpoint_route: String = text(&entry["Route"]);
+        let invalid = |reason: String| ConfigError::InvalidEndpoint(endpoint_route.clone(), reason);
+
+        // unset ids are `0`, the endpoint then syncs no role
+        let snowflake = |value: &Value, field: &str| -> Result<i64, ConfigError> {
+            if value.is_null() {
+                return Ok(0);
+            }
+
+            parse_snowflake(value).map_err(|error| invalid(format!("Discord.{}: {}", field, error)))
+        };
+
+        let endpoint: EndpointConfigStripe = EndpointConfigStripe {
+            name: entry["Name"].as_str().unwrap_or(&endpoint_route).to_string(),
+            sender_email: text(&entry["SenderEmail"]),
+            stripe_publish_key: text(&entry["Stripe"]["PublishKey"]),
+            stripe_webhook_secret: text(&entry["Stripe"]["WebhookSecret"]),
+            stripe_private_key: text(&entry["Stripe"]["PrivateKey"]),
+            email_template_path: text(&entry["EmailTemplatePath"]),
+            discord_client_id: text(&entry["Discord"]["ClientId"]),
+            discord_application_id: text(&entry["Discord"]["ApplicationId"]),
+            discord_role_id: snowflake(&entry["Discord"]["RoleId"], "RoleId")?,
+            discord_guild_id: snowflake(&entry["Discord"]["GuildId"], "GuildId")?,
+            discord_bot_token: text(&entry["Discord"]["BotToken"]),
+            replace_keys_with_env_names: entry["ReplaceKeysWithEnvNames"].as_bool().unwrap_or(false),
+            attach_receipts: entry["AttachReceipts"].as_bool(),
+            endpoint_route: endpoint_route.clone(),
+        };
+
+        endpoint.validate().map_err(invalid)?;
+
+        Ok(endpoint)
+    }
+
+    /// # validate
+    /// Checks the route, the sender, the Discord snowflakes and the prefix of the publishable key.
+    /// The secret fields are checked by [`EndpointConfigStripe::validate_secrets`] once they can be
+    /// resolved.
+    ///
+    /// ## Errors
+    /// - `String` - What is wrong with the endpoint
+    pub fn validate(&self) -> Result<(), String> {
+        let route: &str = &self.endpoint_route;
+
+        if !route.starts_with('/') || route.len() < 2 || route.ends_with('/') {
+            return Err("Route must start with `/` and must not end with it".to_string());
+        }
+
+        if route.contains(|character: char| character.is_whitespace() || "<>?#".contains(character)) {
+            return Err("Route must be a static path".to_string());
+        }
+
+        // nested routes such as `/stripe_webhooks/acme` are fine, except below the admin API
+        if RESERVED_ROUTES.contains(&route) || route.starts_with("/admin/") {
+            return Err("Route is already used by a built-in route".to_string());
+        }
+
+        if self.stripe_webhook_secret.is_empty() {
+            return Err("Stripe.WebhookSecret is required".to_string());
+        }
+
+        if !self.stripe_publish_key.is_empty() && !self.stripe_publish_key.starts_with("pk_") {
+            return Err("Stripe.PublishKey must start with pk_".to_string());
+        }
+
+        if !self.sender_email.contains('@') {
+            return Err("SenderEmail must be an email address".to_string());
+        }
+
+        if self.email_template_path.is_empty() {
+            return Err("EmailTemplatePath is required".to_string());
+        }
+
+        for (field, id) in [("GuildId", self.discord_guild_id), ("RoleId", self.discord_role_id)] {
+            if id != 0 {
+                parse_snowflake(&Value::from(id)).map_err(|error| format!("Discord.{}: {}", field, error))?;
+            }
+        }
+
+        // a role needs the guild it lives in and a bot to grant it
+        let discord: [bool; 3] = [self.discord_guild_id != 0, self.discord_role_id != 0, !self.discord_bot_token.is_empty()];
+
+        if discord.contains(&true) && discord.contains(&false) {
+            return Err("Discord.GuildId, Discord.RoleId and Discord.BotToken are required together".to_string());
+        }
+
+        Ok(())
+    }
+}
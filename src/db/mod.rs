@@ -5,10 +5,14 @@
 //! So as we heavily rely on Supabase for databasing we will need to have the following tables and columns:
 //! #### Tables  
 //! - `stripe_customer_data` - The table to store the customer database
+//! - `stripe_customer_audit` - The refund and dispute audit trail, see [audit](operations/audit/index.html)
+//! - `stripe_webhook_events` - Every received webhook, see [webhook_event](operations/webhook_event/index.html)
+//! - `stripe_api_keys` - The hashes of the issued API keys, see [api_key](operations/api_key/index.html)
 //!
 //! #### `stripe_customer_data` columns
 //! - `customer_id` TYPE TEXT - The customer ID from Stripe
 //! - `email` TYPE TEXT - The email address of the customer
+//! - `discord_user_id` TYPE TEXT - The linked Discord user, synced by the admin API
 //!
 //!
 //! ### Db providers
@@ -20,7 +24,8 @@
 //! - [operations](operations/index.html)
 //!
 //!
-use std::env::var;
+use crate::secrets::supabase_client;
+
 use supabase_rs::SupabaseClient;
 
 pub mod format;
@@ -41,15 +46,12 @@ pub mod supabase;
 /// If the environment variables are not set, the function will panic with an error message.
 ///
 /// ## Example: Initializing a Supabase client
-/// ```
+/// ```rust,ignore
 /// let client = init_supabase_client();
 /// // Now `client` can be used to interact with Supabase services.
 /// ```
 pub fn init_supabase_client() -> SupabaseClient {
-    let supabase_url: String = var("SUPABASE_URL").expect("SUPABASE_URL must be set");
-    let supabase_key: String = var("SUPABASE_KEY").expect("SUPABASE_KEY must be set");
-
-    SupabaseClient::new(supabase_url, supabase_key)
+    supabase_client().expect("SUPABASE_URL and SUPABASE_KEY must be set")
 }
 
 
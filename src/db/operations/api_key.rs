@@ -0,0 +1,215 @@
+//! # API key database operations
+//!
+//! This module contains the database operations for the `stripe_api_keys` table, keys issued
+//! through the CLI are stored here as hashes, see [auth](../../../auth/index.html).
+//!
+//! ## `stripe_api_keys` columns
+//! - `id` TYPE INT8 - The row id
+//! - `key_id` TYPE TEXT - The public part of the key, `sdk_<key_id>_...`
+//! - `name` TYPE TEXT - Who or what the key is for
+//! - `key_hash` TYPE TEXT - The hex encoded SHA-256 of the whole key
+//! - `scopes` TYPE JSONB - The scope names, e.g. `["customers:read"]`
+//! - `created_at` TYPE INT8 - The unix timestamp the key was issued at
+//! - `revoked_at` TYPE INT8 - The unix timestamp the key was revoked at, `NULL` while it is valid
+
+use crate::auth::Scope;
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_api_keys_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use supabase_rs::SupabaseClient;
+
+
+/// ## ApiKey
+/// An issued API key, without the key itself
+///
+/// ### Fields
+/// - `id` - The row id, `None` until inserted
+/// - `key_id` - The public part of the key
+/// - `name` - Who or what the key is for
+/// - `key_hash` - The hex encoded SHA-256 of the whole key
+/// - `scopes` - The scope names
+/// - `created_at` - The unix timestamp the key was issued at
+/// - `revoked_at` - The unix timestamp the key was revoked at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub key_id: String,
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+
+impl ApiKey {
+    /// # new
+    /// Creates a key that has not been stored yet.
+    ///
+    /// ## Arguments
+    /// - `key_id`: `String` - The public part of the key
+    /// - `name`: `String` - Who or what the key is for
+    /// - `key_hash`: `String` - The hex encoded SHA-256 of the whole key
+    /// - `scopes`: `&[Scope]` - What the key may do
+    /// - `created_at`: `i64` - The unix timestamp the key is issued at
+    pub fn new(
+        key_id: String,
+        name: String,
+        key_hash: String,
+        scopes: &[Scope],
+        created_at: i64,
+    ) -> Self {
+        ApiKey {
+            id: None,
+            key_id,
+            name,
+            key_hash,
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            created_at,
+            revoked_at: None,
+        }
+    }
+
+
+    /// # scopes
+    /// The scopes of the key, unknown scope names are dropped.
+    pub fn scopes(&self) -> Vec<Scope> {
+        self.scopes
+            .iter()
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+
+
+    /// # insert
+    /// Stores the key.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+    ///
+    /// ## Returns
+    /// - `Result<String, Box<dyn Error>>`: The row `id` of the key or the database error
+    pub async fn insert(
+        &self,
+        supabase: SupabaseClient,
+    ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("api_key_insert");
+
+        let table_name: String = overwrite_stripe_api_keys_table_name();
+
+        let row_id: String = supabase
+            .insert(&table_name, serde_json::to_value(self)?)
+            .await?;
+
+        Ok(row_id)
+    }
+
+
+    /// # get_by_key_id
+    /// Retrieves a key by its key id, revoked keys included.
+    ///
+    /// ## Arguments
+    /// - `key_id`: `&str` - The public part of the key
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+    ///
+    /// ## Returns
+    /// - `Result<Option<ApiKey>, Box<dyn Error>>`: The key, `None` when no key has the id
+    pub async fn get_by_key_id(
+        key_id: &str,
+        supabase: SupabaseClient,
+    ) -> Result<Option<ApiKey>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("api_key_get_by_key_id");
+
+        let table_name: String = overwrite_stripe_api_keys_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("key_id", key_id)
+            .execute()
+            .await?;
+
+        let api_key: Option<ApiKey> = rows
+            .into_iter()
+            .find_map(|row| serde_json::from_value(row).ok());
+
+        Ok(api_key)
+    }
+
+
+    /// # list
+    /// Retrieves every key, oldest first, revoked keys included.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+    pub async fn list(
+        supabase: SupabaseClient,
+    ) -> Result<Vec<ApiKey>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("api_key_list");
+
+        let table_name: String = overwrite_stripe_api_keys_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .execute()
+            .await?;
+
+        let mut api_keys: Vec<ApiKey> = rows
+            .into_iter()
+            .filter_map(|row| serde_json::from_value(row).ok())
+            .collect();
+
+        api_keys.sort_by_key(|api_key| api_key.created_at);
+
+        Ok(api_keys)
+    }
+
+
+    /// # revoke
+    /// Revokes a key, requests with it are rejected from then on.
+    ///
+    /// ## Arguments
+    /// - `key_id`: `&str` - The public part of the key
+    /// - `revoked_at`: `i64` - The unix timestamp the key is revoked at
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+    ///
+    /// ## Returns
+    /// - `Result<Option<ApiKey>, Box<dyn Error>>`: The revoked key, `None` when no key has the id
+    pub async fn revoke(
+        key_id: &str,
+        revoked_at: i64,
+        supabase: SupabaseClient,
+    ) -> Result<Option<ApiKey>, Box<dyn Error>> {
+        let stored: Option<ApiKey> = ApiKey::get_by_key_id(key_id, supabase.clone()).await?;
+
+        let Some(mut api_key) = stored else {
+            return Ok(None);
+        };
+
+        let Some(id) = api_key.id else {
+            return Err("the API key has no row id".into());
+        };
+
+        // revoking twice keeps the first revocation time
+        if api_key.revoked_at.is_some() {
+            return Ok(Some(api_key));
+        }
+
+        let _timer: HistogramTimer = observe_db_operation("api_key_revoke");
+
+        let table_name: String = overwrite_stripe_api_keys_table_name();
+
+        supabase
+            .update(&table_name, &id.to_string(), json!({ "revoked_at": revoked_at }))
+            .await?;
+
+        api_key.revoked_at = Some(revoked_at);
+
+        Ok(Some(api_key))
+    }
+}
+
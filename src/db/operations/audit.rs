@@ -0,0 +1,138 @@
+//! # Audit trail database operations
+//!
+//! This module contains the database operations for the `stripe_customer_audit` table, every
+//! refund and dispute that touches a customer is appended here so the history survives the
+//! customer record being overwritten by later events.
+//!
+//! ## `stripe_customer_audit` columns
+//! - `customer_id` TYPE TEXT - The customer ID the entry belongs to
+//! - `email` TYPE TEXT - The email of the customer when known
+//! - `event_id` TYPE TEXT - The id of the Stripe event that caused the entry
+//! - `event_type` TYPE TEXT - The type of the Stripe event, e.g. `charge.refunded`
+//! - `amount` TYPE INT8 - The refunded or disputed amount in minor units of its currency
+//! - `currency` TYPE TEXT - The lowercase ISO 4217 currency of `amount`, e.g. `usd`
+//! - `status` TYPE TEXT - `partial`/`full` for refunds, the dispute status for disputes
+//! - `reason` TYPE TEXT - The refund or dispute reason
+//! - `created_at` TYPE INT8 - The unix timestamp of the Stripe event
+
+use crate::overwrite::overwrite_stripe_customer_audit_table_name;
+use crate::CustomerId;
+use crate::metrics::observe_db_operation;
+use crate::utils::money::Money;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use supabase_rs::SupabaseClient;
+
+
+/// ## AuditEntry
+/// A single refund or dispute event recorded against a customer
+///
+/// ### Fields
+/// - `customer_id` - The customer ID the entry belongs to
+/// - `email` - The email of the customer when known
+/// - `event_id` - The id of the Stripe event
+/// - `event_type` - The type of the Stripe event
+/// - `amount` - The refunded or disputed amount in minor units
+/// - `currency` - The currency of `amount`
+/// - `status` - `partial`/`full` for refunds, the dispute status for disputes
+/// - `reason` - The refund or dispute reason
+/// - `created_at` - The unix timestamp of the Stripe event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub customer_id: String,
+    pub email: String,
+    pub event_id: String,
+    pub event_type: String,
+    pub amount: i64,
+    #[serde(default)]
+    pub currency: String,
+    pub status: String,
+    pub reason: String,
+    pub created_at: i64,
+}
+
+
+impl AuditEntry {
+    /// # money
+    /// The refunded or disputed amount in its currency.
+    pub fn money(&self) -> Money {
+        Money::new(self.amount, &self.currency)
+    }
+
+
+    /// # email_or_unknown
+    /// The email of the customer, `unknown` when Stripe sent none.
+    pub fn email_or_unknown(&self) -> &str {
+        match self.email.is_empty() {
+            true => "unknown",
+            false => &self.email,
+        }
+    }
+
+
+    /// # insert
+    /// Appends the entry to the audit trail.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<String, Box<dyn Error>>`: The row `id` of the new entry or the database error.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let entry: AuditEntry = AuditEntry { /* ... */ };
+    /// entry.insert(supabase).await?;
+    /// ```
+    pub async fn insert(
+        &self,
+        supabase: SupabaseClient,
+    ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("audit_insert");
+
+        let table_name: String = overwrite_stripe_customer_audit_table_name();
+
+        let row_id: String = supabase
+            .insert(&table_name, serde_json::to_value(self)?)
+            .await?;
+
+        Ok(row_id)
+    }
+
+
+    /// # list_by_customer_id
+    /// Retrieves every audit entry of a given `CustomerId`.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `CustomerId` - The customer to list the audit trail of.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Vec<AuditEntry>, Box<dyn Error>>`: The entries ordered by `created_at` or the database error.
+    pub async fn list_by_customer_id(
+        customer_id: CustomerId,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<AuditEntry>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("audit_list_by_customer_id");
+
+        let table_name: String = overwrite_stripe_customer_audit_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("customer_id", customer_id.as_str())
+            .execute()
+            .await?;
+
+        let mut entries: Vec<AuditEntry> = rows
+            .into_iter()
+            .filter_map(|row| serde_json::from_value(row).ok())
+            .collect();
+
+        entries.sort_by_key(|entry| entry.created_at);
+
+        Ok(entries)
+    }
+}
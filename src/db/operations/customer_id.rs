@@ -14,6 +14,7 @@ use crate::CustomerId;
 // temp dev import
 use crate::overwrite::{
     overwrite_stripe_customer_email_sent_column_name, 
+    overwrite_stripe_customer_email_sent_reason_column_name,
     overwrite_stripe_customer_id_column_name,
     overwrite_stripe_customer_paid_column_name, 
     overwrite_stripe_customer_table_name,
@@ -23,14 +24,32 @@ use crate::overwrite::{
     overwrite_stripe_customer_receipt_url_column_name,
     overwrite_stripe_customer_country_column_name,
     overwrite_stripe_customer_amount_total_column_name,
+    overwrite_stripe_customer_amount_total_minor_column_name,
+    overwrite_stripe_customer_currency_column_name,
     overwrite_stripe_customer_payment_link_column_name,
-    overwrite_stripe_plink_cache_table_name
+    overwrite_stripe_plink_cache_table_name,
+    overwrite_stripe_customer_decline_code_column_name,
+    overwrite_stripe_customer_decline_message_column_name,
+    overwrite_stripe_customer_amount_refunded_column_name,
+    overwrite_stripe_customer_refund_status_column_name,
+    overwrite_stripe_customer_dispute_status_column_name,
+    overwrite_stripe_customer_dispute_reason_column_name,
+    overwrite_stripe_customer_discord_user_id_column_name,
+    overwrite_stripe_customer_access_status_column_name,
+    overwrite_stripe_customer_access_start_time_column_name,
+    overwrite_stripe_customer_access_end_time_column_name,
+    overwrite_stripe_customer_access_organization_column_name
 };
 
+use crate::discord::access::{ACCESS_ACTIVE, ACCESS_EXPIRED};
+use crate::metrics::observe_db_operation;
+use crate::utils::money::Money;
+
+use prometheus::HistogramTimer;
 use serde_json::json;
 use serde_json::Value;
-use std::env::var;
 use std::error::Error;
+use supabase_rs::query::QueryBuilder;
 use supabase_rs::SupabaseClient;
 
 impl CustomerId {
@@ -46,7 +65,7 @@ impl CustomerId {
     /// The `CustomerId` that was inserted into Supabase
     /// 
     /// ### Example: Creating an instance of `CustomerId` and inserting it into Supabase
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new();
     /// let customer_id = CustomerId::new(customer_id, true, supabase).await.unwrap();
     /// 
@@ -57,6 +76,8 @@ impl CustomerId {
         create_record: bool,
         supabase: SupabaseClient,
     ) -> Result<CustomerId, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("new_customer");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
 
@@ -99,7 +120,7 @@ impl CustomerId {
     ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Creating a new `CustomerId` object using only the email
-    /// ```rust
+    /// ```rust,ignore
     /// let email = "floris@xylex.ai";
     /// let supabase = SupabaseClient::new("SUPABASE_URL", "SUPABASE_KEY");
     /// let customer_id = CustomerId::new(email.to_string(), supabase).await.unwrap();
@@ -109,13 +130,15 @@ impl CustomerId {
         create_record: bool,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("new_from_email");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
 
         if create_record {
             let existing_record = supabase
                 .select(&table_name)
-                .eq(&column_name_email, &email.as_str())
+                .eq(&column_name_email, email.as_str())
                 .execute()
                 .await
                 .unwrap();
@@ -149,7 +172,7 @@ impl CustomerId {
     /// The `CustomerId` with the attached `EmailAddress` in Supabase
     ///
     /// ### Example: Attaching an `EmailAddress` to a `CustomerId` in Supabase
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new();
     /// let email = "floris@xylex.ai";
     /// let supabase = SupabaseClient::new("SUPABASE_URL", "SUPABASE_KEY");
@@ -165,16 +188,13 @@ impl CustomerId {
         email: String,
         supabase: SupabaseClient,
     ) -> Result<CustomerId, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("attach_email");
+
         // temp check if the tabVle name and column names are being overwritten
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
 
-        // init the supabase client using the standard env vars
-        let supabase: SupabaseClient =
-            SupabaseClient::new(var("SUPABASE_URL").unwrap(), var("SUPABASE_KEY").unwrap());
-
-
         let result_row_id: Vec<Value> = supabase
             .select(&table_name)
             .eq(&column_name_customer_id, customer_id.as_str())
@@ -232,7 +252,7 @@ impl CustomerId {
     ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     ///
     /// ## Example: Retrieving the email address associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let email = get_email(customer_id, supabase_client).await?;
@@ -243,6 +263,8 @@ impl CustomerId {
         customer_id: CustomerId,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_email");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -254,8 +276,6 @@ impl CustomerId {
             .await
             .unwrap();
 
-        println!("{:#?}", result_get_email);
-
         let customer_data_from_result: &Value = result_get_email
             .first()
             .expect("Failed extracting email from Supabase response for `get_email`");
@@ -283,7 +303,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ### Example: Updating the paid status associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let result = update_paid(customer_id, true, supabase_client).await?;
@@ -295,6 +315,8 @@ impl CustomerId {
         paid: bool,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_paid");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -342,7 +364,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Retrieving the paid status associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let paid = get_paid(customer_id, supabase_client).await?;
@@ -353,6 +375,8 @@ impl CustomerId {
         customer_id: CustomerId,
         supabase: SupabaseClient,
     ) -> Result<bool, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_paid");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -389,7 +413,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ### Example: Updating the email sent status associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let result = update_email_sent(customer_id, true, supabase_client).await?;
@@ -401,6 +425,8 @@ impl CustomerId {
         email_sent: bool,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_email_sent");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_email_sent: String = overwrite_stripe_customer_email_sent_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -451,7 +477,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Retrieving the email sent status associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let email_sent = get_email_sent(customer_id, supabase_client).await?;
@@ -463,6 +489,8 @@ impl CustomerId {
         customer_id: CustomerId,
         supabase: SupabaseClient,
     ) -> Result<bool, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_email_sent");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_email_sent: String = overwrite_stripe_customer_email_sent_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -499,7 +527,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ### Example: Updating the email sent status associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let result = update_end_time(customer_id, 11111111111111, supabase_client).await?;
@@ -511,6 +539,8 @@ impl CustomerId {
         end_time: i64,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_end_time");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_end_time: String = overwrite_stripe_customer_end_time_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -558,7 +588,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Retrieving the end time associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let end_time = get_end_time(customer_id, supabase_client).await?;
@@ -569,6 +599,8 @@ impl CustomerId {
         customer_id: CustomerId,
         supabase: SupabaseClient,
     ) -> Result<i64, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_end_time");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_email_sent: String = overwrite_stripe_customer_email_sent_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -606,7 +638,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ### Example: Updating the name associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let result = update_name(customer_id, "New Name", supabase_client).await?;
@@ -617,6 +649,8 @@ impl CustomerId {
         name: String,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_name");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_name: String = overwrite_stripe_customer_name_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -664,7 +698,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Retrieving the name associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let name = get_name(customer_id, supabase_client).await?;
@@ -674,6 +708,8 @@ impl CustomerId {
         customer_id: CustomerId,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_name");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_name: String = overwrite_stripe_customer_name_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -712,38 +748,20 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Updating the receipt URL associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let receipt_url = "https://example.com/receipt";
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let result = update_receipt_url(customer_id, receipt_url.to_string(), supabase_client).await?;
     /// assert_eq!(result, "success");
     /// ```
-    
-    /// # get_receipt_url
-    /// Retrieves the `receipt_url` associated with a given `CustomerId` from the Supabase database.
-    ///
-    /// ## Arguments
-    /// - `customer_id`: `CustomerId` - The unique identifier for the customer whose receipt_url is being retrieved.
-    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
-    /// 
-    /// ## Returns
-    /// - `Result<String, Box<dyn Error>>`: This function returns a `Result` which is either:
-    /// - `Ok(String)`: The receipt URL of the customer if found.
-    /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
-    /// 
-    /// ## Example: Retrieving the receipt URL associated with a `CustomerId`
-    /// ```rust
-    /// let customer_id = CustomerId::new("some_unique_id");
-    /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
-    /// let receipt_url = get_receipt_url(customer_id, supabase_client).await?;
-    /// assert_eq!(receipt_url, "https://example.com/receipt");
-    /// ```
     pub async fn update_receipt_url(
         customer_id: CustomerId,
         receipt_url: String,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_receipt_url");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_receipt_url: String = overwrite_stripe_customer_receipt_url_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -792,7 +810,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Retrieving the receipt URL associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let receipt_url = get_receipt_url(customer_id, supabase_client).await?;
@@ -803,6 +821,8 @@ impl CustomerId {
         customer_id: CustomerId,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_receipt_url");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_receipt_url: String = overwrite_stripe_customer_receipt_url_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -845,6 +865,8 @@ impl CustomerId {
         new_country: String,
         supabase: SupabaseClient,
     ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_country");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
         let column_name_country: String = overwrite_stripe_customer_country_column_name();
@@ -894,6 +916,8 @@ impl CustomerId {
         customer_id: CustomerId,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_country");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
         let column_name_country: String = overwrite_stripe_customer_country_column_name();
@@ -922,7 +946,7 @@ impl CustomerId {
     /// 
     /// ### Arguments
     /// - `customer_id`: `CustomerId` - The unique identifier for the customer whose `amount_total` is being updated.
-    /// - `new_amount_total`: `f64` - The new `amount_total` to be updated for the customer.
+    /// - `new_amount_total`: `&Money` - The new `amount_total`, stored in minor units of its currency in `amount_total_minor`, e.g. `1999` for 19.99 USD or 1999 JPY, next to the `currency`. The legacy `amount_total` column in major units is no longer written.
     /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
     /// 
     /// ### Returns
@@ -931,12 +955,15 @@ impl CustomerId {
     ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     pub async fn update_amount_total(
         customer_id: CustomerId,
-        new_amount_total: f64,
+        new_amount_total: &Money,
         supabase: SupabaseClient,
     ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_amount_total");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
-        let column_name_amount_total: String = overwrite_stripe_customer_amount_total_column_name();
+        let column_name_amount_total_minor: String = overwrite_stripe_customer_amount_total_minor_column_name();
+        let column_name_currency: String = overwrite_stripe_customer_currency_column_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
 
         // fetch email over the self
@@ -959,7 +986,8 @@ impl CustomerId {
                 &table_name,
                 &row_id,
                 json!({
-                    column_name_amount_total: new_amount_total
+                    column_name_amount_total_minor: new_amount_total.minor_units,
+                    column_name_currency: new_amount_total.currency
                 }),
             )
             .await
@@ -977,17 +1005,17 @@ impl CustomerId {
     /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
     /// 
     /// ### Returns
-    /// - `Result<f64, Box<dyn Error>>`: This function returns a `Result` which is either:
-    ///   - `Ok(f64)`: The `amount_total` of the customer if found.
+    /// - `Result<Money, Box<dyn Error>>`: This function returns a `Result` which is either:
+    ///   - `Ok(Money)`: The `amount_total` of the customer in its `currency` if found.
     ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     pub async fn get_amount_total(
         customer_id: CustomerId,
         supabase: SupabaseClient,
-    ) -> Result<f64, Box<dyn Error>> {
+    ) -> Result<Money, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_amount_total");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
-        let column_name_amount_total: String = overwrite_stripe_customer_amount_total_column_name();
-
         let result_get_amount_total: Vec<Value> = supabase
             .select(&table_name)
             .eq(&column_name_customer_id, customer_id.as_str())
@@ -999,14 +1027,38 @@ impl CustomerId {
             .first()
             .expect("Failed extracting amount_total from Supabase response for `get_amount_total`");
 
-        let amount_total: f64 = customer_data_from_result[column_name_amount_total]
-            .as_f64()
-            .unwrap();
+        let amount_total: Money = CustomerId::amount_total_of(customer_data_from_result)
+            .unwrap_or_else(|| Money::new(0, "usd"));
 
         Ok(amount_total)
     }
 
 
+    /// ## `amount_total_of`
+    /// Reads the `amount_total` of a customer row in its `currency`, `usd` when it has none. Rows
+    /// written before `amount_total_minor` existed only have the major units in `amount_total`, so
+    /// those are read as major units instead.
+    ///
+    /// ### Arguments
+    /// - `customer`: `&Value` - A row of the customer table
+    ///
+    /// ### Returns
+    /// - `Option<Money>`: The amount, `None` when neither column holds one.
+    pub fn amount_total_of(customer: &Value) -> Option<Money> {
+        let currency: &str = customer[overwrite_stripe_customer_currency_column_name()]
+            .as_str()
+            .unwrap_or("usd");
+
+        if let Some(minor_units) = customer[overwrite_stripe_customer_amount_total_minor_column_name()].as_i64() {
+            return Some(Money::new(minor_units, currency));
+        }
+
+        customer[overwrite_stripe_customer_amount_total_column_name()]
+            .as_f64()
+            .map(|major_units| Money::from_major(major_units, currency))
+    }
+
+
     /// # update `payment_link` column by `CustomerId`
     /// Type: String
     ///
@@ -1021,7 +1073,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ### Example: Updating the payment link associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let result = update_payment_link(customer_id, "new_payment_link", supabase_client).await?;
@@ -1032,6 +1084,8 @@ impl CustomerId {
         payment_link: String,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("cache_payment_link");
+
         let table_name: String = overwrite_stripe_plink_cache_table_name();
         let column_name_payment_link: String = overwrite_stripe_customer_payment_link_column_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
@@ -1065,7 +1119,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Attaching a payment link associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let result = attach_payment_link(customer_id, "https://example.com/payment", supabase_client).await?;
@@ -1076,6 +1130,8 @@ impl CustomerId {
         payment_link: String,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("attach_payment_link");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_payment_link: String = overwrite_stripe_customer_payment_link_column_name();
         let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
@@ -1120,7 +1176,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Retrieving the payment link associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let email = "example@email.com";
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let payment_link = get_payment_link(email, supabase_client).await?;
@@ -1130,6 +1186,8 @@ impl CustomerId {
         email: String,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_payment_link");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_payment_link: String = overwrite_stripe_customer_payment_link_column_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
@@ -1169,7 +1227,7 @@ impl CustomerId {
     /// - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
     /// 
     /// ## Example: Retrieving the payment link associated with a `CustomerId`
-    /// ```rust
+    /// ```rust,ignore
     /// let customer_id = CustomerId::new("some_unique_id");
     /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
     /// let payment_link = get_payment_link(customer_id, supabase_client).await?;
@@ -1179,6 +1237,8 @@ impl CustomerId {
         email: String,
         supabase: SupabaseClient,
     ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("decache_payment_link");
+
         let table_name: String = overwrite_stripe_plink_cache_table_name();
         let column_name_payment_link: String = overwrite_stripe_customer_payment_link_column_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
@@ -1203,37 +1263,542 @@ impl CustomerId {
     }
 
 
-    // Update the email sent status by email
+    /// # update_email_sent_status_by_email
+    /// Stores whether the last email to the customer with the given `email` was sent, and why
+    /// it was not.
+    ///
+    /// ## Arguments
+    /// - `email`: `String` - The email of the customer.
+    /// - `status`: `bool` - Whether the email was sent.
+    /// - `reason`: `Option<String>` - Why it was not sent, e.g. the address is suppressed, `None` clears it.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<(), Box<dyn Error>>`: An error when there is no customer with the email or the database operation failed.
     pub async fn update_email_sent_status_by_email(
         email: String,
         status: bool,
+        reason: Option<String>,
         supabase: SupabaseClient,
     ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_email_sent_status_by_email");
+
         let table_name: String = overwrite_stripe_customer_table_name();
         let column_name_email: String = overwrite_stripe_email_column_name();
         let column_name_email_sent: String = overwrite_stripe_customer_email_sent_column_name();
+        let column_name_email_sent_reason: String = overwrite_stripe_customer_email_sent_reason_column_name();
 
-        let row_id = SupabaseClient::get_id(
+        let row_id: String = SupabaseClient::get_id(
             supabase.clone(),
             email,
             table_name.clone(),
             column_name_email,
-        );
+        ).await?;
 
-        let row_id: String = row_id.await.unwrap();     
+        supabase
+            .upsert(
+                &table_name,
+                &row_id,
+                json!({
+                    column_name_email_sent: status,
+                    column_name_email_sent_reason: reason
+                }),
+            )
+            .await?;
 
-        let result_update_email_sent: String = supabase
+        Ok(())
+    }
+
+
+    /// # update_payment_failed_by_email
+    /// Marks the customer with the given `email` as unpaid and stores why the payment failed.
+    /// A record is created for the email first if the customer does not exist yet.
+    ///
+    /// ## Arguments
+    /// - `email`: `String` - The email of the customer whose payment failed.
+    /// - `decline_code`: `String` - The decline code Stripe returned, e.g. `insufficient_funds`.
+    /// - `decline_message`: `String` - The human readable message Stripe returned.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<(), Box<dyn Error>>`: This function returns a `Result` which is either:
+    ///   - `Ok(())`: If the `paid`, `decline_code` and `decline_message` columns are updated.
+    ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
+    ///
+    /// ## Example: Recording a declined card
+    /// ```rust,ignore
+    /// let supabase_client = SupabaseClient::new("your_supabase_url", "your_supabase_key");
+    /// CustomerId::update_payment_failed_by_email(
+    ///     "floris@xylex.ai".to_string(),
+    ///     "insufficient_funds".to_string(),
+    ///     "Your card has insufficient funds.".to_string(),
+    ///     supabase_client
+    /// ).await?;
+    /// ```
+    pub async fn update_payment_failed_by_email(
+        email: String,
+        decline_code: String,
+        decline_message: String,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_payment_failed_by_email");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_email: String = overwrite_stripe_email_column_name();
+        let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
+        let column_name_decline_code: String = overwrite_stripe_customer_decline_code_column_name();
+        let column_name_decline_message: String = overwrite_stripe_customer_decline_message_column_name();
+
+        // make sure there is a record to attach the failure to
+        CustomerId::new_from_email(email.clone(), true, supabase.clone()).await?;
+
+        let row_id: String = SupabaseClient::get_id(
+            supabase.clone(),
+            email,
+            table_name.clone(),
+            column_name_email,
+        ).await?;
+
+        supabase
             .upsert(
                 &table_name,
                 &row_id,
                 json!({
-                    column_name_email_sent: status
+                    column_name_paid: false,
+                    column_name_decline_code: decline_code,
+                    column_name_decline_message: decline_message
                 }),
             )
-            .await
-            .unwrap();
-            
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// # get_row_id
+    /// Retrieves the Supabase row `id` of the record of a given `CustomerId`.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `CustomerId` - The unique identifier for the customer.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<String, Box<dyn Error>>`: This function returns a `Result` which is either:
+    ///   - `Ok(String)`: The row `id` of the customer record.
+    ///   - `Err(Box<dyn Error>)`: If there is no record for the `CustomerId` or the database operation failed.
+    pub async fn get_row_id(
+        customer_id: CustomerId,
+        supabase: SupabaseClient,
+    ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_row_id");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
+
+        let row_id: String = SupabaseClient::get_id(
+            supabase,
+            customer_id.id,
+            table_name,
+            column_name_customer_id,
+        ).await?;
+
+        Ok(row_id)
+    }
+
+
+    /// # update_refund
+    /// Stores the refunded amount and whether the refund was `partial` or `full` for a given `CustomerId`.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `CustomerId` - The unique identifier for the refunded customer.
+    /// - `amount_refunded`: `&Money` - The total amount refunded so far, stored in minor units.
+    /// - `refund_status`: `String` - Either `partial` or `full`.
+    /// - `paid`: `Option<bool>` - The new paid status decided by the `RefundPolicy`, `None` leaves it untouched.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<(), Box<dyn Error>>`: This function returns a `Result` which is either:
+    ///   - `Ok(())`: If the refund is stored.
+    ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
+    pub async fn update_refund(
+        customer_id: CustomerId,
+        amount_refunded: &Money,
+        refund_status: String,
+        paid: Option<bool>,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_refund");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_amount_refunded: String = overwrite_stripe_customer_amount_refunded_column_name();
+        let column_name_refund_status: String = overwrite_stripe_customer_refund_status_column_name();
+        let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
+
+        let row_id: String = CustomerId::get_row_id(customer_id, supabase.clone()).await?;
+
+        let mut body: Value = json!({
+            column_name_amount_refunded: amount_refunded.minor_units,
+            column_name_refund_status: refund_status
+        });
+
+        if let Some(paid) = paid {
+            body[column_name_paid] = json!(paid);
+        }
+
+        supabase
+            .upsert(&table_name, &row_id, body)
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// # update_dispute
+    /// Stores the status and reason of the latest dispute for a given `CustomerId`.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `CustomerId` - The unique identifier for the disputed customer.
+    /// - `dispute_status`: `String` - The status of the dispute, e.g. `needs_response`, `won` or `lost`.
+    /// - `dispute_reason`: `String` - The reason of the dispute, e.g. `fraudulent`.
+    /// - `paid`: `Option<bool>` - The new paid status decided by the `DisputePolicy`, `None` leaves it untouched.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<(), Box<dyn Error>>`: This function returns a `Result` which is either:
+    ///   - `Ok(())`: If the dispute is stored.
+    ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
+    pub async fn update_dispute(
+        customer_id: CustomerId,
+        dispute_status: String,
+        dispute_reason: String,
+        paid: Option<bool>,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_dispute");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_dispute_status: String = overwrite_stripe_customer_dispute_status_column_name();
+        let column_name_dispute_reason: String = overwrite_stripe_customer_dispute_reason_column_name();
+        let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
+
+        let row_id: String = CustomerId::get_row_id(customer_id, supabase.clone()).await?;
+
+        let mut body: Value = json!({
+            column_name_dispute_status: dispute_status,
+            column_name_dispute_reason: dispute_reason
+        });
+
+        if let Some(paid) = paid {
+            body[column_name_paid] = json!(paid);
+        }
+
+        supabase
+            .upsert(&table_name, &row_id, body)
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// # get_record
+    /// Retrieves the full customer record of a given `CustomerId`.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `CustomerId` - The unique identifier for the customer.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Option<Value>, Box<dyn Error>>`: This function returns a `Result` which is either:
+    ///   - `Ok(Some(Value))`: The record with every column.
+    ///   - `Ok(None)`: If there is no record for the `CustomerId`.
+    ///   - `Err(Box<dyn Error>)`: An error boxed in a trait object if an issue occurs during the database operation.
+    pub async fn get_record(
+        customer_id: CustomerId,
+        supabase: SupabaseClient,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("get_record");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
+
+        let records: Vec<Value> = supabase
+            .select(&table_name)
+            .eq(&column_name_customer_id, customer_id.as_str())
+            .execute()
+            .await?;
+
+        Ok(records.into_iter().next())
+    }
+
+
+    /// # search
+    /// Retrieves the customer records matching an email and/or customer id.
+    ///
+    /// ## Arguments
+    /// - `email`: `Option<&str>` - Only records with this exact email.
+    /// - `customer_id`: `Option<&str>` - Only the record of this customer id.
+    /// - `limit`: `usize` - The maximum number of records returned.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Vec<Value>, Box<dyn Error>>`: The matching records or the database error.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let records: Vec<Value> = CustomerId::search(Some("floris@xylex.ai"), None, 20, supabase).await?;
+    /// ```
+    pub async fn search(
+        email: Option<&str>,
+        customer_id: Option<&str>,
+        limit: usize,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<Value>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("search_customers");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_email: String = overwrite_stripe_email_column_name();
+        let column_name_customer_id: String = overwrite_stripe_customer_id_column_name();
+
+        let mut query: QueryBuilder = supabase.select(&table_name);
+
+        if let Some(email) = email {
+            query = query.eq(&column_name_email, email);
+        }
+
+        if let Some(customer_id) = customer_id {
+            query = query.eq(&column_name_customer_id, customer_id);
+        }
+
+        let mut records: Vec<Value> = query.execute().await?;
+        records.truncate(limit);
+
+        Ok(records)
+    }
+
+
+    /// # get_discord_user_id
+    /// Retrieves the Discord user id linked to a given `CustomerId`.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `CustomerId` - The unique identifier for the customer.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Option<String>, Box<dyn Error>>`: The Discord user id, `None` when the customer
+    ///   has not linked a Discord account or has no record.
+    pub async fn get_discord_user_id(
+        customer_id: CustomerId,
+        supabase: SupabaseClient,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let record: Option<Value> = CustomerId::get_record(customer_id, supabase).await?;
+
+        Ok(record.as_ref().and_then(CustomerId::discord_user_id))
+    }
+
+
+    /// # discord_user_id
+    /// The Discord user id linked on a customer record, `None` when no account is linked.
+    pub fn discord_user_id(record: &Value) -> Option<String> {
+        let column_name_discord_user_id: String = overwrite_stripe_customer_discord_user_id_column_name();
+
+        // snowflakes may be stored as text or as a number
+        match record.get(&column_name_discord_user_id) {
+            Some(Value::String(discord_user_id)) if !discord_user_id.is_empty() => Some(discord_user_id.clone()),
+            Some(Value::Number(discord_user_id)) => Some(discord_user_id.to_string()),
+            _ => None,
+        }
+    }
+
+
+    /// # update_end_time_by_email
+    /// Stores when the current subscription period of the customer with the given `email` ends.
+    ///
+    /// ## Arguments
+    /// - `email`: `String` - The email of the customer.
+    /// - `end_time`: `i64` - The unix timestamp the period ends at, the subscription renews then.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<(), Box<dyn Error>>`: An error when there is no customer with the email or the database operation failed.
+    pub async fn update_end_time_by_email(
+        email: String,
+        end_time: i64,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_end_time_by_email");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_email: String = overwrite_stripe_email_column_name();
+        let column_name_end_time: String = overwrite_stripe_customer_end_time_column_name();
+
+        let row_id: String = SupabaseClient::get_id(
+            supabase.clone(),
+            email,
+            table_name.clone(),
+            column_name_email,
+        ).await?;
+
+        supabase
+            .upsert(
+                &table_name,
+                &row_id,
+                json!({
+                    column_name_end_time: end_time
+                }),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// # list_ending_between
+    /// Retrieves the paid customers whose `end_time` falls between two timestamps.
+    ///
+    /// ## Arguments
+    /// - `from`: `i64` - The earliest `end_time`, inclusive.
+    /// - `to`: `i64` - The latest `end_time`, inclusive.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Vec<Value>, Box<dyn Error>>`: The matching records or the database error.
+    pub async fn list_ending_between(
+        from: i64,
+        to: i64,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<Value>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("list_ending_between");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_end_time: String = overwrite_stripe_customer_end_time_column_name();
+        let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
+
+        // supabase_rs keeps one filter per column, the upper bound is applied here
+        let records: Vec<Value> = supabase
+            .select(&table_name)
+            .gte(&column_name_end_time, &from.to_string())
+            .execute()
+            .await?;
+
+        Ok(records
+            .into_iter()
+            .filter(|record| record[&column_name_end_time].as_i64().is_some_and(|end_time| end_time <= to))
+            .filter(|record| record[&column_name_paid].as_bool() == Some(true))
+            .collect())
+    }
+
+
+    /// # update_access
+    /// Grants time-boxed access to a customer record, the customer is paid from `start_time`
+    /// until `end_time`. The access is stored apart from the `end_time` of subscription periods.
+    ///
+    /// ## Arguments
+    /// - `row_id`: `&str` - The Supabase row `id` of the customer record.
+    /// - `start_time`: `i64` - The unix timestamp the access started at.
+    /// - `end_time`: `i64` - The unix timestamp the access ends at.
+    /// - `organization`: `&str` - The name of the Organization that granted the access.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<(), Box<dyn Error>>`: An error when the database operation failed.
+    pub async fn update_access(
+        row_id: &str,
+        start_time: i64,
+        end_time: i64,
+        organization: &str,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("update_access");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_access_start_time: String = overwrite_stripe_customer_access_start_time_column_name();
+        let column_name_access_end_time: String = overwrite_stripe_customer_access_end_time_column_name();
+        let column_name_access_status: String = overwrite_stripe_customer_access_status_column_name();
+        let column_name_access_organization: String = overwrite_stripe_customer_access_organization_column_name();
+        let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
+
+        supabase
+            .upsert(
+                &table_name,
+                row_id,
+                json!({
+                    column_name_access_start_time: start_time,
+                    column_name_access_end_time: end_time,
+                    column_name_access_status: ACCESS_ACTIVE,
+                    column_name_access_organization: organization,
+                    column_name_paid: true
+                }),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// # list_access_ended
+    /// Retrieves the customers whose time-boxed access is still active but its `access_end_time`
+    /// is at or before `now`.
+    ///
+    /// ## Arguments
+    /// - `now`: `i64` - The unix timestamp access has ended by.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Vec<Value>, Box<dyn Error>>`: The matching records or the database error.
+    pub async fn list_access_ended(
+        now: i64,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<Value>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("list_access_ended");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_access_end_time: String = overwrite_stripe_customer_access_end_time_column_name();
+        let column_name_access_status: String = overwrite_stripe_customer_access_status_column_name();
+
+        let records: Vec<Value> = supabase
+            .select(&table_name)
+            .eq(&column_name_access_status, ACCESS_ACTIVE)
+            .lte(&column_name_access_end_time, &now.to_string())
+            .execute()
+            .await?;
+
+        Ok(records)
+    }
+
+
+    /// # expire_access
+    /// Marks the time-boxed access of a customer record as expired and the customer as unpaid,
+    /// unless it is still paid by a subscription.
+    ///
+    /// ## Arguments
+    /// - `row_id`: `&str` - The Supabase row `id` of the customer record.
+    /// - `subscribed`: `bool` - Whether a subscription period of the customer has not ended yet.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<(), Box<dyn Error>>`: An error when the database operation failed.
+    pub async fn expire_access(
+        row_id: &str,
+        subscribed: bool,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("expire_access");
+
+        let table_name: String = overwrite_stripe_customer_table_name();
+        let column_name_access_status: String = overwrite_stripe_customer_access_status_column_name();
+        let column_name_paid: String = overwrite_stripe_customer_paid_column_name();
+
+        supabase
+            .upsert(
+                &table_name,
+                row_id,
+                json!({
+                    column_name_access_status: ACCESS_EXPIRED,
+                    column_name_paid: subscribed
+                }),
+            )
+            .await?;
+
         Ok(())
     }
-    
 }
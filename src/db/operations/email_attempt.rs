@@ -0,0 +1,112 @@
+//! # Email attempt database operations
+//!
+//! This module contains the database operations for the `stripe_email_attempts` table, every
+//! attempt to send an email is stored here, retries and fallbacks included, see
+//! [retry](../../../email/retry/index.html).
+//!
+//! ## `stripe_email_attempts` columns
+//! - `email_log_id` TYPE INT8 - The row id of the email in the email log, empty for operator notifications
+//! - `recipient` TYPE TEXT - The addresses the email was sent to, comma separated
+//! - `attempt` TYPE INT8 - The number of the attempt, starting at 1
+//! - `provider` TYPE TEXT - The provider of the attempt, e.g. `resend` or `smtp`
+//! - `status` TYPE TEXT - `sent` or `failed`
+//! - `message_id` TYPE TEXT - The id the provider gave the email when it was sent
+//! - `error` TYPE TEXT - Why the attempt failed
+//! - `error_kind` TYPE TEXT - `transient`, `unauthorized` or `permanent`
+//! - `attempted_at` TYPE INT8 - The unix timestamp of the attempt
+
+use crate::email::retry::EmailError;
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_email_attempts_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use supabase_rs::SupabaseClient;
+
+
+/// ## EmailAttempt
+/// One attempt to send an email
+///
+/// ### Fields
+/// - `email_log_id` - The row id of the email in the email log
+/// - `recipient` - The addresses the email was sent to
+/// - `attempt` - The number of the attempt, starting at 1
+/// - `provider` - The provider of the attempt
+/// - `status` - `sent` or `failed`
+/// - `message_id` - The id the provider gave the email
+/// - `error` - Why the attempt failed
+/// - `error_kind` - `transient`, `unauthorized` or `permanent`
+/// - `attempted_at` - The unix timestamp of the attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailAttempt {
+    pub email_log_id: Option<i64>,
+    pub recipient: String,
+    pub attempt: i64,
+    pub provider: String,
+    pub status: String,
+    pub message_id: Option<String>,
+    pub error: Option<String>,
+    pub error_kind: Option<String>,
+    pub attempted_at: i64,
+}
+
+
+impl EmailAttempt {
+    /// # new
+    /// The attempt of an email with what the provider answered.
+    ///
+    /// ## Arguments
+    /// - `email_log_id`: `Option<i64>` - The row id of the email in the email log
+    /// - `to`: `&[String]` - The recipient addresses
+    /// - `attempt`: `u32` - The number of the attempt, starting at 1
+    /// - `provider`: `&str` - The provider of the attempt
+    /// - `sent`: `&Result<String, EmailError>` - The message id or the error of the provider
+    /// - `attempted_at`: `i64` - The unix timestamp of the attempt
+    pub fn new(
+        email_log_id: Option<i64>,
+        to: &[String],
+        attempt: u32,
+        provider: &str,
+        sent: &Result<String, EmailError>,
+        attempted_at: i64,
+    ) -> Self {
+        let (status, message_id, error, error_kind) = match sent {
+            Ok(message_id) => ("sent", Some(message_id.clone()), None, None),
+            Err(error) => ("failed", None, Some(error.to_string()), Some(error.kind().to_string())),
+        };
+
+        EmailAttempt {
+            email_log_id,
+            recipient: to.join(","),
+            attempt: attempt.into(),
+            provider: provider.to_string(),
+            status: status.to_string(),
+            message_id,
+            error,
+            error_kind,
+            attempted_at,
+        }
+    }
+
+
+    /// # insert
+    /// Stores the attempt.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    pub async fn insert(
+        &self,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("email_attempt_insert");
+
+        let table_name: String = overwrite_stripe_email_attempts_table_name();
+
+        supabase
+            .insert(&table_name, serde_json::to_value(self)?)
+            .await?;
+
+        Ok(())
+    }
+}
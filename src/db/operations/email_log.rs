@@ -0,0 +1,427 @@
+//! # Email log database operations
+//!
+//! This module contains the database operations for the `stripe_email_log` table, every customer
+//! email gets a row when it is queued. The row is updated when the provider accepts the email and
+//! again from the delivery webhooks of the provider, see [delivery](../../../email/delivery/index.html).
+//!
+//! ## `stripe_email_log` columns
+//! - `id` TYPE INT8 - The row id
+//! - `message_id` TYPE TEXT - The id the provider gave the email, empty until it is accepted
+//! - `template` TYPE TEXT - The email that was sent, e.g. `welcome` or `receipt`
+//! - `subject` TYPE TEXT - The subject the email was sent with
+//! - `recipient` TYPE TEXT - The address the email was sent to
+//! - `customer_id` TYPE TEXT - The customer the recipient belongs to, empty without a customer record
+//! - `provider` TYPE TEXT - The provider that sent the email, e.g. `resend`, or `smtp` after a fallback
+//! - `attempts` TYPE INT8 - How often sending was attempted, see `stripe_email_attempts`
+//! - `status` TYPE TEXT - `queued`, `sent`, `failed`, `delivered`, `opened`, `bounced` or `complained`
+//! - `error` TYPE TEXT - Why the provider did not accept the email
+//! - `created_at` TYPE INT8 - The unix timestamp the email was queued at
+//! - `sent_at`, `delivered_at`, `opened_at`, `bounced_at`, `complained_at` TYPE INT8 - The unix
+//!   timestamps the status was first reached at
+//! - `updated_at` TYPE INT8 - The unix timestamp of the last change
+
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_email_log_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use supabase_rs::SupabaseClient;
+
+
+/// ## EmailStatus
+/// Where an email is on its way to the recipient
+///
+/// ### Variants
+/// - `Queued` - Rendered and about to be handed to the provider
+/// - `Sent` - Accepted by the provider
+/// - `Failed` - The provider did not accept it, see `error`
+/// - `Delivered` - Accepted by the mail server of the recipient
+/// - `Opened` - Opened by the recipient
+/// - `Bounced` - Permanently rejected by the mail server of the recipient
+/// - `Complained` - Marked as spam by the recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmailStatus {
+    #[default]
+    Queued,
+    Sent,
+    Failed,
+    Delivered,
+    Opened,
+    Bounced,
+    Complained,
+}
+
+
+impl EmailStatus {
+    /// ## Progress
+    /// How far along the status is, webhooks arrive out of order and never move an email back,
+    /// e.g. a late `delivered` after `opened`
+    pub fn progress(&self) -> u8 {
+        match self {
+            EmailStatus::Queued => 0,
+            EmailStatus::Sent | EmailStatus::Failed => 1,
+            EmailStatus::Delivered => 2,
+            EmailStatus::Opened => 3,
+            EmailStatus::Bounced => 4,
+            EmailStatus::Complained => 5,
+        }
+    }
+
+    /// ## Timestamp column
+    /// The column that stores when the status was first reached, `queued` and `failed` have none
+    pub fn timestamp_column(&self) -> Option<&'static str> {
+        match self {
+            EmailStatus::Sent => Some("sent_at"),
+            EmailStatus::Delivered => Some("delivered_at"),
+            EmailStatus::Opened => Some("opened_at"),
+            EmailStatus::Bounced => Some("bounced_at"),
+            EmailStatus::Complained => Some("complained_at"),
+            EmailStatus::Queued | EmailStatus::Failed => None,
+        }
+    }
+}
+
+
+impl FromStr for EmailStatus {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a string into a status
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::db::operations::email_log::EmailStatus;
+    /// assert_eq!("delivered".parse(), Ok(EmailStatus::Delivered));
+    /// assert!("read".parse::<EmailStatus>().is_err());
+    /// ```
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "queued" => Ok(EmailStatus::Queued),
+            "sent" => Ok(EmailStatus::Sent),
+            "failed" => Ok(EmailStatus::Failed),
+            "delivered" => Ok(EmailStatus::Delivered),
+            "opened" => Ok(EmailStatus::Opened),
+            "bounced" => Ok(EmailStatus::Bounced),
+            "complained" => Ok(EmailStatus::Complained),
+            _ => Err(format!("unknown email status `{}`", status)),
+        }
+    }
+}
+
+
+impl fmt::Display for EmailStatus {
+    /// ## To String
+    /// This will write the status as it is stored in the `status` column
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EmailStatus::Queued => "queued",
+            EmailStatus::Sent => "sent",
+            EmailStatus::Failed => "failed",
+            EmailStatus::Delivered => "delivered",
+            EmailStatus::Opened => "opened",
+            EmailStatus::Bounced => "bounced",
+            EmailStatus::Complained => "complained",
+        })
+    }
+}
+
+
+/// ## EmailLog
+/// A customer email as stored in the email log
+///
+/// ### Fields
+/// - `id` - The row id, `None` until the email is inserted
+/// - `message_id` - The id the provider gave the email
+/// - `template` - The email that was sent, e.g. `welcome`
+/// - `subject` - The subject the email was sent with
+/// - `recipient` - The address the email was sent to
+/// - `customer_id` - The customer the recipient belongs to
+/// - `provider` - The provider that sent the email
+/// - `attempts` - How often sending was attempted
+/// - `status` - See [`EmailStatus`]
+/// - `error` - Why the provider did not accept the email
+/// - `created_at` - The unix timestamp the email was queued at
+/// - `sent_at`, `delivered_at`, `opened_at`, `bounced_at`, `complained_at` - When the status was first reached
+/// - `updated_at` - The unix timestamp of the last change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailLog {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub message_id: Option<String>,
+    pub template: String,
+    pub subject: String,
+    pub recipient: String,
+    pub customer_id: Option<String>,
+    pub provider: String,
+    #[serde(default)]
+    pub attempts: i64,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub sent_at: Option<i64>,
+    pub delivered_at: Option<i64>,
+    pub opened_at: Option<i64>,
+    pub bounced_at: Option<i64>,
+    pub complained_at: Option<i64>,
+    pub updated_at: i64,
+}
+
+
+impl EmailLog {
+    /// # queued
+    /// Creates the entry of an email that is about to be sent.
+    ///
+    /// ## Arguments
+    /// - `template`: `&str` - The email that is sent, e.g. `welcome`
+    /// - `subject`: `&str` - The subject it is sent with
+    /// - `recipient`: `&str` - The address it is sent to
+    /// - `customer_id`: `Option<String>` - The customer the recipient belongs to
+    /// - `provider`: `&str` - The provider that sends it
+    /// - `now`: `i64` - The current unix timestamp
+    pub fn queued(
+        template: &str,
+        subject: &str,
+        recipient: &str,
+        customer_id: Option<String>,
+        provider: &str,
+        now: i64,
+    ) -> Self {
+        EmailLog {
+            id: None,
+            message_id: None,
+            template: template.to_string(),
+            subject: subject.to_string(),
+            recipient: recipient.to_string(),
+            customer_id,
+            provider: provider.to_string(),
+            attempts: 0,
+            status: EmailStatus::Queued.to_string(),
+            error: None,
+            created_at: now,
+            sent_at: None,
+            delivered_at: None,
+            opened_at: None,
+            bounced_at: None,
+            complained_at: None,
+            updated_at: now,
+        }
+    }
+
+
+    /// # insert
+    /// Stores the entry.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<EmailLog, Box<dyn Error>>`: The stored entry with its row `id`.
+    pub async fn insert(
+        mut self,
+        supabase: SupabaseClient,
+    ) -> Result<EmailLog, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("email_log_insert");
+
+        let table_name: String = overwrite_stripe_email_log_table_name();
+
+        let row_id: String = supabase
+            .insert(&table_name, serde_json::to_value(&self)?)
+            .await?;
+
+        self.id = row_id.parse().ok();
+
+        Ok(self)
+    }
+
+
+    /// # record_send
+    /// Stores what the provider of the last attempt answered, `sent` with the message id or
+    /// `failed` with the error.
+    ///
+    /// ## Arguments
+    /// - `sent`: `&Result<String, String>` - The message id or the error of the provider
+    /// - `provider`: `&str` - The provider of the last attempt
+    /// - `attempts`: `u32` - How often sending was attempted
+    /// - `now`: `i64` - The current unix timestamp
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    pub async fn record_send(
+        &mut self,
+        sent: &Result<String, String>,
+        provider: &str,
+        attempts: u32,
+        now: i64,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("email_log_record_send");
+
+        self.provider = provider.to_string();
+        self.attempts = attempts.into();
+
+        match sent {
+            Ok(message_id) => {
+                self.message_id = Some(message_id.clone());
+                self.status = EmailStatus::Sent.to_string();
+                self.sent_at = Some(now);
+            },
+            Err(error) => {
+                self.status = EmailStatus::Failed.to_string();
+                self.error = Some(error.clone());
+            },
+        }
+
+        self.updated_at = now;
+
+        let Some(id) = self.id else {
+            return Err("the email log entry was never inserted".into());
+        };
+
+        let table_name: String = overwrite_stripe_email_log_table_name();
+
+        supabase
+            .update(&table_name, &id.to_string(), json!({
+                "message_id": self.message_id,
+                "provider": self.provider,
+                "attempts": self.attempts,
+                "status": self.status,
+                "error": self.error,
+                "sent_at": self.sent_at,
+                "updated_at": self.updated_at,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// # record_status
+    /// Stores a status the provider reported for an email. The first time a status is reached is
+    /// kept, and the email only moves forward, see [`EmailStatus::progress`].
+    ///
+    /// ## Arguments
+    /// - `message_id`: `&str` - The id the provider gave the email
+    /// - `status`: `EmailStatus` - The reported status
+    /// - `at`: `i64` - The unix timestamp the status was reported at
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<bool, Box<dyn Error>>`: Whether an email with the message id is logged, or the database error.
+    pub async fn record_status(
+        message_id: &str,
+        status: EmailStatus,
+        at: i64,
+        supabase: SupabaseClient,
+    ) -> Result<bool, Box<dyn Error>> {
+        let Some(entry) = EmailLog::get_by_message_id(message_id, supabase.clone()).await? else {
+            return Ok(false);
+        };
+
+        let Some(id) = entry.id else {
+            return Err("the email log entry has no row id".into());
+        };
+
+        let _timer: HistogramTimer = observe_db_operation("email_log_record_status");
+
+        let mut changes: Value = json!({ "updated_at": at });
+
+        // unknown stored statuses count as `queued`
+        if status.progress() > entry.status.parse::<EmailStatus>().unwrap_or_default().progress() {
+            changes["status"] = json!(status.to_string());
+        }
+
+        if let Some(column) = status.timestamp_column() {
+            if entry.reached_at(status).is_none() {
+                changes[column] = json!(at);
+            }
+        }
+
+        let table_name: String = overwrite_stripe_email_log_table_name();
+
+        supabase
+            .update(&table_name, &id.to_string(), changes)
+            .await?;
+
+        Ok(true)
+    }
+
+
+    /// # reached_at
+    /// When the email first reached a status, `None` when it did not or the status has no timestamp.
+    pub fn reached_at(&self, status: EmailStatus) -> Option<i64> {
+        match status {
+            EmailStatus::Sent => self.sent_at,
+            EmailStatus::Delivered => self.delivered_at,
+            EmailStatus::Opened => self.opened_at,
+            EmailStatus::Bounced => self.bounced_at,
+            EmailStatus::Complained => self.complained_at,
+            EmailStatus::Queued | EmailStatus::Failed => None,
+        }
+    }
+
+
+    /// # get_by_message_id
+    /// Retrieves a logged email by the id the provider gave it.
+    ///
+    /// ## Arguments
+    /// - `message_id`: `&str` - The id the provider gave the email
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Option<EmailLog>, Box<dyn Error>>`: The email, `None` when it is not logged.
+    pub async fn get_by_message_id(
+        message_id: &str,
+        supabase: SupabaseClient,
+    ) -> Result<Option<EmailLog>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("email_log_get_by_message_id");
+
+        let table_name: String = overwrite_stripe_email_log_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("message_id", message_id)
+            .execute()
+            .await?;
+
+        Ok(rows.into_iter().find_map(|row| serde_json::from_value(row).ok()))
+    }
+
+
+    /// # list_by_customer_id
+    /// Retrieves the emails sent to a customer.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `&str` - The customer the emails were sent to
+    /// - `limit`: `usize` - The maximum number of emails
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Vec<EmailLog>, Box<dyn Error>>`: The emails, newest first.
+    pub async fn list_by_customer_id(
+        customer_id: &str,
+        limit: usize,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<EmailLog>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("email_log_list_by_customer_id");
+
+        let table_name: String = overwrite_stripe_email_log_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("customer_id", customer_id)
+            .execute()
+            .await?;
+
+        let mut emails: Vec<EmailLog> = rows
+            .into_iter()
+            .filter_map(|row| serde_json::from_value(row).ok())
+            .collect();
+
+        emails.sort_by_key(|email| std::cmp::Reverse(email.created_at));
+        emails.truncate(limit);
+
+        Ok(emails)
+    }
+}
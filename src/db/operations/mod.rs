@@ -1,4 +1,13 @@
 //! # Operations module
 //! This module contains the operations that can be performed on the database.
 //! 
+pub mod api_key;
+pub mod audit;
 pub mod customer_id;
+pub mod email_attempt;
+pub mod email_log;
+pub mod period_reminder;
+pub mod quarantine;
+pub mod scheduled_email;
+pub mod suppression;
+pub mod webhook_event;
@@ -0,0 +1,126 @@
+//! # Period reminder database operations
+//!
+//! This module contains the database operations for the `stripe_period_reminders` table. Every
+//! subscription period gets at most one reminder before it renews, whether it comes from
+//! `invoice.upcoming`, `customer.subscription.trial_will_end` or the scan of the `end_time`
+//! column, so a row settles the period for the address.
+//!
+//! ## `stripe_period_reminders` columns
+//! - `id` TYPE INT8 - The row id
+//! - `organization` TYPE TEXT - The name of the Organization that settled the period
+//! - `email` TYPE TEXT - The address of the customer
+//! - `template` TYPE TEXT - `renewal_reminder` or `trial_ending`
+//! - `period_end` TYPE INT8 - The unix timestamp the period ends and renews at
+//! - `source` TYPE TEXT - The id of the Stripe event, or `scan`
+//! - `status` TYPE TEXT - `sent`, or `skipped` when the period does not renew
+//! - `message_id` TYPE TEXT - The id the provider gave the reminder
+//! - `reason` TYPE TEXT - Why no reminder is sent, e.g. `cancel_at_period_end`
+//! - `created_at` TYPE INT8 - The unix timestamp the period was settled at
+
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_period_reminders_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use supabase_rs::SupabaseClient;
+
+
+/// Renewal dates this close together belong to the same period, Stripe attempts the payment of a
+/// renewal up to an hour after the period ends
+pub const PERIOD_TOLERANCE_SECS: i64 = 86400;
+
+
+/// ## PeriodReminder
+/// How the reminder of a subscription period was settled
+///
+/// ### Fields
+/// - `id` - The row id, `None` until the reminder is inserted
+/// - `organization` - The name of the Organization that settled the period
+/// - `email` - The address of the customer
+/// - `template` - The email that was sent, `renewal_reminder` or `trial_ending`
+/// - `period_end` - The unix timestamp the period ends at
+/// - `source` - The id of the Stripe event, or `scan`
+/// - `status` - `sent` or `skipped`
+/// - `message_id` - The id the provider gave the reminder
+/// - `reason` - Why no reminder is sent
+/// - `created_at` - The unix timestamp the period was settled at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodReminder {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub organization: String,
+    pub email: String,
+    pub template: String,
+    pub period_end: i64,
+    pub source: String,
+    pub status: String,
+    pub message_id: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: i64,
+}
+
+
+impl PeriodReminder {
+    /// # insert
+    /// Stores the reminder.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<PeriodReminder, Box<dyn Error>>`: The stored reminder with its row `id`.
+    pub async fn insert(
+        mut self,
+        supabase: SupabaseClient,
+    ) -> Result<PeriodReminder, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("period_reminder_insert");
+
+        let table_name: String = overwrite_stripe_period_reminders_table_name();
+
+        let row_id: String = supabase
+            .insert(&table_name, serde_json::to_value(&self)?)
+            .await?;
+
+        self.id = row_id.parse().ok();
+
+        Ok(self)
+    }
+
+
+    /// # find_for_period
+    /// Retrieves how the period of an address that ends at `period_end` was settled, within
+    /// [`PERIOD_TOLERANCE_SECS`].
+    ///
+    /// ## Arguments
+    /// - `email`: `&str` - The address of the customer
+    /// - `period_end`: `i64` - The unix timestamp the period ends at
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Option<PeriodReminder>, Box<dyn Error>>`: The reminder, `None` when the period is
+    ///   not settled yet.
+    pub async fn find_for_period(
+        email: &str,
+        period_end: i64,
+        supabase: SupabaseClient,
+    ) -> Result<Option<PeriodReminder>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("period_reminder_find_for_period");
+
+        let table_name: String = overwrite_stripe_period_reminders_table_name();
+
+        // supabase_rs keeps one filter per column, the upper bound is applied here
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("email", email)
+            .gte("period_end", &(period_end - PERIOD_TOLERANCE_SECS).to_string())
+            .execute()
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| serde_json::from_value::<PeriodReminder>(row).ok())
+            .find(|reminder| reminder.period_end <= period_end + PERIOD_TOLERANCE_SECS))
+    }
+}
@@ -0,0 +1,65 @@
+//! # Email quarantine database operations
+//!
+//! This module contains the database operations for the `stripe_email_quarantine` table, customer
+//! addresses that failed validation are kept here for review instead of being written to the
+//! customer data, see [address](../../../email/address/index.html).
+//!
+//! ## `stripe_email_quarantine` columns
+//! - `email` TYPE TEXT - The address exactly as Stripe sent it
+//! - `reason` TYPE TEXT - Why the address was not accepted
+//! - `event_id` TYPE TEXT - The id of the Stripe event that carried the address
+//! - `event_type` TYPE TEXT - The type of the Stripe event, e.g. `charge.succeeded`
+//! - `created_at` TYPE INT8 - The unix timestamp of the Stripe event
+
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_email_quarantine_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use supabase_rs::SupabaseClient;
+
+
+/// ## QuarantinedEmail
+/// A customer address that failed validation
+///
+/// ### Fields
+/// - `email` - The address exactly as Stripe sent it
+/// - `reason` - Why the address was not accepted
+/// - `event_id` - The id of the Stripe event
+/// - `event_type` - The type of the Stripe event
+/// - `created_at` - The unix timestamp of the Stripe event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedEmail {
+    pub email: String,
+    pub reason: String,
+    pub event_id: String,
+    pub event_type: String,
+    pub created_at: i64,
+}
+
+
+impl QuarantinedEmail {
+    /// # insert
+    /// Stores the address in the quarantine.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<String, Box<dyn Error>>`: The row `id` of the new entry or the database error.
+    pub async fn insert(
+        &self,
+        supabase: SupabaseClient,
+    ) -> Result<String, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("email_quarantine_insert");
+
+        let table_name: String = overwrite_stripe_email_quarantine_table_name();
+
+        let row_id: String = supabase
+            .insert(&table_name, serde_json::to_value(self)?)
+            .await?;
+
+        Ok(row_id)
+    }
+}
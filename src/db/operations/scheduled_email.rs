@@ -0,0 +1,353 @@
+//! # Scheduled email database operations
+//!
+//! This module contains the database operations for the `stripe_scheduled_emails` table, every
+//! step of a drip sequence gets a row when the sequence starts and is sent from it by the
+//! [scheduler](../../../background/scheduler/index.html), so pending emails survive restarts.
+//!
+//! ## `stripe_scheduled_emails` columns
+//! - `id` TYPE INT8 - The row id
+//! - `organization` TYPE TEXT - The name of the Organization that sends the email
+//! - `step` TYPE TEXT - The name of the drip step, e.g. `tips`
+//! - `email` TYPE TEXT - The address the email is sent to
+//! - `checkout_session_id` TYPE TEXT - The checkout session that started the sequence
+//! - `locale` TYPE TEXT - The locale the email is rendered in
+//! - `placeholders` TYPE JSONB - The values of the `{{Name}}` placeholders
+//! - `send_at` TYPE INT8 - The unix timestamp the email is due at
+//! - `status` TYPE TEXT - `pending`, `sent`, `failed`, `skipped` or `cancelled`
+//! - `message_id` TYPE TEXT - The id the provider gave the email
+//! - `reason` TYPE TEXT - Why the email was not sent, e.g. `charge.refunded`
+//! - `created_at` TYPE INT8 - The unix timestamp the email was scheduled at
+//! - `updated_at` TYPE INT8 - The unix timestamp of the last change
+
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_scheduled_emails_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use supabase_rs::SupabaseClient;
+
+
+/// ## ScheduledStatus
+/// Where a scheduled email is
+///
+/// ### Variants
+/// - `Pending` - Waiting for its `send_at`
+/// - `Sent` - Accepted by the provider
+/// - `Failed` - The provider did not accept it, see `reason`
+/// - `Skipped` - Its Organization or step is not configured anymore
+/// - `Cancelled` - The sequence was stopped, e.g. by a refund
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduledStatus {
+    Pending,
+    Sent,
+    Failed,
+    Skipped,
+    Cancelled,
+}
+
+
+impl FromStr for ScheduledStatus {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a string into a status
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::db::operations::scheduled_email::ScheduledStatus;
+    /// assert_eq!("cancelled".parse(), Ok(ScheduledStatus::Cancelled));
+    /// assert!("queued".parse::<ScheduledStatus>().is_err());
+    /// ```
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "pending" => Ok(ScheduledStatus::Pending),
+            "sent" => Ok(ScheduledStatus::Sent),
+            "failed" => Ok(ScheduledStatus::Failed),
+            "skipped" => Ok(ScheduledStatus::Skipped),
+            "cancelled" => Ok(ScheduledStatus::Cancelled),
+            _ => Err(format!("unknown scheduled email status `{}`", status)),
+        }
+    }
+}
+
+
+impl fmt::Display for ScheduledStatus {
+    /// ## To String
+    /// This will write the status as it is stored in the `status` column
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScheduledStatus::Pending => "pending",
+            ScheduledStatus::Sent => "sent",
+            ScheduledStatus::Failed => "failed",
+            ScheduledStatus::Skipped => "skipped",
+            ScheduledStatus::Cancelled => "cancelled",
+        })
+    }
+}
+
+
+/// ## ScheduledEmail
+/// A step of a drip sequence as stored in the scheduled emails
+///
+/// ### Fields
+/// - `id` - The row id, `None` until the email is inserted
+/// - `organization` - The name of the Organization that sends the email
+/// - `step` - The name of the drip step
+/// - `email` - The address the email is sent to
+/// - `checkout_session_id` - The checkout session that started the sequence
+/// - `locale` - The locale the email is rendered in
+/// - `placeholders` - The values of the `{{Name}}` placeholders
+/// - `send_at` - The unix timestamp the email is due at
+/// - `status` - See [`ScheduledStatus`]
+/// - `message_id` - The id the provider gave the email
+/// - `reason` - Why the email was not sent
+/// - `created_at` - The unix timestamp the email was scheduled at
+/// - `updated_at` - The unix timestamp of the last change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledEmail {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub organization: String,
+    pub step: String,
+    pub email: String,
+    pub checkout_session_id: String,
+    pub locale: String,
+    #[serde(default)]
+    pub placeholders: HashMap<String, String>,
+    pub send_at: i64,
+    pub status: String,
+    pub message_id: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+
+impl ScheduledEmail {
+    /// # pending
+    /// Creates a step that is due at `send_at`.
+    ///
+    /// ## Arguments
+    /// - `organization`: `&str` - The name of the Organization that sends the email
+    /// - `step`: `&str` - The name of the drip step
+    /// - `email`: `&str` - The address the email is sent to
+    /// - `checkout_session_id`: `&str` - The checkout session that started the sequence
+    /// - `locale`: `&str` - The locale the email is rendered in
+    /// - `placeholders`: `HashMap<String, String>` - The values of the `{{Name}}` placeholders
+    /// - `send_at`: `i64` - The unix timestamp the email is due at
+    /// - `now`: `i64` - The current unix timestamp
+    #[allow(clippy::too_many_arguments)]
+    pub fn pending(
+        organization: &str,
+        step: &str,
+        email: &str,
+        checkout_session_id: &str,
+        locale: &str,
+        placeholders: HashMap<String, String>,
+        send_at: i64,
+        now: i64,
+    ) -> Self {
+        ScheduledEmail {
+            id: None,
+            organization: organization.to_string(),
+            step: step.to_string(),
+            email: email.to_string(),
+            checkout_session_id: checkout_session_id.to_string(),
+            locale: locale.to_string(),
+            placeholders,
+            send_at,
+            status: ScheduledStatus::Pending.to_string(),
+            message_id: None,
+            reason: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+
+    /// # insert
+    /// Stores the scheduled email.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<ScheduledEmail, Box<dyn Error>>`: The stored email with its row `id`.
+    pub async fn insert(
+        mut self,
+        supabase: SupabaseClient,
+    ) -> Result<ScheduledEmail, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("scheduled_email_insert");
+
+        let table_name: String = overwrite_stripe_scheduled_emails_table_name();
+
+        let row_id: String = supabase
+            .insert(&table_name, serde_json::to_value(&self)?)
+            .await?;
+
+        self.id = row_id.parse().ok();
+
+        Ok(self)
+    }
+
+
+    /// # exists_for_session
+    /// Whether a checkout session already started the sequence of an Organization, so a
+    /// replayed `checkout.session.completed` does not start it twice.
+    ///
+    /// ## Arguments
+    /// - `organization`: `&str` - The name of the Organization
+    /// - `checkout_session_id`: `&str` - The checkout session
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    pub async fn exists_for_session(
+        organization: &str,
+        checkout_session_id: &str,
+        supabase: SupabaseClient,
+    ) -> Result<bool, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("scheduled_email_exists_for_session");
+
+        let table_name: String = overwrite_stripe_scheduled_emails_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("organization", organization)
+            .eq("checkout_session_id", checkout_session_id)
+            .execute()
+            .await?;
+
+        Ok(!rows.is_empty())
+    }
+
+
+    /// # list_due
+    /// Retrieves the pending emails of an Organization that are due, the earliest first.
+    ///
+    /// ## Arguments
+    /// - `organization`: `&str` - The name of the Organization
+    /// - `now`: `i64` - The current unix timestamp
+    /// - `limit`: `usize` - The maximum number of emails
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    pub async fn list_due(
+        organization: &str,
+        now: i64,
+        limit: usize,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<ScheduledEmail>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("scheduled_email_list_due");
+
+        let table_name: String = overwrite_stripe_scheduled_emails_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("organization", organization)
+            .eq("status", &ScheduledStatus::Pending.to_string())
+            .lte("send_at", &now.to_string())
+            .execute()
+            .await?;
+
+        let mut due: Vec<ScheduledEmail> = rows
+            .into_iter()
+            .filter_map(|row| serde_json::from_value(row).ok())
+            .collect();
+
+        due.sort_by_key(|scheduled| scheduled.send_at);
+        due.truncate(limit);
+
+        Ok(due)
+    }
+
+
+    /// # record
+    /// Moves the email out of `pending`, with the message id it was sent with or why it was not.
+    ///
+    /// ## Arguments
+    /// - `status`: `ScheduledStatus` - The new status
+    /// - `message_id`: `Option<String>` - The id the provider gave the email
+    /// - `reason`: `Option<String>` - Why the email was not sent
+    /// - `now`: `i64` - The current unix timestamp
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    pub async fn record(
+        &mut self,
+        status: ScheduledStatus,
+        message_id: Option<String>,
+        reason: Option<String>,
+        now: i64,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("scheduled_email_record");
+
+        let Some(id) = self.id else {
+            return Err("the scheduled email was never inserted".into());
+        };
+
+        self.status = status.to_string();
+        self.message_id = message_id;
+        self.reason = reason;
+        self.updated_at = now;
+
+        let table_name: String = overwrite_stripe_scheduled_emails_table_name();
+
+        supabase
+            .update(&table_name, &id.to_string(), json!({
+                "status": self.status,
+                "message_id": self.message_id,
+                "reason": self.reason,
+                "updated_at": self.updated_at,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// # cancel_pending
+    /// Cancels the pending emails an Organization scheduled for an address.
+    ///
+    /// ## Arguments
+    /// - `organization`: `&str` - The name of the Organization
+    /// - `email`: `&str` - The address the emails are scheduled for
+    /// - `reason`: `&str` - What stopped the sequence, e.g. `charge.refunded`
+    /// - `now`: `i64` - The current unix timestamp
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<usize, Box<dyn Error>>`: The number of cancelled emails.
+    pub async fn cancel_pending(
+        organization: &str,
+        email: &str,
+        reason: &str,
+        now: i64,
+        supabase: SupabaseClient,
+    ) -> Result<usize, Box<dyn Error>> {
+        let table_name: String = overwrite_stripe_scheduled_emails_table_name();
+
+        let rows: Vec<Value> = {
+            let _timer: HistogramTimer = observe_db_operation("scheduled_email_list_pending");
+
+            supabase
+                .select(&table_name)
+                .eq("organization", organization)
+                .eq("email", email)
+                .eq("status", &ScheduledStatus::Pending.to_string())
+                .execute()
+                .await?
+        };
+
+        let mut cancelled: usize = 0;
+
+        for mut scheduled in rows.into_iter().filter_map(|row| serde_json::from_value::<ScheduledEmail>(row).ok()) {
+            scheduled
+                .record(ScheduledStatus::Cancelled, None, Some(reason.to_string()), now, supabase.clone())
+                .await?;
+
+            cancelled += 1;
+        }
+
+        Ok(cancelled)
+    }
+}
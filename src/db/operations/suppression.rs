@@ -0,0 +1,101 @@
+//! # Email suppression list database operations
+//!
+//! This module contains the database operations for the `stripe_email_suppressions` table, the
+//! addresses that hard-bounced or complained. Customer emails are not sent to them anymore, see
+//! [suppression](../../../email/suppression/index.html).
+//!
+//! ## `stripe_email_suppressions` columns
+//! - `email` TYPE TEXT - The normalized address
+//! - `reason` TYPE TEXT - `bounce` or `complaint`
+//! - `source` TYPE TEXT - The provider that reported it, `resend` or `ses`
+//! - `detail` TYPE TEXT - The bounce type or complaint feedback type the provider reported
+//! - `provider_event_id` TYPE TEXT - The id of the provider event, e.g. the Resend email id or SES feedback id
+//! - `created_at` TYPE INT8 - The unix timestamp the address was suppressed at
+
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_email_suppressions_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use supabase_rs::SupabaseClient;
+
+
+/// ## Suppression
+/// An address customer emails are no longer sent to
+///
+/// ### Fields
+/// - `email` - The normalized address
+/// - `reason` - `bounce` or `complaint`
+/// - `source` - The provider that reported it, `resend` or `ses`
+/// - `detail` - The bounce type or complaint feedback type the provider reported
+/// - `provider_event_id` - The id of the provider event
+/// - `created_at` - The unix timestamp the address was suppressed at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub source: String,
+    pub detail: String,
+    pub provider_event_id: String,
+    pub created_at: i64,
+}
+
+
+impl Suppression {
+    /// # insert
+    /// Adds the address to the suppression list, addresses that are already on it are kept as
+    /// they are so the first bounce or complaint stays recorded.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<bool, Box<dyn Error>>`: Whether the address was added, or the database error.
+    pub async fn insert(
+        &self,
+        supabase: SupabaseClient,
+    ) -> Result<bool, Box<dyn Error>> {
+        if Suppression::find(&self.email, supabase.clone()).await?.is_some() {
+            return Ok(false);
+        }
+
+        let _timer: HistogramTimer = observe_db_operation("suppression_insert");
+
+        let table_name: String = overwrite_stripe_email_suppressions_table_name();
+
+        supabase
+            .insert(&table_name, serde_json::to_value(self)?)
+            .await?;
+
+        Ok(true)
+    }
+
+
+    /// # find
+    /// Looks up an address on the suppression list.
+    ///
+    /// ## Arguments
+    /// - `email`: `&str` - The normalized address.
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Option<Suppression>, Box<dyn Error>>`: The suppression, `None` when the address is not suppressed.
+    pub async fn find(
+        email: &str,
+        supabase: SupabaseClient,
+    ) -> Result<Option<Suppression>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("suppression_find");
+
+        let table_name: String = overwrite_stripe_email_suppressions_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("email", email)
+            .execute()
+            .await?;
+
+        Ok(rows.into_iter().find_map(|row| serde_json::from_value(row).ok()))
+    }
+}
@@ -0,0 +1,406 @@
+//! # Webhook event log database operations
+//!
+//! This module contains the database operations for the `stripe_webhook_events` table, every
+//! webhook that passes signature verification is stored here before it is handled so it can be
+//! inspected and replayed later on.
+//!
+//! ## `stripe_webhook_events` columns
+//! - `id` TYPE INT8 - The row id
+//! - `event_id` TYPE TEXT UNIQUE - The id of the Stripe event, e.g. `evt_1P...`, the constraint is
+//!   what keeps two concurrent deliveries of the same event from both being handled
+//! - `event_type` TYPE TEXT - The type of the Stripe event, e.g. `charge.succeeded`
+//! - `raw_body` TYPE TEXT - The request body exactly as received
+//! - `headers` TYPE JSONB - The request headers, the `Stripe-Signature` included
+//! - `received_at` TYPE INT8 - The unix timestamp the webhook was first received at
+//! - `verified` TYPE BOOL - Whether the signature was verified, unsigned webhooks are rejected so
+//!   only events stored by earlier versions can be `false`
+//! - `customer_id` TYPE TEXT - The charge the event is about, e.g. `ch_3P...`
+//! - `customer_email` TYPE TEXT - The lowercase email of the customer the event carries
+//! - `attempts` TYPE INT8 - How often the event was processed, replays included
+//! - `outcome` TYPE TEXT - `pending`, `handled`, `ignored` or `failed`
+//! - `last_error` TYPE TEXT - The error of the last failed attempt
+//! - `processed_at` TYPE INT8 - The unix timestamp of the last attempt
+
+use crate::metrics::observe_db_operation;
+use crate::overwrite::overwrite_stripe_webhook_events_table_name;
+
+use prometheus::HistogramTimer;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use supabase_rs::query::{Query, QueryBuilder};
+use supabase_rs::SupabaseClient;
+
+
+/// ## WebhookOutcome
+/// How processing a stored webhook ended
+///
+/// ### Variants
+/// - `Pending` - Stored but not processed yet
+/// - `Handled` - Processed by one of the event handlers
+/// - `Ignored` - An event type we do not handle
+/// - `Failed` - The handler failed, see `last_error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WebhookOutcome {
+    #[default]
+    Pending,
+    Handled,
+    Ignored,
+    Failed,
+}
+
+
+impl FromStr for WebhookOutcome {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a string into an outcome
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::db::operations::webhook_event::WebhookOutcome;
+    /// assert_eq!("failed".parse(), Ok(WebhookOutcome::Failed));
+    /// assert!("lost".parse::<WebhookOutcome>().is_err());
+    /// ```
+    fn from_str(outcome: &str) -> Result<Self, Self::Err> {
+        match outcome {
+            "pending" => Ok(WebhookOutcome::Pending),
+            "handled" => Ok(WebhookOutcome::Handled),
+            "ignored" => Ok(WebhookOutcome::Ignored),
+            "failed" => Ok(WebhookOutcome::Failed),
+            _ => Err(format!("unknown outcome `{}`, expected `pending`, `handled`, `ignored` or `failed`", outcome)),
+        }
+    }
+}
+
+
+impl fmt::Display for WebhookOutcome {
+    /// ## To String
+    /// This will write the outcome as it is stored in the `outcome` column
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WebhookOutcome::Pending => "pending",
+            WebhookOutcome::Handled => "handled",
+            WebhookOutcome::Ignored => "ignored",
+            WebhookOutcome::Failed => "failed",
+        })
+    }
+}
+
+
+/// ## WebhookEvent
+/// A received Stripe webhook as stored in the event log
+///
+/// ### Fields
+/// - `id` - The row id, `None` until the event is inserted
+/// - `event_id` - The id of the Stripe event
+/// - `event_type` - The type of the Stripe event
+/// - `raw_body` - The request body exactly as received
+/// - `headers` - The request headers as a JSON object
+/// - `received_at` - The unix timestamp the webhook was first received at
+/// - `verified` - Whether the signature was verified
+/// - `customer_id` - The charge the event is about, `None` for events without one
+/// - `customer_email` - The email of the customer, `None` for events without one
+/// - `attempts` - How often the event was processed
+/// - `outcome` - `pending`, `handled`, `ignored` or `failed`
+/// - `last_error` - The error of the last failed attempt
+/// - `processed_at` - The unix timestamp of the last attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub event_id: String,
+    pub event_type: String,
+    pub raw_body: String,
+    pub headers: Value,
+    pub received_at: i64,
+    pub verified: bool,
+    #[serde(default)]
+    pub customer_id: Option<String>,
+    #[serde(default)]
+    pub customer_email: Option<String>,
+    pub attempts: i64,
+    pub outcome: String,
+    pub last_error: Option<String>,
+    pub processed_at: Option<i64>,
+}
+
+
+impl WebhookEvent {
+    /// # new
+    /// Creates a pending event from a received webhook, the id, type and customer are read from
+    /// the body.
+    ///
+    /// ## Arguments
+    /// - `raw_body`: `String` - The request body exactly as received
+    /// - `headers`: `Value` - The request headers as a JSON object
+    /// - `received_at`: `i64` - The unix timestamp the webhook was received at
+    ///
+    /// ## Errors
+    /// - When the body has no string `id`, events are deduplicated by it
+    pub fn new(
+        raw_body: String,
+        headers: Value,
+        received_at: i64,
+    ) -> Result<Self, String> {
+        let payload: Value = serde_json::from_str(&raw_body).unwrap_or(Value::Null);
+
+        let event_id: String = payload["id"]
+            .as_str()
+            .ok_or("Webhook body has no event id".to_string())?
+            .to_string();
+        let event_type: String = payload["type"].as_str().unwrap_or("unknown").to_string();
+        let object: &Value = &payload["data"]["object"];
+
+        // charges carry their own id, refunds and disputes the id of the charge
+        let customer_id: Option<String> = match object["object"].as_str() {
+            Some("charge") => object["id"].as_str(),
+            _ => object["charge"].as_str(),
+        }
+            .map(str::to_string);
+
+        let customer_email: Option<String> = [
+            &object["billing_details"]["email"],
+            &object["customer_details"]["email"],
+            &object["customer_email"],
+            &object["receipt_email"],
+            &object["metadata"]["email"],
+        ]
+            .into_iter()
+            .find_map(|email| email.as_str())
+            .map(|email| email.trim().to_lowercase())
+            .filter(|email| !email.is_empty());
+
+        Ok(WebhookEvent {
+            id: None,
+            event_id,
+            event_type,
+            raw_body,
+            headers,
+            received_at,
+            verified: true,
+            customer_id,
+            customer_email,
+            attempts: 0,
+            outcome: WebhookOutcome::Pending.to_string(),
+            last_error: None,
+            processed_at: None,
+        })
+    }
+
+
+    /// # payload
+    /// Parses the stored raw body back into the Stripe event.
+    pub fn payload(&self) -> Result<Value, Box<dyn Error>> {
+        Ok(serde_json::from_str(&self.raw_body)?)
+    }
+
+
+    /// # insert
+    /// Stores the event, the unique `event_id` makes the insert fail with a conflict when the same
+    /// event was stored before, even by a delivery that is still running.
+    ///
+    /// ## Arguments
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Option<WebhookEvent>, Box<dyn Error>>`: The stored event with its row `id`, `None`
+    ///   when the event is a duplicate.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let stored: Option<WebhookEvent> = WebhookEvent::new(raw_body, headers, unix_now())?
+    ///     .insert(supabase)
+    ///     .await?;
+    /// ```
+    pub async fn insert(
+        mut self,
+        supabase: SupabaseClient,
+    ) -> Result<Option<WebhookEvent>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("webhook_event_insert");
+
+        let table_name: String = overwrite_stripe_webhook_events_table_name();
+
+        match supabase.insert(&table_name, serde_json::to_value(&self)?).await {
+            Ok(row_id) => {
+                self.id = row_id.parse().ok();
+
+                Ok(Some(self))
+            },
+            // PostgREST answers a unique violation with `409 Conflict`
+            Err(error) if error.contains("409") => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+
+    /// # get_by_event_id
+    /// Retrieves a stored event by its Stripe event id.
+    ///
+    /// ## Arguments
+    /// - `event_id`: `&str` - The id of the Stripe event, e.g. `evt_1P...`
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Option<WebhookEvent>, Box<dyn Error>>`: The event, `None` when it was never stored.
+    pub async fn get_by_event_id(
+        event_id: &str,
+        supabase: SupabaseClient,
+    ) -> Result<Option<WebhookEvent>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("webhook_event_get_by_event_id");
+
+        let table_name: String = overwrite_stripe_webhook_events_table_name();
+
+        let rows: Vec<Value> = supabase
+            .select(&table_name)
+            .eq("event_id", event_id)
+            .execute()
+            .await?;
+
+        let event: Option<WebhookEvent> = rows
+            .into_iter()
+            .find_map(|row| serde_json::from_value(row).ok());
+
+        Ok(event)
+    }
+
+
+    /// # list
+    /// Retrieves the most recently received events, optionally filtered.
+    ///
+    /// ## Arguments
+    /// - `event_type`: `Option<&str>` - Only list events of this type
+    /// - `outcome`: `Option<WebhookOutcome>` - Only list events with this outcome
+    /// - `limit`: `usize` - The maximum number of events
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    ///
+    /// ## Returns
+    /// - `Result<Vec<WebhookEvent>, Box<dyn Error>>`: The events, newest first.
+    pub async fn list(
+        event_type: Option<&str>,
+        outcome: Option<WebhookOutcome>,
+        limit: usize,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<WebhookEvent>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("webhook_event_list");
+
+        let table_name: String = overwrite_stripe_webhook_events_table_name();
+        let outcome: Option<String> = outcome.map(|outcome| outcome.to_string());
+
+        let mut query: QueryBuilder = supabase.select(&table_name);
+
+        if let Some(event_type) = event_type {
+            query = query.eq("event_type", event_type);
+        }
+
+        if let Some(outcome) = &outcome {
+            query = query.eq("outcome", outcome);
+        }
+
+        let rows: Vec<Value> = query.execute().await?;
+
+        let mut events: Vec<WebhookEvent> = rows
+            .into_iter()
+            .filter_map(|row| serde_json::from_value(row).ok())
+            .collect();
+
+        events.sort_by_key(|event| std::cmp::Reverse(event.received_at));
+        events.truncate(limit);
+
+        Ok(events)
+    }
+
+
+    /// # list_for_customer
+    /// Lists the most recently received events about a customer, by its charge id or its email.
+    /// Both are filtered and limited in the database.
+    ///
+    /// ## Arguments
+    /// - `customer_id`: `&str` - The charge id of the customer
+    /// - `email`: `&str` - The email of the customer, skipped when empty
+    /// - `limit`: `usize` - The maximum number of events returned
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+    ///
+    /// ## Returns
+    /// - `Result<Vec<WebhookEvent>, Box<dyn Error>>`: The events, newest first, or the database error
+    pub async fn list_for_customer(
+        customer_id: &str,
+        email: &str,
+        limit: usize,
+        supabase: SupabaseClient,
+    ) -> Result<Vec<WebhookEvent>, Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("webhook_event_list_for_customer");
+
+        let table_name: String = overwrite_stripe_webhook_events_table_name();
+        let email: String = email.trim().to_lowercase();
+        let mut events: Vec<WebhookEvent> = Vec::new();
+
+        for (column, value) in [("customer_id", customer_id), ("customer_email", email.as_str())] {
+            if value.is_empty() {
+                continue;
+            }
+
+            // the query builder has no ordering or limit, the query is built by hand
+            let mut query: Query = Query::new();
+            query.add_param(column, &format!("eq.{}", value));
+            query.add_param("order", "received_at.desc");
+            query.add_param("limit", &limit.to_string());
+
+            let rows: Vec<Value> = supabase.execute(&table_name, &query.build()).await?;
+
+            for event in rows.into_iter().filter_map(|row| serde_json::from_value::<WebhookEvent>(row).ok()) {
+                if !events.iter().any(|other| other.event_id == event.event_id) {
+                    events.push(event);
+                }
+            }
+        }
+
+        events.sort_by_key(|event| std::cmp::Reverse(event.received_at));
+        events.truncate(limit);
+
+        Ok(events)
+    }
+
+
+    /// # record_attempt
+    /// Counts a processing attempt and stores its outcome.
+    ///
+    /// ## Arguments
+    /// - `outcome`: `WebhookOutcome` - How the attempt ended
+    /// - `error`: `Option<String>` - The error when the attempt failed
+    /// - `processed_at`: `i64` - The unix timestamp of the attempt
+    /// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database.
+    pub async fn record_attempt(
+        &mut self,
+        outcome: WebhookOutcome,
+        error: Option<String>,
+        processed_at: i64,
+        supabase: SupabaseClient,
+    ) -> Result<(), Box<dyn Error>> {
+        let _timer: HistogramTimer = observe_db_operation("webhook_event_record_attempt");
+
+        self.attempts += 1;
+        self.outcome = outcome.to_string();
+        self.last_error = error;
+        self.processed_at = Some(processed_at);
+
+        let Some(id) = self.id else {
+            return Err("the webhook event was never inserted".into());
+        };
+
+        let table_name: String = overwrite_stripe_webhook_events_table_name();
+
+        supabase
+            .update(&table_name, &id.to_string(), json!({
+                "attempts": self.attempts,
+                "outcome": self.outcome,
+                "last_error": self.last_error,
+                "processed_at": self.processed_at,
+            }))
+            .await?;
+
+        Ok(())
+    }
+}
@@ -4,7 +4,7 @@
 //!
 //! ### I don't want to make a Supabase account
 //! - Supabase also offers self-hosting, this is also possible via this crate which you can host
-//! for less than 12$ a month on DigitalOcean
+//!   for less than 12$ a month on DigitalOcean
 //!
 //!
 //!
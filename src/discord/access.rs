@@ -0,0 +1,381 @@
+//! ## Time-boxed access
+//!
+//! A one-off payment for a product under `Entitlement.Products` grants access for its
+//! `AccessDays` instead of for good. `checkout.session.completed` stores the `access_start_time`
+//! and `access_end_time` of the access on the customer and marks its `access_status` as `active`,
+//! buying again while the access is active extends `access_end_time` by another period.
+//! `charge.succeeded` does the same for charges that were not made through Checkout, see
+//! [`grant_charge_access`].
+//!
+//! The access has its own `access_` columns rather than `start_time` and `end_time`, those hold
+//! the current period of a subscription and are scanned for renewal and trial reminders. A
+//! customer with a subscription and a pass would otherwise have one overwrite the other, and pass
+//! holders would be reminded of a renewal that never comes.
+//!
+//! Once `access_end_time` has passed the [scheduler](../../background/scheduler/index.html)
+//! revokes the role under `DISCORD_ROLE_ID` (see [roles](../roles/index.html)), sets `paid=false`
+//! and marks the access `expired`. Subscriptions keep their `paid` status, only customers with an
+//! `access_status` expire, and a customer whose subscription period has not ended keeps the role.
+//! The role is revoked with the guild, role and bot of the Organization that granted the access,
+//! see [`Organization::discord_roles`](../../struct.Organization.html#method.discord_roles).
+//!
+//! Products are matched by the price or product of the line items of the checkout session, the
+//! line items are retrieved from `STRIPE_API_URL` when the event does not carry them. A charge is
+//! matched by the lines of its invoice, or by the `price` or `product` in its `metadata` when it
+//! has no invoice.
+//!
+//! ### Usage example
+//! ```yaml
+//! Entitlement:
+//!   Products:
+//!     - Price: price_1PthirtyDayPass
+//!       AccessDays: 30
+//!     - Product: prod_QweekendPass
+//!       AccessDays: 3
+//! ```
+
+use crate::api::client::{fetch_checkout_sessions, fetch_invoice_lines, fetch_line_items};
+use crate::discord::roles::DiscordRoles;
+use crate::log::redact::redact_email;
+use crate::overwrite::{
+    overwrite_stripe_customer_access_end_time_column_name,
+    overwrite_stripe_customer_access_organization_column_name,
+    overwrite_stripe_customer_access_start_time_column_name,
+    overwrite_stripe_customer_access_status_column_name,
+    overwrite_stripe_customer_end_time_column_name,
+    overwrite_stripe_email_column_name,
+};
+use crate::secrets::Secret;
+use crate::CustomerId;
+use crate::Organization;
+
+use serde_json::{json, Value};
+use std::time::Duration;
+use supabase_rs::SupabaseClient;
+use tracing::{error, info, warn};
+
+
+/// The `access_status` of a customer whose time-boxed access has not ended yet
+pub const ACCESS_ACTIVE: &str = "active";
+
+/// The `access_status` of a customer whose time-boxed access has ended and was revoked
+pub const ACCESS_EXPIRED: &str = "expired";
+
+
+/// ## AccessProduct
+/// A product that grants access for a limited time
+///
+/// ### Fields
+/// - `id` - The Stripe price (`price_...`) or product (`prod_...`) id
+/// - `duration` - How long one purchase grants access for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessProduct {
+    pub id: String,
+    pub duration: Duration,
+}
+
+
+impl AccessProduct {
+    /// # new
+    /// Creates a product that grants access for `duration` per purchase.
+    pub fn new(id: &str, duration: Duration) -> Self {
+        AccessProduct { id: id.to_string(), duration }
+    }
+
+    /// # matches
+    /// Whether a Stripe price is this product, by the id of the price or of its product.
+    pub fn matches(&self, price: &Value) -> bool {
+        let product: Option<&str> = price["product"]
+            .as_str()
+            .or(price["product"]["id"].as_str());
+
+        price["id"].as_str() == Some(self.id.as_str()) || product == Some(self.id.as_str())
+    }
+}
+
+
+/// # access_duration
+/// The access the line items of a checkout session grant, the duration of every matching item
+/// times its quantity.
+///
+/// ## Arguments
+/// - `line_items`: `&Value` - The `line_items` list of a checkout session
+/// - `products`: `&[AccessProduct]` - The products that grant time-boxed access
+///
+/// ## Returns
+/// - `Option<Duration>`: `None` when no item is a time-boxed product
+pub fn access_duration(line_items: &Value, products: &[AccessProduct]) -> Option<Duration> {
+    line_items["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let product: &AccessProduct = products.iter().find(|product| product.matches(&item["price"]))?;
+            let quantity: u32 = item["quantity"].as_u64().unwrap_or(1) as u32;
+
+            Some(product.duration * quantity)
+        })
+        .reduce(|total, duration| total + duration)
+}
+
+
+/// # grant_access
+/// Grants the access a checkout session bought, starting at `created_at` or at the end of the
+/// access the customer still has.
+///
+/// ## Arguments
+/// - `session`: `&Value` - The `data.object` of the `checkout.session.completed` event
+/// - `email`: `&str` - The validated address of the customer
+/// - `created_at`: `i64` - The unix timestamp of the purchase
+/// - `organization`: `&Organization` - The Organization the checkout belongs to
+/// - `supabase`: `&SupabaseClient` - The client the access is stored with
+///
+/// ## Returns
+/// - `Option<Result<i64, String>>`: `None` when nothing bought is a time-boxed product, otherwise
+///   the new `end_time` or why the access could not be stored.
+pub async fn grant_access(
+    session: &Value,
+    email: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Option<Result<i64, String>> {
+    if organization.access_products.is_empty() {
+        return None;
+    }
+
+    let line_items: Value = match session_line_items(session, organization).await {
+        Ok(line_items) => line_items,
+        Err(error) => return Some(Err(format!("failed to retrieve the line items: {}", error))),
+    };
+
+    let duration: Duration = access_duration(&line_items, &organization.access_products)?;
+
+    Some(extend_access(email, duration, created_at, &organization.name, supabase).await.map_err(|error| error.to_string()))
+}
+
+
+/// # grant_charge_access
+/// Grants the access a charge bought that was not made through Checkout, like the payment of a
+/// one-off invoice or a payment intent created through the API. Charges of a checkout session are
+/// granted by `checkout.session.completed` instead, so a purchase is never counted twice.
+///
+/// ## Arguments
+/// - `charge`: `&Value` - The `data.object` of the `charge.succeeded` event
+/// - `email`: `&str` - The validated address of the customer
+/// - `created_at`: `i64` - The unix timestamp of the purchase
+/// - `organization`: `&Organization` - The Organization the charge belongs to
+/// - `supabase`: `&SupabaseClient` - The client the access is stored with
+///
+/// ## Returns
+/// - `Option<Result<i64, String>>`: `None` when the charge came from Checkout or nothing it paid
+///   for is a time-boxed product, otherwise the new `end_time` or why the access could not be stored.
+pub async fn grant_charge_access(
+    charge: &Value,
+    email: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Option<Result<i64, String>> {
+    if organization.access_products.is_empty() {
+        return None;
+    }
+
+    let line_items: Value = match charge_line_items(charge, organization).await {
+        Ok(Some(line_items)) => line_items,
+        Ok(None) => return None,
+        Err(error) => return Some(Err(format!("failed to retrieve the line items: {}", error))),
+    };
+
+    let duration: Duration = access_duration(&line_items, &organization.access_products)?;
+
+    Some(extend_access(email, duration, created_at, &organization.name, supabase).await.map_err(|error| error.to_string()))
+}
+
+
+/// # charge_line_items
+/// What a charge paid for as line items, the lines of its invoice or the `price` and `product` of
+/// its `metadata`. `None` for charges of a checkout session.
+async fn charge_line_items(charge: &Value, organization: &Organization) -> Result<Option<Value>, String> {
+    let api_key: Secret = organization.stripe_api_key().map_err(|error| error.to_string())?;
+
+    if let Some(payment_intent) = charge["payment_intent"].as_str() {
+        let sessions: Value = fetch_checkout_sessions(payment_intent, &api_key).await?;
+
+        if sessions["data"].as_array().is_some_and(|sessions| !sessions.is_empty()) {
+            return Ok(None);
+        }
+    }
+
+    if let Some(invoice_id) = charge["invoice"].as_str().or(charge["invoice"]["id"].as_str()) {
+        return fetch_invoice_lines(invoice_id, &api_key).await.map(Some);
+    }
+
+    let metadata: &Value = &charge["metadata"];
+
+    if metadata["price"].is_null() && metadata["product"].is_null() {
+        return Ok(None);
+    }
+
+    Ok(Some(json!({
+        "data": [{ "price": { "id": metadata["price"], "product": metadata["product"] }, "quantity": 1 }],
+    })))
+}
+
+
+/// # session_line_items
+/// The line items of a checkout session, retrieved from Stripe when they are not expanded.
+async fn session_line_items(session: &Value, organization: &Organization) -> Result<Value, String> {
+    if session["line_items"]["data"].is_array() {
+        return Ok(session["line_items"].clone());
+    }
+
+    let session_id: &str = session["id"]
+        .as_str()
+        .ok_or("the checkout session has no id".to_string())?;
+
+    let api_key: Secret = organization.stripe_api_key().map_err(|error| error.to_string())?;
+
+    fetch_line_items(session_id, &api_key).await
+}
+
+
+/// # extend_access
+/// Stores the access of the customer, `duration` past the end of the access that is still
+/// active or past `now` otherwise. Customers that bought before their charge arrived get a record,
+/// the access is read from and written to the same record along with the Organization that granted
+/// it.
+async fn extend_access(
+    email: &str,
+    duration: Duration,
+    now: i64,
+    organization: &str,
+    supabase: &SupabaseClient,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let column_name_access_start_time: String = overwrite_stripe_customer_access_start_time_column_name();
+    let column_name_access_end_time: String = overwrite_stripe_customer_access_end_time_column_name();
+    let column_name_access_status: String = overwrite_stripe_customer_access_status_column_name();
+
+    let mut record: Option<Value> = customer_record(email, supabase).await?;
+
+    if record.is_none() {
+        CustomerId::new_from_email(email.to_string(), true, supabase.clone()).await?;
+        record = customer_record(email, supabase).await?;
+    }
+
+    let record: Value = record.ok_or(format!("no customer record was created for {}", redact_email(email)))?;
+
+    let row_id: String = record["id"]
+        .as_i64()
+        .map(|row_id| row_id.to_string())
+        .ok_or("the customer record has no id")?;
+
+    // the end of the access that has not ended yet, a repeat purchase stacks on top of it
+    let active_end: Option<i64> = Some(&record)
+        .filter(|record| record[&column_name_access_status].as_str() == Some(ACCESS_ACTIVE))
+        .and_then(|record| record[&column_name_access_end_time].as_i64())
+        .filter(|end_time| *end_time > now);
+
+    let start_time: i64 = match active_end {
+        Some(_) => record[&column_name_access_start_time].as_i64().unwrap_or(now),
+        None => now,
+    };
+
+    let end_time: i64 = active_end.unwrap_or(now).saturating_add(duration.as_secs() as i64);
+
+    CustomerId::update_access(&row_id, start_time, end_time, organization, supabase.clone()).await?;
+
+    Ok(end_time)
+}
+
+
+/// # customer_record
+/// The first customer record of an address.
+async fn customer_record(email: &str, supabase: &SupabaseClient) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+    Ok(CustomerId::search(Some(email), None, 1, supabase.clone())
+        .await?
+        .into_iter()
+        .next())
+}
+
+
+/// # granted_by
+/// The Organization that granted the time-boxed access of a customer record, `None` for customers
+/// without access or whose Organization is not among `organizations`.
+pub fn granted_by<'o>(customer: &Value, organizations: &'o [Organization]) -> Option<&'o Organization> {
+    let column_name_access_organization: String = overwrite_stripe_customer_access_organization_column_name();
+    let name: &str = customer[&column_name_access_organization].as_str()?;
+
+    organizations.iter().find(|organization| organization.name == name)
+}
+
+
+/// # expire_ended_access
+/// Revokes the role of every customer whose access ended at or before `now`, then marks the
+/// access `expired` and the customer unpaid. The role is revoked with the Discord settings of the
+/// Organization that granted the access, the environment when it is not among `organizations`.
+/// Customers whose role could not be revoked stay active and are attempted again by the next run,
+/// without a bot the access expires without revoking a role. Customers whose subscription period
+/// ends after `now` keep the role and stay paid.
+///
+/// ## Arguments
+/// - `organizations`: `&[Organization]` - The Organizations that may have granted the access
+/// - `now`: `i64` - The unix timestamp access has ended by
+/// - `supabase`: `&SupabaseClient` - The client the customers are read with
+///
+/// ## Returns
+/// - `Result<usize, String>`: The number of customers whose access expired, or why the customers
+///   could not be read.
+pub async fn expire_ended_access(
+    organizations: &[Organization],
+    now: i64,
+    supabase: &SupabaseClient,
+) -> Result<usize, String> {
+    let customers: Vec<Value> = CustomerId::list_access_ended(now, supabase.clone())
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if customers.is_empty() {
+        return Ok(0);
+    }
+
+    let column_name_email: String = overwrite_stripe_email_column_name();
+    let column_name_end_time: String = overwrite_stripe_customer_end_time_column_name();
+    let mut expired: usize = 0;
+
+    for customer in customers {
+        let email: &str = customer[&column_name_email].as_str().unwrap_or_default();
+
+        let Some(row_id) = customer["id"].as_i64().map(|row_id| row_id.to_string()) else {
+            continue;
+        };
+
+        // the role stays with a subscription whose period has not ended yet
+        let subscribed: bool = customer[&column_name_end_time].as_i64().is_some_and(|end_time| end_time > now);
+
+        let roles: Option<DiscordRoles> = match granted_by(&customer, organizations).map_or_else(DiscordRoles::from_env, Organization::discord_roles) {
+            Ok(roles) => Some(roles),
+            Err(error) => {
+                warn!(email = %redact_email(email), %error, "Time-boxed access expires without revoking the Discord role");
+                None
+            },
+        };
+
+        if let (false, Some(roles), Some(discord_user_id)) = (subscribed, roles, CustomerId::discord_user_id(&customer)) {
+            if let Err(error) = roles.sync_member(&discord_user_id, false).await {
+                warn!(email = %redact_email(email), %discord_user_id, %error, "Failed to revoke the Discord role of ended access");
+                continue;
+            }
+        }
+
+        match CustomerId::expire_access(&row_id, subscribed, supabase.clone()).await {
+            Ok(()) => {
+                info!(email = %redact_email(email), "Time-boxed access expired");
+                expired += 1;
+            },
+            Err(error) => error!(email = %redact_email(email), %error, "Failed to mark the access as expired"),
+        }
+    }
+
+    Ok(expired)
+}
@@ -1,6 +1,13 @@
 //! ## Discord Oath2 integration
 //!
 //! ### Table of contents
+//! - [access](access/index.html) - Expiring the paid role of time-boxed purchases
+//! - [client](client/index.html) - Authenticated calls to the Discord REST API
+//! - [roles](roles/index.html) - Granting and revoking the paid role
+//! - [snowflake](snowflake/index.html) - Parsing and checking Discord ids
 
+pub mod access;
 pub mod client;
 pub mod request_builder;
+pub mod roles;
+pub mod snowflake;
@@ -0,0 +1,107 @@
+//! ## Syncing the paid role of customers
+//!
+//! Customers that linked their Discord account have their Discord user id stored on their customer
+//! record, the bot grants them the role under `DISCORD_ROLE_ID` in the guild under
+//! `DISCORD_GUILD_ID` while they are paid and revokes it once they are not. Endpoints from the
+//! config carry their own guild, role and bot token, see [`DiscordRoles::from_endpoint`].
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let roles: DiscordRoles = DiscordRoles::from_env()?;
+//! let sync: RoleSync = roles.sync_member("80351110224678912", paid).await?;
+//! ```
+
+use crate::discord::client::DiscordClient;
+use crate::secrets::Secret;
+use crate::EndpointConfigStripe;
+
+use dotenv::dotenv;
+use serde_derive::Serialize;
+use std::env::var;
+
+
+/// ## RoleSync
+/// What a role sync did to the member
+///
+/// ### Variants
+/// - `Granted` - The member is paid and has the role
+/// - `Revoked` - The member is not paid and no longer has the role
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleSync {
+    Granted,
+    Revoked,
+}
+
+
+/// ## DiscordRoles
+/// The bot and the guild role paid customers receive
+///
+/// ### Fields
+/// - `client` - The bot client
+/// - `guild_id` - The snowflake of the guild
+/// - `role_id` - The snowflake of the role paid customers receive
+#[derive(Debug, Clone)]
+pub struct DiscordRoles {
+    pub client: DiscordClient,
+    pub guild_id: String,
+    pub role_id: String,
+}
+
+
+impl DiscordRoles {
+    /// # from_env
+    /// Creates the role syncer from `DISCORD_BOT_TOKEN`, `DISCORD_GUILD_ID` and `DISCORD_ROLE_ID`.
+    ///
+    /// ## Errors
+    /// - `String` - Which of the variables is not set
+    pub fn from_env() -> Result<Self, String> {
+        dotenv().ok();
+
+        let client: DiscordClient = DiscordClient::from_env()?;
+        let guild_id: String = var("DISCORD_GUILD_ID").map_err(|_| "DISCORD_GUILD_ID is not set".to_string())?;
+        let role_id: String = var("DISCORD_ROLE_ID").map_err(|_| "DISCORD_ROLE_ID is not set".to_string())?;
+
+        Ok(DiscordRoles { client, guild_id, role_id })
+    }
+
+    /// # from_endpoint
+    /// Creates the role syncer from the Discord ids and bot token of an endpoint.
+    ///
+    /// ## Errors
+    /// - `String` - The endpoint syncs no role or its bot token can not be resolved
+    pub fn from_endpoint(endpoint: &EndpointConfigStripe) -> Result<Self, String> {
+        if endpoint.discord_guild_id == 0 || endpoint.discord_role_id == 0 {
+            return Err(format!("the endpoint {} syncs no Discord role", endpoint.endpoint_route));
+        }
+
+        let bot_token: Secret = endpoint.discord_bot_token().map_err(|error| error.to_string())?;
+
+        Ok(DiscordRoles {
+            client: DiscordClient::new(bot_token.expose().to_string()),
+            guild_id: endpoint.discord_guild_id.to_string(),
+            role_id: endpoint.discord_role_id.to_string(),
+        })
+    }
+
+    /// # sync_member
+    /// Grants the role to a paid member and revokes it from an unpaid one.
+    ///
+    /// ## Arguments
+    /// - `user_id`: `&str` - The snowflake of the Discord user
+    /// - `paid`: `bool` - The paid status of the customer
+    ///
+    /// ## Errors
+    /// - `String` - The error Discord answered with
+    pub async fn sync_member(&self, user_id: &str, paid: bool) -> Result<RoleSync, String> {
+        if paid {
+            self.client.add_member_role(&self.guild_id, user_id, &self.role_id).await?;
+
+            return Ok(RoleSync::Granted);
+        }
+
+        self.client.remove_member_role(&self.guild_id, user_id, &self.role_id).await?;
+
+        Ok(RoleSync::Revoked)
+    }
+}
@@ -0,0 +1,48 @@
+//! ## Discord snowflakes
+//!
+//! Discord ids are snowflakes, 64 bit integers whose upper 42 bits are the milliseconds since the
+//! Discord epoch (the first second of 2015). YAML users tend to write them as strings to dodge
+//! float rounding, so both strings and integers are accepted.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let guild_id: i64 = parse_snowflake(&json!("81384788765712384"))?;
+//! ```
+
+use crate::events::signature::unix_now;
+
+use serde_json::Value;
+
+
+/// The unix timestamp in milliseconds snowflake timestamps count from
+pub const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+
+/// # parse_snowflake
+/// Reads a snowflake from a string or integer and checks its timestamp lies between the Discord
+/// epoch and now.
+///
+/// ## Arguments
+/// - `value`: `&Value` - The id, e.g. `"81384788765712384"`
+///
+/// ## Errors
+/// - `String` - Why the value is not a snowflake
+pub fn parse_snowflake(value: &Value) -> Result<i64, String> {
+    let id: i64 = match value {
+        Value::String(id) if !id.is_empty() && id.bytes().all(|byte| byte.is_ascii_digit()) => id
+            .parse()
+            .map_err(|_| format!("`{}` is too large for a snowflake", id))?,
+        Value::Number(id) => id
+            .as_i64()
+            .ok_or(format!("`{}` is not a snowflake", id))?,
+        other => return Err(format!("`{}` is not a snowflake", other)),
+    };
+
+    let created_at_ms: i64 = (id >> 22) + DISCORD_EPOCH_MS;
+
+    if id <= 0 || created_at_ms <= DISCORD_EPOCH_MS || created_at_ms > unix_now() * 1000 {
+        return Err(format!("`{}` is not a snowflake", id));
+    }
+
+    Ok(id)
+}
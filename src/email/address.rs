@@ -0,0 +1,360 @@
+//! ## Email addresses
+//!
+//! Every customer address Stripe sends us is checked by [`EmailValidation::check`] before it is
+//! written to the database or emailed:
+//! 1. Surrounding whitespace and angle brackets are trimmed
+//! 2. The domain is lowercased and converted to ASCII with IDNA, `jane@bücher.de` becomes
+//!    `jane@xn--bcher-kva.de`, the local part keeps its case
+//! 3. The address is checked against the rules of RFC 5321: a local part of at most 64
+//!    characters without leading, trailing or double dots, a domain of at least two labels of
+//!    letters, digits and hyphens, and 254 characters in total
+//! 4. When `Email.Validation.CheckMx` is on, the domain must have an MX record, or an A record to
+//!    fall back on, see [`MxResolver`]
+//!
+//! Invalid addresses are quarantined in the
+//! [quarantine table](../../db/operations/quarantine/index.html) or rejected, see
+//! [`InvalidEmailPolicy`]. Setting `ALLOW_DIRTY_EMAIL=1` lets addresses that fail the format
+//! checks through as they are, which is disadvised. Missing addresses are never let through.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let address: EmailAddress = EmailAddress::parse(" Jane.Doe@Example.COM ")?;
+//! assert_eq!(address.to_string(), "Jane.Doe@example.com");
+//!
+//! match organization.email_validation.check("jane@example.com").await {
+//!     CheckedEmail::Valid(email) | CheckedEmail::Dirty(email) => { /* write and send */ },
+//!     CheckedEmail::Invalid(error) => { /* quarantine or reject */ },
+//! }
+//! ```
+
+use crate::email::EmailAddress;
+use crate::organization::model::InvalidEmailPolicy;
+
+use dotenv::dotenv;
+use reqwest::{Client, Response};
+use serde_json::Value;
+use std::env::var;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+
+/// The DNS over HTTPS resolver MX records are looked up with by default
+pub const DNS_RESOLVER_URL: &str = "https://cloudflare-dns.com/dns-query";
+
+/// How long a DNS lookup may take
+pub const DNS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The characters a local part may have besides letters, digits and dots
+const LOCAL_PART_SYMBOLS: &str = "!#$%&'*+/=?^_`{|}~-";
+
+
+/// # EmailAddressError
+/// Why an address was not accepted
+///
+/// ## Variants
+/// - `Missing` - There is no address, Stripe sent none or an empty one
+/// - `MissingAt` - The address has no `@`
+/// - `LocalPart` - The part before the `@` is empty, too long or has characters it may not have
+/// - `Domain` - The domain is not a valid, IDNA convertible, domain name
+/// - `TooLong` - The address is longer than 254 characters
+/// - `NoMailServer` - The domain has no MX or A record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailAddressError {
+    Missing,
+    MissingAt,
+    LocalPart,
+    Domain(String),
+    TooLong,
+    NoMailServer(String),
+}
+
+
+impl fmt::Display for EmailAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmailAddressError::Missing => write!(f, "the email address is missing"),
+            EmailAddressError::MissingAt => write!(f, "the email address has no @"),
+            EmailAddressError::LocalPart => write!(f, "the part before the @ is not valid"),
+            EmailAddressError::Domain(domain) => write!(f, "{} is not a valid domain", domain),
+            EmailAddressError::TooLong => write!(f, "the email address is longer than 254 characters"),
+            EmailAddressError::NoMailServer(domain) => write!(f, "{} has no mail server", domain),
+        }
+    }
+}
+
+impl Error for EmailAddressError {}
+
+
+impl EmailAddress {
+    /// # parse
+    /// Normalizes and validates an address, see the [module docs](index.html) for the rules.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::EmailAddress;
+    /// # use stripe_discord::email::address::EmailAddressError;
+    /// assert_eq!(EmailAddress::parse("<jane@Bücher.de>").unwrap().email, "jane@xn--bcher-kva.de");
+    /// assert_eq!(EmailAddress::parse("jane@localhost").unwrap_err(), EmailAddressError::Domain("localhost".to_string()));
+    /// ```
+    ///
+    /// ## Errors
+    /// - `EmailAddressError` - Why the address is not valid
+    pub fn parse(raw: &str) -> Result<Self, EmailAddressError> {
+        let trimmed: &str = raw.trim().trim_start_matches('<').trim_end_matches('>').trim();
+
+        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("unknown") {
+            return Err(EmailAddressError::Missing);
+        }
+
+        let (local_part, domain): (&str, &str) = trimmed.rsplit_once('@').ok_or(EmailAddressError::MissingAt)?;
+
+        if !is_local_part(local_part) {
+            return Err(EmailAddressError::LocalPart);
+        }
+
+        // a single trailing dot is the DNS root, `example.com.` is `example.com`
+        let domain: &str = domain.strip_suffix('.').unwrap_or(domain);
+        let ascii_domain: String = idna::domain_to_ascii(domain)
+            .ok()
+            .filter(|ascii_domain| is_domain(ascii_domain))
+            .ok_or(EmailAddressError::Domain(domain.to_string()))?;
+
+        let email: String = format!("{}@{}", local_part, ascii_domain);
+
+        if email.len() > 254 {
+            return Err(EmailAddressError::TooLong);
+        }
+
+        Ok(EmailAddress { email })
+    }
+
+    /// # domain
+    /// The ASCII domain of the address, everything after the last `@`.
+    pub fn domain(&self) -> &str {
+        self.email.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default()
+    }
+}
+
+
+/// # is_local_part
+/// Whether the part before the `@` is a dot-atom of at most 64 characters.
+fn is_local_part(local_part: &str) -> bool {
+    (1..=64).contains(&local_part.len())
+        && !local_part.starts_with('.')
+        && !local_part.ends_with('.')
+        && !local_part.contains("..")
+        && local_part.chars().all(|character| {
+            character.is_ascii_alphanumeric() || character == '.' || LOCAL_PART_SYMBOLS.contains(character)
+        })
+}
+
+
+/// # is_domain
+/// Whether an ASCII domain has at least two labels of letters, digits and hyphens that do not
+/// start or end with a hyphen, and a top level domain that is not a number.
+fn is_domain(domain: &str) -> bool {
+    let labels: Vec<&str> = domain.split('.').collect();
+
+    let valid_labels: bool = labels.iter().all(|label| {
+        (1..=63).contains(&label.len())
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|character| character.is_ascii_alphanumeric() || character == '-')
+    });
+
+    let top_level_domain: &str = labels.last().copied().unwrap_or_default();
+
+    labels.len() >= 2 && valid_labels && !top_level_domain.chars().all(|character| character.is_ascii_digit())
+}
+
+
+/// # allow_dirty_email
+/// Whether `ALLOW_DIRTY_EMAIL=1` lets addresses through that fail the format checks.
+pub fn allow_dirty_email() -> bool {
+    dotenv().ok();
+
+    matches!(var("ALLOW_DIRTY_EMAIL").as_deref(), Ok("1") | Ok("true"))
+}
+
+
+/// # normalize_email
+/// The normalized address, or the trimmed one when it is invalid and `ALLOW_DIRTY_EMAIL=1`. For
+/// addresses that are not customer input, like the recipients of an email, no MX records are
+/// looked up.
+///
+/// ## Errors
+/// - `EmailAddressError` - Why the address is not valid
+pub fn normalize_email(raw: &str) -> Result<String, EmailAddressError> {
+    match EmailAddress::parse(raw) {
+        Ok(address) => Ok(address.email),
+        Err(EmailAddressError::Missing) => Err(EmailAddressError::Missing),
+        Err(_) if allow_dirty_email() => Ok(raw.trim().to_string()),
+        Err(error) => Err(error),
+    }
+}
+
+
+/// # MxResolver
+/// Looks up whether a domain accepts mail, implement it to check MX records another way or to
+/// mock the lookup in tests.
+pub trait MxResolver {
+    /// # accepts_mail
+    /// `Ok(true)` when the domain has an MX record, or an A record when it has none, `Ok(false)`
+    /// when it has neither or a null MX record, `Err` when the lookup itself failed.
+    fn accepts_mail(&self, domain: &str) -> impl Future<Output = Result<bool, String>> + Send;
+}
+
+
+/// ## DnsOverHttps
+/// Resolves MX records with the JSON API of a DNS over HTTPS resolver
+///
+/// ### Fields
+/// - `url` - The resolver, `EMAIL_DNS_RESOLVER_URL` or [`DNS_RESOLVER_URL`] by default
+#[derive(Debug, Clone)]
+pub struct DnsOverHttps {
+    pub url: String,
+}
+
+
+impl DnsOverHttps {
+    /// # from_env
+    /// The resolver of `EMAIL_DNS_RESOLVER_URL`, Cloudflare when it is not set.
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        DnsOverHttps {
+            url: var("EMAIL_DNS_RESOLVER_URL").unwrap_or(DNS_RESOLVER_URL.to_string()),
+        }
+    }
+
+    /// # query
+    /// The `data` of the answers of a given record type, `None` when the domain does not exist.
+    async fn query(&self, domain: &str, record_type: &str) -> Result<Option<Vec<String>>, String> {
+        let response: Response = Client::new()
+            .get(&self.url)
+            .query(&[("name", domain), ("type", record_type)])
+            .header("accept", "application/dns-json")
+            .timeout(DNS_REQUEST_TIMEOUT)
+            .send()
+            .await
+            .map_err(|error| error.to_string())?;
+
+        if !response.status().is_success() {
+            return Err(format!("the resolver answered {} for {}", response.status(), domain));
+        }
+
+        let body: Value = response.json().await.map_err(|error| error.to_string())?;
+
+        // status 3 is NXDOMAIN, the domain does not exist
+        match body["Status"].as_i64() {
+            Some(0) => {},
+            Some(3) => return Ok(None),
+            status => return Err(format!("the resolver answered status {:?} for {}", status, domain)),
+        }
+
+        let record_code: i64 = if record_type == "MX" { 15 } else { 1 };
+
+        let answers: Vec<String> = body["Answer"]
+            .as_array()
+            .map(|answers| answers.iter()
+                .filter(|answer| answer["type"].as_i64() == Some(record_code))
+                .filter_map(|answer| answer["data"].as_str().map(|data| data.to_string()))
+                .collect())
+            .unwrap_or_default();
+
+        Ok(Some(answers))
+    }
+}
+
+
+impl MxResolver for DnsOverHttps {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, String> {
+        let mail_servers: Vec<String> = match self.query(domain, "MX").await? {
+            Some(mail_servers) => mail_servers,
+            None => return Ok(false),
+        };
+
+        // a null MX record, `0 .`, says the domain accepts no mail at all
+        if !mail_servers.is_empty() {
+            return Ok(mail_servers.iter().any(|mail_server| mail_server.trim() != "0 ."));
+        }
+
+        let addresses: Vec<String> = self.query(domain, "A").await?.unwrap_or_default();
+
+        Ok(!addresses.is_empty())
+    }
+}
+
+
+/// ## EmailValidation
+/// How an Organization checks the addresses of its customers, configured under
+/// `Email.Validation`
+///
+/// ### Fields
+/// - `check_mx` - Whether the domain must accept mail, `CheckMx` (off by default)
+/// - `invalid` - What happens to invalid addresses, `InvalidAddress` (`quarantine` by default)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EmailValidation {
+    pub check_mx: bool,
+    pub invalid: InvalidEmailPolicy,
+}
+
+
+/// ## CheckedEmail
+/// The outcome of checking an address
+///
+/// ### Variants
+/// - `Valid` - The normalized address
+/// - `Dirty` - The trimmed address, invalid but let through by `ALLOW_DIRTY_EMAIL=1`
+/// - `Invalid` - Why the address may not be written or emailed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckedEmail {
+    Valid(String),
+    Dirty(String),
+    Invalid(EmailAddressError),
+}
+
+
+impl EmailValidation {
+    /// # check
+    /// Normalizes and validates an address, looking up its MX records with [`DnsOverHttps`]
+    /// when `check_mx` is on.
+    ///
+    /// ## Arguments
+    /// - `raw`: `&str` - The address as Stripe sent it, empty when it sent none
+    pub async fn check(&self, raw: &str) -> CheckedEmail {
+        self.check_with(raw, &DnsOverHttps::from_env()).await
+    }
+
+    /// # check_with
+    /// [`EmailValidation::check`] with another resolver. A failed lookup lets the address
+    /// through, an outage of the resolver should not hold back emails.
+    ///
+    /// ## Arguments
+    /// - `raw`: `&str` - The address as Stripe sent it, empty when it sent none
+    /// - `resolver`: `&R` - Looks up whether the domain accepts mail
+    pub async fn check_with<R: MxResolver>(&self, raw: &str, resolver: &R) -> CheckedEmail {
+        let address: EmailAddress = match EmailAddress::parse(raw) {
+            Ok(address) => address,
+            Err(EmailAddressError::Missing) => return CheckedEmail::Invalid(EmailAddressError::Missing),
+            Err(_) if allow_dirty_email() => return CheckedEmail::Dirty(raw.trim().to_string()),
+            Err(error) => return CheckedEmail::Invalid(error),
+        };
+
+        if !self.check_mx {
+            return CheckedEmail::Valid(address.email);
+        }
+
+        match resolver.accepts_mail(address.domain()).await {
+            Ok(true) => CheckedEmail::Valid(address.email),
+            Ok(false) => CheckedEmail::Invalid(EmailAddressError::NoMailServer(address.domain().to_string())),
+            Err(error) => {
+                warn!(domain = address.domain(), %error, "Failed to look up the mail servers, accepting the address");
+                CheckedEmail::Valid(address.email)
+            },
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! ## Email attachments
+//!
+//! Organizations with receipts enabled attach a PDF to the welcome email sent after checkout:
+//! - The Stripe invoice PDF when the checkout session has an `invoice`, downloaded from its
+//!   `invoice_pdf`. Invoices are retrieved from `STRIPE_API_URL` (`https://api.stripe.com` by
+//!   default) with the private key of the Organization or `STRIPE_PRIVATE_API_KEY`
+//! - A receipt generated from the payment otherwise, or when the invoice can not be downloaded,
+//!   see [receipt](../receipt/index.html)
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let attachments: Vec<EmailAttachment> = receipt_attachments(&session, &organization).await;
+//! ```
+
+use crate::api::client::{download, fetch_invoice};
+use crate::email::receipt::Receipt;
+use crate::secrets::Secret;
+use crate::Organization;
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+
+/// ## EmailAttachment
+/// A file attached to an email
+///
+/// ### Fields
+/// - `filename` - The name the recipient sees
+/// - `content_type` - The MIME type, e.g. `application/pdf`
+/// - `content` - The raw bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+
+impl EmailAttachment {
+    /// # pdf
+    /// Creates a PDF attachment.
+    pub fn pdf(filename: String, content: Vec<u8>) -> Self {
+        EmailAttachment {
+            filename,
+            content_type: "application/pdf".to_string(),
+            content,
+        }
+    }
+}
+
+
+/// # receipt_attachments
+/// The receipt of a checkout session, the Stripe invoice PDF when there is one and the generated
+/// receipt otherwise. Empty when the Organization has receipts disabled.
+///
+/// ## Arguments
+/// - `session`: `&Value` - The `data.object` of a `checkout.session.completed` event
+/// - `organization`: `&Organization` - The Organization the checkout belongs to
+pub async fn receipt_attachments(session: &Value, organization: &Organization) -> Vec<EmailAttachment> {
+    if !organization.attach_receipts {
+        return Vec::new();
+    }
+
+    if !session["invoice"].is_null() {
+        match invoice_attachment(&session["invoice"], organization).await {
+            Ok(attachment) => return vec![attachment],
+            Err(error) => warn!(%error, "Failed to download the invoice PDF, attaching a generated receipt"),
+        }
+    }
+
+    let receipt: Receipt = Receipt::from_checkout_session(session, &organization.name);
+    debug!(receipt_number = %receipt.receipt_number, "Generated a receipt PDF");
+
+    vec![EmailAttachment::pdf(receipt.filename(), receipt.to_pdf())]
+}
+
+
+/// # invoice_attachment
+/// Downloads the PDF of an invoice, retrieving the invoice first when only its id is known.
+///
+/// ## Arguments
+/// - `invoice`: `&Value` - The `invoice` of a checkout session, an id or an expanded invoice
+/// - `organization`: `&Organization` - The Organization whose Stripe account has the invoice
+///
+/// ## Errors
+/// - `String` - No private key is configured, the invoice has no PDF or the download failed
+pub async fn invoice_attachment(invoice: &Value, organization: &Organization) -> Result<EmailAttachment, String> {
+    let invoice: Value = match invoice.as_str() {
+        Some(invoice_id) => {
+            let api_key: Secret = organization.stripe_api_key().map_err(|error| error.to_string())?;
+
+            fetch_invoice(invoice_id, &api_key).await?
+        },
+        None => invoice.clone(),
+    };
+
+    let invoice_pdf: &str = invoice["invoice_pdf"]
+        .as_str()
+        .ok_or("the invoice has no invoice_pdf yet".to_string())?;
+
+    let number: &str = invoice["number"]
+        .as_str()
+        .or(invoice["id"].as_str())
+        .unwrap_or("invoice");
+
+    let content: Vec<u8> = download(invoice_pdf).await?;
+
+    Ok(EmailAttachment::pdf(format!("invoice-{}.pdf", number), content))
+}
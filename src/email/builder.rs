@@ -17,7 +17,7 @@ impl Email {
     /// Returns a new instance of `Email`.
     ///
     /// ## Examples
-    /// ```rust
+    /// ```rust,ignore
     /// let email = Email::new(
     ///     "recipient@example.com".to_string(),
     ///     "floris@xylex.ai".to_string(),
@@ -13,8 +13,398 @@
 //!
 //!
 //! ### Security checks
-//! - `AllowDirtyEmail` - Setting this `.env` variable will bypass the regex checking of email
-//! addresses, see more in `./utils/format.rs`
+//! - Every recipient is normalized and validated before it is sent to, setting
+//!   `ALLOW_DIRTY_EMAIL=1` lets invalid addresses through, see more in `./address.rs`
 //!
 //!
 //! ### Notes
+
+
+use crate::db::operations::email_attempt::EmailAttempt;
+use crate::db::operations::email_log::EmailLog;
+use crate::email::address::normalize_email;
+use crate::email::content::EmailContent;
+use crate::email::locale::Locale;
+use crate::email::resend;
+use crate::email::suppression::suppression_reason;
+use crate::email::attachments::EmailAttachment;
+use crate::email::resend::{ResendAttachment, ResendMail};
+use crate::email::retry::{EmailError, EmailRetry};
+use crate::email::smtp::send_smtp;
+use crate::email::EmailProvider;
+use crate::events::signature::unix_now;
+use crate::metrics::observe_email;
+use crate::overwrite::overwrite_stripe_customer_id_column_name;
+use crate::log::redact::redact_email;
+use crate::secrets::{secret, supabase_client, Secret};
+use crate::organization::model::EmailEvent;
+use crate::CustomerId;
+use crate::EmailConfig;
+use crate::Organization;
+
+use dotenv::dotenv;
+use std::collections::HashMap;
+use supabase_rs::SupabaseClient;
+use tokio::time::sleep;
+use tracing::{debug, warn};
+
+
+/// ## send_email
+/// Sends an HTML email with its plain-text alternative through the email provider configured
+/// under `Email.Provider` in `stripe_discord.yaml`, retrying it and falling back to another
+/// provider like configured under `Email.Retry`, see [retry](../retry/index.html).
+///
+/// ### Arguments
+/// - `organization`: `Organization` - The organization that sends the email, used for the sender address.
+/// - `to`: `Vec<String>` - A list of recipient email addresses.
+/// - `subject`: `String` - The subject line of the email.
+/// - `content`: `EmailContent` - The HTML and plain-text bodies of the email.
+///
+/// ### Returns
+/// - `Result<String, String>`: The message ID of the sent email as `Ok(String)` or an error message as `Err(String)`.
+///
+/// ### Errors
+/// - One of the recipients is not a valid email address
+/// - The last attempt failed, or an attempt failed permanently
+///
+/// ### Example
+/// ```rust,ignore
+/// let recipients = vec!["user@example.com".to_string()];
+/// let result = send_email(organization, recipients, "Welcome!".to_string(), EmailContent::from_html(html)).await;
+/// ```
+pub async fn send_email(
+    organization: Organization,
+    to: Vec<String>,
+    subject: String,
+    content: EmailContent,
+) -> Result<String, String> {
+    deliver_email(&organization, to, &subject, &content, None).await.sent
+}
+
+
+/// ## Delivery
+/// How sending an email ended
+///
+/// ### Fields
+/// - `sent` - The message ID of the sent email, or the error of the last attempt
+/// - `provider` - The provider of the last attempt
+/// - `attempts` - How often sending was attempted, `0` when a recipient is not a valid address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub sent: Result<String, String>,
+    pub provider: EmailProvider,
+    pub attempts: u32,
+}
+
+
+/// ## deliver_email
+/// Attempts an email with the provider of the organization until it is sent, fails permanently or `Email.Retry.MaxAttempts` is reached,
+/// waiting between attempts and switching to `Email.Retry.Fallback` after
+/// `Email.Retry.FallbackAfter` failures, or right away when the provider rejects our credentials.
+/// Every attempt is stored in the email attempts and sends the same idempotency key.
+///
+/// ### Arguments
+/// - `organization`: `&Organization` - The organization that sends the email, with its sender and retries.
+/// - `to`: `Vec<String>` - A list of recipient email addresses.
+/// - `subject`: `&str` - The subject line of the email.
+/// - `content`: `&EmailContent` - The HTML and plain-text bodies of the email.
+/// - `email_log_id`: `Option<i64>` - The row id of the email in the email log, stored with every attempt.
+pub async fn deliver_email(
+    organization: &Organization,
+    to: Vec<String>,
+    subject: &str,
+    content: &EmailContent,
+    email_log_id: Option<i64>,
+) -> Delivery {
+    dotenv().ok();
+
+    let primary: EmailProvider = organization.email_provider;
+
+    let to: Vec<String> = match to
+        .iter()
+        .map(|recipient| normalize_email(recipient).map_err(|error| format!("invalid recipient: {}", error)))
+        .collect::<Result<Vec<String>, String>>()
+    {
+        Ok(to) => to,
+        Err(error) => return Delivery { sent: Err(error), provider: primary, attempts: 0 },
+    };
+
+    let retry: &EmailRetry = &organization.email_retry;
+    let supabase: Option<SupabaseClient> = supabase_client().ok();
+    let idempotency_key: String = idempotency_key(email_log_id);
+    let mut failures: u32 = 0;
+    let mut unauthorized: bool = false;
+
+    // once the primary provider rejected our credentials only the fallback is attempted
+    let provider_after = |failures: u32, unauthorized: bool| -> EmailProvider {
+        match (unauthorized, retry.fallback) {
+            (true, Some(fallback)) => fallback,
+            _ => retry.provider(primary, failures),
+        }
+    };
+
+    loop {
+        let provider: EmailProvider = provider_after(failures, unauthorized);
+
+        let sent: Result<String, EmailError> = send_with(provider, organization, &to, subject, content, &idempotency_key).await;
+
+        observe_email(&provider.to_string(), sent.is_ok());
+
+        if let Some(supabase) = &supabase {
+            let attempt: EmailAttempt = EmailAttempt::new(email_log_id, &to, failures + 1, &provider.to_string(), &sent, unix_now());
+
+            if let Err(error) = attempt.insert(supabase.clone()).await.map_err(|error| error.to_string()) {
+                warn!(%error, "Failed to record the email attempt");
+            }
+        }
+
+        let error: EmailError = match sent {
+            Ok(message_id) => return Delivery { sent: Ok(message_id), provider, attempts: failures + 1 },
+            Err(error) => error,
+        };
+
+        failures += 1;
+
+        let to_fallback: bool = error.is_unauthorized() && retry.fallback.is_some_and(|fallback| fallback != provider);
+        unauthorized = unauthorized || to_fallback;
+
+        if !(error.is_transient() || to_fallback) || failures >= retry.max_attempts {
+            return Delivery { sent: Err(error.to_string()), provider, attempts: failures };
+        }
+
+        let next: EmailProvider = provider_after(failures, unauthorized);
+
+        warn!(provider = %provider.to_string(), next = %next.to_string(), attempt = failures, %error, "Email attempt failed, retrying");
+
+        // the fallback provider is attempted right away
+        if next == provider {
+            sleep(retry.backoff(failures)).await;
+        }
+    }
+}
+
+
+/// ## idempotency_key
+/// The key every attempt of an email is sent with, the email log id when the email is logged and
+/// a random key otherwise.
+fn idempotency_key(email_log_id: Option<i64>) -> String {
+    match email_log_id {
+        Some(email_log_id) => format!("email-log-{}", email_log_id),
+        None => format!("email-{}", hex::encode(rand::random::<[u8; 16]>())),
+    }
+}
+
+
+/// ## send_with
+/// Makes one attempt to send an email through a provider.
+async fn send_with(
+    provider: EmailProvider,
+    organization: &Organization,
+    to: &[String],
+    subject: &str,
+    content: &EmailContent,
+    idempotency_key: &str,
+) -> Result<String, EmailError> {
+    let from: &str = &organization.email_config.sender_email;
+
+    match provider {
+        EmailProvider::Resend => {
+            let api_key: Secret = secret("RESEND_API_KEY")
+                .map_err(|error| EmailError::Unauthorized(format!("{}, it is needed to send emails with resend", error)))?;
+
+            let mail: ResendMail = ResendMail {
+                from: from.to_string(),
+                to: to.to_vec(),
+                subject: subject.to_string(),
+                attachments: content.attachments.iter().map(ResendAttachment::from).collect(),
+                html: content.html.clone(),
+                text: content.text.clone(),
+            };
+
+            resend::post_email(api_key.expose(), idempotency_key, &mail).await
+        },
+        EmailProvider::Smtp => send_smtp(from, to, subject, content).await,
+    }
+}
+
+
+/// ## send_event_email
+/// Renders the email of a lifecycle moment with its template, subject and sender and sends it to
+/// a customer.
+///
+/// ### Arguments
+/// - `organization`: `&Organization` - The organization whose `emails` are sent.
+/// - `event`: `EmailEvent` - The moment the email is sent at.
+/// - `locale`: `&Locale` - The locale of the customer, it picks the translated subject and template.
+/// - `email`: `&str` - The email address of the customer.
+/// - `placeholders`: `&HashMap<String, String>` - The values of the `{{Name}}` placeholders.
+/// - `attachments`: `Vec<EmailAttachment>` - The files to attach.
+///
+/// ### Returns
+/// - `Option<Result<String, String>>`: `None` when the organization has the email disabled,
+///   otherwise the result of [`send_customer_email`].
+pub async fn send_event_email(
+    organization: &Organization,
+    event: EmailEvent,
+    locale: &Locale,
+    email: &str,
+    placeholders: &HashMap<String, String>,
+    attachments: Vec<EmailAttachment>,
+) -> Option<Result<String, String>> {
+    let email_config: EmailConfig = organization.email(event)?.localized(locale);
+
+    Some(send_customer_email(organization, event.as_str(), email_config, email, placeholders, attachments).await)
+}
+
+
+/// ## send_customer_email
+/// Renders an email of the organization and sends it to a customer, shared by the lifecycle
+/// emails and the steps of drip sequences.
+///
+/// ### Arguments
+/// - `organization`: `&Organization` - The organization that sends the email.
+/// - `template`: `&str` - The name the email is logged under, e.g. `welcome` or a drip step.
+/// - `email_config`: `EmailConfig` - The localized sender, subject and template of the email.
+/// - `email`: `&str` - The email address of the customer.
+/// - `placeholders`: `&HashMap<String, String>` - The values of the `{{Name}}` placeholders.
+/// - `attachments`: `Vec<EmailAttachment>` - The files to attach.
+///
+/// ### Returns
+/// - `Result<String, String>`: The message ID of the sent email or why the address is
+///   suppressed, the template could not be loaded or the email not be sent. A suppressed address
+///   also gets `email_sent` set to `false` with the reason on the customer record, every email that
+///   is sent is logged in the email log, see [email_log](../../db/operations/email_log/index.html).
+pub async fn send_customer_email(
+    organization: &Organization,
+    template: &str,
+    email_config: EmailConfig,
+    email: &str,
+    placeholders: &HashMap<String, String>,
+    attachments: Vec<EmailAttachment>,
+) -> Result<String, String> {
+    if let Some(reason) = suppression_reason(email).await {
+        record_suppressed(email, &reason).await;
+
+        return Err(reason);
+    }
+
+    let content: EmailContent = match email_config.render(placeholders).await {
+        Ok(content) => content.with_attachments(attachments),
+        Err(error) => return Err(format!("failed to load the {} template: {}", template, error)),
+    };
+
+    // send from the sender of this email instead of the default sender
+    let mut sender: Organization = organization.clone();
+    sender.email_config = email_config.clone();
+
+    let logged: Option<(EmailLog, SupabaseClient)> = log_queued(template, &email_config.subject, email, organization.email_provider).await;
+    let email_log_id: Option<i64> = logged.as_ref().and_then(|(entry, _)| entry.id);
+
+    let delivery: Delivery = deliver_email(&sender, vec![email.to_string()], &email_config.subject, &content, email_log_id).await;
+
+    if let Some((mut entry, supabase)) = logged {
+        let recorded: Result<(), String> = entry
+            .record_send(&delivery.sent, &delivery.provider.to_string(), delivery.attempts, unix_now(), supabase)
+            .await
+            .map_err(|error| error.to_string());
+
+        if let Err(error) = recorded {
+            warn!(email = %redact_email(email), %error, "Failed to log the sent email");
+        }
+    }
+
+    delivery.sent
+}
+
+
+/// ## log_queued
+/// Logs an email that is about to be sent in the email log, linked to the customer record of the
+/// address when there is one. The email is still sent when it can not be logged.
+async fn log_queued(template: &str, subject: &str, email: &str, provider: EmailProvider) -> Option<(EmailLog, SupabaseClient)> {
+    let supabase: SupabaseClient = supabase_client().ok()?;
+
+    let customer_id: Option<String> = CustomerId::search(Some(email), None, 1, supabase.clone())
+        .await
+        .ok()
+        .and_then(|records| records.into_iter().next())
+        .and_then(|record| record[overwrite_stripe_customer_id_column_name()].as_str().map(|id| id.to_string()));
+
+    let queued: Result<EmailLog, String> = EmailLog::queued(template, subject, email, customer_id, &provider.to_string(), unix_now())
+        .insert(supabase.clone())
+        .await
+        .map_err(|error| error.to_string());
+
+    match queued {
+        Ok(entry) => Some((entry, supabase)),
+        Err(error) => {
+            warn!(email = %redact_email(email), %error, "Failed to log the queued email, sending it anyway");
+            None
+        },
+    }
+}
+
+
+/// ## record_suppressed
+/// Marks `email_sent` as `false` with why on the customer the suppressed email was meant for,
+/// addresses without a customer record are skipped.
+async fn record_suppressed(email: &str, reason: &str) {
+    let Ok(supabase) = supabase_client() else { return };
+
+    let recorded: Result<(), String> = CustomerId::update_email_sent_status_by_email(
+        email.to_string(),
+        false,
+        Some(reason.to_string()),
+        supabase
+    )
+        .await
+        .map_err(|error| error.to_string());
+
+    if let Err(error) = recorded {
+        debug!(email = %redact_email(email), %error, "No customer record to mark the suppressed email on");
+    }
+}
+
+
+/// ## send_welcome_email
+/// Loads the welcome template of the organization and sends it to a customer, used after
+/// checkout and when support resends the welcome email.
+///
+/// ### Arguments
+/// - `organization`: `Organization` - The organization whose welcome email is sent.
+/// - `email`: `String` - The email address of the customer.
+/// - `locale`: `&Locale` - The locale of the customer.
+/// - `placeholders`: `&HashMap<String, String>` - The values of the `{{Name}}` placeholders.
+/// - `attachments`: `Vec<EmailAttachment>` - The receipts to attach, see [attachments](../attachments/index.html).
+///
+/// ### Returns
+/// - `Result<String, String>`: The message ID of the sent email or why the welcome email is
+///   disabled, the template could not be downloaded or the email not be sent.
+pub async fn send_welcome_email(
+    organization: Organization,
+    email: String,
+    locale: &Locale,
+    placeholders: &HashMap<String, String>,
+    attachments: Vec<EmailAttachment>,
+) -> Result<String, String> {
+    send_event_email(&organization, EmailEvent::Welcome, locale, &email, placeholders, attachments)
+        .await
+        .unwrap_or(Err("the welcome email is disabled".to_string()))
+}
+
+
+/// ## customer_placeholders
+/// The `FirstName`, `FullName` and `Email` placeholders every customer email has.
+///
+/// ### Example
+/// ```rust,ignore
+/// let placeholders: HashMap<String, String> = customer_placeholders("Jenny Rosen", "jenny@example.com");
+/// assert_eq!(placeholders["FirstName"], "Jenny");
+/// ```
+pub fn customer_placeholders(name: &str, email: &str) -> HashMap<String, String> {
+    let mut placeholders: HashMap<String, String> = HashMap::new();
+    placeholders.insert("FirstName".to_string(), name.split_whitespace().next().unwrap_or(name).to_string());
+    placeholders.insert("FullName".to_string(), name.to_string());
+    placeholders.insert("Email".to_string(), email.to_string());
+
+    placeholders
+}
@@ -0,0 +1,181 @@
+//! ## The content of an email
+//!
+//! Every email is sent as HTML with a plain-text alternative. The text is either rendered from an
+//! explicit text template or generated from the HTML, so clients that do not show HTML and spam
+//! filters that score HTML-only mail still get a readable body.
+//!
+//! ### Preheader
+//! The preheader is the preview line clients show after the subject. It is added as a hidden
+//! element at the top of the body, otherwise clients preview the first text of the template.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let content: EmailContent = EmailContent::from_html(html).with_preheader("Your receipt from Xylex");
+//! ```
+
+use crate::email::attachments::EmailAttachment;
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+
+/// Elements that end a paragraph in the plain-text alternative
+static BLOCKS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)</(p|div|h[1-6]|table|ul|ol|blockquote)>").expect("valid regex"));
+
+/// Elements that end a line in the plain-text alternative
+static LINE_BREAKS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<br\s*/?>|</tr>").expect("valid regex"));
+
+/// List items, they are dashed
+static LIST_ITEMS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<li\b[^>]*>").expect("valid regex"));
+
+/// Elements whose content is never shown
+static HIDDEN_ELEMENTS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<(head|style|script|title)\b.*?</(head|style|script|title)>|<!--.*?-->").expect("valid regex"));
+
+/// Links, the url is kept next to the label
+static LINKS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?is)<a\b[^>]*\bhref\s*=\s*["']([^"']*)["'][^>]*>(.*?)</a>"#).expect("valid regex"));
+
+/// Any remaining tag
+static TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<[^>]*>").expect("valid regex"));
+
+/// The opening `<body>` tag the preheader is placed after
+static BODY_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<body\b[^>]*>").expect("valid regex"));
+
+
+/// ## EmailContent
+/// The HTML and plain-text bodies of an email
+///
+/// ### Fields
+/// - `html` - The rendered HTML
+/// - `text` - The plain-text alternative
+/// - `attachments` - The files attached to the email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailContent {
+    pub html: String,
+    pub text: String,
+    pub attachments: Vec<EmailAttachment>,
+}
+
+
+impl EmailContent {
+    /// # from_html
+    /// Creates the content of an HTML email, generating the plain-text alternative.
+    pub fn from_html(html: String) -> Self {
+        let text: String = html_to_text(&html);
+
+        EmailContent { html, text, attachments: Vec::new() }
+    }
+
+    /// # with_text
+    /// Replaces the generated plain-text alternative, e.g. with a rendered text template.
+    pub fn with_text(mut self, text: String) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// # with_attachments
+    /// Attaches files to the email.
+    pub fn with_attachments(mut self, attachments: Vec<EmailAttachment>) -> Self {
+        self.attachments.extend(attachments);
+        self
+    }
+
+    /// # with_preheader
+    /// Adds a hidden preheader at the top of the HTML body.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::content::EmailContent;
+    /// let content: EmailContent = EmailContent::from_html("<p>Hi</p>".to_string()).with_preheader("Thanks for your order");
+    /// assert!(content.html.starts_with("<div style=\"display:none"));
+    /// ```
+    pub fn with_preheader(mut self, preheader: &str) -> Self {
+        let hidden: String = format!(
+            "<div style=\"display:none;max-height:0;overflow:hidden;opacity:0\">{}</div>",
+            escape_html(preheader)
+        );
+
+        self.html = match BODY_TAG.find(&self.html) {
+            Some(body) => format!("{}{}{}", &self.html[..body.end()], hidden, &self.html[body.end()..]),
+            None => format!("{}{}", hidden, self.html),
+        };
+
+        self
+    }
+}
+
+
+/// # html_to_text
+/// Generates a plain-text version of an HTML email. Block elements end a line, list items are
+/// dashed, links keep their url and hidden elements are dropped.
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::email::content::html_to_text;
+/// assert_eq!(html_to_text("<p>Hi <b>Jenny</b></p><p><a href=\"https://x.ai\">Log in</a></p>"), "Hi Jenny\n\nLog in (https://x.ai)");
+/// ```
+pub fn html_to_text(html: &str) -> String {
+    let visible: String = HIDDEN_ELEMENTS.replace_all(html, "").to_string();
+
+    let linked: String = LINKS
+        .replace_all(&visible, |captures: &regex::Captures| {
+            let url: &str = &captures[1];
+            let label: String = TAGS.replace_all(&captures[2], "").trim().to_string();
+
+            match label.is_empty() || label == url {
+                true => url.to_string(),
+                false => format!("{} ({})", label, url),
+            }
+        })
+        .to_string();
+
+    let listed: String = LIST_ITEMS.replace_all(&linked, "\n- ").to_string();
+    let paragraphs: String = BLOCKS.replace_all(&listed, "$0\n\n").to_string();
+    let broken: String = LINE_BREAKS.replace_all(&paragraphs, "$0\n").to_string();
+    let stripped: String = decode_entities(&TAGS.replace_all(&broken, ""));
+
+    // collapse whitespace within lines and keep at most one blank line between paragraphs
+    let mut text: String = String::new();
+    let mut blank_lines: usize = 0;
+
+    for line in stripped.lines() {
+        let line: String = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+
+        if !text.is_empty() {
+            text.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+
+        text.push_str(&line);
+        blank_lines = 0;
+    }
+
+    text
+}
+
+
+/// # decode_entities
+/// Decodes the HTML entities templates commonly use.
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+
+/// # escape_html
+/// Escapes text for use inside an HTML element or a quoted attribute.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,90 @@
+//! ## Email delivery tracking
+//!
+//! Every customer email is logged in the [email log](../../db/operations/email_log/index.html)
+//! with the message id the provider gave it. The delivery webhooks of the provider, see
+//! [email_webhooks](../../api/email_webhooks/index.html), move it along from `sent` to
+//! `delivered`, `opened`, `bounced` or `complained`.
+//!
+//! ### Provider events
+//! | Status | Resend | SES |
+//! | --- | --- | --- |
+//! | `sent` | `email.sent` | `Send` |
+//! | `delivered` | `email.delivered` | `Delivery` |
+//! | `opened` | `email.opened`, `email.clicked` | `Open`, `Click` |
+//! | `bounced` | `email.bounced` | `Bounce` of type `Permanent` |
+//! | `complained` | `email.complained` | `Complaint` |
+//!
+//! A click counts as an open, images are often blocked so the open itself is not always seen.
+
+use crate::db::operations::email_log::EmailStatus;
+
+use serde_json::Value;
+
+
+/// ## DeliveryUpdate
+/// A status a provider reported for one of our emails
+///
+/// ### Fields
+/// - `message_id` - The id the provider gave the email
+/// - `status` - The reported status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryUpdate {
+    pub message_id: String,
+    pub status: EmailStatus,
+}
+
+
+/// # resend_delivery
+/// The status a Resend webhook event reports, `None` for events without one, e.g.
+/// `email.delivery_delayed`, or a soft bounce.
+///
+/// ## Arguments
+/// - `event`: `&Value` - The webhook body
+pub fn resend_delivery(event: &Value) -> Option<DeliveryUpdate> {
+    let data: &Value = &event["data"];
+
+    let status: EmailStatus = match event["type"].as_str()? {
+        "email.sent" => EmailStatus::Sent,
+        "email.delivered" => EmailStatus::Delivered,
+        "email.opened" | "email.clicked" => EmailStatus::Opened,
+        "email.bounced" if data["bounce"]["type"].as_str().unwrap_or("Permanent") == "Permanent" => EmailStatus::Bounced,
+        "email.complained" => EmailStatus::Complained,
+        _ => return None,
+    };
+
+    delivery_update(data["email_id"].as_str(), status)
+}
+
+
+/// # ses_delivery
+/// The status an SES notification reports, `None` for notifications without one, e.g. a
+/// transient bounce.
+///
+/// ## Arguments
+/// - `message`: `&Value` - The `Message` of the SNS notification, parsed
+pub fn ses_delivery(message: &Value) -> Option<DeliveryUpdate> {
+    // notifications carry `notificationType`, event publishing carries `eventType`
+    let kind: &str = message["notificationType"]
+        .as_str()
+        .or(message["eventType"].as_str())?;
+
+    let status: EmailStatus = match kind {
+        "Send" => EmailStatus::Sent,
+        "Delivery" => EmailStatus::Delivered,
+        "Open" | "Click" => EmailStatus::Opened,
+        "Bounce" if message["bounce"]["bounceType"].as_str() == Some("Permanent") => EmailStatus::Bounced,
+        "Complaint" => EmailStatus::Complained,
+        _ => return None,
+    };
+
+    delivery_update(message["mail"]["messageId"].as_str(), status)
+}
+
+
+/// # delivery_update
+/// The update of a non-empty message id.
+fn delivery_update(message_id: Option<&str>, status: EmailStatus) -> Option<DeliveryUpdate> {
+    message_id
+        .filter(|message_id| !message_id.is_empty())
+        .map(|message_id| DeliveryUpdate { message_id: message_id.to_string(), status })
+}
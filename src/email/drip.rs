@@ -0,0 +1,192 @@
+//! ## Drip sequences
+//!
+//! An Organization can follow up a purchase with a sequence of emails, e.g. tips after three days
+//! and a feedback request after two weeks. The sequence starts on `checkout.session.completed`,
+//! every step is stored in the [scheduled emails](../../db/operations/scheduled_email/index.html)
+//! with the time it is due at and sent from there by the
+//! [scheduler](../../background/scheduler/index.html), so pending steps survive restarts.
+//!
+//! A refund (`charge.refunded`) or cancellation (`customer.subscription.deleted`) cancels the
+//! steps that are still pending for the address.
+//!
+//! ### Usage example
+//! ```yaml
+//! Email:
+//!   Drip:
+//!     - Name: tips
+//!       DelayDays: 3
+//!       Subject: Three tips to get the most out of Xylex
+//!       TemplateUrl: tips
+//!     - Name: feedback
+//!       DelayDays: 14
+//!       Subject: How is Xylex working out for you?
+//!       TemplateUrl: feedback
+//!       Locales:
+//!         de:
+//!           Subject: Wie gefällt Ihnen Xylex?
+//! ```
+//! The welcome email stays the day 0 email, a step with `DelayDays: 0` is sent by the next run of
+//! the scheduler.
+
+use crate::db::operations::scheduled_email::ScheduledEmail;
+use crate::email::client::send_customer_email;
+use crate::email::locale::Locale;
+use crate::events::signature::unix_now;
+use crate::log::redact::redact_email;
+use crate::EmailConfig;
+use crate::Organization;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use supabase_rs::SupabaseClient;
+use tracing::{error, info};
+
+
+/// ## DripStep
+/// One email of the drip sequence of an Organization
+///
+/// ### Fields
+/// - `name` - The name of the step, it is stored with the scheduled email and in the email log
+/// - `delay` - How long after the purchase the step is sent
+/// - `email_config` - The sender, subject, template and translations of the email
+#[derive(Clone, Debug)]
+pub struct DripStep {
+    pub name: String,
+    pub delay: Duration,
+    pub email_config: EmailConfig,
+}
+
+
+impl DripStep {
+    /// # new
+    /// Creates a step that is sent `delay` after the purchase.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let tips: DripStep = DripStep::new("tips", Duration::from_secs(3 * 86400), email_config);
+    /// ```
+    pub fn new(name: &str, delay: Duration, email_config: EmailConfig) -> Self {
+        DripStep { name: name.to_string(), delay, email_config }
+    }
+}
+
+
+/// # schedule_drips
+/// Starts the drip sequence of an Organization for a purchase, once per checkout session.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The Organization whose sequence is started
+/// - `checkout_session_id`: `&str` - The checkout session of the purchase
+/// - `email`: `&str` - The validated address of the customer
+/// - `locale`: `&Locale` - The locale the steps are rendered in
+/// - `placeholders`: `&HashMap<String, String>` - The values of the `{{Name}}` placeholders
+/// - `purchased_at`: `i64` - The unix timestamp the delays count from, now when it is `0`
+/// - `supabase`: `&SupabaseClient` - The client the steps are stored with
+///
+/// ## Returns
+/// - `Result<usize, String>`: The number of scheduled steps, `0` without a sequence or when the
+///   checkout session already started it.
+pub async fn schedule_drips(
+    organization: &Organization,
+    checkout_session_id: &str,
+    email: &str,
+    locale: &Locale,
+    placeholders: &HashMap<String, String>,
+    purchased_at: i64,
+    supabase: &SupabaseClient,
+) -> Result<usize, String> {
+    if organization.drip_sequence.is_empty() {
+        return Ok(0);
+    }
+
+    let started: bool = ScheduledEmail::exists_for_session(&organization.name, checkout_session_id, supabase.clone())
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if started {
+        return Ok(0);
+    }
+
+    let now: i64 = unix_now();
+    let purchased_at: i64 = if purchased_at > 0 { purchased_at } else { now };
+
+    for step in &organization.drip_sequence {
+        let send_at: i64 = purchased_at.saturating_add(step.delay.as_secs() as i64);
+
+        ScheduledEmail::pending(
+            &organization.name,
+            &step.name,
+            email,
+            checkout_session_id,
+            &locale.tag,
+            placeholders.clone(),
+            send_at,
+            now,
+        )
+            .insert(supabase.clone())
+            .await
+            .map_err(|error| error.to_string())?;
+    }
+
+    Ok(organization.drip_sequence.len())
+}
+
+
+/// # cancel_drips
+/// Cancels the pending steps an Organization scheduled for an address, does nothing when the
+/// Organization has no sequence.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The Organization whose steps are cancelled
+/// - `email`: `&str` - The address of the customer
+/// - `reason`: `&str` - The Stripe event type that stopped the sequence
+/// - `supabase`: `&SupabaseClient` - The client the steps are stored with
+pub async fn cancel_drips(
+    organization: &Organization,
+    email: &str,
+    reason: &str,
+    supabase: &SupabaseClient,
+) {
+    if organization.drip_sequence.is_empty() || email.is_empty() {
+        return;
+    }
+
+    let cancelled: Result<usize, String> = ScheduledEmail::cancel_pending(&organization.name, email, reason, unix_now(), supabase.clone())
+        .await
+        .map_err(|error| error.to_string());
+
+    match cancelled {
+        Ok(0) => {},
+        Ok(cancelled) => info!(email = %redact_email(email), cancelled, reason, "Drip sequence stopped"),
+        Err(error) => error!(email = %redact_email(email), reason, %error, "Failed to stop the drip sequence"),
+    }
+}
+
+
+/// # send_drip_email
+/// Sends a scheduled step in the locale it was scheduled in.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The Organization that sends the step
+/// - `step`: `&DripStep` - The step as it is configured now
+/// - `scheduled`: `&ScheduledEmail` - The scheduled email with its address and placeholders
+///
+/// ## Returns
+/// - `Result<String, String>`: The message ID of the sent email or why it was not sent, see
+///   [`send_customer_email`](../client/fn.send_customer_email.html).
+pub async fn send_drip_email(
+    organization: &Organization,
+    step: &DripStep,
+    scheduled: &ScheduledEmail,
+) -> Result<String, String> {
+    let locale: Locale = organization.locale(Some(&scheduled.locale), None);
+
+    send_customer_email(
+        organization,
+        &step.name,
+        step.email_config.localized(&locale),
+        &scheduled.email,
+        &scheduled.placeholders,
+        Vec::new(),
+    ).await
+}
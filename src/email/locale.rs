@@ -0,0 +1,180 @@
+//! ## Email locales
+//!
+//! Every customer email is rendered for a [`Locale`], picked in this order:
+//! 1. The `locale` of the checkout session, unless it is `auto`
+//! 2. The locale spoken in the billing country of the customer, e.g. `DE` is `de`
+//! 3. The default locale of the Organization, `Email.DefaultLocale` (`en` by default)
+//!
+//! The locale picks the translated subject and template of the email, see
+//! [`EmailTranslation`], and how the `{{PaymentAmount}}` and date placeholders are written, see
+//! [money](../../utils/money/index.html) for amounts:
+//!
+//! | Locale | Amount | Date |
+//! |---|---|---|
+//! | `en` | `€1,234.56` | `April 24, 2024` |
+//! | `de` | `1.234,56 €` | `24.04.2024` |
+//! | `nl` | `€ 1.234,56` | `24-04-2024` |
+//! | `fr` | `1 234,56 €` | `24/04/2024` |
+//! | `es`, `it`, `pt` | `1.234,56 €` | `24/04/2024` |
+//!
+//! Other locales are written like `en`.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let locale: Locale = Locale::resolve(session["locale"].as_str(), Some("NL"), "en");
+//! let amount: String = Money::new(123456, "eur").format(&locale);
+//! ```
+
+use crate::utils::format::civil_date;
+
+
+/// The locale emails are rendered in when `Email.DefaultLocale` is not configured
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// The locale spoken in a billing country, by ISO country code
+const COUNTRY_LOCALES: &[(&str, &str)] = &[
+    ("AT", "de"),
+    ("BE", "nl"),
+    ("BR", "pt-BR"),
+    ("CH", "de"),
+    ("DE", "de"),
+    ("ES", "es"),
+    ("FR", "fr"),
+    ("GB", "en-GB"),
+    ("IE", "en-GB"),
+    ("IT", "it"),
+    ("LU", "fr"),
+    ("MX", "es"),
+    ("NL", "nl"),
+    ("PT", "pt"),
+    ("US", "en-US"),
+];
+
+/// Month names for the `en` date format
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+
+/// ## Locale
+/// A BCP 47 language tag like `de` or `pt-BR`
+///
+/// ### Fields
+/// - `tag` - The tag, with a lowercase language and an uppercase region
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Locale {
+    pub tag: String,
+}
+
+
+/// ## EmailTranslation
+/// What an email uses instead of its defaults in one locale, configured under
+/// `Email.<Event>.Locales.<locale>`
+///
+/// ### Fields
+/// - `subject` - The translated subject
+/// - `template_url` - The translated HTML template
+/// - `text_template_url` - The translated plain-text template
+/// - `preheader` - The translated preheader
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmailTranslation {
+    pub subject: Option<String>,
+    pub template_url: Option<String>,
+    pub text_template_url: Option<String>,
+    pub preheader: Option<String>,
+}
+
+
+impl Locale {
+    /// # parse
+    /// Reads a locale like `de`, `pt-BR` or `pt_br`. `auto`, the value Stripe uses when the
+    /// browser decides, is not a locale.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::locale::Locale;
+    /// assert_eq!(Locale::parse("pt_br"), Some(Locale { tag: "pt-BR".to_string() }));
+    /// assert_eq!(Locale::parse("auto"), None);
+    /// ```
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut parts = tag.trim().split(['-', '_']);
+
+        let language: &str = parts.next().filter(|language| {
+            (2..=3).contains(&language.len()) && language.chars().all(|character| character.is_ascii_alphabetic())
+        })?;
+
+        if language.eq_ignore_ascii_case("auto") {
+            return None;
+        }
+
+        let tag: String = match parts.next() {
+            Some(region) if region.len() == 2 && region.chars().all(|character| character.is_ascii_alphabetic()) => {
+                format!("{}-{}", language.to_ascii_lowercase(), region.to_ascii_uppercase())
+            },
+            Some(_) => return None,
+            None => language.to_ascii_lowercase(),
+        };
+
+        Some(Locale { tag })
+    }
+
+    /// # from_country
+    /// The locale spoken in a billing country, `None` for countries without one.
+    pub fn from_country(country: &str) -> Option<Self> {
+        COUNTRY_LOCALES
+            .iter()
+            .find(|(code, _)| code.eq_ignore_ascii_case(country.trim()))
+            .and_then(|(_, tag)| Locale::parse(tag))
+    }
+
+    /// # resolve
+    /// The locale of a customer: the session locale, else the locale of the billing country,
+    /// else the default.
+    ///
+    /// ## Arguments
+    /// - `session_locale`: `Option<&str>` - The `locale` of the checkout session, if any
+    /// - `country`: `Option<&str>` - The ISO code of the billing country, if known
+    /// - `default`: `&str` - The default locale of the Organization
+    pub fn resolve(session_locale: Option<&str>, country: Option<&str>, default: &str) -> Self {
+        session_locale
+            .and_then(Locale::parse)
+            .or(country.and_then(Locale::from_country))
+            .or(Locale::parse(default))
+            .unwrap_or(Locale { tag: DEFAULT_LOCALE.to_string() })
+    }
+
+    /// # language
+    /// The language of the locale, `pt` for `pt-BR`.
+    pub fn language(&self) -> &str {
+        self.tag.split('-').next().unwrap_or(&self.tag)
+    }
+
+    /// # candidates
+    /// The locales translations are looked up under, most specific first: `["pt-BR", "pt"]`.
+    pub fn candidates(&self) -> Vec<String> {
+        match self.language() == self.tag {
+            true => vec![self.tag.clone()],
+            false => vec![self.tag.clone(), self.language().to_string()],
+        }
+    }
+
+    /// # format_date
+    /// Writes the date of a unix timestamp in UTC the way the locale does.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::locale::Locale;
+    /// assert_eq!(Locale::parse("en").unwrap().format_date(1714000000), "April 24, 2024");
+    /// ```
+    pub fn format_date(&self, timestamp: i64) -> String {
+        let (year, month, day): (i64, i64, i64) = civil_date(timestamp);
+
+        match self.language() {
+            "de" => format!("{:02}.{:02}.{}", day, month, year),
+            "nl" => format!("{:02}-{:02}-{}", day, month, year),
+            "fr" | "es" | "it" | "pt" => format!("{:02}/{:02}/{}", day, month, year),
+            _ => format!("{} {}, {}", MONTHS[(month - 1) as usize], day, year),
+        }
+    }
+}
@@ -13,23 +13,44 @@
 //!
 //!
 //! ### Table of contents
+//! - `address` - Normalizing and validating customer addresses
 //! - `client`
+//! - `delivery` - The delivery statuses providers report for the emails in the email log
+//! - `drip` - Sequences of emails sent at delays after a purchase
+//! - `locale` - The locale an email is rendered in and how it writes amounts and dates
+//! - `receipt` - Receipt PDFs generated from a payment
+//! - `reminder` - One renewal or trial ending reminder per subscription period
 //! - `resend`
+//! - `retry` - Retrying failed emails with backoff and falling back to another provider
 //! - `smtp`
+//! - `suppression` - Addresses that bounced or complained and are not emailed anymore
 //! - `templates`
+//! - `attachments` - Invoice and receipt PDFs attached to emails
 //! - `builder`
+//! - `content` - The HTML, plain-text alternative and preheader of an email
 //!
 //!
 //! ### Errors
 //!
 //!
 //! ### Notes
-//! Neither Smtp or Resend is currently implemented
+//! Emails are sent through `resend` or `smtp`, see [retry](retry/index.html) for falling back
+//! from one to the other
 //!
+pub mod address;
+pub mod attachments;
 pub mod builder;
+pub mod content;
 pub mod client;
+pub mod delivery;
+pub mod drip;
+pub mod locale;
+pub mod receipt;
+pub mod reminder;
 pub mod resend;
+pub mod retry;
 pub mod smtp;
+pub mod suppression;
 pub mod templates;
 pub mod utils;
 
@@ -96,6 +117,7 @@ impl Email {}
 /// ### Usage example
 ///
 ///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EmailProvider {
     Resend,
     Smtp,
@@ -105,7 +127,6 @@ pub enum EmailProvider {
 // temp clippy patches FIXME
 #[allow(clippy::should_implement_trait)]
 #[allow(clippy::inherent_to_string)]
-
 impl EmailProvider {
     /// ## From String
     /// This will convert a string into an email provider
@@ -119,6 +140,7 @@ impl EmailProvider {
     /// ### Example
     /// This will convert a string into an email provider
     /// ```rust
+    /// # use stripe_discord::email::EmailProvider;
     /// let provider = EmailProvider::from_str("resend");
     /// ```
     ///
@@ -140,6 +162,7 @@ impl EmailProvider {
     /// ### Example
     /// This will give you the email provider as a string
     /// ```rust
+    /// # use stripe_discord::email::EmailProvider;
     /// let provider = EmailProvider::Resend;
     /// let provider_string = provider.to_string();
     ///
@@ -0,0 +1,180 @@
+//! ## Generated receipt PDFs
+//!
+//! When Stripe has no invoice for a payment, a one page receipt is rendered from the payment
+//! itself. The PDF is written by hand with the standard Helvetica fonts, so no fonts or PDF
+//! libraries are needed. Characters outside of ASCII are replaced with `?`.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let receipt: Receipt = Receipt::from_charge(&charge, "Xylex");
+//! let pdf: Vec<u8> = receipt.to_pdf();
+//! ```
+
+use crate::utils::format::format_date;
+use crate::utils::money::Money;
+
+use serde_json::Value;
+
+
+/// ## Receipt
+/// What a generated receipt shows
+///
+/// ### Fields
+/// - `receipt_number` - The Stripe receipt number, or the id of the payment without one
+/// - `organization` - The name of the seller
+/// - `customer_name` - The name of the customer
+/// - `customer_email` - The email of the customer
+/// - `product` - What was bought
+/// - `amount` - The amount paid in minor units of the currency, see [`Receipt::money`]
+/// - `currency` - The lowercase ISO currency code, e.g. `eur`
+/// - `created_at` - The unix timestamp of the payment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub receipt_number: String,
+    pub organization: String,
+    pub customer_name: String,
+    pub customer_email: String,
+    pub product: String,
+    pub amount: i64,
+    pub currency: String,
+    pub created_at: i64,
+}
+
+
+impl Receipt {
+    /// # from_charge
+    /// Reads a receipt from the `data.object` of a `charge.succeeded` event.
+    ///
+    /// ## Arguments
+    /// - `charge`: `&Value` - The charge
+    /// - `organization`: `&str` - The name of the seller, also the product without a `description`
+    pub fn from_charge(charge: &Value, organization: &str) -> Self {
+        let text = |value: &Value, fallback: &str| -> String { value.as_str().unwrap_or(fallback).to_string() };
+
+        Receipt {
+            receipt_number: text(&charge["receipt_number"], charge["id"].as_str().unwrap_or("unknown")),
+            organization: organization.to_string(),
+            customer_name: text(&charge["billing_details"]["name"], "unknown"),
+            customer_email: text(&charge["billing_details"]["email"], "unknown"),
+            product: text(&charge["description"], organization),
+            amount: charge["amount_captured"].as_i64().or(charge["amount"].as_i64()).unwrap_or(0),
+            currency: text(&charge["currency"], "usd"),
+            created_at: charge["created"].as_i64().unwrap_or(0),
+        }
+    }
+
+    /// # from_checkout_session
+    /// Reads a receipt from the `data.object` of a `checkout.session.completed` event. The
+    /// product is the first of the `line_items` when they are expanded, `metadata.product`
+    /// otherwise, falling back to the name of the seller.
+    ///
+    /// ## Arguments
+    /// - `session`: `&Value` - The checkout session
+    /// - `organization`: `&str` - The name of the seller
+    pub fn from_checkout_session(session: &Value, organization: &str) -> Self {
+        let text = |value: &Value, fallback: &str| -> String { value.as_str().unwrap_or(fallback).to_string() };
+
+        let product: String = session["line_items"]["data"][0]["description"]
+            .as_str()
+            .or(session["metadata"]["product"].as_str())
+            .unwrap_or(organization)
+            .to_string();
+
+        Receipt {
+            receipt_number: text(&session["payment_intent"], session["id"].as_str().unwrap_or("unknown")),
+            organization: organization.to_string(),
+            customer_name: text(&session["customer_details"]["name"], "unknown"),
+            customer_email: text(&session["customer_details"]["email"], "unknown"),
+            product,
+            amount: session["amount_total"].as_i64().unwrap_or(0),
+            currency: text(&session["currency"], "usd"),
+            created_at: session["created"].as_i64().unwrap_or(0),
+        }
+    }
+
+    /// # money
+    /// The amount paid with its currency.
+    pub fn money(&self) -> Money {
+        Money::new(self.amount, &self.currency)
+    }
+
+    /// # filename
+    /// The filename of the receipt, e.g. `receipt-1234-5678.pdf`.
+    pub fn filename(&self) -> String {
+        format!("receipt-{}.pdf", self.receipt_number.replace(|character: char| !character.is_ascii_alphanumeric() && character != '-' && character != '_', ""))
+    }
+
+    /// # lines
+    /// The lines printed below the title of the receipt.
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("Receipt number: {}", self.receipt_number),
+            format!("Date paid: {}", format_date(self.created_at)),
+            format!("Billed to: {} <{}>", self.customer_name, self.customer_email),
+            String::new(),
+            format!("Product: {}", self.product),
+            format!("Amount paid: {}", self.money()),
+        ]
+    }
+
+    /// # to_pdf
+    /// Renders the receipt as a single A4 page PDF.
+    pub fn to_pdf(&self) -> Vec<u8> {
+        render_pdf(&format!("Receipt from {}", self.organization), &self.lines())
+    }
+}
+
+
+/// # render_pdf
+/// Writes a single A4 page PDF with a bold title and lines of text below it.
+fn render_pdf(title: &str, lines: &[String]) -> Vec<u8> {
+    let mut text: String = format!("BT /F1 18 Tf 56 770 Td ({}) Tj ET\nBT /F2 11 Tf 16 TL 56 730 Td\n", escape_pdf_text(title));
+
+    for line in lines {
+        text.push_str(&format!("({}) Tj T*\n", escape_pdf_text(line)));
+    }
+
+    text.push_str("ET");
+
+    let objects: Vec<String> = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 4 0 R /F2 5 0 R >> >> /Contents 6 0 R >>".to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+        format!("<< /Length {} >>\nstream\n{}\nendstream", text.len(), text),
+    ];
+
+    let mut pdf: String = "%PDF-1.4\n".to_string();
+    let mut offsets: Vec<usize> = Vec::new();
+
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", index + 1, object));
+    }
+
+    // the cross-reference table points at the byte offset of every object
+    let xref_offset: usize = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+
+    pdf.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref_offset));
+
+    pdf.into_bytes()
+}
+
+
+/// # escape_pdf_text
+/// Escapes text for a PDF string, replacing what the standard fonts can not show.
+fn escape_pdf_text(text: &str) -> String {
+    text.chars()
+        .map(|character| match character {
+            '(' | ')' | '\\' => format!("\\{}", character),
+            ' '..='~' => character.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,223 @@
+//! ## Period reminders
+//!
+//! Before a subscription period renews the customer gets one reminder with the amount and date,
+//! the `trial_ending` email when a trial converts and the `renewal_reminder` email otherwise.
+//! Reminders come from `customer.subscription.trial_will_end`, `invoice.upcoming` and the scan of
+//! customers whose `end_time` falls within `Email.RenewalReminder.WindowDays`, see the
+//! [scheduler](../../background/scheduler/index.html).
+//!
+//! Every period is settled once in the [period reminders](../../db/operations/period_reminder/index.html),
+//! whichever source comes first sends it and the others skip it. Periods that do not renew, e.g.
+//! a subscription that cancels at the end of the period, are settled without a reminder.
+//!
+//! ### Usage example
+//! ```yaml
+//! Email:
+//!   RenewalReminder:
+//!     Enabled: true
+//!     WindowDays: 7
+//!   TrialEnding:
+//!     Enabled: true
+//! ```
+
+use crate::db::operations::period_reminder::PeriodReminder;
+use crate::email::client::{customer_placeholders, send_event_email};
+use crate::email::locale::Locale;
+use crate::events::signature::unix_now;
+use crate::log::redact::redact_email;
+use crate::organization::model::EmailEvent;
+use crate::overwrite::{
+    overwrite_stripe_customer_country_column_name,
+    overwrite_stripe_customer_end_time_column_name,
+    overwrite_stripe_customer_name_column_name,
+    overwrite_stripe_email_column_name,
+};
+use crate::CustomerId;
+use crate::Organization;
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use supabase_rs::SupabaseClient;
+use tracing::{debug, error, info, warn};
+
+
+/// The `source` of reminders sent by the scan of the `end_time` column
+pub const SCAN_SOURCE: &str = "scan";
+
+
+/// # send_period_reminder
+/// Sends the reminder of a subscription period unless the period is already settled, and settles
+/// it once the reminder is sent.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The Organization that sends the reminder
+/// - `event`: `EmailEvent` - `RenewalReminder` or `TrialEnding`
+/// - `email`: `&str` - The validated address of the customer
+/// - `period_end`: `i64` - The unix timestamp the period ends and renews at
+/// - `locale`: `&Locale` - The locale the reminder is rendered in
+/// - `placeholders`: `&HashMap<String, String>` - The values of the `{{Name}}` placeholders
+/// - `source`: `&str` - The id of the Stripe event, or [`SCAN_SOURCE`]
+/// - `supabase`: `&SupabaseClient` - The client the period is settled with
+///
+/// ## Returns
+/// - `Option<Result<String, String>>`: `None` when the email is disabled or the period already
+///   had its reminder, otherwise the result of
+///   [`send_event_email`](../client/fn.send_event_email.html).
+#[allow(clippy::too_many_arguments)]
+pub async fn send_period_reminder(
+    organization: &Organization,
+    event: EmailEvent,
+    email: &str,
+    period_end: i64,
+    locale: &Locale,
+    placeholders: &HashMap<String, String>,
+    source: &str,
+    supabase: &SupabaseClient,
+) -> Option<Result<String, String>> {
+    organization.email(event)?;
+
+    // a failed lookup sends nothing, the next source or scan tries again
+    match PeriodReminder::find_for_period(email, period_end, supabase.clone()).await {
+        Ok(Some(settled)) => {
+            debug!(email = %redact_email(email), event = event.as_str(), status = %settled.status, source = %settled.source, "Period already settled, skipping the reminder");
+            return None;
+        },
+        Ok(None) => {},
+        Err(error) => return Some(Err(format!("failed to look up the reminders of the period: {}", error))),
+    }
+
+    let sent: Result<String, String> = send_event_email(organization, event, locale, email, placeholders, Vec::new()).await?;
+
+    if let Ok(message_id) = &sent {
+        let reminder: PeriodReminder = PeriodReminder {
+            id: None,
+            organization: organization.name.clone(),
+            email: email.to_string(),
+            template: event.as_str().to_string(),
+            period_end,
+            source: source.to_string(),
+            status: "sent".to_string(),
+            message_id: Some(message_id.clone()),
+            reason: None,
+            created_at: unix_now(),
+        };
+
+        if let Err(error) = reminder.insert(supabase.clone()).await {
+            error!(email = %redact_email(email), event = event.as_str(), %error, "Failed to settle the period of the reminder");
+        }
+    }
+
+    Some(sent)
+}
+
+
+/// # skip_period_reminder
+/// Settles a period that does not renew without a reminder, so neither a webhook nor the scan
+/// sends one for it.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The Organization the subscription belongs to
+/// - `email`: `&str` - The validated address of the customer
+/// - `period_end`: `i64` - The unix timestamp the period ends at
+/// - `source`: `&str` - The id of the Stripe event
+/// - `reason`: `&str` - Why the period does not renew, e.g. `cancel_at_period_end`
+/// - `supabase`: `&SupabaseClient` - The client the period is settled with
+pub async fn skip_period_reminder(
+    organization: &Organization,
+    email: &str,
+    period_end: i64,
+    source: &str,
+    reason: &str,
+    supabase: &SupabaseClient,
+) {
+    match PeriodReminder::find_for_period(email, period_end, supabase.clone()).await {
+        Ok(None) => {},
+        Ok(Some(_)) => return,
+        Err(error) => {
+            error!(email = %redact_email(email), %error, "Failed to look up the reminders of the period");
+            return;
+        },
+    }
+
+    let reminder: PeriodReminder = PeriodReminder {
+        id: None,
+        organization: organization.name.clone(),
+        email: email.to_string(),
+        template: EmailEvent::RenewalReminder.as_str().to_string(),
+        period_end,
+        source: source.to_string(),
+        status: "skipped".to_string(),
+        message_id: None,
+        reason: Some(reason.to_string()),
+        created_at: unix_now(),
+    };
+
+    match reminder.insert(supabase.clone()).await {
+        Ok(_) => info!(email = %redact_email(email), reason, "Period does not renew, no reminder is sent"),
+        Err(error) => error!(email = %redact_email(email), %error, "Failed to settle the period without a reminder"),
+    }
+}
+
+
+/// # remind_ending_customers
+/// Sends the renewal reminder to the paid customers whose `end_time` is within the reminder
+/// window of the Organization and whose period has no reminder yet. The amount is the last
+/// captured `amount_total` in its `currency`, see [`CustomerId::amount_total_of`], customers
+/// without an amount get no `{{PaymentAmount}}`.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The Organization that sends the reminders
+/// - `now`: `i64` - The unix timestamp the window starts at
+/// - `supabase`: `&SupabaseClient` - The client the customers are read with
+///
+/// ## Returns
+/// - `Result<usize, String>`: The number of sent reminders, `0` without a window or renewal
+///   reminder, or why the customers could not be read.
+pub async fn remind_ending_customers(
+    organization: &Organization,
+    now: i64,
+    supabase: &SupabaseClient,
+) -> Result<usize, String> {
+    let window: Duration = match organization.reminder_window {
+        Some(window) if organization.email(EmailEvent::RenewalReminder).is_some() => window,
+        _ => return Ok(0),
+    };
+
+    let customers: Vec<Value> = CustomerId::list_ending_between(now, now.saturating_add(window.as_secs() as i64), supabase.clone())
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let column_name_email: String = overwrite_stripe_email_column_name();
+    let column_name_end_time: String = overwrite_stripe_customer_end_time_column_name();
+    let column_name_name: String = overwrite_stripe_customer_name_column_name();
+    let column_name_country: String = overwrite_stripe_customer_country_column_name();
+
+    let mut sent: usize = 0;
+
+    for customer in customers {
+        let (Some(email), Some(end_time)) = (customer[&column_name_email].as_str(), customer[&column_name_end_time].as_i64()) else {
+            continue;
+        };
+
+        let locale: Locale = organization.locale(None, customer[&column_name_country].as_str());
+
+        let mut placeholders: HashMap<String, String> = customer_placeholders(customer[&column_name_name].as_str().unwrap_or_default(), email);
+        placeholders.insert("RenewalDate".to_string(), locale.format_date(end_time));
+
+        if let Some(amount_total) = CustomerId::amount_total_of(&customer) {
+            placeholders.insert("PaymentAmount".to_string(), amount_total.format(&locale));
+        }
+
+        match send_period_reminder(organization, EmailEvent::RenewalReminder, email, end_time, &locale, &placeholders, SCAN_SOURCE, supabase).await {
+            Some(Ok(message_id)) => {
+                info!(email = %redact_email(email), %message_id, "Renewal reminder sent");
+                sent += 1;
+            },
+            Some(Err(error)) => warn!(email = %redact_email(email), %error, "Renewal reminder failed to send"),
+            None => {},
+        }
+    }
+
+    Ok(sent)
+}
@@ -13,9 +13,136 @@
 //!
 //!
 
+use crate::email::attachments::EmailAttachment;
+use crate::email::retry::EmailError;
 use crate::Organization;
-use crate::EmailConfig;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use dotenv::dotenv;
+use reqwest::{Client, Response, StatusCode};
 use resend_email_rs::{Attachment, MailHtml, ResendClient};
+use serde::Serialize;
+use serde_json::Value;
+use std::env::var;
+use std::time::Duration;
+
+
+/// The Resend API emails are sent with by default
+pub const RESEND_API_URL: &str = "https://api.resend.com";
+
+/// How long sending an email to Resend may take
+pub const RESEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// ## resend_api_url
+/// The base url of the Resend API, `RESEND_API_URL` overrides it e.g. to point at a local fake.
+pub fn resend_api_url() -> String {
+    dotenv().ok();
+
+    var("RESEND_API_URL").unwrap_or(RESEND_API_URL.to_string())
+}
+
+
+/// ## ResendMail
+/// An email as the `/emails` endpoint takes it, Resend sends `html` and `text` together as a
+/// `multipart/alternative` message
+///
+/// ### Fields
+/// - `from` - The sender address
+/// - `to` - The recipient addresses
+/// - `subject` - The subject line
+/// - `html` - The HTML body
+/// - `text` - The plain-text alternative
+/// - `attachments` - The attached files, left out when there are none
+#[derive(Debug, Clone, Serialize)]
+pub struct ResendMail {
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ResendAttachment>,
+}
+
+
+/// ## ResendAttachment
+/// An attachment as Resend takes it, with base64 content
+#[derive(Debug, Clone, Serialize)]
+pub struct ResendAttachment {
+    pub filename: String,
+    pub content: String,
+    pub content_type: String,
+}
+
+
+impl From<&EmailAttachment> for ResendAttachment {
+    fn from(attachment: &EmailAttachment) -> Self {
+        ResendAttachment {
+            filename: attachment.filename.clone(),
+            content: BASE64.encode(&attachment.content),
+            content_type: attachment.content_type.clone(),
+        }
+    }
+}
+
+
+/// ## post_email
+/// Posts an email to the `/emails` endpoint of the Resend API.
+///
+/// ### Arguments
+/// - `api_key`: `&str` - The Resend API key.
+/// - `idempotency_key`: `&str` - The same key for every attempt of the email, Resend sends an
+///   email it already took for the key only once.
+/// - `mail`: `&impl Serialize` - The email, e.g. a [`ResendMail`].
+///
+/// ### Returns
+/// - `Result<String, EmailError>`: The message ID of the sent email or the error Resend answered
+///   with. Timeouts, connection errors, `429` and `5xx` are transient, rejected API keys (`401`,
+///   `403`) unauthorized and other answers permanent.
+///
+/// ### Example
+/// ```rust,ignore
+/// let message_id: String = post_email(&api_key, "email-log-42", &mail).await?;
+/// ```
+pub async fn post_email(
+    api_key: &str,
+    idempotency_key: &str,
+    mail: &impl Serialize,
+) -> Result<String, EmailError> {
+    let response: Response = Client::new()
+        .post(format!("{}/emails", resend_api_url()))
+        .bearer_auth(api_key)
+        .header("Idempotency-Key", idempotency_key)
+        .timeout(RESEND_TIMEOUT)
+        .json(mail)
+        .send()
+        .await
+        .map_err(|error| EmailError::Transient(error.to_string()))?;
+
+    let status: StatusCode = response.status();
+
+    if !status.is_success() {
+        let body: String = response.text().await.unwrap_or_default();
+        let error: String = format!("resend answered {}: {}", status, body);
+
+        return match status {
+            StatusCode::TOO_MANY_REQUESTS => Err(EmailError::Transient(error)),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(EmailError::Unauthorized(error)),
+            status if status.is_server_error() => Err(EmailError::Transient(error)),
+            _ => Err(EmailError::Permanent(error)),
+        };
+    }
+
+    // the email was accepted, sending it again would deliver it twice
+    let email: Value = response.json().await.map_err(|error| EmailError::Permanent(error.to_string()))?;
+
+    email["id"]
+        .as_str()
+        .map(|id| id.to_string())
+        .ok_or(EmailError::Permanent("resend answered without a message id".to_string()))
+}
 
 /// ## authenticate
 /// Creates a new `ResendClient` instance using the provided API key to authenticate with the Resend service.
@@ -27,7 +154,7 @@ use resend_email_rs::{Attachment, MailHtml, ResendClient};
 /// Returns a `ResendClient` instance which can be used to interact with Resend services.
 ///
 /// ### Example: Authenticating with Resend
-/// ```rust
+/// ```rust,ignore
 /// let api_key = "your_resend_api_key".to_string();
 /// let client = authenticate(api_key);
 /// ```
@@ -53,12 +180,12 @@ pub fn authenticate(
 ///
 /// Your list of recipients should be a vector of strings, where each string is an email address.
 /// It could look like this:
-/// ```rust
+/// ```rust,ignore
 /// vec!["email1@domain.com", "email2@domain.com"]
 /// ```
 /// 
 /// For addressing just 1 recipient, you can use a vector with a single email address:
-/// ```rust
+/// ```rust,ignore
 /// vec!["email@domain.com"]
 /// ```
 /// 
@@ -67,7 +194,7 @@ pub fn authenticate(
 /// - `Result<String, String>`: Returns either the message ID of the sent email as `Ok(String)` or an error message as `Err(String)`.
 ///
 /// ### Example: Sending an HTML email
-/// ```rust
+/// ```rust,ignore
 /// use resend_email_rs::{ResendClient, Attachment};
 /// use crate::Organization;
 ///
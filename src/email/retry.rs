@@ -0,0 +1,166 @@
+//! ## Retrying emails and falling back to another provider
+//!
+//! Sending an email is attempted up to `Email.Retry.MaxAttempts` times. Failed attempts are
+//! classified by [`EmailError`]:
+//! - Transient - The provider could not take the email right now: timeouts, connection errors,
+//!   rate limits (`429`), server errors (`5xx`), `4xx` SMTP replies and a missing SMTP host.
+//!   The email is attempted again after a backoff
+//! - Unauthorized - The provider rejected our API key (`401`, `403`) or it is not set. The
+//!   provider is not attempted again, the remaining attempts go to the fallback right away
+//! - Permanent - The email itself is refused, e.g. a validation error (`400`, `422`) or a `5xx`
+//!   SMTP reply. Attempting it again would fail the same way, so sending stops
+//!
+//! After `Email.Retry.FallbackAfter` failures the remaining attempts go to
+//! `Email.Retry.Fallback`, e.g. `smtp` when Resend is down. Every attempt is recorded in the
+//! [email attempts](../../db/operations/email_attempt/index.html).
+//!
+//! Every attempt of an email sends the same `Idempotency-Key` to Resend, so an attempt that timed
+//! out after Resend took it is not delivered twice.
+//!
+//! ### Backoff
+//! The backoff doubles after every failure, starting at `Email.Retry.BackoffMs` and capped at
+//! `Email.Retry.MaxBackoffMs`. Switching to the fallback provider does not wait.
+//!
+//! ### Usage example
+//! ```yaml
+//! Email:
+//!   Provider: resend
+//!   Retry:
+//!     MaxAttempts: 4
+//!     BackoffMs: 500
+//!     MaxBackoffMs: 5000
+//!     Fallback: smtp
+//!     FallbackAfter: 2
+//! ```
+
+use crate::email::EmailProvider;
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+
+/// ## EmailError
+/// Why a provider did not send an email
+///
+/// ### Variants
+/// - `Transient` - Attempting it again, or with another provider, may succeed
+/// - `Unauthorized` - The provider rejected our credentials, only another provider may succeed
+/// - `Permanent` - The email is refused, attempting it again would fail the same way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailError {
+    Transient(String),
+    Unauthorized(String),
+    Permanent(String),
+}
+
+
+impl EmailError {
+    /// # is_transient
+    /// Whether attempting the email again may succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, EmailError::Transient(_))
+    }
+
+    /// # is_unauthorized
+    /// Whether the provider rejected our credentials, so only the fallback provider may succeed.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, EmailError::Unauthorized(_))
+    }
+
+    /// # kind
+    /// `transient`, `unauthorized` or `permanent`, as stored with the attempt.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EmailError::Transient(_) => "transient",
+            EmailError::Unauthorized(_) => "unauthorized",
+            EmailError::Permanent(_) => "permanent",
+        }
+    }
+}
+
+
+impl fmt::Display for EmailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmailError::Transient(error) | EmailError::Unauthorized(error) | EmailError::Permanent(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for EmailError {}
+
+
+/// ## EmailRetry
+/// How often and where an email is attempted, configured under `Email.Retry`
+///
+/// ### Fields
+/// - `max_attempts` - The attempts of an email in total, fallback attempts included, `MaxAttempts` (4)
+/// - `backoff` - The wait after the first failure, `BackoffMs` (500)
+/// - `max_backoff` - The longest wait between attempts, `MaxBackoffMs` (5000)
+/// - `fallback` - The provider the remaining attempts go to, `Fallback` (none)
+/// - `fallback_after` - The failures before the fallback provider is used, `FallbackAfter` (2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailRetry {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub fallback: Option<EmailProvider>,
+    pub fallback_after: u32,
+}
+
+
+impl Default for EmailRetry {
+    fn default() -> Self {
+        EmailRetry {
+            max_attempts: 4,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_millis(5000),
+            fallback: None,
+            fallback_after: 2,
+        }
+    }
+}
+
+
+impl EmailRetry {
+    /// # provider
+    /// The provider of the next attempt after a number of failures.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::retry::EmailRetry;
+    /// # use stripe_discord::email::EmailProvider;
+    /// let retry: EmailRetry = EmailRetry { fallback: Some(EmailProvider::Smtp), ..EmailRetry::default() };
+    ///
+    /// assert_eq!(retry.provider(EmailProvider::Resend, 1), EmailProvider::Resend);
+    /// assert_eq!(retry.provider(EmailProvider::Resend, 2), EmailProvider::Smtp);
+    /// ```
+    pub fn provider(&self, primary: EmailProvider, failures: u32) -> EmailProvider {
+        match self.fallback {
+            Some(fallback) if failures >= self.fallback_after => fallback,
+            _ => primary,
+        }
+    }
+
+
+    /// # backoff
+    /// The wait after a number of failures, doubling from `backoff` up to `max_backoff`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::retry::EmailRetry;
+    /// # use std::time::Duration;
+    /// let retry: EmailRetry = EmailRetry::default();
+    ///
+    /// assert_eq!(retry.backoff(1), Duration::from_millis(500));
+    /// assert_eq!(retry.backoff(3), Duration::from_millis(2000));
+    /// ```
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let doublings: u32 = failures.saturating_sub(1).min(16);
+
+        self.backoff
+            .saturating_mul(2u32.pow(doublings))
+            .min(self.max_backoff)
+    }
+}
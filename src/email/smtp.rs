@@ -1,7 +1,170 @@
 //! ## Mailing over an `SMTP` server
 //!
-//! This module allows you to send emails over an `SMTP` server. You can use this module by setting the `email.Provider` to `smtp` in your `config.yaml` file.
+//! This module allows you to send emails over an `SMTP` server. You can use this module by setting the `email.Provider` to `smtp` in your `config.yaml` file,
+//! or `Email.Retry.Fallback` to `smtp` to only use it when the main provider fails, see [retry](../retry/index.html).
 //!
 //! ### Requirements of the `SMTP` server
 //! - `smtp_host` - The host of the `SMTP` server (the ip or domain)
 //! - `smtp_port` - The port of the `SMTP` server (usually 25, 465, or 587, your email provider will have this information)
+//! - `smtp_email_address` - The account to log in with, `SMTP_USERNAME` takes precedence
+//! - `smtp_password` - The password of the account, no login is attempted without one
+//! - `smtp_tls` - `starttls` (587 and other ports), `tls` (465) or `none` for local relays
+//!
+//! ### Message ids
+//! SMTP servers do not answer with an id, every email gets a `Message-ID` header of its own which
+//! is returned as its message id.
+
+use crate::email::content::EmailContent;
+use crate::email::retry::EmailError;
+use crate::secrets::secret;
+
+use dotenv::dotenv;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::env::var;
+use std::time::Duration;
+
+
+/// How long an SMTP conversation may take
+pub const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The port used without `SMTP_PORT`
+pub const DEFAULT_SMTP_PORT: u16 = 587;
+
+
+/// # send_smtp
+/// Sends an email over the SMTP server configured by the `SMTP_*` environment variables.
+///
+/// ## Arguments
+/// - `from`: `&str` - The sender address
+/// - `to`: `&[String]` - The recipient addresses
+/// - `subject`: `&str` - The subject line
+/// - `content`: `&EmailContent` - The HTML, plain-text alternative and attachments
+///
+/// ## Returns
+/// - `Result<String, EmailError>`: The `Message-ID` of the email, or why it was not sent. `4xx`
+///   replies, timeouts and connection errors are transient, `5xx` replies permanent.
+///
+/// ## Example
+/// ```rust,ignore
+/// let message_id: String = send_smtp("billing@xylex.ai", &["jenny@example.com".to_string()], "Welcome!", &content).await?;
+/// ```
+pub async fn send_smtp(
+    from: &str,
+    to: &[String],
+    subject: &str,
+    content: &EmailContent,
+) -> Result<String, EmailError> {
+    let transport: AsyncSmtpTransport<Tokio1Executor> = smtp_transport()?;
+
+    let domain: &str = from.rsplit_once('@').map(|(_, domain)| domain).unwrap_or("localhost");
+    let message_id: String = format!("{:032x}@{}", rand::random::<u128>(), domain);
+
+    let message: Message = smtp_message(from, to, subject, content, &message_id)
+        .map_err(EmailError::Permanent)?;
+
+    match transport.send(message).await {
+        Ok(_) => Ok(message_id),
+        Err(error) if error.is_permanent() => Err(EmailError::Permanent(format!("smtp refused the email: {}", error))),
+        Err(error) => Err(EmailError::Transient(format!("smtp failed: {}", error))),
+    }
+}
+
+
+/// # check_smtp
+/// Connects to `SMTP_HOST` and logs in like sending would, used by the `/readyz` check.
+///
+/// ## Errors
+/// - `String` - `SMTP_HOST` is not set, or the server could not be reached or refused the login
+pub async fn check_smtp() -> Result<(), String> {
+    let transport: AsyncSmtpTransport<Tokio1Executor> = smtp_transport().map_err(|error| error.to_string())?;
+
+    match transport.test_connection().await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("smtp did not accept the connection".to_string()),
+        Err(error) => Err(format!("smtp failed: {}", error)),
+    }
+}
+
+
+/// # smtp_transport
+/// The transport to `SMTP_HOST`, a missing host is transient so the email can still go out
+/// through another provider.
+fn smtp_transport() -> Result<AsyncSmtpTransport<Tokio1Executor>, EmailError> {
+    dotenv().ok();
+
+    let host: String = var("SMTP_HOST")
+        .map_err(|_| EmailError::Transient("SMTP_HOST is not set, it is needed to send emails over smtp".to_string()))?;
+
+    let port: u16 = var("SMTP_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_SMTP_PORT);
+
+    let tls: String = var("SMTP_TLS").unwrap_or(match port {
+        465 => "tls".to_string(),
+        _ => "starttls".to_string(),
+    });
+
+    let builder = match tls.as_str() {
+        "none" => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host.as_str())),
+        "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&host),
+        _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host),
+    }
+    .map_err(|error| EmailError::Transient(format!("smtp failed: {}", error)))?
+    .port(port)
+    .timeout(Some(SMTP_TIMEOUT));
+
+    let username: Option<String> = var("SMTP_USERNAME").or(var("SMTP_EMAIL_ADDRESS")).ok();
+
+    let builder = match (username, secret("SMTP_PASSWORD")) {
+        (Some(username), Ok(password)) => builder.credentials(Credentials::new(username, password.expose().to_string())),
+        _ => builder,
+    };
+
+    Ok(builder.build())
+}
+
+
+/// # smtp_message
+/// The email as a `multipart/mixed` message of the `multipart/alternative` bodies and the
+/// attachments.
+fn smtp_message(
+    from: &str,
+    to: &[String],
+    subject: &str,
+    content: &EmailContent,
+    message_id: &str,
+) -> Result<Message, String> {
+    let mailbox = |address: &str| -> Result<Mailbox, String> {
+        address.parse().map_err(|error| format!("{} is not a valid address: {}", address, error))
+    };
+
+    let mut builder = Message::builder()
+        .from(mailbox(from)?)
+        .subject(subject)
+        .message_id(Some(format!("<{}>", message_id)));
+
+    for recipient in to {
+        builder = builder.to(mailbox(recipient)?);
+    }
+
+    let bodies: MultiPart = MultiPart::alternative_plain_html(content.text.clone(), content.html.clone());
+
+    if content.attachments.is_empty() {
+        return builder.multipart(bodies).map_err(|error| error.to_string());
+    }
+
+    let mut mixed: MultiPart = MultiPart::mixed().multipart(bodies);
+
+    for attachment in &content.attachments {
+        let content_type: ContentType = ContentType::parse(&attachment.content_type)
+            .unwrap_or(ContentType::parse("application/octet-stream").expect("a valid content type"));
+
+        mixed = mixed.singlepart(Attachment::new(attachment.filename.clone()).body(attachment.content.clone(), content_type));
+    }
+
+    builder.multipart(mixed).map_err(|error| error.to_string())
+}
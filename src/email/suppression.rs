@@ -0,0 +1,398 @@
+//! ## Email suppression
+//!
+//! Addresses that hard-bounce or complain are added to the
+//! [suppression list](../../db/operations/suppression/index.html) by the bounce and complaint
+//! webhooks of the email provider, see [email_webhooks](../../api/email_webhooks/index.html).
+//! Customer emails are not sent to suppressed addresses, [`suppression_reason`] is checked by
+//! `send_event_email` which marks `email_sent` with the reason instead.
+//!
+//! ### Providers
+//! - Resend - `email.bounced` and `email.complained` events, signed by Svix with the
+//!   `RESEND_WEBHOOK_SECRET`, see <https://resend.com/docs/dashboard/webhooks/verify-webhooks-requests>
+//! - SES - `Bounce` and `Complaint` notifications delivered by SNS, both the notification and
+//!   the event publishing formats. SNS signs every message with the certificate at its
+//!   `SigningCertURL`, see <https://docs.aws.amazon.com/sns/latest/dg/sns-verify-signature-of-message.html>
+//!
+//! Transient and undetermined bounces, like a full mailbox, never suppress an address.
+//!
+//! ### Resend signature format
+//! ```text
+//! svix-id: msg_2KWPBgLlAfxdpx2AI54pPJ85f4W
+//! svix-timestamp: 1714000000
+//! svix-signature: v1,K5oZfzN95Z9UVu1EsfQmfVNQhnkZ2pj9o9NDN/H/pI4=
+//! ```
+//! The `v1` signature is the base64 encoded HMAC-SHA256 of `{id}.{timestamp}.{raw body}` keyed
+//! with the base64 decoded part of the secret after `whsec_`.
+//!
+//! ### SNS signature format
+//! The `Signature` is the base64 encoded RSA signature, SHA1 for `SignatureVersion` 1 and SHA256
+//! for 2, of the `Key\nValue\n` lines of the signed fields in alphabetical order:
+//! - `Notification` - `Message`, `MessageId`, `Subject` when there is one, `Timestamp`, `TopicArn`, `Type`
+//! - `SubscriptionConfirmation` and `UnsubscribeConfirmation` - `Message`, `MessageId`,
+//!   `SubscribeURL`, `Timestamp`, `Token`, `TopicArn`, `Type`
+
+use crate::db::operations::suppression::Suppression;
+use crate::email::EmailAddress;
+use crate::events::signature::SIGNATURE_TOLERANCE;
+use crate::secrets::supabase_client;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Public};
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+use serde_json::Value;
+use sha2::Sha256;
+use std::error::Error;
+use std::fmt;
+use tracing::warn;
+
+
+/// The `source` of suppressions reported by Resend
+pub const RESEND_SOURCE: &str = "resend";
+
+/// The `source` of suppressions reported by SES
+pub const SES_SOURCE: &str = "ses";
+
+
+/// ## EmailWebhookError
+/// The reasons a bounce or complaint webhook is rejected
+///
+/// ### Variants
+/// - `MissingHeader` - A signature header was not sent, with its name
+/// - `MalformedHeader` - The timestamp is not a number or there is no `v1` signature
+/// - `TimestampOutsideTolerance` - The signed timestamp is older or newer than [`SIGNATURE_TOLERANCE`]
+/// - `NoMatchingSignature` - None of the `v1` signatures match the payload
+/// - `InvalidSignature` - The SNS signature does not verify against its signing certificate, with why
+/// - `UnknownTopic` - The SNS topic is not `SES_SNS_TOPIC_ARN`, with the topic
+/// - `Malformed` - The body is not the JSON the provider sends, with why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailWebhookError {
+    MissingHeader(String),
+    MalformedHeader,
+    TimestampOutsideTolerance,
+    NoMatchingSignature,
+    InvalidSignature(String),
+    UnknownTopic(String),
+    Malformed(String),
+}
+
+
+impl fmt::Display for EmailWebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmailWebhookError::MissingHeader(name) => write!(f, "{} header is missing", name),
+            EmailWebhookError::MalformedHeader => write!(f, "svix headers are malformed"),
+            EmailWebhookError::TimestampOutsideTolerance => write!(f, "svix-timestamp is outside the tolerance"),
+            EmailWebhookError::NoMatchingSignature => write!(f, "No svix-signature matches the payload"),
+            EmailWebhookError::InvalidSignature(error) => write!(f, "the SNS signature is invalid: {}", error),
+            EmailWebhookError::UnknownTopic(topic) => write!(f, "{} is not the configured SNS topic", topic),
+            EmailWebhookError::Malformed(error) => write!(f, "the webhook body is malformed: {}", error),
+        }
+    }
+}
+
+impl Error for EmailWebhookError {}
+
+
+/// # sign_resend_payload
+/// Computes the base64 encoded `v1` signature of a payload the way Svix does for Resend.
+///
+/// ## Arguments
+/// - `payload`: `&str` - The raw request body
+/// - `id`: `&str` - The `svix-id` of the message
+/// - `timestamp`: `i64` - The `svix-timestamp` that is signed along with the payload
+/// - `secret`: `&str` - The `whsec_` webhook secret
+pub fn sign_resend_payload(payload: &str, id: &str, timestamp: i64, secret: &str) -> String {
+    let mut mac: Hmac<Sha256> = resend_mac(secret);
+    mac.update(format!("{}.{}.{}", id, timestamp, payload).as_bytes());
+
+    BASE64.encode(mac.finalize().into_bytes())
+}
+
+
+/// # verify_resend_signature
+/// Verifies the Svix headers of a Resend webhook against its raw body.
+///
+/// ## Arguments
+/// - `payload`: `&str` - The raw request body, exactly as received
+/// - `id`: `&str` - The `svix-id` header
+/// - `timestamp`: `&str` - The `svix-timestamp` header
+/// - `signatures`: `&str` - The `svix-signature` header, space separated `v1,<signature>` entries
+/// - `secret`: `&str` - The `whsec_` webhook secret
+/// - `now`: `i64` - The current unix timestamp
+///
+/// ## Errors
+/// - [`EmailWebhookError`] - When the headers are malformed, too old or do not match
+pub fn verify_resend_signature(
+    payload: &str,
+    id: &str,
+    timestamp: &str,
+    signatures: &str,
+    secret: &str,
+    now: i64,
+) -> Result<(), EmailWebhookError> {
+    let timestamp: i64 = timestamp.trim().parse().map_err(|_| EmailWebhookError::MalformedHeader)?;
+
+    let signatures: Vec<&str> = signatures
+        .split_whitespace()
+        .filter_map(|signature| signature.strip_prefix("v1,"))
+        .collect();
+
+    if signatures.is_empty() {
+        return Err(EmailWebhookError::MalformedHeader);
+    }
+
+    if (now - timestamp).abs() > SIGNATURE_TOLERANCE {
+        return Err(EmailWebhookError::TimestampOutsideTolerance);
+    }
+
+    for signature in signatures {
+        let Ok(signature) = BASE64.decode(signature) else { continue };
+
+        let mut mac: Hmac<Sha256> = resend_mac(secret);
+        mac.update(format!("{}.{}.{}", id, timestamp, payload).as_bytes());
+
+        // constant time comparison
+        if mac.verify_slice(&signature).is_ok() {
+            return Ok(());
+        }
+    }
+
+    Err(EmailWebhookError::NoMatchingSignature)
+}
+
+
+/// # resend_mac
+/// The HMAC keyed with the secret, the part after `whsec_` is base64 encoded.
+fn resend_mac(secret: &str) -> Hmac<Sha256> {
+    let encoded: &str = secret.strip_prefix("whsec_").unwrap_or(secret);
+    let key: Vec<u8> = BASE64.decode(encoded).unwrap_or(encoded.as_bytes().to_vec());
+
+    Hmac::new_from_slice(&key).expect("HMAC accepts keys of any length")
+}
+
+
+/// # is_sns_url
+/// Whether a url is an `https` url of SNS itself, the only hosts signing certificates are
+/// downloaded from and subscriptions are confirmed at.
+pub fn is_sns_url(url: &str) -> bool {
+    let host: &str = url
+        .strip_prefix("https://")
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or_default();
+
+    host.starts_with("sns.") && host.ends_with(".amazonaws.com") && !host.contains(['@', ':'])
+}
+
+
+/// # sns_string_to_sign
+/// The string SNS signs for a message, see the SNS signature format above.
+///
+/// ## Errors
+/// - `InvalidSignature` - The message type is not signed or a signed field is missing
+pub fn sns_string_to_sign(envelope: &Value) -> Result<String, EmailWebhookError> {
+    let fields: &[&str] = match envelope["Type"].as_str() {
+        Some("Notification") => &["Message", "MessageId", "Subject", "Timestamp", "TopicArn", "Type"],
+        Some("SubscriptionConfirmation") | Some("UnsubscribeConfirmation") => &["Message", "MessageId", "SubscribeURL", "Timestamp", "Token", "TopicArn", "Type"],
+        _ => return Err(EmailWebhookError::InvalidSignature("unknown message type".to_string())),
+    };
+
+    let mut string_to_sign: String = String::new();
+
+    for field in fields {
+        match envelope[field].as_str() {
+            Some(value) => string_to_sign.push_str(&format!("{}\n{}\n", field, value)),
+            None if *field == "Subject" => continue,
+            None => return Err(EmailWebhookError::InvalidSignature(format!("{} is missing", field))),
+        }
+    }
+
+    Ok(string_to_sign)
+}
+
+
+/// # verify_sns_signature
+/// Verifies the `Signature` of an SNS message with the public key of its signing certificate.
+///
+/// ## Arguments
+/// - `envelope`: `&Value` - The SNS message, exactly as received
+/// - `certificate`: `&[u8]` - The PEM certificate downloaded from the `SigningCertURL`
+///
+/// ## Errors
+/// - `InvalidSignature` - The signature version is unknown, the certificate unreadable or the
+///   signature does not match
+pub fn verify_sns_signature(envelope: &Value, certificate: &[u8]) -> Result<(), EmailWebhookError> {
+    let invalid = |error: &str| EmailWebhookError::InvalidSignature(error.to_string());
+
+    let digest: MessageDigest = match envelope["SignatureVersion"].as_str() {
+        Some("1") => MessageDigest::sha1(),
+        Some("2") => MessageDigest::sha256(),
+        _ => return Err(invalid("unknown SignatureVersion")),
+    };
+
+    let signature: Vec<u8> = envelope["Signature"]
+        .as_str()
+        .and_then(|signature| BASE64.decode(signature).ok())
+        .ok_or(invalid("Signature is not base64"))?;
+
+    let public_key: PKey<Public> = X509::from_pem(certificate)
+        .and_then(|certificate| certificate.public_key())
+        .map_err(|_| invalid("the signing certificate is unreadable"))?;
+
+    let string_to_sign: String = sns_string_to_sign(envelope)?;
+
+    let verified: bool = Verifier::new(digest, &public_key)
+        .and_then(|mut verifier| {
+            verifier.update(string_to_sign.as_bytes())?;
+            verifier.verify(&signature)
+        })
+        .unwrap_or(false);
+
+    match verified {
+        true => Ok(()),
+        false => Err(invalid("the signature does not match")),
+    }
+}
+
+
+/// # resend_suppressions
+/// The addresses a Resend webhook event suppresses: every recipient of a hard `email.bounced`
+/// and of an `email.complained`, nothing for other events.
+///
+/// ## Arguments
+/// - `event`: `&Value` - The webhook body
+/// - `now`: `i64` - The unix timestamp the addresses are suppressed at
+pub fn resend_suppressions(event: &Value, now: i64) -> Vec<Suppression> {
+    let data: &Value = &event["data"];
+
+    let (reason, detail): (&str, String) = match event["type"].as_str() {
+        Some("email.bounced") => {
+            let bounce_type: &str = data["bounce"]["type"].as_str().unwrap_or("Permanent");
+
+            // `email.bounced` is a permanent rejection, newer payloads also say so in `bounce.type`
+            if bounce_type != "Permanent" {
+                return Vec::new();
+            }
+
+            match data["bounce"]["subType"].as_str() {
+                Some(sub_type) => ("bounce", format!("{}/{}", bounce_type, sub_type)),
+                None => ("bounce", bounce_type.to_string()),
+            }
+        },
+        Some("email.complained") => ("complaint", "abuse".to_string()),
+        _ => return Vec::new(),
+    };
+
+    let recipients: Vec<&str> = match &data["to"] {
+        Value::Array(recipients) => recipients.iter().filter_map(|recipient| recipient.as_str()).collect(),
+        Value::String(recipient) => vec![recipient.as_str()],
+        _ => Vec::new(),
+    };
+
+    suppressions(recipients, reason, RESEND_SOURCE, &detail, data["email_id"].as_str().unwrap_or_default(), now)
+}
+
+
+/// # ses_suppressions
+/// The addresses an SES notification suppresses: the bounced recipients of a `Permanent`
+/// bounce and the complained recipients of a complaint, nothing for other notifications.
+///
+/// ## Arguments
+/// - `message`: `&Value` - The `Message` of the SNS notification, parsed
+/// - `now`: `i64` - The unix timestamp the addresses are suppressed at
+pub fn ses_suppressions(message: &Value, now: i64) -> Vec<Suppression> {
+    // notifications carry `notificationType`, event publishing carries `eventType`
+    let kind: &str = message["notificationType"]
+        .as_str()
+        .or(message["eventType"].as_str())
+        .unwrap_or_default();
+
+    match kind {
+        "Bounce" => {
+            let bounce: &Value = &message["bounce"];
+
+            if bounce["bounceType"].as_str() != Some("Permanent") {
+                return Vec::new();
+            }
+
+            let detail: String = format!("Permanent/{}", bounce["bounceSubType"].as_str().unwrap_or("General"));
+
+            suppressions(
+                recipient_addresses(&bounce["bouncedRecipients"]),
+                "bounce",
+                SES_SOURCE,
+                &detail,
+                bounce["feedbackId"].as_str().unwrap_or_default(),
+                now
+            )
+        },
+        "Complaint" => {
+            let complaint: &Value = &message["complaint"];
+
+            suppressions(
+                recipient_addresses(&complaint["complainedRecipients"]),
+                "complaint",
+                SES_SOURCE,
+                complaint["complaintFeedbackType"].as_str().unwrap_or("abuse"),
+                complaint["feedbackId"].as_str().unwrap_or_default(),
+                now
+            )
+        },
+        _ => Vec::new(),
+    }
+}
+
+
+/// # recipient_addresses
+/// The `emailAddress` of every recipient in an SES recipient list.
+fn recipient_addresses(recipients: &Value) -> Vec<&str> {
+    recipients
+        .as_array()
+        .map(|recipients| recipients.iter().filter_map(|recipient| recipient["emailAddress"].as_str()).collect())
+        .unwrap_or_default()
+}
+
+
+/// # suppressions
+/// A suppression for every recipient that is a valid address, normalized like the addresses
+/// emails are sent to.
+fn suppressions(recipients: Vec<&str>, reason: &str, source: &str, detail: &str, provider_event_id: &str, now: i64) -> Vec<Suppression> {
+    recipients
+        .into_iter()
+        .filter_map(|recipient| EmailAddress::parse(recipient).ok())
+        .map(|address| Suppression {
+            email: address.email,
+            reason: reason.to_string(),
+            source: source.to_string(),
+            detail: detail.to_string(),
+            provider_event_id: provider_event_id.to_string(),
+            created_at: now,
+        })
+        .collect()
+}
+
+
+/// # suppression_reason
+/// Why emails to an address are suppressed, `None` when they are not. A failing lookup does not
+/// hold emails back.
+///
+/// ## Example
+/// ```rust,ignore
+/// assert_eq!(suppression_reason("jane@example.com").await, Some("suppressed after a bounce (Permanent/General)".to_string()));
+/// ```
+pub async fn suppression_reason(email: &str) -> Option<String> {
+    let supabase = supabase_client().ok()?;
+
+    match Suppression::find(email, supabase).await {
+        Ok(suppression) => suppression.map(|suppression| {
+            format!("suppressed after a {} ({})", suppression.reason, suppression.detail)
+        }),
+        Err(error) => {
+            warn!(%error, "Failed to look up the email suppression list, sending anyway");
+            None
+        },
+    }
+}
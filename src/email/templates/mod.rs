@@ -1,6 +1,8 @@
 //! ## Email Templates Module
 //!
 //! ### Modules
+//! - [source](./source/index.html) - Where templates are loaded from
 //! - [template](./template/index.html)
 //!
+pub mod source;
 pub mod template;
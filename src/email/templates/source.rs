@@ -0,0 +1,299 @@
+//! ## Where email templates are loaded from
+//!
+//! The `template_url` of an [`EmailConfig`](../../../struct.EmailConfig.html) names one of these
+//! [`TemplateSource`]s:
+//! - `https://cdn.example.com/welcome.html` - Downloaded, cached and revalidated with its `ETag`,
+//!   falling back to the compiled-in default named like the file, e.g. `welcome`
+//! - `file:///srv/templates/welcome.html` or `./email/templates/welcome.html` - Read from disk
+//! - `welcome` - `<TemplatesDir>/welcome.html` (`Email.TemplatesDir`, `EMAIL_TEMPLATES_DIR`), falling
+//!   back to the compiled-in default of that name. Localized emails look in
+//!   `<TemplatesDir>/<locale>/welcome.html` first, e.g. `de/welcome.html` or `pt-BR/welcome.html`
+//! - `embedded:welcome` - Always the compiled-in default
+//!
+//! ### Caching remote templates
+//! Every download is kept in memory with its `ETag`. The next send asks with `If-None-Match` and
+//! keeps the cached copy on `304 Not Modified`. When the CDN is down or answers an error the cached
+//! copy is sent anyway. The cache does not survive a restart, a template that was not downloaded
+//! since the process started is sent as the compiled-in default of its file name instead, so an
+//! outage does not block welcome emails. Templates without a default of their name fail until the
+//! CDN is back.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let html: String = TemplateSource::parse("welcome").load(Path::new("./email/templates")).await?;
+//! ```
+
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{Client, Response, StatusCode};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+
+/// The templates directory when `Email.TemplatesDir` is not configured
+pub const DEFAULT_TEMPLATES_DIR: &str = "./email/templates";
+
+/// How long a template download may take before the cached copy is used
+pub const TEMPLATE_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The templates compiled into the binary, by name
+pub const EMBEDDED_TEMPLATES: &[(&str, &str)] = &[
+    ("welcome", include_str!("defaults/welcome.html")),
+    ("receipt", include_str!("defaults/receipt.html")),
+    ("trial_ending", include_str!("defaults/trial_ending.html")),
+    ("renewal_reminder", include_str!("defaults/renewal_reminder.html")),
+    ("payment_failed", include_str!("defaults/payment_failed.html")),
+    ("cancellation", include_str!("defaults/cancellation.html")),
+];
+
+
+/// Remote templates by url, with the `ETag` they were served with
+static REMOTE_TEMPLATES: LazyLock<Mutex<HashMap<String, CachedTemplate>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+
+/// ## CachedTemplate
+/// A downloaded template
+#[derive(Debug, Clone)]
+struct CachedTemplate {
+    etag: Option<String>,
+    html: String,
+}
+
+
+/// ## TemplateSource
+/// Where a template is loaded from
+///
+/// ### Variants
+/// - `Http` - A remote url
+/// - `File` - A path on disk
+/// - `Named` - A name in the templates directory, or of a compiled-in default
+/// - `Embedded` - The name of a compiled-in default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    Http(String),
+    File(PathBuf),
+    Named(String),
+    Embedded(String),
+}
+
+
+impl TemplateSource {
+    /// # parse
+    /// Reads a source from a `template_url`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::templates::source::TemplateSource;
+    /// # use std::path::PathBuf;
+    /// assert_eq!(TemplateSource::parse("welcome"), TemplateSource::Named("welcome".to_string()));
+    /// assert_eq!(TemplateSource::parse("file:///srv/welcome.html"), TemplateSource::File(PathBuf::from("/srv/welcome.html")));
+    /// ```
+    pub fn parse(template_url: &str) -> Self {
+        if template_url.starts_with("http://") || template_url.starts_with("https://") {
+            return TemplateSource::Http(template_url.to_string());
+        }
+
+        if let Some(path) = template_url.strip_prefix("file://") {
+            return TemplateSource::File(PathBuf::from(path));
+        }
+
+        if let Some(name) = template_url.strip_prefix("embedded:") {
+            return TemplateSource::Embedded(name.to_string());
+        }
+
+        let is_name: bool = !template_url.is_empty() && template_url
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '_' || character == '-');
+
+        if is_name {
+            return TemplateSource::Named(template_url.to_string());
+        }
+
+        TemplateSource::File(PathBuf::from(template_url))
+    }
+
+    /// # load
+    /// Loads the template.
+    ///
+    /// ## Arguments
+    /// - `templates_dir`: `&Path` - The directory `Named` templates are looked up in
+    ///
+    /// ## Errors
+    /// - [`TemplateError`] - The template does not exist, can not be read or downloaded
+    pub async fn load(&self, templates_dir: &Path) -> Result<String, TemplateError> {
+        self.load_localized(templates_dir, &[]).await
+    }
+
+    /// # load_localized
+    /// Loads the template, `Named` templates from the directory of the first locale that has
+    /// one, most specific first.
+    ///
+    /// ## Arguments
+    /// - `templates_dir`: `&Path` - The directory `Named` templates are looked up in
+    /// - `locales`: `&[String]` - The locales to look in, e.g. `["pt-BR", "pt"]`
+    ///
+    /// ## Errors
+    /// - [`TemplateError`] - The template does not exist, can not be read or downloaded
+    pub async fn load_localized(&self, templates_dir: &Path, locales: &[String]) -> Result<String, TemplateError> {
+        match self {
+            TemplateSource::Http(url) => match download(url).await {
+                Err(error) => match embedded(url_name(url)) {
+                    Some(html) => {
+                        warn!(template_url = %url, %error, "Email template download failed, sending the embedded default");
+                        Ok(html)
+                    },
+                    None => Err(error),
+                },
+                downloaded => downloaded,
+            },
+            TemplateSource::File(path) => read_file(path).await,
+            TemplateSource::Embedded(name) => embedded(name).ok_or(TemplateError::NotFound(format!("embedded:{}", name))),
+            TemplateSource::Named(name) => {
+                for locale in locales {
+                    let localized: PathBuf = templates_dir.join(locale).join(format!("{}.html", name));
+
+                    if localized.is_file() {
+                        return read_file(&localized).await;
+                    }
+                }
+
+                let path: PathBuf = templates_dir.join(format!("{}.html", name));
+
+                if path.is_file() {
+                    return read_file(&path).await;
+                }
+
+                embedded(name).ok_or(TemplateError::NotFound(format!("{} or the embedded `{}` template", path.display(), name)))
+            },
+        }
+    }
+}
+
+
+/// ## TemplateError
+/// The reasons a template could not be loaded
+///
+/// ### Variants
+/// - `NotFound` - No template exists under the name or path
+/// - `Unreadable` - The file could not be read, with its path and the io error
+/// - `Download` - The download failed, nothing is cached and there is no default of its name,
+///   with the url and the error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    NotFound(String),
+    Unreadable(String, String),
+    Download(String, String),
+}
+
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::NotFound(name) => write!(f, "no template at {}", name),
+            TemplateError::Unreadable(path, error) => write!(f, "failed to read the template {}: {}", path, error),
+            TemplateError::Download(url, error) => write!(f, "failed to download the template {}: {}", url, error),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+
+/// # embedded
+/// The compiled-in default template of a name.
+pub fn embedded(name: &str) -> Option<String> {
+    EMBEDDED_TEMPLATES
+        .iter()
+        .find(|(embedded_name, _)| *embedded_name == name)
+        .map(|(_, html)| html.to_string())
+}
+
+
+/// # url_name
+/// The file name of a template url without its extension, e.g. `welcome` for
+/// `https://cdn.example.com/emails/welcome.html?v=2`.
+fn url_name(url: &str) -> &str {
+    let path: &str = url.split(['?', '#']).next().unwrap_or_default();
+    let file: &str = path.rsplit('/').next().unwrap_or_default();
+
+    file.strip_suffix(".html").unwrap_or(file)
+}
+
+
+/// # read_file
+/// Reads a template from disk.
+async fn read_file(path: &Path) -> Result<String, TemplateError> {
+    tokio::fs::read_to_string(path)
+        .await
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::NotFound => TemplateError::NotFound(path.display().to_string()),
+            _ => TemplateError::Unreadable(path.display().to_string(), error.to_string()),
+        })
+}
+
+
+/// # download
+/// Downloads a remote template, revalidating the cached copy with its `ETag` and falling back to
+/// it when the download fails.
+async fn download(url: &str) -> Result<String, TemplateError> {
+    let cached: Option<CachedTemplate> = REMOTE_TEMPLATES
+        .lock()
+        .expect("template cache lock")
+        .get(url)
+        .cloned();
+
+    let mut request = Client::new()
+        .get(url)
+        .timeout(TEMPLATE_DOWNLOAD_TIMEOUT);
+
+    if let Some(etag) = cached.as_ref().and_then(|cached| cached.etag.as_ref()) {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let downloaded: Result<CachedTemplate, String> = match request.send().await {
+        Ok(response) if response.status() == StatusCode::NOT_MODIFIED && cached.is_some() => {
+            debug!(template_url = %url, "Email template not modified");
+            return Ok(cached.map(|cached| cached.html).unwrap_or_default());
+        },
+        Ok(response) if response.status().is_success() => read_response(response).await,
+        Ok(response) => Err(format!("answered {}", response.status())),
+        Err(error) => Err(error.to_string()),
+    };
+
+    match (downloaded, cached) {
+        (Ok(template), _) => {
+            debug!(template_url = %url, bytes = template.html.len(), "Email template downloaded");
+
+            REMOTE_TEMPLATES
+                .lock()
+                .expect("template cache lock")
+                .insert(url.to_string(), template.clone());
+
+            Ok(template.html)
+        },
+        (Err(error), Some(cached)) => {
+            warn!(template_url = %url, %error, "Email template download failed, sending the cached copy");
+            Ok(cached.html)
+        },
+        (Err(error), None) => Err(TemplateError::Download(url.to_string(), error)),
+    }
+}
+
+
+/// # read_response
+/// Reads a downloaded template with its `ETag`.
+async fn read_response(response: Response) -> Result<CachedTemplate, String> {
+    let etag: Option<String> = response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.to_string());
+
+    let html: String = response.text().await.map_err(|error| error.to_string())?;
+
+    Ok(CachedTemplate { etag, html })
+}
@@ -1,29 +1,184 @@
 //! ## Email template routing
 //!
 //! ### How do i add a new email template?
+//! Drop `<name>.html` in the templates directory (`./email/templates` by default) and set the
+//! `template_url` of the email to `<name>`, or point it at a `file://` path or an HTTP url, see
+//! [source](../source/index.html).
 
 
+use crate::email::content::{escape_html, EmailContent};
+use crate::email::locale::{EmailTranslation, Locale};
+use crate::email::templates::source::{TemplateError, TemplateSource, DEFAULT_TEMPLATES_DIR};
+use crate::ConfigSetup;
 use crate::EmailConfig;
 
-use reqwest::Client;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+
+/// A `{{Name}}` placeholder
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{(\w+)\}\}").expect("valid regex"));
 
 impl EmailConfig {
-    /// # download_email_template
-    pub async fn download_email_template(
+    /// # load_email_template
+    /// Loads the template under `template_url` from its [`TemplateSource`].
+    ///
+    /// ## Errors
+    /// - [`TemplateError`] - The template does not exist, can not be read or downloaded
+    pub async fn load_email_template(
         &self,
-    ) -> Result<String, reqwest::Error> {
-        let client: Client = Client::new();
-        let email_template_url: String = self.template_url.clone();
+    ) -> Result<String, TemplateError> {
+        TemplateSource::parse(&self.template_url)
+            .load_localized(&configured_templates_dir(), &self.locale_candidates())
+            .await
+    }
 
-        let response: String = client.get(email_template_url)
-            .send()
-            .await?
-            .text()
-            .await?;
+    /// # localized
+    /// The email in a locale: the subject and templates of its translation, most specific
+    /// first (`pt-BR` before `pt`), and the untranslated ones otherwise.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let german: EmailConfig = email_config.localized(&Locale::parse("de-AT").unwrap());
+    /// ```
+    pub fn localized(&self, locale: &Locale) -> EmailConfig {
+        let mut localized: EmailConfig = self.clone();
 
-        println!("Email template downloaded: {}", response);
+        // apply the least specific translation first so the most specific one wins
+        for candidate in locale.candidates().iter().rev() {
+            if let Some(translation) = self.translations.get(candidate) {
+                let EmailTranslation { subject, template_url, text_template_url, preheader } = translation.clone();
+
+                localized.subject = subject.unwrap_or(localized.subject);
+                localized.template_url = template_url.unwrap_or(localized.template_url);
+                localized.text_template_url = text_template_url.or(localized.text_template_url);
+                localized.preheader = preheader.or(localized.preheader);
+            }
+        }
+
+        localized.locale = Some(locale.clone());
+        localized
+    }
 
-        Ok(response)
+    /// # locale_candidates
+    /// The locales named templates are looked up under, none without a locale.
+    fn locale_candidates(&self) -> Vec<String> {
+        self.locale.as_ref().map(|locale| locale.candidates()).unwrap_or_default()
     }
 
-}
\ No newline at end of file
+    /// # render
+    /// Loads the HTML template and the text template, if any, populates both with the
+    /// placeholders and adds the preheader. Without a text template the plain-text alternative is
+    /// generated from the HTML. Values come from customers and Stripe, they are escaped in the
+    /// HTML and the preheader and only the text template gets them as they are.
+    ///
+    /// ## Arguments
+    /// - `placeholders`: `&HashMap<String, String>` - The values of the `{{Name}}` placeholders
+    ///
+    /// ## Errors
+    /// - [`TemplateError`] - One of the templates does not exist, can not be read or downloaded
+    pub async fn render(
+        &self,
+        placeholders: &HashMap<String, String>,
+    ) -> Result<EmailContent, TemplateError> {
+        let html: String = populate_html_placeholders(&self.load_email_template().await?, placeholders);
+        let mut content: EmailContent = EmailContent::from_html(html);
+
+        if let Some(text_template_url) = &self.text_template_url {
+            let text_template: String = TemplateSource::parse(text_template_url)
+                .load_localized(&configured_templates_dir(), &self.locale_candidates())
+                .await?;
+
+            content = content.with_text(populate_placeholders(&text_template, placeholders));
+        }
+
+        // the preheader is escaped as a whole when it is added to the HTML
+        if let Some(preheader) = &self.preheader {
+            content = content.with_preheader(&populate_placeholders(preheader, placeholders));
+        }
+
+        Ok(content)
+    }
+
+}
+
+
+/// # configured_templates_dir
+/// The templates directory from the config, or the default one.
+fn configured_templates_dir() -> PathBuf {
+    ConfigSetup::try_new()
+        .map(|config| PathBuf::from(config.templates_dir))
+        .unwrap_or(PathBuf::from(DEFAULT_TEMPLATES_DIR))
+}
+
+
+/// # populate_placeholders
+/// Replaces every `{{Placeholder}}` in the template with its value as it is, for text. The
+/// template is scanned once, so placeholders inside a value are never expanded.
+///
+/// ## Arguments
+/// - `template`: `&str` - The email template containing the placeholders
+/// - `placeholders`: `&HashMap<String, String>` - The placeholder names (without braces) and their values
+///
+/// ## Returns
+/// The populated template, placeholders without a value are left untouched.
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::email::templates::template::populate_placeholders;
+/// # use std::collections::HashMap;
+/// let mut placeholders: HashMap<String, String> = HashMap::new();
+/// placeholders.insert("FirstName".to_string(), "Floris".to_string());
+///
+/// let html: String = populate_placeholders("<p>Hi {{FirstName}}</p>", &placeholders);
+/// assert_eq!(html, "<p>Hi Floris</p>");
+/// ```
+pub fn populate_placeholders(
+    template: &str,
+    placeholders: &HashMap<String, String>,
+) -> String {
+    replace_placeholders(template, placeholders, |value| value.to_string())
+}
+
+
+/// # populate_html_placeholders
+/// Replaces every `{{Placeholder}}` in an HTML template with its escaped value, so names, emails
+/// and decline messages can not add markup or links to the email.
+///
+/// ## Arguments
+/// - `template`: `&str` - The HTML template containing the placeholders
+/// - `placeholders`: `&HashMap<String, String>` - The placeholder names (without braces) and their values
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::email::templates::template::populate_html_placeholders;
+/// # use std::collections::HashMap;
+/// let placeholders: HashMap<String, String> = HashMap::from([("FirstName".to_string(), "<b>Jo</b>".to_string())]);
+///
+/// assert_eq!(populate_html_placeholders("<p>Hi {{FirstName}}</p>", &placeholders), "<p>Hi &lt;b&gt;Jo&lt;/b&gt;</p>");
+/// ```
+pub fn populate_html_placeholders(
+    template: &str,
+    placeholders: &HashMap<String, String>,
+) -> String {
+    replace_placeholders(template, placeholders, escape_html)
+}
+
+
+/// # replace_placeholders
+/// Replaces the placeholders of the template in a single pass, placeholders without a value are
+/// left untouched.
+fn replace_placeholders(
+    template: &str,
+    placeholders: &HashMap<String, String>,
+    render: impl Fn(&str) -> String,
+) -> String {
+    PLACEHOLDER
+        .replace_all(template, |captures: &Captures| match placeholders.get(&captures[1]) {
+            Some(value) => render(value),
+            None => captures[0].to_string(),
+        })
+        .into_owned()
+}
@@ -3,7 +3,7 @@
 //! ## Formatting email related data
 //!
 //! ### Table of contents
-//! - Regex checking if a sender or recipient email CAN be a valid email address
+//! - Checking if a sender or recipient email is a valid email address
 //!
 //! ### Return types
 //!
@@ -13,38 +13,33 @@
 //!
 //!
 //! ### Notes
-//! - This can be disabled by setting the `.env` variable to `ALLOW_DIRTY_EMAIL=1` (This is
-//! disadvised)
+//! - The rules live in [address](../../address/index.html), which also normalizes addresses
 //!
 //!
 //!
 
-// importing regex
-use regex::Regex;
-
 // importing the email object, make sure to implement a to_string
 use crate::email::EmailAddress;
 
 
 impl EmailAddress {
     /// # verify_email
-    /// Checks if the email address stored in this instance matches a standard email format using a regular expression.
+    /// Checks if the email address stored in this instance is a valid email address, see [`EmailAddress::parse`].
     ///
     /// ## Arguments
     /// - `&self`: A reference to the instance of `EmailAddress` containing the email to verify.
     ///
     /// ## Returns
-    /// Returns `true` if the email matches the regular expression, otherwise returns `false`.
+    /// Returns `true` if the email is valid, otherwise returns `false`.
     ///
     /// ## Example: Verifying an email address
     /// ```rust
+    /// # use stripe_discord::email::EmailAddress;
     /// let email = EmailAddress { email: "example@example.com".to_string() };
     /// assert!(email.verify_email());
     /// ```
     pub fn verify_email(&self) -> bool {
-        let email_regex = r"^[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+\.[a-zA-Z0-9-.]+$";
-        let re = Regex::new(email_regex).unwrap();
-        re.is_match(&self.email)
+        EmailAddress::parse(&self.email).is_ok()
     }
 
 
@@ -59,6 +54,7 @@ impl EmailAddress {
     ///
     /// ## Example: Converting an `EmailAddress` instance to a `String`
     /// ```rust
+    /// # use stripe_discord::email::EmailAddress;
     /// let email_address = EmailAddress { email: "example@example.com".to_string() };
     /// assert_eq!(email_address.to_string(), "example@example.com");
     /// ```
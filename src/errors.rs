@@ -37,6 +37,18 @@ impl Display for ConfigError {
                 "Invalid file type, expected .yaml file at path: {}",
                 path
             ),
+            ConfigError::InvalidEndpoint(ref route, ref reason) => write!(
+                f,
+                "Invalid endpoint `{}`: {}",
+                route,
+                reason
+            ),
+            ConfigError::InvalidValue(ref key, ref reason) => write!(
+                f,
+                "Invalid value of `{}`: {}",
+                key,
+                reason
+            ),
         }
     }
 }
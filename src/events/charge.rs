@@ -0,0 +1,146 @@
+//! ## Charge events
+//! Unwraps the `charge.*` event objects into their event scopes
+//!
+//! ### Events
+//! - `charge.failed` - [`ChargeFailed`]
+//! - `charge.refunded` - [`ChargeRefunded`]
+
+use crate::events::{ChargeFailed, ChargeRefunded};
+
+use serde_json::Value;
+
+
+impl ChargeFailed {
+    /// # from_object
+    /// Unwraps the `data.object` of a `charge.failed` event into a `ChargeFailed`.
+    ///
+    /// ## Arguments
+    /// - `object`: `&Value` - The charge object of the event
+    ///
+    /// ## Returns
+    /// A `ChargeFailed`, missing values are filled with `"unknown"`.
+    ///
+    /// The decline code is taken from `outcome.reason` and falls back to `failure_code`, the
+    /// message is taken from `failure_message` and falls back to `outcome.seller_message`.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let charge_failed: ChargeFailed = ChargeFailed::from_object(&object);
+    /// println!("Charge declined with {}", charge_failed.decline_code);
+    /// ```
+    pub fn from_object(object: &Value) -> Self {
+        let billing_details: &Value = object.get("billing_details").unwrap_or(&Value::Null);
+
+        let email: String = billing_details.get("email")
+            .and_then(|email| email.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let name: String = billing_details.get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let country: String = billing_details.get("address")
+            .and_then(|address| address.get("country"))
+            .and_then(|country| country.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let decline_code: String = object.get("outcome")
+            .and_then(|outcome| outcome.get("reason"))
+            .and_then(|reason| reason.as_str())
+            .or_else(|| object.get("failure_code").and_then(|v| v.as_str()))
+            .unwrap_or("unknown")
+            .to_string();
+
+        let decline_message: String = object.get("failure_message")
+            .and_then(|v| v.as_str())
+            .or_else(|| {
+                object.get("outcome")
+                    .and_then(|outcome| outcome.get("seller_message"))
+                    .and_then(|message| message.as_str())
+            })
+            .unwrap_or("unknown")
+            .to_string();
+
+        let payment_intent: Option<String> = object.get("payment_intent")
+            .and_then(|v| v.as_str())
+            .map(|payment_intent| payment_intent.to_string());
+
+        ChargeFailed {
+            email,
+            name,
+            paid_status: false,
+            country,
+            decline_code,
+            decline_message,
+            payment_intent,
+        }
+    }
+}
+
+
+impl ChargeRefunded {
+    /// # from_object
+    /// Unwraps the `data.object` of a `charge.refunded` event into a `ChargeRefunded`.
+    ///
+    /// ## Arguments
+    /// - `object`: `&Value` - The charge object of the event
+    ///
+    /// ## Returns
+    /// A `ChargeRefunded`, the refund reason is taken from the latest refund in `refunds.data`.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let charge_refunded: ChargeRefunded = ChargeRefunded::from_object(&object);
+    /// println!("Refunded {} of {}", charge_refunded.amount_refunded, charge_refunded.amount);
+    /// ```
+    pub fn from_object(object: &Value) -> Self {
+        let customer_id: String = object.get("id")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let email: String = object.get("billing_details")
+            .and_then(|billing_details| billing_details.get("email"))
+            .and_then(|email| email.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let amount: i64 = object.get("amount")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0);
+
+        let amount_refunded: i64 = object.get("amount_refunded")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0);
+
+        let currency: String = object.get("currency")
+            .and_then(|v| v.as_str())
+            .unwrap_or("usd")
+            .to_string();
+
+        let fully_refunded: bool = object.get("refunded")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(amount > 0 && amount_refunded >= amount);
+
+        let refund_reason: String = object.get("refunds")
+            .and_then(|refunds| refunds.get("data"))
+            .and_then(|data| data.get(0))
+            .and_then(|refund| refund.get("reason"))
+            .and_then(|reason| reason.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        ChargeRefunded {
+            customer_id,
+            email,
+            amount,
+            amount_refunded,
+            currency,
+            fully_refunded,
+            refund_reason,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! ## Dispute events
+//! Unwraps the `charge.dispute.*` event objects into their event scopes
+//!
+//! ### Events
+//! - `charge.dispute.created` - [`ChargeDispute`]
+//! - `charge.dispute.closed` - [`ChargeDispute`]
+
+use crate::events::ChargeDispute;
+
+use serde_json::Value;
+
+
+impl ChargeDispute {
+    /// # from_object
+    /// Unwraps the `data.object` of a `charge.dispute.*` event into a `ChargeDispute`.
+    ///
+    /// ## Arguments
+    /// - `object`: `&Value` - The dispute object of the event
+    ///
+    /// ## Returns
+    /// A `ChargeDispute`, missing values are filled with `"unknown"`.
+    ///
+    /// A dispute does not carry billing details, the email is taken from
+    /// `evidence.customer_email_address` when the merchant supplied it.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let dispute: ChargeDispute = ChargeDispute::from_object(&object);
+    /// println!("Dispute {} is {}", dispute.dispute_id, dispute.status);
+    /// ```
+    pub fn from_object(object: &Value) -> Self {
+        let dispute_id: String = object.get("id")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let customer_id: String = object.get("charge")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let email: String = object.get("evidence")
+            .and_then(|evidence| evidence.get("customer_email_address"))
+            .and_then(|email| email.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let amount: i64 = object.get("amount")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0);
+
+        let currency: String = object.get("currency")
+            .and_then(|v| v.as_str())
+            .unwrap_or("usd")
+            .to_string();
+
+        let status: String = object.get("status")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let reason: String = object.get("reason")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        ChargeDispute {
+            dispute_id,
+            customer_id,
+            email,
+            amount,
+            currency,
+            status,
+            reason,
+        }
+    }
+}
@@ -6,12 +6,22 @@
 //! - [charge](charge/index.html)
 //! - [checkout](checkout/index.html)
 //!
+//! ## Receiving and replaying
+//! - [signature](signature/index.html) - Verifying the `Stripe-Signature` header
+//! - [pipeline](pipeline/index.html) - Processing a stored event and recording the attempt
+//! - [fixtures](fixtures/index.html) - Realistic Stripe events for every handled type
+//! - [test_event](test_event/index.html) - Synthetic events with configurable email, amount and price
+//!
 //!
 
-use serde_json::Value;
-
-
+pub mod charge;
+pub mod dispute;
+pub mod fixtures;
+pub mod payment_intent;
+pub mod pipeline;
 pub mod router;
+pub mod signature;
+pub mod test_event;
 
 
 /// ## EventHandler
@@ -30,10 +40,37 @@ pub enum EventHandler {
     CheckoutSessionCompleted,
     ChargeSucceeded,
     ChargeFailed,
+    ChargeRefunded,
+    ChargeDisputeCreated,
+    ChargeDisputeClosed,
+    InvoiceUpcoming,
+    CustomerSubscriptionCreated,
+    CustomerSubscriptionUpdated,
+    CustomerSubscriptionTrialWillEnd,
+    CustomerSubscriptionDeleted,
     Unknown
 }
 
 
+/// The event types [`EventHandler::new`] handles, every other type is `Unknown`
+pub const HANDLED_EVENT_TYPES: [&str; 14] = [
+    "payment_intent.created",
+    "payment_intent.payment_failed",
+    "payment_intent.succeeded",
+    "charge.succeeded",
+    "charge.failed",
+    "charge.refunded",
+    "charge.dispute.created",
+    "charge.dispute.closed",
+    "checkout.session.completed",
+    "invoice.upcoming",
+    "customer.subscription.trial_will_end",
+    "customer.subscription.created",
+    "customer.subscription.updated",
+    "customer.subscription.deleted",
+];
+
+
 #[derive(Debug, Clone)]
 pub struct CheckoutSessionCompleted {
     pub email: String,
@@ -56,6 +93,8 @@ pub struct CheckoutSessionCompleted {
 /// - `paid_status` - The paid status of the user
 /// - `created_at` - The created at timestamp
 /// - `country` - The country of the user
+/// - `decline_code` - The decline code of the last payment error, e.g. `insufficient_funds`
+/// - `decline_message` - The human readable message of the last payment error
 #[derive(Debug, Clone)]
 pub struct PaymentIntentPaymentFailed {
     pub email: String,
@@ -63,6 +102,8 @@ pub struct PaymentIntentPaymentFailed {
     pub paid_status: bool,
     pub created_ad: i64,
     pub country: String,
+    pub decline_code: String,
+    pub decline_message: String,
 }
 
 
@@ -119,12 +160,72 @@ pub struct ChargeSucceeded {
 
 
 /// ## Charge.failed event scope
-/// This struct represents the `charge.succeeded` event
+/// This struct represents the `charge.failed` event
 ///
 /// ### Fields
+/// - `email` - The email of the user
+/// - `name` - The name of the user
 /// - `paid_status` - The paid status of the user
-/// 
+/// - `country` - The country of the user
+/// - `decline_code` - The decline reason of the charge, e.g. `insufficient_funds`
+/// - `decline_message` - The human readable failure message of the charge
+/// - `payment_intent` - The payment intent the charge belongs to, if any
+///
 #[derive(Debug, Clone)]
 pub struct ChargeFailed {
+    pub email: String,
+    pub name: String,
     pub paid_status: bool,
-}
\ No newline at end of file
+    pub country: String,
+    pub decline_code: String,
+    pub decline_message: String,
+    pub payment_intent: Option<String>,
+}
+
+
+/// ## Charge.refunded event scope
+/// This struct represents the `charge.refunded` event
+///
+/// ### Fields
+/// - `customer_id` - The id of the refunded charge, which is the `CustomerId` of the customer
+/// - `email` - The email of the user
+/// - `amount` - The amount of the charge
+/// - `amount_refunded` - The total amount refunded so far, partial refunds add up
+/// - `currency` - The currency of both amounts, e.g. `eur`
+/// - `fully_refunded` - Whether the whole charge has been refunded
+/// - `refund_reason` - The reason of the latest refund, e.g. `requested_by_customer`
+///
+#[derive(Debug, Clone)]
+pub struct ChargeRefunded {
+    pub customer_id: String,
+    pub email: String,
+    pub amount: i64,
+    pub amount_refunded: i64,
+    pub currency: String,
+    pub fully_refunded: bool,
+    pub refund_reason: String,
+}
+
+
+/// ## Charge.dispute.* event scope
+/// This struct represents the `charge.dispute.created` and `charge.dispute.closed` events
+///
+/// ### Fields
+/// - `dispute_id` - The id of the dispute
+/// - `customer_id` - The id of the disputed charge, which is the `CustomerId` of the customer
+/// - `email` - The email of the user as supplied in the dispute evidence
+/// - `amount` - The disputed amount
+/// - `currency` - The currency of the amount, e.g. `eur`
+/// - `status` - The status of the dispute, e.g. `needs_response`, `won` or `lost`
+/// - `reason` - The reason of the dispute, e.g. `fraudulent`
+///
+#[derive(Debug, Clone)]
+pub struct ChargeDispute {
+    pub dispute_id: String,
+    pub customer_id: String,
+    pub email: String,
+    pub amount: i64,
+    pub currency: String,
+    pub status: String,
+    pub reason: String,
+}
@@ -0,0 +1,79 @@
+//! ## PaymentIntent events
+//! Unwraps the `payment_intent.*` event objects into their event scopes
+//!
+//! ### Events
+//! - `payment_intent.payment_failed` - [`PaymentIntentPaymentFailed`]
+
+use crate::events::PaymentIntentPaymentFailed;
+
+use serde_json::Value;
+
+
+impl PaymentIntentPaymentFailed {
+    /// # from_object
+    /// Unwraps the `data.object` of a `payment_intent.payment_failed` event into a `PaymentIntentPaymentFailed`.
+    ///
+    /// ## Arguments
+    /// - `object`: `&Value` - The payment intent object of the event
+    ///
+    /// ## Returns
+    /// A `PaymentIntentPaymentFailed`, missing values are filled with `"unknown"`.
+    ///
+    /// The customer details are read from `last_payment_error.payment_method.billing_details`,
+    /// the email falls back to the `receipt_email` of the payment intent.
+    ///
+    /// ## Example
+    /// ```rust,ignore
+    /// let payment_failed: PaymentIntentPaymentFailed = PaymentIntentPaymentFailed::from_object(&object);
+    /// println!("Payment failed for {}", payment_failed.email);
+    /// ```
+    pub fn from_object(object: &Value) -> Self {
+        let last_payment_error: &Value = object.get("last_payment_error").unwrap_or(&Value::Null);
+
+        let billing_details: &Value = last_payment_error.get("payment_method")
+            .and_then(|payment_method| payment_method.get("billing_details"))
+            .unwrap_or(&Value::Null);
+
+        let email: String = billing_details.get("email")
+            .and_then(|email| email.as_str())
+            .or_else(|| object.get("receipt_email").and_then(|v| v.as_str()))
+            .unwrap_or("unknown")
+            .to_string();
+
+        let name: String = billing_details.get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let country: String = billing_details.get("address")
+            .and_then(|address| address.get("country"))
+            .and_then(|country| country.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        let created_at: i64 = object.get("created")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0);
+
+        let decline_code: String = last_payment_error.get("decline_code")
+            .and_then(|v| v.as_str())
+            .or_else(|| last_payment_error.get("code").and_then(|v| v.as_str()))
+            .unwrap_or("unknown")
+            .to_string();
+
+        let decline_message: String = last_payment_error.get("message")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        PaymentIntentPaymentFailed {
+            email,
+            name,
+            paid_status: false,
+            created_ad: created_at,
+            country,
+            decline_code,
+            decline_message,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! ## Processing stored webhook events
+//!
+//! Both freshly received webhooks and replays from the event log go through [`process_event`], so
+//! a replay behaves exactly like the original delivery: same span, same metrics, same handlers.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let mut event: WebhookEvent = WebhookEvent::get_by_event_id("evt_1P...", supabase.clone())
+//!     .await?
+//!     .ok_or("event not found")?;
+//!
+//! let outcome: WebhookOutcome = process_event(&mut event, organization, supabase).await;
+//! ```
+
+use crate::db::operations::webhook_event::{WebhookEvent, WebhookOutcome};
+use crate::events::signature::unix_now;
+use crate::events::EventHandler;
+use crate::log::redact::redact_payload;
+use crate::metrics::{observe_webhook, time_webhook};
+use crate::Organization;
+
+use prometheus::HistogramTimer;
+use serde_json::Value;
+use supabase_rs::SupabaseClient;
+use tokio::task::JoinError;
+use tracing::{debug, error, info, info_span, Instrument, Span};
+
+
+/// # process_event
+/// Runs a stored event through the `EventHandler` and records the attempt in the event log.
+///
+/// ## Arguments
+/// - `event`: `&mut WebhookEvent` - The stored event, its attempts and outcome are updated
+/// - `organization`: `Organization` - The Organization the event is handled for
+/// - `supabase`: `SupabaseClient` - The client used to record the attempt
+///
+/// ## Returns
+/// - `WebhookOutcome`: `handled`, `ignored` for event types we do not handle, or `failed` when
+///   the body is not valid JSON, a handler returned an error or panicked
+pub async fn process_event(
+    event: &mut WebhookEvent,
+    organization: Organization,
+    supabase: SupabaseClient,
+) -> WebhookOutcome {
+    // every line logged while handling this event carries its id, type and organization
+    let span: Span = info_span!(
+        "webhook",
+        event_id = %event.event_id,
+        event_type = %event.event_type,
+        organization = %organization.name,
+        attempt = event.attempts + 1
+    );
+
+    // observed when the event is processed
+    let _timer: HistogramTimer = time_webhook(&event.event_type);
+
+    let payload: Result<Value, String> = event.payload().map_err(|error| error.to_string());
+
+    let (outcome, failure): (WebhookOutcome, Option<String>) = match payload {
+        Ok(payload) => {
+            debug!(parent: &span, payload = %redact_payload(&payload), "Processing webhook");
+
+            // handlers return the database and Stripe errors they hit, they run in their own task so
+            // a panic is recorded as a failure as well
+            let handled: Result<Result<EventHandler, String>, JoinError> = tokio::spawn(
+                handle_payload(payload, organization, supabase.clone()).instrument(span.clone())
+            ).await;
+
+            match handled {
+                Ok(Ok(EventHandler::Unknown)) => (WebhookOutcome::Ignored, None),
+                Ok(Ok(event_handler)) => {
+                    info!(parent: &span, handler = ?event_handler, "Handled webhook");
+                    (WebhookOutcome::Handled, None)
+                },
+                Ok(Err(error)) => (WebhookOutcome::Failed, Some(error)),
+                Err(join_error) => (WebhookOutcome::Failed, Some(panic_message(join_error))),
+            }
+        },
+        Err(parse_error) => (WebhookOutcome::Failed, Some(parse_error)),
+    };
+
+    if let Some(failure) = &failure {
+        error!(parent: &span, error = %failure, "Webhook failed");
+    }
+
+    observe_webhook(&event.event_type, &outcome.to_string());
+
+    if let Err(error) = event
+        .record_attempt(outcome, failure, unix_now(), supabase)
+        .await
+        .map_err(|error| error.to_string())
+    {
+        error!(parent: &span, %error, "Failed to record the webhook attempt");
+    }
+
+    outcome
+}
+
+
+/// # handle_payload
+/// Owns the payload so the `EventHandler` can run on its own task.
+async fn handle_payload(payload: Value, organization: Organization, supabase: SupabaseClient) -> Result<EventHandler, String> {
+    EventHandler::new(&payload, organization, supabase).await
+}
+
+
+/// # panic_message
+/// Extracts the message of a panicked handler task.
+fn panic_message(join_error: JoinError) -> String {
+    if !join_error.is_panic() {
+        return join_error.to_string();
+    }
+
+    let panic = join_error.into_panic();
+
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+
+    if let Some(message) = panic.downcast_ref::<String>() {
+        return message.clone();
+    }
+
+    "the event handler panicked".to_string()
+}
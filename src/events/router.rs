@@ -1,36 +1,58 @@
 use crate::events::EventHandler;
+use crate::events::ChargeFailed;
+use crate::events::PaymentIntentPaymentFailed;
+use crate::events::ChargeRefunded;
+use crate::events::ChargeDispute;
+use crate::db::operations::audit::AuditEntry;
+use crate::db::operations::quarantine::QuarantinedEmail;
+use crate::discord::access::{grant_access, grant_charge_access};
+use crate::discord::roles::{DiscordRoles, RoleSync};
+use crate::background::spawn_background;
+use crate::log::redact::redact_email;
+use crate::email::address::{normalize_email, CheckedEmail, EmailAddressError};
+use crate::email::client::{customer_placeholders, send_email, send_event_email, send_welcome_email};
+use crate::email::locale::Locale;
+use crate::email::attachments::{receipt_attachments, EmailAttachment};
+use crate::email::content::{escape_html, EmailContent};
+use crate::email::drip::{cancel_drips, schedule_drips};
+use crate::email::receipt::Receipt;
+use crate::email::reminder::{send_period_reminder, skip_period_reminder};
+use crate::api::client::fetch_customer;
+use crate::organization::model::{EmailEvent, InvalidEmailPolicy};
 use crate::CustomerId;
-use crate::utils::format::format_total_amount;
+use crate::utils::money::Money;
 use crate::Organization;
-use crate::email::resend;
-use crate::email::EmailConfig;
-use crate::email::resend::send_email_html;
 
 
-use serde_json::Value;
+use serde_json::{json, Value};
 use supabase_rs::SupabaseClient;
-use std::env::var;
+use std::collections::HashMap;
 use dotenv::dotenv;
-use resend_email_rs::ResendClient;
 
 use tokio::time::sleep;
 use tokio::time::Duration;
-use tokio::spawn;
+use tracing::{debug, error, info, warn};
 
 
 impl EventHandler {
+    /// # new
+    /// Handles a Stripe event for the Organization and returns the handler it went to.
+    ///
+    /// ## Errors
+    /// - `String` - A database or Stripe call the event depends on failed, the event is recorded as
+    ///   `failed` with the error so it can be replayed
     pub async fn new(
         json_data: &Value,
-        organization: Organization
-    ) -> Self {
+        organization: Organization,
+        supabase: SupabaseClient,
+    ) -> Result<Self, String> {
         dotenv().ok();
     
         let event_type: &str = json_data.get("type").and_then(|v| v.as_str()).unwrap_or("unknown");
 
-        // init the supabase client using the standard env vars
-        let supabase: SupabaseClient =
-            SupabaseClient::new(var("SUPABASE_URL").unwrap(), var("SUPABASE_KEY").unwrap());
-
+        // the event id and timestamp for the audit trail
+        let event_id: &str = json_data.get("id").and_then(|v| v.as_str()).unwrap_or("unknown");
+        let created_at: i64 = json_data.get("created").and_then(|v| v.as_i64()).unwrap_or(0);
 
         // unwrapped object
         let object = json_data.get("data")
@@ -41,12 +63,45 @@ impl EventHandler {
             "payment_intent.created" => { 
                 // ghost event, pretty irrelevant for now
 
-                EventHandler::PaymentIntentCreated 
+                Ok(EventHandler::PaymentIntentCreated)
+            },
+            "payment_intent.payment_failed" => {
+                let payment_failed: PaymentIntentPaymentFailed = PaymentIntentPaymentFailed::from_object(object);
+
+                let email: String = match customer_email(&payment_failed.email, event_type, event_id, created_at, &organization, &supabase).await? {
+                    Some(email) => email,
+                    None => return Ok(EventHandler::PaymentIntentPaymentFailed),
+                };
+
+                handle_payment_failed(
+                    &email,
+                    &payment_failed.name,
+                    &payment_failed.decline_code,
+                    &payment_failed.decline_message,
+                    &payment_failed.country,
+                    true,
+                    &organization,
+                    supabase.clone()
+                ).await?;
+
+                Ok(EventHandler::PaymentIntentPaymentFailed)
             },
-            "payment_intent.payment_failed" => { EventHandler::PaymentIntentPaymentFailed },
-            "payment_intent.succeeded" => { EventHandler::PaymentIntentSucceeded },
+            "payment_intent.succeeded" => { Ok(EventHandler::PaymentIntentSucceeded) },
             "charge.succeeded" => {
 
+                // unwrapped email, the customer is keyed by it so nothing is stored without one
+                let email: String = match customer_email(
+                    object["billing_details"]["email"].as_str().unwrap_or_default(),
+                    event_type,
+                    event_id,
+                    created_at,
+                    &organization,
+                    &supabase
+                ).await? {
+                    Some(email) => email,
+                    None => return Ok(EventHandler::ChargeSucceeded),
+                };
+
                 // unwrapped customer_id
                 let customer_id: String = object.get("id")
                     .and_then(|v| v.as_str())
@@ -58,20 +113,13 @@ impl EventHandler {
                     CustomerId {id: customer_id.clone()}, 
                     true, 
                     supabase.clone()
-                ).await.unwrap();
-
-                // unwrapped email
-                let email: String = object.get("billing_details")
-                    .and_then(|billing_details| billing_details.get("email"))
-                    .and_then(|email| email.as_str())
-                    .unwrap_or("unknown")
-                    .to_string();
+                ).await.map_err(|error| error.to_string())?;
 
                 CustomerId::attach_email(
                     CustomerId {id: customer_id.clone()}, 
-                    email, 
+                    email.clone(), 
                     supabase.clone()
-                ).await.unwrap();
+                ).await.map_err(|error| error.to_string())?;
 
                 // unwrapped name
                 let name: String = object.get("billing_details")
@@ -84,18 +132,16 @@ impl EventHandler {
                     CustomerId {id: customer_id.clone()}, 
                     name, 
                     supabase.clone()
-                ).await.unwrap();
+                ).await.map_err(|error| error.to_string())?;
 
-                // unwrapped amount_captured
-                let amount_captured: i64 = object.get("amount_captured")
-                    .and_then(|v| v.as_i64())
-                    .unwrap_or(0);
+                // unwrapped amount_captured in its currency
+                let amount_captured: Money = Money::from_stripe(object, "amount_captured");
 
                 CustomerId::update_amount_total(
                     CustomerId {id: customer_id.clone()}, 
-                    format_total_amount(amount_captured).await, 
+                    &amount_captured, 
                     supabase.clone()
-                ).await.unwrap();
+                ).await.map_err(|error| error.to_string())?;
 
                 // unwrapped country
                 let country: String = object.get("billing_details")
@@ -109,7 +155,7 @@ impl EventHandler {
                     CustomerId {id: customer_id.clone()}, 
                     country, 
                     supabase.clone()
-                ).await.unwrap();
+                ).await.map_err(|error| error.to_string())?;
 
                 // unwrapped receipt_url
                 let receipt_url: String = object.get("receipt_url")
@@ -121,7 +167,7 @@ impl EventHandler {
                     CustomerId {id: customer_id.clone()}, 
                     receipt_url, 
                     supabase.clone()
-                ).await.unwrap();
+                ).await.map_err(|error| error.to_string())?;
 
                 // payment status status: succeeded
                 let payment_status: String = object.get("status")
@@ -134,20 +180,92 @@ impl EventHandler {
                         CustomerId {id: customer_id.clone()}, 
                         true, 
                         supabase.clone()
-                    ).await.unwrap();
+                    ).await.map_err(|error| error.to_string())?;
+
+                    // time-boxed products bought without Checkout, checkout sessions grant their own
+                    if let Some(granted) = grant_charge_access(object, &email, created_at, &organization, &supabase).await {
+                        let end_time: i64 = granted.map_err(|error| format!("failed to grant the time-boxed access: {}", error))?;
+                        info!(email = %redact_email(&email), end_time, "Time-boxed access granted");
+                    }
 
                 } else {
                     CustomerId::update_paid(
                         CustomerId {id: customer_id.clone()}, 
                         false, 
                         supabase.clone()
-                    ).await.unwrap();
+                    ).await.map_err(|error| error.to_string())?;
                 }
 
+                handle_receipt(object, &email, &organization).await;
+
+                Ok(EventHandler::ChargeSucceeded)
+            },
+            "charge.failed" => {
+                let charge_failed: ChargeFailed = ChargeFailed::from_object(object);
+
+                // charges that belong to a payment intent also fire `payment_intent.payment_failed`
+                // which sends the email, so only notify for standalone charges
+                let notify_customer: bool = charge_failed.payment_intent.is_none();
+
+                let email: String = match customer_email(&charge_failed.email, event_type, event_id, created_at, &organization, &supabase).await? {
+                    Some(email) => email,
+                    None => return Ok(EventHandler::ChargeFailed),
+                };
+
+                handle_payment_failed(
+                    &email,
+                    &charge_failed.name,
+                    &charge_failed.decline_code,
+                    &charge_failed.decline_message,
+                    &charge_failed.country,
+                    notify_customer,
+                    &organization,
+                    supabase.clone()
+                ).await?;
+
+                Ok(EventHandler::ChargeFailed)
+            },
+            "charge.refunded" => {
+                let charge_refunded: ChargeRefunded = ChargeRefunded::from_object(object);
+
+                handle_refund(
+                    &charge_refunded,
+                    event_id,
+                    created_at,
+                    &organization,
+                    supabase.clone()
+                ).await?;
+
+                Ok(EventHandler::ChargeRefunded)
+            },
+            "charge.dispute.created" => {
+                let dispute: ChargeDispute = ChargeDispute::from_object(object);
+
+                handle_dispute(
+                    &dispute,
+                    event_type,
+                    event_id,
+                    created_at,
+                    &organization,
+                    supabase.clone()
+                ).await?;
 
-                EventHandler::ChargeSucceeded
+                Ok(EventHandler::ChargeDisputeCreated)
+            },
+            "charge.dispute.closed" => {
+                let dispute: ChargeDispute = ChargeDispute::from_object(object);
+
+                handle_dispute(
+                    &dispute,
+                    event_type,
+                    event_id,
+                    created_at,
+                    &organization,
+                    supabase.clone()
+                ).await?;
+
+                Ok(EventHandler::ChargeDisputeClosed)
             },
-            "charge.failed" => { EventHandler::ChargeFailed },
             "checkout.session.completed" => { 
                 
                 // unwrap payment_link
@@ -157,27 +275,33 @@ impl EventHandler {
                     .to_string();
 
                 // unwrap email
-                let email: String = object.get("customer_details")
-                    .and_then(|customer_details| customer_details.get("email"))
-                    .and_then(|email| email.as_str())
-                    .unwrap_or("unknown")
-                    .to_string();
+                let email: String = match customer_email(
+                    object["customer_details"]["email"].as_str().unwrap_or_default(),
+                    event_type,
+                    event_id,
+                    created_at,
+                    &organization,
+                    &supabase
+                ).await? {
+                    Some(email) => email,
+                    None => return Ok(EventHandler::CheckoutSessionCompleted),
+                };
 
                 CustomerId::cache_payment_link(
                     email.clone(),
                     payment_link.clone(), 
                     supabase.clone()
-                ).await.unwrap();
+                ).await.map_err(|error| error.to_string())?;
                 
                 let email_ghost: String = email.clone();
                 let supabase_ghost: SupabaseClient = supabase.clone();
              
 
-                // spawn a new thread to attach the payment link to the customer as background task
-                spawn(async move {
-                    println!("Scheduling a background task in 5 seconds before attaching payment link to customer");
+                // attach the payment link to the customer as background task
+                spawn_background(async move {
+                    debug!("Scheduling a background task in 5 seconds before attaching payment link to customer");
                     sleep(Duration::from_secs(5)).await;
-                    println!("Attaching payment link to customer");
+                    debug!(email = %redact_email(&email_ghost), "Attaching payment link to customer");
 
                     let _ = CustomerId::attach_payment_link(
                         email_ghost.clone(),
@@ -186,58 +310,830 @@ impl EventHandler {
                     ).await;
 
                 });
-                println!("Payment link attached to customer");
-                
-                sleep(Duration::from_secs(6)).await;
-                // authenticate resend with an env var
-                let resend_client: ResendClient = resend::authenticate(var("RESEND_API_KEY").unwrap());
-
-                let recipient_email: Vec<String> = vec![email.clone()];
-                let subject: String = organization.email_config.subject.to_string();
+                debug!("Payment link attachment scheduled");
 
+                // the session locale picks the translation, else the billing country does
+                let locale: Locale = organization.locale(
+                    object["locale"].as_str(),
+                    object["customer_details"]["address"]["country"].as_str()
+                );
 
-                // get the email template
-                let email_template: String = organization.email_config.download_email_template().await.unwrap();
+                let receipt: Receipt = Receipt::from_checkout_session(object, &organization.name);
 
-                // format the email template
-                let html: String = format!(
-                    "{}",
-                    email_template                   
+                let mut placeholders: HashMap<String, String> = customer_placeholders(
+                    object["customer_details"]["name"].as_str().unwrap_or_default(),
+                    &email
                 );
+                placeholders.insert("PaymentAmount".to_string(), receipt.money().format(&locale));
+                placeholders.insert("ProductName".to_string(), receipt.product);
+                placeholders.insert("PaymentDate".to_string(), locale.format_date(receipt.created_at));
+
+                // start the drip sequence, its steps are sent by the scheduler
+                let scheduled: usize = schedule_drips(
+                    &organization,
+                    object["id"].as_str().unwrap_or(event_id),
+                    &email,
+                    &locale,
+                    &placeholders,
+                    created_at,
+                    &supabase
+                ).await.map_err(|error| format!("failed to schedule the drip sequence: {}", error))?;
+
+                if scheduled > 0 {
+                    info!(email = %redact_email(&email), scheduled, "Drip sequence scheduled");
+                }
+
+                // time-boxed products grant access until `end_time`, the scheduler expires it
+                if let Some(granted) = grant_access(object, &email, created_at, &organization, &supabase).await {
+                    let end_time: i64 = granted.map_err(|error| format!("failed to grant the time-boxed access: {}", error))?;
+                    info!(email = %redact_email(&email), end_time, "Time-boxed access granted");
+                }
+
+                sleep(Duration::from_secs(6)).await;
 
-                let email_sent_status: Result<String, String> = send_email_html(
-                    resend_client,
+                if organization.email(EmailEvent::Welcome).is_none() {
+                    debug!("The welcome email is disabled, skipping");
+
+                    return Ok(EventHandler::CheckoutSessionCompleted);
+                }
+
+                // attach the invoice or a generated receipt when the organization has receipts on
+                let attachments: Vec<EmailAttachment> = receipt_attachments(object, &organization).await;
+
+                // load the welcome template and send it through the configured email provider
+                let email_sent_status: Result<String, String> = send_welcome_email(
                     organization,
-                    recipient_email,
-                    subject,
-                    html,
-                    None
+                    email.clone(),
+                    &locale,
+                    &placeholders,
+                    attachments
                 ).await;
 
-                println!("Email sent status: {:?}", email_sent_status);
-
 
                 if email_sent_status.is_ok() {
 
-                    println!("Email sent successfully");
+                    info!(email = %redact_email(&email), message_id = ?email_sent_status.as_ref().ok(), "Welcome email sent");
                     CustomerId::update_email_sent_status_by_email(
                         email.clone(),
                         true,
+                        None,
                         supabase.clone()
-                    ).await.unwrap();
+                    ).await.map_err(|error| error.to_string())?;
                 } else {
 
-                    println!("Email failed to send");
+                    error!(email = %redact_email(&email), error = ?email_sent_status.as_ref().err(), "Welcome email failed to send");
                     CustomerId::update_email_sent_status_by_email(
                         email.clone(),
                         false,
+                        email_sent_status.err(),
                         supabase.clone()
-                    ).await.unwrap();
+                    ).await.map_err(|error| error.to_string())?;
                 }
         
-                EventHandler::CheckoutSessionCompleted 
+                Ok(EventHandler::CheckoutSessionCompleted)
             },
-            _ => EventHandler::Unknown,
+            "invoice.upcoming" => {
+                handle_renewal_reminder(object, event_id, created_at, &organization, &supabase).await?;
+
+                Ok(EventHandler::InvoiceUpcoming)
+            },
+            "customer.subscription.trial_will_end" => {
+                handle_trial_ending(object, event_id, created_at, &organization, &supabase).await?;
+
+                Ok(EventHandler::CustomerSubscriptionTrialWillEnd)
+            },
+            "customer.subscription.created" => {
+                handle_subscription_period(object, event_type, event_id, created_at, &organization, &supabase).await?;
+
+                Ok(EventHandler::CustomerSubscriptionCreated)
+            },
+            "customer.subscription.updated" => {
+                handle_subscription_period(object, event_type, event_id, created_at, &organization, &supabase).await?;
+
+                Ok(EventHandler::CustomerSubscriptionUpdated)
+            },
+            "customer.subscription.deleted" => {
+                handle_cancellation(object, event_id, created_at, &organization, &supabase).await?;
+
+                Ok(EventHandler::CustomerSubscriptionDeleted)
+            },
+            _ => Ok(EventHandler::Unknown),
         }
     }
-}
\ No newline at end of file
+}
+
+
+/// # handle_payment_failed
+/// Marks the customer as unpaid, stores the decline code and message and sends the payment
+/// failed email when the organization has one configured.
+///
+/// ## Arguments
+/// - `email`: `&str` - The validated email of the customer, see [`customer_email`]
+/// - `name`: `&str` - The full name of the customer
+/// - `decline_code`: `&str` - The decline code of the failed payment
+/// - `decline_message`: `&str` - The decline message of the failed payment
+/// - `country`: `&str` - The billing country of the customer, it picks the locale of the email
+/// - `notify_customer`: `bool` - Whether the payment failed email may be sent for this event
+/// - `organization`: `&Organization` - The organization the payment failed for
+/// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+///
+/// ## Errors
+/// - `String` - The failed payment could not be stored
+#[allow(clippy::too_many_arguments)]
+async fn handle_payment_failed(
+    email: &str,
+    name: &str,
+    decline_code: &str,
+    decline_message: &str,
+    country: &str,
+    notify_customer: bool,
+    organization: &Organization,
+    supabase: SupabaseClient,
+) -> Result<(), String> {
+    CustomerId::update_payment_failed_by_email(
+        email.to_string(),
+        decline_code.to_string(),
+        decline_message.to_string(),
+        supabase
+    ).await.map_err(|error| format!("failed to record the failed payment: {}", error))?;
+
+    if !notify_customer {
+        return Ok(());
+    }
+
+    let mut placeholders: HashMap<String, String> = customer_placeholders(name, email);
+    placeholders.insert("DeclineCode".to_string(), decline_code.to_string());
+    placeholders.insert("DeclineMessage".to_string(), decline_message.to_string());
+
+    let locale: Locale = organization.locale(None, Some(country));
+
+    log_sent(EmailEvent::PaymentFailed, email, send_event_email(organization, EmailEvent::PaymentFailed, &locale, email, &placeholders, Vec::new()).await);
+
+    Ok(())
+}
+
+
+/// # handle_receipt
+/// Sends the receipt email for a successful charge when the organization has it enabled, with a
+/// generated receipt attached when it has receipts on.
+///
+/// ## Arguments
+/// - `charge`: `&Value` - The `data.object` of the `charge.succeeded` event
+/// - `email`: `&str` - The validated email of the customer, see [`customer_email`]
+/// - `organization`: `&Organization` - The organization the charge belongs to
+async fn handle_receipt(charge: &Value, email: &str, organization: &Organization) {
+    if organization.email(EmailEvent::Receipt).is_none() {
+        return;
+    }
+
+    let receipt: Receipt = Receipt::from_charge(charge, &organization.name);
+
+    let locale: Locale = organization.locale(None, charge["billing_details"]["address"]["country"].as_str());
+
+    let mut placeholders: HashMap<String, String> = customer_placeholders(&receipt.customer_name, email);
+    placeholders.insert("PaymentAmount".to_string(), receipt.money().format(&locale));
+    placeholders.insert("ProductName".to_string(), receipt.product.clone());
+    placeholders.insert("PaymentDate".to_string(), locale.format_date(receipt.created_at));
+    placeholders.insert("ReceiptNumber".to_string(), receipt.receipt_number.clone());
+    placeholders.insert("ReceiptUrl".to_string(), charge["receipt_url"].as_str().unwrap_or_default().to_string());
+
+    let attachments: Vec<EmailAttachment> = match organization.attach_receipts {
+        true => vec![EmailAttachment::pdf(receipt.filename(), receipt.to_pdf())],
+        false => Vec::new(),
+    };
+
+    log_sent(EmailEvent::Receipt, email, send_event_email(organization, EmailEvent::Receipt, &locale, email, &placeholders, attachments).await);
+}
+
+
+/// # handle_renewal_reminder
+/// Stores the end of the current period of the customer for an `invoice.upcoming` event and
+/// sends the renewal reminder when the organization has it enabled and the period has none yet.
+/// The renewal date is the next payment attempt, or the end of the invoice period.
+///
+/// ## Arguments
+/// - `invoice`: `&Value` - The `data.object` of the `invoice.upcoming` event
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the subscription belongs to
+/// - `supabase`: `&SupabaseClient` - The client used to interact with the Supabase database
+///
+/// ## Errors
+/// - `String` - The end of the period could not be stored
+async fn handle_renewal_reminder(
+    invoice: &Value,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Result<(), String> {
+    let email: String = match customer_email(
+        invoice["customer_email"].as_str().unwrap_or_default(),
+        "invoice.upcoming",
+        event_id,
+        created_at,
+        organization,
+        supabase
+    ).await? {
+        Some(email) => email,
+        None => return Ok(()),
+    };
+    let email: &str = &email;
+
+    let renewal_date: i64 = invoice["next_payment_attempt"]
+        .as_i64()
+        .or(invoice["period_end"].as_i64())
+        .unwrap_or(0);
+
+    store_end_time(email, renewal_date, supabase).await?;
+
+    let locale: Locale = organization.locale(None, invoice["customer_address"]["country"].as_str());
+
+    let mut placeholders: HashMap<String, String> = customer_placeholders(invoice["customer_name"].as_str().unwrap_or_default(), email);
+    placeholders.insert("PaymentAmount".to_string(), Money::from_stripe(invoice, "amount_due").format(&locale));
+    placeholders.insert("RenewalDate".to_string(), locale.format_date(renewal_date));
+
+    log_sent(EmailEvent::RenewalReminder, email, send_period_reminder(
+        organization,
+        EmailEvent::RenewalReminder,
+        email,
+        renewal_date,
+        &locale,
+        &placeholders,
+        event_id,
+        supabase
+    ).await);
+
+    Ok(())
+}
+
+
+/// # handle_trial_ending
+/// Stores the end of the trial of the customer for a `customer.subscription.trial_will_end`
+/// event and sends the trial ending email when the organization has it enabled and the period
+/// has no reminder yet. The amount is what the items of the subscription cost per period.
+///
+/// ## Arguments
+/// - `subscription`: `&Value` - The `data.object` of the `customer.subscription.trial_will_end` event
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the subscription belongs to
+/// - `supabase`: `&SupabaseClient` - The client used to interact with the Supabase database
+///
+/// ## Errors
+/// - `String` - The customer could not be retrieved or the end of the trial not be stored
+async fn handle_trial_ending(
+    subscription: &Value,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Result<(), String> {
+    let (email, customer): (String, Value) = match subscription_customer(subscription, "customer.subscription.trial_will_end", event_id, created_at, organization, supabase).await? {
+        Some(customer) => customer,
+        None => return Ok(()),
+    };
+    let email: &str = &email;
+
+    let trial_end: i64 = subscription["trial_end"]
+        .as_i64()
+        .or(subscription["current_period_end"].as_i64())
+        .unwrap_or(0);
+
+    store_end_time(email, trial_end, supabase).await?;
+
+    if subscription["cancel_at_period_end"].as_bool() == Some(true) {
+        skip_period_reminder(organization, email, trial_end, event_id, "cancel_at_period_end", supabase).await;
+        return Ok(());
+    }
+
+    let locale: Locale = organization.locale(
+        customer["preferred_locales"][0].as_str(),
+        customer["address"]["country"].as_str()
+    );
+
+    let mut placeholders: HashMap<String, String> = customer_placeholders(customer["name"].as_str().unwrap_or_default(), email);
+    placeholders.insert("PaymentAmount".to_string(), subscription_amount(subscription).format(&locale));
+    placeholders.insert("RenewalDate".to_string(), locale.format_date(trial_end));
+
+    log_sent(EmailEvent::TrialEnding, email, send_period_reminder(
+        organization,
+        EmailEvent::TrialEnding,
+        email,
+        trial_end,
+        &locale,
+        &placeholders,
+        event_id,
+        supabase
+    ).await);
+
+    Ok(())
+}
+
+
+/// # handle_subscription_period
+/// Stores the end of the current period of a created or updated subscription as the `end_time`
+/// of the customer. A subscription that cancels at the end of the period settles the period
+/// without a reminder, it does not renew.
+///
+/// ## Arguments
+/// - `subscription`: `&Value` - The `data.object` of the `customer.subscription.created` or `.updated` event
+/// - `event_type`: `&str` - The type of the Stripe event
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the subscription belongs to
+/// - `supabase`: `&SupabaseClient` - The client used to interact with the Supabase database
+///
+/// ## Errors
+/// - `String` - The customer could not be retrieved or the end of the period not be stored
+async fn handle_subscription_period(
+    subscription: &Value,
+    event_type: &str,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Result<(), String> {
+    let Some(period_end) = subscription["current_period_end"].as_i64() else {
+        debug!(event_id, "Subscription without a current period, skipping");
+        return Ok(());
+    };
+
+    let email: String = match subscription_customer(subscription, event_type, event_id, created_at, organization, supabase).await? {
+        Some((email, _)) => email,
+        None => return Ok(()),
+    };
+
+    store_end_time(&email, period_end, supabase).await?;
+
+    if subscription["cancel_at_period_end"].as_bool() == Some(true) {
+        skip_period_reminder(organization, &email, period_end, event_id, "cancel_at_period_end", supabase).await;
+    }
+
+    Ok(())
+}
+
+
+/// # store_end_time
+/// Stores when the current period of the customer ends, logs when there is no customer for the
+/// address.
+///
+/// ## Errors
+/// - `String` - The customer could not be looked up or the end not be stored
+async fn store_end_time(email: &str, end_time: i64, supabase: &SupabaseClient) -> Result<(), String> {
+    if end_time <= 0 {
+        return Ok(());
+    }
+
+    let customers: Vec<Value> = CustomerId::search(Some(email), None, 1, supabase.clone())
+        .await
+        .map_err(|error| format!("failed to look up the customer: {}", error))?;
+
+    if customers.is_empty() {
+        warn!(email = %redact_email(email), "No customer for the subscription, its period end is not stored");
+        return Ok(());
+    }
+
+    CustomerId::update_end_time_by_email(email.to_string(), end_time, supabase.clone())
+        .await
+        .map_err(|error| format!("failed to store the end of the subscription period: {}", error))
+}
+
+
+/// # subscription_amount
+/// What the items of a subscription cost per period, in the currency of the subscription.
+fn subscription_amount(subscription: &Value) -> Money {
+    let minor_units: i64 = subscription["items"]["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|item| item["price"]["unit_amount"].as_i64().unwrap_or(0) * item["quantity"].as_i64().unwrap_or(1))
+        .sum();
+
+    Money::new(minor_units, subscription["currency"].as_str().unwrap_or("usd"))
+}
+
+
+/// # handle_cancellation
+/// Sends the cancellation confirmation for a deleted subscription when the organization has it
+/// enabled and stops its drip sequence for the customer. The email is taken from
+/// `metadata.email`, or retrieved from the Stripe customer.
+///
+/// ## Arguments
+/// - `subscription`: `&Value` - The `data.object` of the `customer.subscription.deleted` event
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the subscription belongs to
+/// - `supabase`: `&SupabaseClient` - The client invalid addresses are quarantined with
+///
+/// ## Errors
+/// - `String` - The customer could not be retrieved
+async fn handle_cancellation(
+    subscription: &Value,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Result<(), String> {
+    if organization.email(EmailEvent::Cancellation).is_none() && organization.drip_sequence.is_empty() {
+        return Ok(());
+    }
+
+    let (email, customer): (String, Value) = match subscription_customer(subscription, "customer.subscription.deleted", event_id, created_at, organization, supabase).await? {
+        Some(customer) => customer,
+        None => return Ok(()),
+    };
+    let email: &str = &email;
+
+    cancel_drips(organization, email, "customer.subscription.deleted", supabase).await;
+
+    let end_date: i64 = subscription["ended_at"]
+        .as_i64()
+        .or(subscription["current_period_end"].as_i64())
+        .unwrap_or(0);
+
+    // customers retrieved from Stripe carry their preferred locales and address
+    let locale: Locale = organization.locale(
+        customer["preferred_locales"][0].as_str(),
+        customer["address"]["country"].as_str()
+    );
+
+    let mut placeholders: HashMap<String, String> = customer_placeholders(customer["name"].as_str().unwrap_or_default(), email);
+    placeholders.insert("EndDate".to_string(), locale.format_date(end_date));
+
+    log_sent(EmailEvent::Cancellation, email, send_event_email(organization, EmailEvent::Cancellation, &locale, email, &placeholders, Vec::new()).await);
+
+    Ok(())
+}
+
+
+/// # subscription_customer
+/// The validated address of the customer of a subscription and the customer itself. The address
+/// is taken from `metadata.email`, or from the Stripe customer retrieved with the key of the
+/// organization, which also carries the name, preferred locales and address.
+///
+/// ## Arguments
+/// - `subscription`: `&Value` - The `data.object` of a `customer.subscription.*` event
+/// - `event_type`: `&str` - The type of the Stripe event
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the subscription belongs to
+/// - `supabase`: `&SupabaseClient` - The client invalid addresses are quarantined with
+///
+/// ## Returns
+/// - `Option<(String, Value)>`: The normalized address and the customer, `None` when there is no
+///   address to use
+///
+/// ## Errors
+/// - `String` - The customer could not be retrieved from Stripe or the address not be quarantined
+async fn subscription_customer(
+    subscription: &Value,
+    event_type: &str,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Result<Option<(String, Value)>, String> {
+    let customer: Value = match (subscription["metadata"]["email"].as_str(), subscription["customer"].as_str()) {
+        (Some(email), _) => json!({ "email": email }),
+        (None, Some(customer_id)) => {
+            let customer: Result<Value, String> = match organization.stripe_api_key() {
+                Ok(api_key) => fetch_customer(customer_id, &api_key).await,
+                Err(error) => Err(error.to_string()),
+            };
+
+            customer.map_err(|error| format!("failed to retrieve the customer of the subscription: {}", error))?
+        },
+        (None, None) => Value::Null,
+    };
+
+    let email: Option<String> = customer_email(
+        customer["email"].as_str().unwrap_or_default(),
+        event_type,
+        event_id,
+        created_at,
+        organization,
+        supabase
+    ).await?;
+
+    Ok(email.map(|email| (email, customer)))
+}
+
+
+/// # customer_email
+/// Checks the address Stripe sent for a customer with the `EmailValidation` of the organization
+/// before anything is stored or emailed. Invalid addresses are quarantined or dropped by its
+/// `InvalidEmailPolicy`.
+///
+/// ## Arguments
+/// - `raw`: `&str` - The address as Stripe sent it, empty when it sent none
+/// - `event_type`: `&str` - The type of the Stripe event that carried the address
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the customer belongs to
+/// - `supabase`: `&SupabaseClient` - The client invalid addresses are quarantined with
+///
+/// ## Returns
+/// - `Option<String>`: The normalized address, `None` when there is none to use
+///
+/// ## Errors
+/// - `String` - An invalid address could not be quarantined
+async fn customer_email(
+    raw: &str,
+    event_type: &str,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: &SupabaseClient,
+) -> Result<Option<String>, String> {
+    let error: EmailAddressError = match organization.email_validation.check(raw).await {
+        CheckedEmail::Valid(email) => return Ok(Some(email)),
+        CheckedEmail::Dirty(email) => {
+            warn!(email = %redact_email(&email), event_type, "Using an invalid email because ALLOW_DIRTY_EMAIL is set");
+            return Ok(Some(email));
+        },
+        CheckedEmail::Invalid(EmailAddressError::Missing) => {
+            warn!(event_type, event_id, "Event without a customer email, skipping");
+            return Ok(None);
+        },
+        CheckedEmail::Invalid(error) => error,
+    };
+
+    match organization.email_validation.invalid {
+        InvalidEmailPolicy::Reject => {
+            warn!(email = %redact_email(raw), event_type, event_id, %error, "Rejected an invalid customer email");
+        },
+        InvalidEmailPolicy::Quarantine => {
+            warn!(email = %redact_email(raw), event_type, event_id, %error, "Quarantining an invalid customer email");
+
+            let quarantined: QuarantinedEmail = QuarantinedEmail {
+                email: raw.to_string(),
+                reason: error.to_string(),
+                event_id: event_id.to_string(),
+                event_type: event_type.to_string(),
+                created_at,
+            };
+
+            quarantined
+                .insert(supabase.clone())
+                .await
+                .map_err(|error| format!("failed to quarantine the customer email: {}", error))?;
+        },
+    }
+
+    Ok(None)
+}
+
+
+/// # log_sent
+/// Logs whether the email of a lifecycle moment was sent, nothing when it is disabled.
+fn log_sent(event: EmailEvent, email: &str, email_sent_status: Option<Result<String, String>>) {
+    match email_sent_status {
+        Some(Ok(message_id)) => info!(email = %redact_email(email), event = event.as_str(), %message_id, "Email sent"),
+        Some(Err(error)) => error!(email = %redact_email(email), event = event.as_str(), %error, "Email failed to send"),
+        None => debug!(event = event.as_str(), "Email disabled, skipping"),
+    }
+}
+
+
+/// # handle_refund
+/// Stores the refunded amount on the customer, applies the `RefundPolicy` of the organization to
+/// the paid status and the Discord role, stops its drip sequence for the customer, appends the
+/// refund to the audit trail and notifies the operator.
+///
+/// ## Arguments
+/// - `charge_refunded`: `&ChargeRefunded` - The unwrapped `charge.refunded` event
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the refund belongs to
+/// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+///
+/// ## Errors
+/// - `String` - The refund could not be stored on the customer, the Discord role could not be
+///   synced or the refund could not be appended to the audit trail
+async fn handle_refund(
+    charge_refunded: &ChargeRefunded,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: SupabaseClient,
+) -> Result<(), String> {
+    let refund_status: &str = if charge_refunded.fully_refunded { "full" } else { "partial" };
+    let amount_refunded: Money = Money::new(charge_refunded.amount_refunded, &charge_refunded.currency);
+    let paid: Option<bool> = organization.refund_policy.paid_after_refund(charge_refunded.fully_refunded);
+
+    if let Some(customer) = stored_customer(&charge_refunded.customer_id, &supabase).await? {
+        CustomerId::update_refund(
+            CustomerId { id: charge_refunded.customer_id.clone() },
+            &amount_refunded,
+            refund_status.to_string(),
+            paid,
+            supabase.clone()
+        ).await.map_err(|error| format!("failed to record the refund: {}", error))?;
+
+        sync_role(organization, &customer, paid).await?;
+    } else {
+        warn!(customer_id = %charge_refunded.customer_id, "Refund of a charge without a customer, only auditing it");
+    }
+
+    let audit_entry: AuditEntry = AuditEntry {
+        customer_id: charge_refunded.customer_id.clone(),
+        email: normalize_email(&charge_refunded.email).unwrap_or_default(),
+        event_id: event_id.to_string(),
+        event_type: "charge.refunded".to_string(),
+        amount: amount_refunded.minor_units,
+        currency: amount_refunded.currency.clone(),
+        status: refund_status.to_string(),
+        reason: charge_refunded.refund_reason.clone(),
+        created_at,
+    };
+
+    // a refunded customer gets no more onboarding emails
+    cancel_drips(organization, &audit_entry.email, "charge.refunded", &supabase).await;
+
+    audit_entry
+        .insert(supabase)
+        .await
+        .map_err(|error| format!("failed to append the refund to the audit trail: {}", error))?;
+
+    notify_operator(
+        organization,
+        format!("[{}] {} refund for {}", organization.name, refund_status, audit_entry.email_or_unknown()),
+        &audit_entry,
+        paid
+    ).await;
+
+    Ok(())
+}
+
+
+/// # handle_dispute
+/// Stores the dispute status and reason on the customer, applies the `DisputePolicy` of the
+/// organization to the paid status and the Discord role, appends the dispute to the audit trail
+/// and notifies the operator.
+///
+/// ## Arguments
+/// - `dispute`: `&ChargeDispute` - The unwrapped `charge.dispute.*` event
+/// - `event_type`: `&str` - Either `charge.dispute.created` or `charge.dispute.closed`
+/// - `event_id`: `&str` - The id of the Stripe event
+/// - `created_at`: `i64` - The unix timestamp of the Stripe event
+/// - `organization`: `&Organization` - The organization the dispute belongs to
+/// - `supabase`: `SupabaseClient` - The client used to interact with the Supabase database
+///
+/// ## Errors
+/// - `String` - The dispute could not be stored on the customer, the Discord role could not be
+///   synced or the dispute could not be appended to the audit trail
+async fn handle_dispute(
+    dispute: &ChargeDispute,
+    event_type: &str,
+    event_id: &str,
+    created_at: i64,
+    organization: &Organization,
+    supabase: SupabaseClient,
+) -> Result<(), String> {
+    let closed: bool = event_type == "charge.dispute.closed";
+    let amount: Money = Money::new(dispute.amount, &dispute.currency);
+    let paid: Option<bool> = organization.dispute_policy.paid_after_dispute(closed, &dispute.status);
+
+    if let Some(customer) = stored_customer(&dispute.customer_id, &supabase).await? {
+        CustomerId::update_dispute(
+            CustomerId { id: dispute.customer_id.clone() },
+            dispute.status.clone(),
+            dispute.reason.clone(),
+            paid,
+            supabase.clone()
+        ).await.map_err(|error| format!("failed to record the dispute: {}", error))?;
+
+        sync_role(organization, &customer, paid).await?;
+    } else {
+        warn!(dispute_id = %dispute.dispute_id, customer_id = %dispute.customer_id, "Dispute of a charge without a customer, only auditing it");
+    }
+
+    let audit_entry: AuditEntry = AuditEntry {
+        customer_id: dispute.customer_id.clone(),
+        email: normalize_email(&dispute.email).unwrap_or_default(),
+        event_id: event_id.to_string(),
+        event_type: event_type.to_string(),
+        amount: amount.minor_units,
+        currency: amount.currency.clone(),
+        status: dispute.status.clone(),
+        reason: dispute.reason.clone(),
+        created_at,
+    };
+
+    audit_entry
+        .insert(supabase)
+        .await
+        .map_err(|error| format!("failed to append the dispute to the audit trail: {}", error))?;
+
+    notify_operator(
+        organization,
+        format!("[{}] Dispute {} is {}", organization.name, dispute.dispute_id, dispute.status),
+        &audit_entry,
+        paid
+    ).await;
+
+    Ok(())
+}
+
+
+/// # stored_customer
+/// The stored customer of the charge, refunds and disputes of charges we never stored are only
+/// audited.
+///
+/// ## Errors
+/// - `String` - The customer could not be looked up
+async fn stored_customer(customer_id: &str, supabase: &SupabaseClient) -> Result<Option<Value>, String> {
+    CustomerId::search(None, Some(customer_id), 1, supabase.clone())
+        .await
+        .map(|customers| customers.into_iter().next())
+        .map_err(|error| format!("failed to look up the customer: {}", error))
+}
+
+
+/// # sync_role
+/// Gives or removes the Discord role of the customer when a refund or dispute policy changed its
+/// paid status. Customers without a linked Discord account and Organizations without Discord
+/// settings are skipped.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The organization whose Discord role is synced
+/// - `customer`: `&Value` - The stored customer
+/// - `paid`: `Option<bool>` - The paid status the policy applied, `None` leaves the role untouched
+///
+/// ## Errors
+/// - `String` - Discord did not accept the role change, the event fails so it can be replayed
+async fn sync_role(organization: &Organization, customer: &Value, paid: Option<bool>) -> Result<(), String> {
+    let (Some(paid), Some(discord_user_id)) = (paid, CustomerId::discord_user_id(customer)) else {
+        return Ok(());
+    };
+
+    let roles: DiscordRoles = match organization.discord_roles() {
+        Ok(roles) => roles,
+        Err(error) => {
+            warn!(%discord_user_id, %error, "Paid status changed without syncing the Discord role");
+            return Ok(());
+        },
+    };
+
+    let role: RoleSync = roles
+        .sync_member(&discord_user_id, paid)
+        .await
+        .map_err(|error| format!("failed to sync the Discord role: {}", error))?;
+
+    info!(%discord_user_id, paid, ?role, "Discord role synced after a refund or dispute");
+
+    Ok(())
+}
+
+
+/// # notify_operator
+/// Emails the audit entry to the operator of the organization, does nothing when the
+/// organization has no `operator_email`.
+///
+/// ## Arguments
+/// - `organization`: `&Organization` - The organization to notify the operator of
+/// - `subject`: `String` - The subject of the notification
+/// - `audit_entry`: `&AuditEntry` - The refund or dispute to report
+/// - `paid`: `Option<bool>` - The paid status the policy applied, if any
+async fn notify_operator(
+    organization: &Organization,
+    subject: String,
+    audit_entry: &AuditEntry,
+    paid: Option<bool>,
+) {
+    let operator_email: String = match &organization.operator_email {
+        Some(operator_email) => operator_email.clone(),
+        None => return,
+    };
+
+    let paid_change: String = match paid {
+        Some(paid) => format!("paid set to {}", paid),
+        None => "paid left unchanged".to_string(),
+    };
+
+    // everything but the policy comes from Stripe and may hold markup
+    let html: String = format!(
+        "<p><b>{}</b></p><ul><li>Customer: {} ({})</li><li>Amount: {}</li><li>Status: {}</li><li>Reason: {}</li><li>Stripe event: {}</li><li>Entitlement: {}</li></ul>",
+        escape_html(&audit_entry.event_type),
+        escape_html(&audit_entry.customer_id),
+        escape_html(audit_entry.email_or_unknown()),
+        escape_html(&audit_entry.money().to_string()),
+        escape_html(&audit_entry.status),
+        escape_html(&audit_entry.reason),
+        escape_html(&audit_entry.event_id),
+        paid_change
+    );
+
+    let email_sent_status: Result<String, String> = send_email(
+        organization.clone(),
+        vec![operator_email],
+        subject,
+        EmailContent::from_html(html)
+    ).await;
+
+    match email_sent_status {
+        Ok(message_id) => info!(%message_id, "Operator notification sent"),
+        Err(error) => error!(%error, "Operator notification failed to send"),
+    }
+}
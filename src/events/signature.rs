@@ -0,0 +1,152 @@
+//! ## Verifying Stripe webhook signatures
+//!
+//! Stripe signs every webhook with the endpoint's `whsec_` secret and sends the signature in the
+//! `Stripe-Signature` header, see <https://docs.stripe.com/webhooks#verify-manually>.
+//!
+//! ### Header format
+//! ```text
+//! Stripe-Signature: t=1492774577,v1=5257a869e7ecebeda32affa62cdca3fa51cad7e77a0e56ff536d0ce8e108d8bd
+//! ```
+//! The `v1` signature is the hex encoded HMAC-SHA256 of `{t}.{raw body}` keyed with the secret,
+//! there can be more than one `v1` while a secret is being rolled.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! verify_signature(&raw_body, &signature_header, &secret, unix_now())?;
+//! ```
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::error::Error;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// How old a signed timestamp may be before the webhook is rejected as a replay, in seconds
+pub const SIGNATURE_TOLERANCE: i64 = 300;
+
+
+/// ## SignatureError
+/// The reasons a webhook signature is rejected
+///
+/// ### Variants
+/// - `MissingHeader` - The `Stripe-Signature` header was not sent
+/// - `MalformedHeader` - The header has no timestamp or no `v1` signature
+/// - `TimestampOutsideTolerance` - The signed timestamp is older or newer than [`SIGNATURE_TOLERANCE`]
+/// - `NoMatchingSignature` - None of the `v1` signatures match the payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    MissingHeader,
+    MalformedHeader,
+    TimestampOutsideTolerance,
+    NoMatchingSignature,
+}
+
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignatureError::MissingHeader => write!(f, "Stripe-Signature header is missing"),
+            SignatureError::MalformedHeader => write!(f, "Stripe-Signature header is malformed"),
+            SignatureError::TimestampOutsideTolerance => write!(f, "Stripe-Signature timestamp is outside the tolerance"),
+            SignatureError::NoMatchingSignature => write!(f, "No Stripe-Signature matches the payload"),
+        }
+    }
+}
+
+impl Error for SignatureError {}
+
+
+/// # sign_payload
+/// Computes the hex encoded `v1` signature of a payload the way Stripe does.
+///
+/// ## Arguments
+/// - `payload`: `&str` - The raw request body
+/// - `timestamp`: `i64` - The unix timestamp that is signed along with the payload
+/// - `secret`: `&str` - The `whsec_` webhook secret
+pub fn sign_payload(payload: &str, timestamp: i64, secret: &str) -> String {
+    let mut mac: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+
+/// # signature_header
+/// Builds a `Stripe-Signature` header value for a payload, e.g. to send signed test events.
+///
+/// ## Example
+/// ```rust,ignore
+/// let header: String = signature_header(&body, unix_now(), "whsec_test");
+/// // t=1714000000,v1=...
+/// ```
+pub fn signature_header(payload: &str, timestamp: i64, secret: &str) -> String {
+    format!("t={},v1={}", timestamp, sign_payload(payload, timestamp, secret))
+}
+
+
+/// # verify_signature
+/// Verifies a `Stripe-Signature` header against the raw body of a webhook.
+///
+/// ## Arguments
+/// - `payload`: `&str` - The raw request body, exactly as received
+/// - `header`: `&str` - The value of the `Stripe-Signature` header
+/// - `secret`: `&str` - The `whsec_` webhook secret
+/// - `now`: `i64` - The current unix timestamp
+///
+/// ## Errors
+/// - [`SignatureError`] - When the header is malformed, too old or does not match
+pub fn verify_signature(
+    payload: &str,
+    header: &str,
+    secret: &str,
+    now: i64,
+) -> Result<(), SignatureError> {
+    let mut timestamp: Option<i64> = None;
+    let mut signatures: Vec<&str> = Vec::new();
+
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {},
+        }
+    }
+
+    let timestamp: i64 = timestamp.ok_or(SignatureError::MalformedHeader)?;
+
+    if signatures.is_empty() {
+        return Err(SignatureError::MalformedHeader);
+    }
+
+    if (now - timestamp).abs() > SIGNATURE_TOLERANCE {
+        return Err(SignatureError::TimestampOutsideTolerance);
+    }
+
+    for signature in signatures {
+        let Ok(signature) = hex::decode(signature) else { continue };
+
+        let mut mac: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+
+        // constant time comparison
+        if mac.verify_slice(&signature).is_ok() {
+            return Ok(());
+        }
+    }
+
+    Err(SignatureError::NoMatchingSignature)
+}
+
+
+/// # unix_now
+/// The current unix timestamp in seconds.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,292 @@
+//! ## Synthetic test events
+//!
+//! Builds Stripe-shaped events to fire at a running instance while developing, without the Stripe
+//! CLI or a network. Charges and checkouts start from the [fixtures](../fixtures/index.html) so
+//! they look exactly like what Stripe sends, subscriptions are built from scratch.
+//!
+//! Every built event gets fresh ids so it is never mistaken for a redelivery of an earlier one.
+//!
+//! ### Where the options end up
+//! - `email` - `billing_details.email` on charges, `customer_details.email` on checkouts and
+//!   `metadata.email` on subscriptions, which carry no email of their own
+//! - `amount` - In minor units, the charge `amount`, checkout `amount_total` and price `unit_amount`
+//! - `price` - The price of the checkout line item and the subscription item
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let event: Value = TestEvent::new("checkout.session.completed")?
+//!     .with_email("jane@example.com".to_string())
+//!     .with_amount(1999)
+//!     .with_price("price_1PmonthlyPlan".to_string())
+//!     .build();
+//! ```
+
+use crate::events::fixtures::fixture;
+use crate::events::signature::unix_now;
+
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// The event types test events can be built for
+pub const TEST_EVENT_TYPES: [&str; 6] = [
+    "checkout.session.completed",
+    "charge.succeeded",
+    "customer.subscription.created",
+    "customer.subscription.updated",
+    "customer.subscription.trial_will_end",
+    "customer.subscription.deleted",
+];
+
+
+/// Makes ids built within the same nanosecond unique
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+
+/// Length of a subscription period, 30 days in seconds
+const SUBSCRIPTION_PERIOD: i64 = 30 * 24 * 60 * 60;
+
+/// How long before the end of a trial `customer.subscription.trial_will_end` fires, 3 days in seconds
+const TRIAL_WILL_END: i64 = 3 * 24 * 60 * 60;
+
+
+/// ## TestEvent
+/// The options of a synthetic Stripe event
+///
+/// ### Fields
+/// - `event_type`: `String` - One of [`TEST_EVENT_TYPES`]
+/// - `email`: `String` - The customer email, defaults to the fixture customer
+/// - `amount`: `i64` - The amount in minor units, defaults to `5000`
+/// - `currency`: `String` - The lowercase ISO currency, defaults to `eur`
+/// - `price`: `String` - The Stripe price id, defaults to `price_test_default`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestEvent {
+    pub event_type: String,
+    pub email: String,
+    pub amount: i64,
+    pub currency: String,
+    pub price: String,
+}
+
+
+impl TestEvent {
+    /// # new
+    /// Creates the options of a test event with the defaults of the fixtures.
+    ///
+    /// ## Arguments
+    /// - `event_type`: `&str` - One of [`TEST_EVENT_TYPES`]
+    ///
+    /// ## Errors
+    /// - `String` - When test events cannot be built for the event type
+    pub fn new(event_type: &str) -> Result<TestEvent, String> {
+        if !TEST_EVENT_TYPES.contains(&event_type) {
+            return Err(format!(
+                "cannot build `{}` test events, supported types are {}",
+                event_type,
+                TEST_EVENT_TYPES.join(", ")
+            ));
+        }
+
+        Ok(TestEvent {
+            event_type: event_type.to_string(),
+            email: "jenny.rosen@example.com".to_string(),
+            amount: 5000,
+            currency: "eur".to_string(),
+            price: "price_test_default".to_string(),
+        })
+    }
+
+
+    /// # with_email
+    /// Sets the email of the customer.
+    pub fn with_email(mut self, email: String) -> TestEvent {
+        self.email = email;
+
+        self
+    }
+
+
+    /// # with_amount
+    /// Sets the amount in minor units, e.g. `1999` for €19.99.
+    pub fn with_amount(mut self, amount: i64) -> TestEvent {
+        self.amount = amount;
+
+        self
+    }
+
+
+    /// # with_currency
+    /// Sets the lowercase ISO currency, e.g. `usd`.
+    pub fn with_currency(mut self, currency: String) -> TestEvent {
+        self.currency = currency.to_lowercase();
+
+        self
+    }
+
+
+    /// # with_price
+    /// Sets the Stripe price id of the purchased item.
+    pub fn with_price(mut self, price: String) -> TestEvent {
+        self.price = price;
+
+        self
+    }
+
+
+    /// # build
+    /// Builds the Stripe event, created now and with fresh ids.
+    ///
+    /// ## Returns
+    /// - `Value`: The event as Stripe would post it to the webhook endpoint
+    pub fn build(&self) -> Value {
+        let created: i64 = unix_now();
+        let suffix: String = unique_suffix();
+
+        let object: Value = match self.event_type.as_str() {
+            "charge.succeeded" => self.charge(&suffix, created),
+            "checkout.session.completed" => self.checkout_session(&suffix, created),
+            _ => self.subscription(&suffix, created),
+        };
+
+        let mut data: Value = json!({ "object": object });
+
+        if self.event_type == "customer.subscription.updated" {
+            data["previous_attributes"] = json!({ "status": "trialing" });
+        }
+
+        json!({
+            "id": format!("evt_test_{}", suffix),
+            "object": "event",
+            "api_version": "2024-04-10",
+            "created": created,
+            "livemode": false,
+            "pending_webhooks": 1,
+            "request": { "id": null, "idempotency_key": null },
+            "type": self.event_type,
+            "data": data,
+        })
+    }
+
+
+    /// # charge
+    /// The `charge.succeeded` fixture with the options applied.
+    fn charge(&self, suffix: &str, created: i64) -> Value {
+        let mut charge: Value = fixture("charge.succeeded").expect("charge.succeeded fixture")["data"]["object"].take();
+
+        charge["id"] = json!(format!("ch_test_{}", suffix));
+        charge["amount"] = json!(self.amount);
+        charge["amount_captured"] = json!(self.amount);
+        charge["currency"] = json!(self.currency);
+        charge["created"] = json!(created);
+        charge["customer"] = json!(format!("cus_test_{}", suffix));
+        charge["billing_details"]["email"] = json!(self.email);
+        charge["receipt_email"] = json!(self.email);
+        charge["payment_intent"] = json!(format!("pi_test_{}", suffix));
+        charge["balance_transaction"] = json!(format!("txn_test_{}", suffix));
+
+        charge
+    }
+
+
+    /// # checkout_session
+    /// The `checkout.session.completed` fixture with the options applied and an expanded line item.
+    fn checkout_session(&self, suffix: &str, created: i64) -> Value {
+        let mut session: Value = fixture("checkout.session.completed").expect("checkout.session.completed fixture")["data"]["object"].take();
+
+        session["id"] = json!(format!("cs_test_{}", suffix));
+        session["amount_subtotal"] = json!(self.amount);
+        session["amount_total"] = json!(self.amount);
+        session["currency"] = json!(self.currency);
+        session["created"] = json!(created);
+        session["customer"] = json!(format!("cus_test_{}", suffix));
+        session["customer_details"]["email"] = json!(self.email);
+        session["payment_intent"] = json!(format!("pi_test_{}", suffix));
+        session["line_items"] = json!({
+            "object": "list",
+            "has_more": false,
+            "data": [{
+                "id": format!("li_test_{}", suffix),
+                "object": "item",
+                "amount_subtotal": self.amount,
+                "amount_total": self.amount,
+                "currency": self.currency,
+                "price": self.price_object(None),
+                "quantity": 1,
+            }],
+        });
+
+        session
+    }
+
+
+    /// # subscription
+    /// A subscription in the state the event type describes.
+    fn subscription(&self, suffix: &str, created: i64) -> Value {
+        let deleted: bool = self.event_type == "customer.subscription.deleted";
+        let trialing: bool = self.event_type == "customer.subscription.trial_will_end";
+
+        // a trial ends with the current period, the event fires three days before it does
+        let period_end: i64 = match trialing {
+            true => created + TRIAL_WILL_END,
+            false => created + SUBSCRIPTION_PERIOD,
+        };
+
+        json!({
+            "id": format!("sub_test_{}", suffix),
+            "object": "subscription",
+            "cancel_at_period_end": false,
+            "canceled_at": if deleted { json!(created) } else { Value::Null },
+            "created": created,
+            "currency": self.currency,
+            "current_period_start": created,
+            "current_period_end": period_end,
+            "customer": format!("cus_test_{}", suffix),
+            "ended_at": if deleted { json!(created) } else { Value::Null },
+            "items": {
+                "object": "list",
+                "has_more": false,
+                "data": [{
+                    "id": format!("si_test_{}", suffix),
+                    "object": "subscription_item",
+                    "created": created,
+                    "price": self.price_object(Some("month")),
+                    "quantity": 1,
+                }],
+            },
+            "latest_invoice": format!("in_test_{}", suffix),
+            "livemode": false,
+            "metadata": { "email": self.email },
+            "status": if deleted { "canceled" } else if trialing { "trialing" } else { "active" },
+            "trial_end": if trialing { json!(period_end) } else { Value::Null },
+        })
+    }
+
+
+    /// # price_object
+    /// The price of the purchased item, recurring when an interval is given.
+    fn price_object(&self, interval: Option<&str>) -> Value {
+        json!({
+            "id": self.price,
+            "object": "price",
+            "active": true,
+            "currency": self.currency,
+            "product": "prod_test_default",
+            "recurring": interval.map(|interval| json!({ "interval": interval, "interval_count": 1 })),
+            "type": if interval.is_some() { "recurring" } else { "one_time" },
+            "unit_amount": self.amount,
+        })
+    }
+}
+
+
+/// # unique_suffix
+/// A suffix for the ids of a built event, unique within and across runs.
+fn unique_suffix() -> String {
+    let nanos: u128 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+
+    format!("{:x}{:x}", nanos, ID_COUNTER.fetch_add(1, Ordering::Relaxed))
+}
@@ -31,11 +31,44 @@
 //! ## Overwriting the default Supabase table names and column names
 //! You can overwrite the default Supabase table names and column names by setting the following environment variables:
 //! - `OVERWRITE_STRIPE_CUSTOMER_TABLE_NAME` (default: `stripe_customer_data`) to overwrite the default table name for the customer data in Supabase
-//!
-//!
-//!
-//! REMINDER TO HANDLE ENV VARIABLE:
-//! ALLOW_DIRTY_EMAIL=1
+//! - `OVERWRITE_STRIPE_CUSTOMER_DECLINE_CODE_COLUMN_NAME` (default: `decline_code`) to overwrite the column that stores the decline code of the last failed payment
+//! - `OVERWRITE_STRIPE_CUSTOMER_DECLINE_MESSAGE_COLUMN_NAME` (default: `decline_message`) to overwrite the column that stores the decline message of the last failed payment
+//! - `OVERWRITE_STRIPE_CUSTOMER_AMOUNT_REFUNDED_COLUMN_NAME` (default: `amount_refunded`), `OVERWRITE_STRIPE_CUSTOMER_REFUND_STATUS_COLUMN_NAME` (default: `refund_status`)
+//! - `OVERWRITE_STRIPE_CUSTOMER_DISPUTE_STATUS_COLUMN_NAME` (default: `dispute_status`), `OVERWRITE_STRIPE_CUSTOMER_DISPUTE_REASON_COLUMN_NAME` (default: `dispute_reason`)
+//! - `OVERWRITE_STRIPE_CUSTOMER_DISCORD_USER_ID_COLUMN_NAME` (default: `discord_user_id`) to overwrite the column that stores the linked Discord user id
+//! - `OVERWRITE_STRIPE_CUSTOMER_AUDIT_TABLE_NAME` (default: `stripe_customer_audit`) to overwrite the table of the refund and dispute audit trail
+//! - `OVERWRITE_STRIPE_WEBHOOK_EVENTS_TABLE_NAME` (default: `stripe_webhook_events`) to overwrite the table of the webhook event log
+//! - `OVERWRITE_STRIPE_API_KEYS_TABLE_NAME` (default: `stripe_api_keys`) to overwrite the table of the issued API keys
+//! - `OVERWRITE_STRIPE_EMAIL_QUARANTINE_TABLE_NAME` (default: `stripe_email_quarantine`) to overwrite the table of quarantined customer addresses
+//! - `OVERWRITE_STRIPE_EMAIL_SUPPRESSIONS_TABLE_NAME` (default: `stripe_email_suppressions`) to overwrite the table of bounced and complained addresses
+//! - `OVERWRITE_STRIPE_CUSTOMER_EMAIL_SENT_REASON_COLUMN_NAME` (default: `email_sent_reason`) to overwrite the column that stores why the last email was not sent
+//! - `OVERWRITE_STRIPE_EMAIL_LOG_TABLE_NAME` (default: `stripe_email_log`) to overwrite the table of sent emails and their delivery status
+//! - `OVERWRITE_STRIPE_EMAIL_ATTEMPTS_TABLE_NAME` (default: `stripe_email_attempts`) to overwrite the table of every attempt to send an email
+//! - `OVERWRITE_STRIPE_SCHEDULED_EMAILS_TABLE_NAME` (default: `stripe_scheduled_emails`) to overwrite the table of pending drip emails
+//! - `OVERWRITE_STRIPE_PERIOD_REMINDERS_TABLE_NAME` (default: `stripe_period_reminders`) to overwrite the table of the reminder of every subscription period
+//! - `OVERWRITE_STRIPE_CUSTOMER_AMOUNT_TOTAL_MINOR_COLUMN_NAME` (default: `amount_total_minor`) to overwrite the column that stores the captured amount in minor units, the older `amount_total` column in major units is only read for rows without it
+//! - `OVERWRITE_STRIPE_CUSTOMER_CURRENCY_COLUMN_NAME` (default: `currency`) to overwrite the column that stores the currency of `amount_total_minor` and `amount_refunded`, both in minor units
+//! - `OVERWRITE_STRIPE_CUSTOMER_ACCESS_STATUS_COLUMN_NAME` (default: `access_status`) to overwrite the column that stores whether time-boxed access is `active` or `expired`
+//! - `OVERWRITE_STRIPE_CUSTOMER_ACCESS_START_TIME_COLUMN_NAME` (default: `access_start_time`), `OVERWRITE_STRIPE_CUSTOMER_ACCESS_END_TIME_COLUMN_NAME` (default: `access_end_time`) to overwrite the columns that store when time-boxed access started and ends, apart from the `end_time` of subscriptions
+//! - `OVERWRITE_STRIPE_CUSTOMER_ACCESS_ORGANIZATION_COLUMN_NAME` (default: `access_organization`) to overwrite the column that stores the name of the Organization that granted time-boxed access, its Discord role is revoked once the access ends
+//!
+//!
+//! ## Email validation
+//! Customer addresses are trimmed, get a lowercase IDNA domain and are validated before they are
+//! stored or emailed, see [address](email/address/index.html). Invalid addresses are quarantined
+//! or rejected by `Email.Validation.InvalidAddress`, `Email.Validation.CheckMx: true` also looks
+//! up the MX records of the domain (through `EMAIL_DNS_RESOLVER_URL`).
+//! - `ALLOW_DIRTY_EMAIL=1` lets addresses that fail the format checks through as they are (disadvised)
+//!
+//!
+//! ## Bounces and complaints
+//! Resend and SES report hard bounces and complaints to `/email_webhooks`, the addresses are
+//! added to the suppression list and no longer emailed, see [suppression](email/suppression/index.html).
+//! The same webhooks update the delivery status of every email in the email log, see
+//! [delivery](email/delivery/index.html).
+//! - `RESEND_WEBHOOK_SECRET` - The `whsec_` signing secret of the Resend webhook
+//! - `SES_SNS_TOPIC_ARN` - The comma separated SNS topics that are accepted, their messages are
+//!   verified with the SNS signing certificate
 //!
 //!
 //! ## Required Environment Variables
@@ -50,7 +83,10 @@
 //! - `SMTP_EMAIL_ADDRESS`
 //!
 //! ## Automatic Emails
-//! When automatic emails are enabled, you can choose between Resend or SMTP. Templates go in HTML format in `./email/templates`.
+//! When automatic emails are enabled, you can choose between Resend or SMTP. Templates are HTML and
+//! loaded from an HTTP url (cached and revalidated with its `ETag`), a `file://` path, a name in the
+//! templates directory (`./email/templates`, `Email.TemplatesDir` or `EMAIL_TEMPLATES_DIR`) or the
+//! built-in defaults, see [source](email/templates/source/index.html).
 //!
 //! ### Dynamically populating emails
 //! You can use these pre-built placeholders that are extracted from the Stripe payment to customize and design your email template around these with no additional effort.
@@ -62,7 +98,88 @@
 //! - Payment amount: `{{PaymentAmount}}`
 //! - Purchase product name: `{{ProductName}}`
 //! - Payment date: `{{PaymentDate}}`
-//! These are used to personalize emails and use payment-oriented references.
+//!
+//! These are used to personalize emails and use payment-oriented references. Amounts keep the
+//! decimals of their currency, none for `jpy` and three for `kwd`, see [money](utils/money/index.html).
+//!
+//! ### Plain-text alternative and preheader
+//! Every email goes out as a `multipart/alternative` message with a plain-text part, generated
+//! from the HTML or rendered from `TextTemplateUrl`. `Preheader` sets the preview line after the
+//! subject, both are set per email, e.g. under `Email.Welcome`, see
+//! [content](email/content/index.html).
+//!
+//! ### Emails per event
+//! Every [`EmailEvent`] has its own sender, subject and template and is enabled on its own:
+//! - `Welcome` on `checkout.session.completed`, enabled by default
+//! - `Receipt` on `charge.succeeded`, also with `{{ReceiptNumber}}` and `{{ReceiptUrl}}`
+//! - `TrialEnding` on `customer.subscription.trial_will_end`, also with `{{RenewalDate}}`
+//! - `RenewalReminder` on `invoice.upcoming` and the scan of `end_time`, also with `{{RenewalDate}}`
+//! - `PaymentFailed` on `charge.failed` and `payment_intent.payment_failed`, also with
+//!   `{{DeclineCode}}` and `{{DeclineMessage}}`
+//! - `Cancellation` on `customer.subscription.deleted`, also with `{{EndDate}}`
+//! ```yaml
+//! Email:
+//!   Receipt:
+//!     Enabled: true
+//!     Subject: Your receipt from Xylex
+//!     TemplateUrl: https://cdn.example.com/receipt.html
+//! ```
+//! Without `Enabled` an email other than the welcome email is on once it has a `TemplateUrl`.
+//! Failed payments always mark the customer as `paid=false` and store the `decline_code` and
+//! `decline_message`, whether the email is on or not.
+//!
+//! ### Localized emails
+//! Emails are rendered in the locale of the customer, the session `locale`, else the language of
+//! the billing country, else `Email.DefaultLocale`. It picks the translation under
+//! `Email.<Event>.Locales.<locale>`, the `<TemplatesDir>/<locale>/` directory for named templates
+//! and how amounts and dates are written, see [locale](email/locale/index.html).
+//!
+//! ### Renewal reminders
+//! Subscription events store the end of the current period as the `end_time` of the customer.
+//! With `Email.RenewalReminder.WindowDays` the customers renewing within the window are scanned
+//! for, and every period gets one reminder from whichever source comes first, see
+//! [reminder](email/reminder/index.html).
+//!
+//! ### Drip sequences
+//! The steps under `Email.Drip` are scheduled on `checkout.session.completed`, each `DelayDays`
+//! after the purchase, and sent by the scheduler in the background. A refund or cancellation
+//! stops the steps still pending, see [drip](email/drip/index.html).
+//!
+//! ### Receipts and invoices
+//! With `Email.AttachReceipts` (or `AttachReceipts` of an endpoint) the welcome and receipt emails
+//! carry the Stripe invoice PDF, or a receipt generated from the payment when there is no
+//! invoice. Invoices are retrieved from `STRIPE_API_URL`, see
+//! [attachments](email/attachments/index.html).
+//!
+//! ## Refunds and disputes
+//! `charge.refunded`, `charge.dispute.created` and `charge.dispute.closed` store the refunded
+//! amount (`amount_refunded`, `refund_status` is `partial` or `full`) and the dispute status and
+//! reason (`dispute_status`, `dispute_reason`) on the customer. Every refund and dispute is also
+//! appended to the `stripe_customer_audit` table.
+//!
+//! How `paid` is adjusted is decided by the entitlement policies, the Discord role of a linked
+//! customer follows it, and an operator can be notified:
+//! ```yaml
+//! Entitlement:
+//!   Refund: revoke_on_full_refund # keep | revoke_on_full_refund | revoke_on_any_refund
+//!   Dispute: revoke_on_lost # keep | revoke_on_lost | revoke_on_open
+//!
+//! Email:
+//!   Operator: billing-ops@example.com
+//! ```
+//!
+//! ## Time-boxed access
+//! Products under `Entitlement.Products` grant access for their `AccessDays` instead of for good.
+//! `checkout.session.completed`, or `charge.succeeded` for charges made without Checkout, stores
+//! the `access_start_time` and `access_end_time` of the access, a repeat purchase extends it, and the scheduler revokes the Discord role and sets `paid=false`
+//! once `access_end_time` has passed, see [access](discord/access/index.html). A subscription
+//! whose period has not ended keeps the role.
+//! ```yaml
+//! Entitlement:
+//!   Products:
+//!     - Price: price_1PthirtyDayPass
+//!       AccessDays: 30
+//! ```
 //!
 //! ### Picking an email provider
 //! In the `stripe_discord.yaml` file, you can opt for one of the following email providers:
@@ -93,6 +210,8 @@
 //! - `smtp_host`
 //! - `smtp_port`
 //! - `smtp_email_address`
+//! - `smtp_password` (optional, no login is attempted without it)
+//! - `smtp_tls` (optional, `starttls` by default, `tls` on port 465 and `none` for local relays)
 //!
 //! Making `smtp` your chosen email provider:
 //! ```yaml
@@ -105,8 +224,67 @@
 //! SMTP_HOST=
 //! SMTP_PORT=
 //! SMTP_EMAIL_ADDRESS=
+//! SMTP_PASSWORD=
+//! ```
+//!
+//! ### Retries and fallback
+//! A failed email is attempted again with a doubling backoff. Timeouts, rate limits, server errors and rejected credentials are transient, an email the provider refuses, e.g. an invalid one, is not attempted again. After `FallbackAfter` failures the remaining attempts go to the `Fallback` provider:
+//! ```yaml
+//! Email:
+//!   Provider: resend
+//!   Retry:
+//!     MaxAttempts: 4
+//!     BackoffMs: 500
+//!     MaxBackoffMs: 5000
+//!     Fallback: smtp
+//!     FallbackAfter: 2
 //! ```
 //!
+//! Every attempt is stored in the `stripe_email_attempts` table (`email_log_id`, `recipient`, `attempt`, `provider`, `status`, `message_id`, `error`, `error_kind`, `attempted_at`), the email log keeps the provider and the number of attempts of the email.
+//!
+//!
+//! ## Logging
+//! Logs are written with `tracing`, each webhook runs in a `webhook` span with its `event_id`,
+//! `event_type` and `organization`. Emails, names and secrets are redacted before they are logged.
+//! ```yaml
+//! Log:
+//!   Level: info # trace | debug | info | warn | error, `RUST_LOG` takes precedence
+//!   Format: pretty # pretty | json
+//! ```
+//!
+//! ## Metrics
+//! `GET /metrics` serves Prometheus metrics, see [`metrics`](metrics/index.html) for the full list:
+//! webhooks by event type and outcome, webhook handling time, emails by provider and result,
+//! Discord API calls by status, database operation latency and background tasks in flight.
+//!
+//! ## Health checks
+//! `GET /healthz` answers as long as the process is alive. `GET /readyz` loads the config and
+//! checks the database, the email provider credentials and the Discord bot token, each bounded by
+//! a timeout, and answers `503` with a JSON breakdown when one of them is down, see
+//! [`api::health`](api/health/index.html).
+//! ```yaml
+//! Health:
+//!   TimeoutMs: 2000
+//! ```
+//!
+//! ## Secrets
+//! The Stripe, Supabase, Resend and Discord secrets are read through [`secrets`](secrets/index.html),
+//! from the environment or from the file under `<NAME>_FILE`, e.g. a mounted Docker secret. The
+//! secret fields of [`EndpointConfigStripe`] also take `env:<NAME>` and `file:<path>`. `serve`
+//! validates the secrets before it starts and `Debug` output never shows them.
+//!
+//! ## Webhook endpoints
+//! Every entry under `Endpoints` in `stripe_discord.yaml` is read into an [`EndpointConfigStripe`],
+//! validated, and mounted as its own webhook route with its own secret and Organization next to
+//! `/stripe_webhooks`, see [`api::endpoints`](api/endpoints/index.html).
+//!
+//! ## Admin API
+//! Support staff can search customers, view a record with its audit trail and webhook events,
+//! toggle `paid`, resend the welcome email, force a Discord role sync and replay webhook events
+//! through JSON endpoints under `/admin`, see [`api::admin`](api/admin/index.html). Requests need
+//! `Authorization: Bearer <key>` with a key holding the scope of the route, keys are issued with
+//! `stripe_discord keys issue` or listed by hash under `Auth.ApiKeys`, see [`auth`](auth/index.html). Role syncs need `DISCORD_BOT_TOKEN`, `DISCORD_GUILD_ID`
+//! and `DISCORD_ROLE_ID` and a `discord_user_id` on the customer record.
 //!
 //! ## Databasing
 //! In the `stripe_discord.yaml` file, you can choose between Sled and Supabase.
@@ -141,14 +319,44 @@
 //! ### Tests
 //! You can run tests with `cargo test` to check if your configuration is correct.
 //!
+//! `fixtures/stripe` holds realistic Stripe events for every handled event type. The replay tests
+//! sign them with a test secret and send them through the Rocket endpoint, while Supabase, Resend
+//! and Discord are replaced by an in-memory fake, so the whole flow runs offline.
+//!
+//! ## Webhook event log
+//! Every webhook must carry a valid `Stripe-Signature` signed with `STRIPE_WEBHOOK_SECRET`, others
+//! are rejected with `400` and webhooks are rejected with `500` without the secret. Each accepted webhook is stored in the `stripe_webhook_events` table
+//! with its raw body, headers, receipt time, processing attempts and outcome, acknowledged and
+//! handled in the background. The `event_id` column is unique, so Stripe redeliveries of a stored
+//! event are not handled twice, even when they arrive together.
+//!
 //! ## CLI
 //! There is a CLI to add more organizations to your Stripe config.
+//!
+//! Stored webhook events can be listed, inspected and replayed through the normal `EventHandler`
+//! pipeline, see [`cli`](cli/index.html):
+//! ```text
+//! stripe_discord events list [--type <event_type>] [--outcome <outcome>] [--limit <n>]
+//! stripe_discord events show <event_id>
+//! stripe_discord events replay <event_id>
+//! ```
+//!
+//! While developing, signed synthetic events can be fired at a running instance without the
+//! Stripe CLI. They are posted to the configured `Api` address (`PORT` wins like it does for
+//! `serve`) and signed with `STRIPE_WEBHOOK_SECRET`:
+//! ```text
+//! stripe_discord send-test-event <event_type> [--email <email>] [--amount <minor units>]
+//!     [--currency <currency>] [--price <price_id>] [--url <webhook_url>]
+//! ```
+//! Supported types are `checkout.session.completed`, `charge.succeeded` and
+//! `customer.subscription.created`, `.updated`, `.trial_will_end` and `.deleted`.
 
 // externally exposing the `regex` crate
 extern crate regex;
 
 pub mod api;
 pub mod auth;
+pub mod cli;
 pub mod config;
 pub mod data;
 pub mod db;
@@ -157,13 +365,26 @@ pub mod email;
 pub mod errors;
 pub mod events;
 pub mod log;
+pub mod metrics;
 pub mod organization;
 pub mod overwrite;
+pub mod secrets;
 pub mod tests;
 pub mod utils;
 pub mod background;
 
-use crate::email::EmailAddress;
+use crate::auth::ConfiguredApiKey;
+use crate::secrets::{Secret, SecretError, SecretSource};
+use crate::organization::model::{DisputePolicy, EmailEvent, RefundPolicy};
+use crate::email::address::EmailValidation;
+use crate::email::retry::EmailRetry;
+use crate::email::EmailProvider;
+use crate::discord::access::AccessProduct;
+use crate::discord::roles::DiscordRoles;
+use crate::email::drip::DripStep;
+use crate::email::locale::{EmailTranslation, Locale};
+use std::collections::HashMap;
+use std::time::Duration;
 
 
 /// ## Configuration #[derive(Debug)]
@@ -177,7 +398,30 @@ pub struct ConfigSetup {
     pub host: String,
     pub port: u64,
     pub supabase_url: String,
-    pub supabase_key: String,
+    pub supabase_key: Secret,
+    pub emails: Vec<EmailEventConfig>,
+    pub operator_email: Option<String>,
+    pub refund_policy: String,
+    pub dispute_policy: String,
+    pub log_level: String,
+    pub log_format: String,
+    pub sled_path: String,
+    pub health_timeout_ms: u64,
+    pub api_keys: Vec<ConfiguredApiKey>,
+    pub endpoints: Vec<EndpointConfigStripe>,
+    pub templates_dir: String,
+    pub attach_receipts: bool,
+    pub default_locale: String,
+    pub invalid_email_policy: String,
+    pub check_mx: bool,
+    pub email_max_attempts: u32,
+    pub email_backoff_ms: u64,
+    pub email_max_backoff_ms: u64,
+    pub email_fallback_provider: Option<String>,
+    pub email_fallback_after: u32,
+    pub drips: Vec<DripStepConfig>,
+    pub renewal_reminder_window_days: Option<u64>,
+    pub access_products: Vec<AccessProduct>,
 }
 
 
@@ -189,9 +433,11 @@ pub struct ConfigSetup {
 ///
 /// - `FileNotFound` - Indicates that the file was not found at the specified path
 /// - `InvalidFileType` - Indicates that the file type is not supported, expected .yaml file
+/// - `InvalidEndpoint` - Indicates that an entry under `Endpoints` is invalid, with its route and the reason
+/// - `InvalidValue` - Indicates that a setting has a value it does not know, with its key and the reason
 ///
 /// ## Example
-/// ```rust
+/// ```rust,ignore
 /// use crate::ConfigError;
 ///
 /// let error = ConfigError::FileNotFound("stripe_discord.yaml".to_string());
@@ -206,68 +452,94 @@ pub struct ConfigSetup {
 pub enum ConfigError {
     FileNotFound(String),
     InvalidFileType(String),
+    InvalidEndpoint(String, String),
+    InvalidValue(String, String),
 }
 
 
 /// ## EndpointConfigStripe for `rocket`
-/// This is your entry endpoint configuration that you will attach to your `endpoint_stripe` to
-/// irrigate it with it's configuration.
+/// This is your entry endpoint configuration, every entry under `Endpoints` in
+/// `stripe_discord.yaml` becomes a webhook route at `endpoint_route` that is verified with its
+/// own webhook secret and handled for its own Organization.
 ///
 ///
 /// ### Usage example
-/// ```rust
-///
-///
+/// ```yaml
+/// Endpoints:
+///   - Route: /stripe_webhooks/acme
+///     Name: Acme
+///     SenderEmail: billing@acme.example
+///     EmailTemplatePath: https://cdn.acme.example/welcome.html
+///     ReplaceKeysWithEnvNames: true
+///     Stripe:
+///       PublishKey: pk_live_51H...
+///       WebhookSecret: ACME_STRIPE_WEBHOOK_SECRET
+///       PrivateKey: ACME_STRIPE_PRIVATE_API_KEY
+///     Discord:
+///       ClientId: "1089273409192386560"
+///       ApplicationId: "1089273409192386560"
+///       GuildId: "81384788765712384"
+///       RoleId: "41771983423143936"
+///       BotToken: ACME_DISCORD_BOT_TOKEN
 /// ```
 ///
 /// ### Arguments
 /// - [`endpoint_route`] This will set the api route your endpoint will listen to, (STRIPE HAS TO
-/// MATCH TO WHAT YOU SET HERE).
+///   MATCH TO WHAT YOU SET HERE).
+/// - [`name`] The name of the Organization the webhooks of this endpoint are handled for,
+///   defaults to the route
 /// - [`sender_email`] The email that will send out for this stripe instance.
-/// - [`stripe_publish_key`] This is the *LIVE* publishable key found in your stripe dashboard, for
-/// more infro go to #FIXME
+/// - [`stripe_publish_key`] This is the *LIVE* publishable key found in your stripe dashboard,
+///   starts with `pk_`, optional
 /// - [`stripe_webhook_secret`] This is the *LIVE* webhook secret that stripe will give you after
-/// assigning an endpoint route in the Stripe dashboard
-/// - [`stripe_private_key`] This is the *LIVE* private api key stripe will give you.
+///   assigning an endpoint route in the Stripe dashboard, starts with `whsec_`
+/// - [`stripe_private_key`] This is the *LIVE* private api key stripe will give you, starts with
+///   `sk_` or `rk_`, optional
 /// - [`email_template_path`] This has to lead to either HTTP or FilePath of what `.html` email
-/// template should be sent out under the `sender_email`
+///   template should be sent out under the `sender_email`
 /// - [`discord_client_id`] This is the discord `client_id` that is used for `Oath2` Configs
 /// - [`discord_application_id`] This is the discord application id that is used to assign a
-/// specific discord application
+///   specific discord application
 /// - [`discord_role_id`] This is the `role_id` members should receive or be revoked based on
-/// Stripe dictation
+///   Stripe dictation, `0` when the endpoint syncs no role
 /// - [`discord_guild_id`] This is the `guild_id` of your server where the members should receive
-/// said `role_id`
+///   said `role_id`, `0` when the endpoint syncs no role
 /// - [`discord_bot_token`] This is the discord `bot_token` for authenticating into your `discord`
-/// bot to mitigate `Oath2` limitations such as revoking roles when subscription fails, Read more
-/// -> FIXME
+///   bot to mitigate `Oath2` limitations such as revoking roles when subscription fails
 /// - [`replace_keys_with_env_names`] When `enabled` it will extract the aforementioned from an
-/// `.env` file by the by your provided `.env` names
+///   `.env` file by the by your provided `.env` names
+/// - [`attach_receipts`] Turns receipt PDFs on the welcome email on or off for this endpoint,
+///   `Email.AttachReceipts` applies when unset
 ///
+/// The secret fields accept `env:<NAME>` and `file:<path>` as well, see
+/// [secrets](secrets/index.html).
 ///
-/// ### Implementations
 ///
+/// ### Implementations
+/// - [`EndpointConfigStripe::from_config`] - Every endpoint under `Endpoints`, validated
+/// - [`EndpointConfigStripe::validate`] - Checks the route, the Discord snowflakes and the key prefixes
+/// - [`EndpointConfigStripe::secret_source`] - Where a secret field is read from
+/// - [`EndpointConfigStripe::stripe_webhook_secret`], [`EndpointConfigStripe::stripe_private_key`],
+///   [`EndpointConfigStripe::discord_bot_token`] - The resolved and checked secrets
+/// - [`EndpointConfigStripe::validate_secrets`] - Every problem with the secrets, for startup
 ///
-/// ### Returns
 ///
 /// ### Errors
+/// - `ConfigError::InvalidEndpoint` - An entry under `Endpoints` is missing a field or fails validation
 ///
 /// ### Notes
 /// * Discord roles can only be revoked OUTSIDE of the traditional `Oath2` portal otherwise discord
-/// users would need to supply permissions themselves
+///   users would need to supply permissions themselves
 /// * When `replace_keys_with_env_names` - This DEFAULTS to FALSE, is enabled it will NOT accept the traditional keys,
+///   every secret field is read as the name of an environment variable (or `<NAME>_FILE`)
+/// * `Debug` never shows the secret values, only the variable or file they come from
+/// * The secrets are resolved when `serve` starts, not when the config is loaded
 ///
-/// FIXME
-/// NONE OF THESE ARGUMENTS ARE `TYPED` NOR IMPLEMENTED OR HANDLED YET
-///
-/// MAKE THE ACTUAL ENDPOINT IMPLEMENTATION DERIVE FROM EITHER AN ORGANIZATION OR FROM A CONFIG
-///
-///
-///
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct EndpointConfigStripe {
     pub endpoint_route: String,
-    // import the email type from the email module but make sure to implement a string derivative
+    pub name: String,
+    pub sender_email: String,
 
     // handled by auth
     pub stripe_publish_key: String,
@@ -278,21 +550,135 @@ pub struct EndpointConfigStripe {
     pub email_template_path: String,
 
     // handled by discord auth client router
-    //
-    //
     pub discord_client_id: String,
     pub discord_application_id: String,
     pub discord_role_id: i64,
     pub discord_guild_id: i64,
     pub discord_bot_token: String,
     pub replace_keys_with_env_names: bool,
+    pub attach_receipts: Option<bool>,
+}
+
+
+impl EndpointConfigStripe {
+    /// # secret_source
+    /// Where a secret field is read from, the name of an environment variable when
+    /// `replace_keys_with_env_names` is enabled, `env:<NAME>`, `file:<path>` or the value otherwise.
+    ///
+    /// ## Arguments
+    /// - `value`: `&str` - The value of the field, e.g. `self.stripe_webhook_secret`
+    pub fn secret_source(&self, value: &str) -> SecretSource {
+        if self.replace_keys_with_env_names {
+            return SecretSource::from_env_name(value);
+        }
+
+        SecretSource::parse(value)
+    }
+
+    /// # stripe_webhook_secret
+    /// The resolved `whsec_` webhook secret.
+    pub fn stripe_webhook_secret(&self) -> Result<Secret, SecretError> {
+        self.resolve_secret("STRIPE_WEBHOOK_SECRET", &self.stripe_webhook_secret)
+    }
+
+    /// # stripe_private_key
+    /// The resolved `sk_` or `rk_` private API key.
+    pub fn stripe_private_key(&self) -> Result<Secret, SecretError> {
+        self.resolve_secret("STRIPE_PRIVATE_API_KEY", &self.stripe_private_key)
+    }
+
+    /// # discord_bot_token
+    /// The resolved bot token.
+    pub fn discord_bot_token(&self) -> Result<Secret, SecretError> {
+        self.resolve_secret("DISCORD_BOT_TOKEN", &self.discord_bot_token)
+    }
+
+    /// # validate_secrets
+    /// Resolves and checks every secret field, the optional private key and bot token only when
+    /// they are set.
+    ///
+    /// ## Returns
+    /// - `Vec<SecretError>` - Every problem found, empty when the secrets are usable
+    pub fn validate_secrets(&self) -> Vec<SecretError> {
+        let mut resolved: Vec<Result<Secret, SecretError>> = vec![self.stripe_webhook_secret()];
+
+        if !self.stripe_private_key.is_empty() {
+            resolved.push(self.stripe_private_key());
+        }
+
+        if !self.discord_bot_token.is_empty() {
+            resolved.push(self.discord_bot_token());
+        }
+
+        resolved
+            .into_iter()
+            .filter_map(|resolved| resolved.err())
+            .collect()
+    }
+
+    /// # resolve_secret
+    /// Resolves a secret field and checks its format.
+    fn resolve_secret(&self, name: &str, value: &str) -> Result<Secret, SecretError> {
+        let secret: Secret = self.secret_source(value).resolve()?;
+
+        secrets::validate_format(name, &secret)?;
+
+        Ok(secret)
+    }
 }
 
 
+// only the variables and files the secrets come from are shown
+impl std::fmt::Debug for EndpointConfigStripe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = |value: &str| -> SecretSource {
+            if self.replace_keys_with_env_names {
+                return SecretSource::Env(value.to_string());
+            }
+
+            SecretSource::parse(value)
+        };
+
+        f.debug_struct("EndpointConfigStripe")
+            .field("endpoint_route", &self.endpoint_route)
+            .field("name", &self.name)
+            .field("sender_email", &self.sender_email)
+            .field("stripe_publish_key", &self.stripe_publish_key)
+            .field("stripe_webhook_secret", &source(&self.stripe_webhook_secret))
+            .field("stripe_private_key", &source(&self.stripe_private_key))
+            .field("email_template_path", &self.email_template_path)
+            .field("discord_client_id", &self.discord_client_id)
+            .field("discord_application_id", &self.discord_application_id)
+            .field("discord_role_id", &self.discord_role_id)
+            .field("discord_guild_id", &self.discord_guild_id)
+            .field("discord_bot_token", &source(&self.discord_bot_token))
+            .field("replace_keys_with_env_names", &self.replace_keys_with_env_names)
+            .field("attach_receipts", &self.attach_receipts)
+            .finish()
+    }
+}
+
+
+/// ## EmailConfig
+/// An email the organization sends
+///
+/// ### Fields
+/// - `sender_email` - The address the email is sent from
+/// - `subject` - The subject of the email
+/// - `template_url` - The HTML template, see [source](email/templates/source/index.html)
+/// - `text_template_url` - The plain-text template, the text is generated from the HTML without one
+/// - `preheader` - The preview line shown after the subject
+/// - `translations` - The translated subject and templates by locale, e.g. `de` or `pt-BR`
+/// - `locale` - The locale the email is rendered in, set by [`EmailConfig::localized`]
+#[derive(Clone, Debug)]
 pub struct EmailConfig {
     pub sender_email: String,
     pub subject: String,
-    pub template_url: String
+    pub template_url: String,
+    pub text_template_url: Option<String>,
+    pub preheader: Option<String>,
+    pub translations: HashMap<String, EmailTranslation>,
+    pub locale: Option<Locale>,
 }
 
 impl EmailConfig {
@@ -315,9 +701,102 @@ impl EmailConfig {
         Self {
             sender_email,
             subject,
-            template_url
+            template_url,
+            text_template_url: None,
+            preheader: None,
+            translations: HashMap::new(),
+            locale: None,
         }
     }
+
+    /// # with_text_template
+    /// Renders the plain-text alternative from a template instead of generating it from the HTML.
+    pub fn with_text_template(mut self, text_template_url: String) -> Self {
+        self.text_template_url = Some(text_template_url);
+        self
+    }
+
+    /// # with_preheader
+    /// Sets the preview line shown after the subject.
+    pub fn with_preheader(mut self, preheader: String) -> Self {
+        self.preheader = Some(preheader);
+        self
+    }
+
+    /// # with_translation
+    /// Translates the subject or templates of the email for a locale like `de` or `pt-BR`.
+    pub fn with_translation(mut self, locale: &str, translation: EmailTranslation) -> Self {
+        let tag: String = Locale::parse(locale).map(|locale| locale.tag).unwrap_or(locale.to_string());
+
+        self.translations.insert(tag, translation);
+        self
+    }
+}
+
+
+/// ## EmailEventConfig
+/// The email of one [`EmailEvent`] as configured under `Email` in `stripe_discord.yaml`
+///
+/// ### Fields
+/// - `event` - The lifecycle moment the email is sent at
+/// - `enabled` - Whether the email is sent
+/// - `sender_email`, `subject`, `template_url`, `text_template_url`, `preheader` - Overrides of
+///   the defaults of the event, see [`EmailEventConfig::to_email_config`]
+/// - `translations` - The translations under `Locales`, by locale
+///
+/// ### Example
+/// ```yaml
+/// Email:
+///   Receipt:
+///     Enabled: true
+///     Subject: Your receipt from Xylex
+///     TemplateUrl: receipt
+///     Locales:
+///       de:
+///         Subject: Ihre Quittung von Xylex
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmailEventConfig {
+    pub event: EmailEvent,
+    pub enabled: bool,
+    pub sender_email: Option<String>,
+    pub subject: Option<String>,
+    pub template_url: Option<String>,
+    pub text_template_url: Option<String>,
+    pub preheader: Option<String>,
+    pub translations: HashMap<String, EmailTranslation>,
+}
+
+
+/// ## DripStepConfig
+/// A step of the drip sequence as configured under `Email.Drip` in `stripe_discord.yaml`
+///
+/// ### Fields
+/// - `name` - The name of the step, `TemplateUrl` when unset
+/// - `delay_secs` - How long after the purchase the step is sent, `DelayDays` and `DelayHours` added up
+/// - `sender_email` - The sender, `Email.Sender` when unset
+/// - `subject`, `template_url`, `text_template_url`, `preheader` - The email of the step
+/// - `translations` - The translations under `Locales`, by locale
+///
+/// ### Example
+/// ```yaml
+/// Email:
+///   Drip:
+///     - Name: tips
+///       DelayDays: 3
+///       Subject: Three tips to get the most out of Xylex
+///       TemplateUrl: tips
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DripStepConfig {
+    pub name: String,
+    pub delay_secs: u64,
+    pub sender_email: Option<String>,
+    pub subject: String,
+    pub template_url: String,
+    pub text_template_url: Option<String>,
+    pub preheader: Option<String>,
+    pub translations: HashMap<String, EmailTranslation>,
 }
 
 
@@ -331,11 +810,45 @@ impl EmailConfig {
 /// - `stripe_secret` - The stripe secret of the organization
 /// - `stripe_webhook_secret` - The stripe webhook secret of the organization
 /// - `config` - The stripe endpoint config of the organization`
+/// - `emails` - The enabled emails by the moment they are sent at, see [`EmailEvent`]
+/// - `refund_policy` - How refunds adjust the `paid` status of a customer
+/// - `dispute_policy` - How disputes adjust the `paid` status of a customer
+/// - `operator_email` - The optional address that is notified about refunds and disputes
+/// - `attach_receipts` - Whether the welcome email carries the invoice or a generated receipt
+/// - `stripe_private_key` - The key invoices are retrieved with, `STRIPE_PRIVATE_API_KEY` when None
+/// - `default_locale` - The locale of customers without a session locale or a known country
+/// - `email_validation` - How customer addresses are checked before they are stored or emailed
+/// - `email_provider` - The provider emails are sent with, `Email.Provider` read once at startup
+/// - `email_retry` - How often failed emails are attempted again and where they fall back to
+/// - `drip_sequence` - The emails sent at delays after a purchase, see [drip](email/drip/index.html)
+/// - `reminder_window` - How long before their `end_time` customers are reminded of a renewal,
+///   not scanned for when None, see [reminder](email/reminder/index.html)
+/// - `access_products` - The products that grant access for a limited time, see
+///   [access](discord/access/index.html)
+/// - `discord_roles` - The bot and role paid customers receive, `DISCORD_BOT_TOKEN`,
+///   `DISCORD_GUILD_ID` and `DISCORD_ROLE_ID` when None
 ///
+#[derive(Clone, Debug)]
 pub struct Organization {
     /// `The name of the organization that is used to identify the organization in the db`
     pub name: String,
     pub email_config: EmailConfig,
+    /// `The welcome email is enabled with the email_config by default, the others are disabled`
+    pub emails: HashMap<EmailEvent, EmailConfig>,
+    pub refund_policy: RefundPolicy,
+    pub dispute_policy: DisputePolicy,
+    /// `Receives refund and dispute notifications, disabled when None`
+    pub operator_email: Option<String>,
+    pub attach_receipts: bool,
+    pub stripe_private_key: Option<Secret>,
+    pub default_locale: String,
+    pub email_validation: EmailValidation,
+    pub email_provider: EmailProvider,
+    pub email_retry: EmailRetry,
+    pub drip_sequence: Vec<DripStep>,
+    pub reminder_window: Option<Duration>,
+    pub access_products: Vec<AccessProduct>,
+    pub discord_roles: Option<DiscordRoles>,
 }
 
 
@@ -369,7 +882,7 @@ impl CustomerId {
     /// A string slice (`&str`) representing the `id` of the customer.
     ///
     /// ## Example: Getting the customer id as a string slice
-    /// ```rust
+    /// ```rust,ignore
     /// use stripe_discord::CustomerId;
     ///
     /// let customer_id = CustomerId::new("cus_12345".to_string());
@@ -0,0 +1,45 @@
+//! ## Installing the logging subscriber
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let config: ConfigSetup = ConfigSetup::new();
+//! init_logging(&config);
+//!
+//! tracing::info!("Listening for Stripe webhooks");
+//! ```
+
+use crate::log::LogFormat;
+use crate::ConfigSetup;
+
+use tracing_subscriber::EnvFilter;
+
+
+/// # init_logging
+/// Installs the global `tracing` subscriber with the level and format from the config.
+///
+/// ## Arguments
+/// - `config`: `&ConfigSetup` - The config holding `Log.Level` and `Log.Format`
+///
+/// ## Returns
+/// `true` when the subscriber was installed, `false` when a subscriber was already installed,
+/// which makes it safe to call more than once (e.g. from tests).
+pub fn init_logging(config: &ConfigSetup) -> bool {
+    // `RUST_LOG` wins over the configured level
+    let filter: EnvFilter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(&config.log_level));
+
+    // unknown formats fall back to `pretty`
+    let installed = match config.log_format.parse().unwrap_or_default() {
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_env_filter(filter)
+            .try_init(),
+        LogFormat::Pretty => tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .try_init(),
+    };
+
+    installed.is_ok()
+}
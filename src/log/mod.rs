@@ -1,3 +1,61 @@
 //! ## Logging clients and methods
 //!
+//! Logging is built on [`tracing`](https://docs.rs/tracing), every webhook is handled inside a
+//! `webhook` span carrying the `event_id`, `event_type` and `organization` so all lines logged
+//! while handling an event can be correlated.
+//!
+//! ### Table of contents
+//! - [client](client/index.html) - Installing the subscriber with the configured level and format
+//! - [redact](redact/index.html) - Redacting emails, names, secrets and webhook payloads
+//!
+//! ### Configuring
+//! ```yaml
+//! Log:
+//!   Level: info # trace | debug | info | warn | error
+//!   Format: pretty # pretty | json
+//! ```
+//! `RUST_LOG` takes precedence over `Log.Level` when it is set, e.g. `RUST_LOG=stripe_discord=debug`.
+//!
+//! ### Notes
+//! - Never log customer data as-is, pass it through [`redact`](redact/index.html) first
+//! - `supabase_rs` and `resend_email_rs` print to stdout themselves, this is outside of our control
 pub mod client;
+pub mod redact;
+
+use std::str::FromStr;
+
+
+/// ## Log Format
+/// This is the format log lines are written in
+///
+/// ### Formats
+/// - `pretty` - Human readable lines, the default
+/// - `json` - One JSON object per line for log shippers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a string into a log format
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::log::LogFormat;
+    /// assert_eq!("json".parse(), Ok(LogFormat::Json));
+    /// assert!("xml".parse::<LogFormat>().is_err());
+    /// ```
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format `{}`, expected `pretty` or `json`", format)),
+        }
+    }
+}
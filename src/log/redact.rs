@@ -0,0 +1,136 @@
+//! ## Redacting customer data and secrets
+//!
+//! Emails, names and secrets must never end up in the logs in full, these helpers keep just
+//! enough of a value to recognise it while debugging.
+//!
+//! ### Examples
+//! - `floris@xylex.ai` -> `f***@xylex.ai`
+//! - `Floris Xylex` -> `F*** X***`
+//! - `sk_live_51H8abc` -> `sk_live_***`
+
+use serde_json::Value;
+
+
+/// Keys of a webhook payload that hold an email address
+const EMAIL_KEYS: [&str; 3] = ["email", "receipt_email", "customer_email_address"];
+
+/// Keys of a webhook payload that hold a name
+const NAME_KEYS: [&str; 3] = ["name", "customer_name", "shipping_name"];
+
+/// Keys of a webhook payload that hold other personal data which is dropped entirely
+const PERSONAL_KEYS: [&str; 6] = ["phone", "line1", "line2", "postal_code", "city", "ip_address"];
+
+/// Parts of a key that mark its value as a secret
+const SECRET_KEY_PARTS: [&str; 4] = ["secret", "token", "api_key", "password"];
+
+/// Prefixes of known keys that are kept in full, they tell the kind and mode of a key
+const SECRET_PREFIXES: [&str; 7] = ["sk_live_", "sk_test_", "rk_live_", "rk_test_", "pk_live_", "pk_test_", "whsec_"];
+
+/// Longest text before the first `_` that is kept for other secrets, like `re` of `re_...`
+const MAX_SECRET_PREFIX: usize = 8;
+
+
+/// # redact_email
+/// Keeps the first character of the local part and the domain of an email address.
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::log::redact::redact_email;
+/// assert_eq!(redact_email("floris@xylex.ai"), "f***@xylex.ai");
+/// ```
+pub fn redact_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first: String = local.chars().take(1).collect();
+            format!("{}***@{}", first, domain)
+        },
+        None => "***".to_string(),
+    }
+}
+
+
+/// # redact_name
+/// Keeps the first character of every part of a name.
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::log::redact::redact_name;
+/// assert_eq!(redact_name("Floris Xylex"), "F*** X***");
+/// ```
+pub fn redact_name(name: &str) -> String {
+    let parts: Vec<String> = name
+        .split_whitespace()
+        .map(|part| format!("{}***", part.chars().take(1).collect::<String>()))
+        .collect();
+
+    if parts.is_empty() {
+        return "***".to_string();
+    }
+
+    parts.join(" ")
+}
+
+
+/// # redact_secret
+/// Keeps a known key prefix or the short text before the first `_` so the kind of key stays
+/// visible, nothing of the secret itself is kept.
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::log::redact::redact_secret;
+/// assert_eq!(redact_secret("whsec_abc123"), "whsec_***");
+/// assert_eq!(redact_secret("pi_1_secret_abc"), "pi_***");
+/// assert_eq!(redact_secret("abc123"), "***");
+/// ```
+pub fn redact_secret(secret: &str) -> String {
+    if let Some(prefix) = SECRET_PREFIXES.iter().find(|prefix| secret.starts_with(*prefix)) {
+        return format!("{}***", prefix);
+    }
+
+    match secret.find('_') {
+        Some(index) if index <= MAX_SECRET_PREFIX => format!("{}***", &secret[..=index]),
+        _ => "***".to_string(),
+    }
+}
+
+
+/// # redact_payload
+/// Returns a copy of a webhook payload with emails, names, personal data and secrets redacted.
+///
+/// ## Arguments
+/// - `payload`: `&Value` - The JSON payload, e.g. a Stripe event
+///
+/// ## Returns
+/// The redacted copy, the structure and all other values are left intact.
+///
+/// ## Example
+/// ```rust,ignore
+/// tracing::debug!(payload = %redact_payload(&event), "Received webhook");
+/// ```
+pub fn redact_payload(payload: &Value) -> Value {
+    match payload {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), redact_field(key, value)))
+                .collect()
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(redact_payload).collect()),
+        _ => payload.clone(),
+    }
+}
+
+
+/// # redact_field
+/// Redacts a single field of a payload by its key, recursing into nested objects.
+fn redact_field(key: &str, value: &Value) -> Value {
+    let text: Option<&str> = value.as_str();
+    let key: String = key.to_lowercase();
+
+    match text {
+        Some(text) if EMAIL_KEYS.contains(&key.as_str()) => Value::String(redact_email(text)),
+        Some(text) if NAME_KEYS.contains(&key.as_str()) => Value::String(redact_name(text)),
+        Some(_) if PERSONAL_KEYS.contains(&key.as_str()) => Value::String("***".to_string()),
+        Some(text) if SECRET_KEY_PARTS.iter().any(|part| key.contains(part)) => Value::String(redact_secret(text)),
+        _ => redact_payload(value),
+    }
+}
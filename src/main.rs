@@ -25,6 +25,8 @@ use rocket::response::{
 };
 use rocket::launch;
 use rocket::post;
+use rocket::get;
+use rocket::http::ContentType;
 use rocket::routes;
 use rocket::{
     Request,
@@ -41,7 +43,48 @@ use std::env;
 
 
 
-#[launch]
+#[rocket::main]
+async fn main() {
+    // install the logging subscriber before anything logs
+    init_logging(&ConfigSetup::new());
+
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match Command::parse(&args) {
+        Ok(Command::Serve) => {
+            // refuse to start with missing or malformed secrets instead of failing the first webhook
+            let config: ConfigSetup = ConfigSetup::new();
+            let secret_errors: Vec<String> = validate_startup(&config)
+                .iter()
+                .map(|error| error.to_string())
+                .collect();
+
+            if !secret_errors.is_empty() {
+                for error in secret_errors {
+                    eprintln!("{}", error);
+                }
+                process::exit(1);
+            }
+
+            if let Err(error) = rocket().await.launch().await {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        Ok(command) => {
+            if let Err(error) = cli::run(command).await {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        },
+    }
+}
+
+
 pub async fn rocket() -> Rocket<Build> {
     // Determine the port to listen on from the `PORT` environment variable, defaulting to 8000.
     let port: u16 = env::var("PORT")
@@ -49,16 +92,17 @@ pub async fn rocket() -> Rocket<Build> {
     .parse()
     .expect("Failed to parse PORT");
 
+    // the organization webhooks are handled for, an invalid config stops the startup
+    let config: ConfigSetup = ConfigSetup::new();
+    let organization: Organization = organization_from_config(&config);
+
     // Build the Rocket instance, registering error catchers and configuring the server.
-    let rocket: Rocket<Build> = rocket::build()
+    let rocket: Rocket<Build> = build_rocket(organization, &config)
         .configure(Config {
             address: "0.0.0.0".parse().unwrap(), // Listen on all interfaces.
             port,
             ..Config::default()
-        })
-        .mount("/", routes![ // Mount API routes.
-            stripe_webhook
-        ]);
+        });
 
     // Return the Rocket instance.
     rocket
@@ -66,44 +110,11 @@ pub async fn rocket() -> Rocket<Build> {
 
 
 
-use stripe_discord::events::EventHandler;
 use stripe_discord::Organization;
-use stripe_discord::EmailConfig;
-
-
-#[post("/stripe_webhooks", format = "json", data = "<webhook_data>")]
-pub async fn stripe_webhook(
-    webhook_data: Json<Value>,
-) -> status::Custom<String> {
-    println!("JSON Payload: {:#?}", webhook_data);
-
-
-    // build the email config
-    let email_config: EmailConfig = EmailConfig::new(
-        "billing@xylex.cloud".to_string(),
-        "Welcome to Xylex Enterprise!".to_string(),
-        "https://xylex.ams3.cdn.digitaloceanspaces.com/email_templates/diamant_ai_new_sub.html".to_string(),
-    );
-
-
-    // build the organization
-    let organization: Organization = Organization::new(
-        "Xylex".to_string(),
-        email_config
-    );
-    // build the event handler
-    let event_handler: EventHandler = EventHandler::new(
-        &webhook_data,
-        organization
-    ).await;
-
-
-
-
-
-    println!("\x1b[32mEvent Handler: {:#?}\x1b[0m", event_handler);
-    status::Custom(
-        Status::Ok,
-        "Received webhook".to_string()
-    )
-}
+use stripe_discord::ConfigSetup;
+use stripe_discord::api::routes::build_rocket;
+use stripe_discord::cli::{self, Command, USAGE};
+use stripe_discord::log::client::init_logging;
+use stripe_discord::organization::router::organization_from_config;
+use stripe_discord::secrets::validate_startup;
+use std::process;
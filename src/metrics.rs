@@ -0,0 +1,217 @@
+//! ## Prometheus metrics
+//!
+//! Counters and histograms for everything that talks to the outside world, exposed in the
+//! Prometheus text format on the `/metrics` route.
+//!
+//! ### Metrics
+//! - `stripe_webhooks_total{event_type, outcome}` - Webhooks received by event type and outcome
+//! - `stripe_webhook_duration_seconds{event_type}` - Time spent handling a webhook
+//! - `emails_sent_total{provider, result}` - Emails sent by provider and `sent`/`failed`
+//! - `discord_api_requests_total{status}` - Discord API calls by HTTP status (`error` when no response)
+//! - `db_operation_duration_seconds{operation}` - Latency of database operations
+//! - `background_tasks_in_flight` - Background tasks that are queued or running
+//!
+//! The `event_type` label is one of the event types we handle or `other`, so senders of unknown
+//! or made-up types cannot grow the number of series.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let _timer: HistogramTimer = observe_db_operation("update_paid");
+//! // the latency is observed when `_timer` is dropped
+//! ```
+
+use prometheus::{
+    Encoder,
+    HistogramOpts,
+    HistogramTimer,
+    HistogramVec,
+    IntCounterVec,
+    IntGauge,
+    Opts,
+    Registry,
+    TextEncoder
+};
+use crate::events::HANDLED_EVENT_TYPES;
+
+use std::sync::LazyLock;
+
+
+/// The registry all metrics of this crate are registered in
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+
+/// `stripe_webhooks_total{event_type, outcome}`
+pub static WEBHOOKS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let counter: IntCounterVec = IntCounterVec::new(
+        Opts::new("stripe_webhooks_total", "Stripe webhooks received by event type and outcome"),
+        &["event_type", "outcome"]
+    ).expect("valid stripe_webhooks_total metric");
+
+    REGISTRY.register(Box::new(counter.clone())).expect("stripe_webhooks_total registered once");
+    counter
+});
+
+
+/// `stripe_webhook_duration_seconds{event_type}`
+pub static WEBHOOK_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    let histogram: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("stripe_webhook_duration_seconds", "Time spent handling a Stripe webhook"),
+        &["event_type"]
+    ).expect("valid stripe_webhook_duration_seconds metric");
+
+    REGISTRY.register(Box::new(histogram.clone())).expect("stripe_webhook_duration_seconds registered once");
+    histogram
+});
+
+
+/// `emails_sent_total{provider, result}`
+pub static EMAILS_SENT_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let counter: IntCounterVec = IntCounterVec::new(
+        Opts::new("emails_sent_total", "Emails sent by provider and result"),
+        &["provider", "result"]
+    ).expect("valid emails_sent_total metric");
+
+    REGISTRY.register(Box::new(counter.clone())).expect("emails_sent_total registered once");
+    counter
+});
+
+
+/// `discord_api_requests_total{status}`
+pub static DISCORD_API_REQUESTS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let counter: IntCounterVec = IntCounterVec::new(
+        Opts::new("discord_api_requests_total", "Discord API calls by HTTP status"),
+        &["status"]
+    ).expect("valid discord_api_requests_total metric");
+
+    REGISTRY.register(Box::new(counter.clone())).expect("discord_api_requests_total registered once");
+    counter
+});
+
+
+/// `db_operation_duration_seconds{operation}`
+pub static DB_OPERATION_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    let histogram: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("db_operation_duration_seconds", "Latency of database operations"),
+        &["operation"]
+    ).expect("valid db_operation_duration_seconds metric");
+
+    REGISTRY.register(Box::new(histogram.clone())).expect("db_operation_duration_seconds registered once");
+    histogram
+});
+
+
+/// `background_tasks_in_flight`
+pub static BACKGROUND_TASKS_IN_FLIGHT: LazyLock<IntGauge> = LazyLock::new(|| {
+    let gauge: IntGauge = IntGauge::new(
+        "background_tasks_in_flight",
+        "Background tasks that are queued or running"
+    ).expect("valid background_tasks_in_flight metric");
+
+    REGISTRY.register(Box::new(gauge.clone())).expect("background_tasks_in_flight registered once");
+    gauge
+});
+
+
+/// # event_type_label
+/// The `event_type` label of a Stripe event type, `other` for the types we do not handle.
+///
+/// ## Example
+/// ```rust
+/// # use stripe_discord::metrics::event_type_label;
+/// assert_eq!(event_type_label("charge.succeeded"), "charge.succeeded");
+/// assert_eq!(event_type_label("charge.captured"), "other");
+/// ```
+pub fn event_type_label(event_type: &str) -> &str {
+    if HANDLED_EVENT_TYPES.contains(&event_type) {
+        event_type
+    } else {
+        "other"
+    }
+}
+
+
+/// # observe_webhook
+/// Counts a received webhook by its event type and outcome.
+///
+/// ## Arguments
+/// - `event_type`: `&str` - The Stripe event type, e.g. `charge.succeeded`, see [`event_type_label`]
+/// - `outcome`: `&str` - How the webhook ended, e.g. `handled` or `ignored`
+pub fn observe_webhook(event_type: &str, outcome: &str) {
+    WEBHOOKS_TOTAL.with_label_values(&[event_type_label(event_type), outcome]).inc();
+}
+
+
+/// # time_webhook
+/// Starts a timer that observes `stripe_webhook_duration_seconds` when dropped.
+///
+/// ## Arguments
+/// - `event_type`: `&str` - The Stripe event type, e.g. `charge.succeeded`, see [`event_type_label`]
+pub fn time_webhook(event_type: &str) -> HistogramTimer {
+    WEBHOOK_DURATION.with_label_values(&[event_type_label(event_type)]).start_timer()
+}
+
+
+/// # observe_email
+/// Counts an email send attempt.
+///
+/// ## Arguments
+/// - `provider`: `&str` - The email provider, e.g. `resend`
+/// - `sent`: `bool` - Whether the provider accepted the email
+pub fn observe_email(provider: &str, sent: bool) {
+    let result: &str = if sent { "sent" } else { "failed" };
+
+    EMAILS_SENT_TOTAL.with_label_values(&[provider, result]).inc();
+}
+
+
+/// # observe_discord_request
+/// Counts a Discord API call.
+///
+/// ## Arguments
+/// - `status`: `Option<u16>` - The HTTP status Discord answered with, `None` when the request failed
+pub fn observe_discord_request(status: Option<u16>) {
+    let status: String = match status {
+        Some(status) => status.to_string(),
+        None => "error".to_string(),
+    };
+
+    DISCORD_API_REQUESTS_TOTAL.with_label_values(&[&status]).inc();
+}
+
+
+/// # observe_db_operation
+/// Starts a timer that observes `db_operation_duration_seconds` when dropped.
+///
+/// ## Arguments
+/// - `operation`: `&str` - The name of the database operation, e.g. `update_paid`
+pub fn observe_db_operation(operation: &str) -> HistogramTimer {
+    DB_OPERATION_DURATION.with_label_values(&[operation]).start_timer()
+}
+
+
+/// # render_metrics
+/// Renders every metric in the Prometheus text exposition format.
+///
+/// ## Returns
+/// The metrics as a `String`, served with the [`CONTENT_TYPE`] content type.
+pub fn render_metrics() -> String {
+    // make sure metrics that were never touched are registered and exported as well
+    LazyLock::force(&WEBHOOKS_TOTAL);
+    LazyLock::force(&WEBHOOK_DURATION);
+    LazyLock::force(&EMAILS_SENT_TOTAL);
+    LazyLock::force(&DISCORD_API_REQUESTS_TOTAL);
+    LazyLock::force(&DB_OPERATION_DURATION);
+    LazyLock::force(&BACKGROUND_TASKS_IN_FLIGHT);
+
+    let mut buffer: Vec<u8> = Vec::new();
+
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .expect("metrics encode as text");
+
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+
+/// The content type of the Prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
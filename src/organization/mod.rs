@@ -11,9 +11,20 @@
 //!
 //!
 
-use crate::EndpointConfigStripe;
+use crate::email::address::EmailValidation;
+use crate::email::retry::EmailRetry;
+use crate::email::EmailProvider;
+use crate::discord::access::AccessProduct;
+use crate::discord::roles::DiscordRoles;
+use crate::email::drip::DripStep;
+use crate::email::locale::{Locale, DEFAULT_LOCALE};
+use crate::secrets::{secret, Secret, SecretError};
 use crate::EmailConfig;
 use crate::Organization;
+use crate::organization::model::{DisputePolicy, EmailEvent, RefundPolicy};
+
+use std::collections::HashMap;
+use std::time::Duration;
 
 pub mod model;
 pub mod router;
@@ -24,17 +35,17 @@ pub mod router;
 ///
 impl Organization {
     /// # new
-    /// Constructs a new `Organization` instance with specified name and sender email.
+    /// Constructs a new `Organization` instance with specified name and welcome email.
     ///
     /// ## Arguments
     /// - `name`: `String` - The name of the Organization.
-    /// - `sender_email`: `String` - The email address associated with the Organization.
+    /// - `email_config`: `EmailConfig` - The welcome email, its sender is the default sender of the Organization.
     ///
     /// ## Returns
     /// - `Organization`: Returns a new instance of `Organization` populated with the provided name and email.
     ///
     /// ## Examples
-    /// ```
+    /// ```rust,ignore
     /// use crate::Organization;
     ///
     /// let org = Organization::new("Acme Corp".to_string(), "contact@acmecorp.com".to_string());
@@ -45,11 +56,392 @@ impl Organization {
         email_config: EmailConfig
     ) -> Organization {
 
+        let mut emails: HashMap<EmailEvent, EmailConfig> = HashMap::new();
+        emails.insert(EmailEvent::Welcome, email_config.clone());
+
         // return the new instance of Organization
         Organization { 
             name, 
-            email_config
+            email_config,
+            emails,
+            refund_policy: RefundPolicy::default(),
+            dispute_policy: DisputePolicy::default(),
+            operator_email: None,
+            attach_receipts: false,
+            stripe_private_key: None,
+            default_locale: DEFAULT_LOCALE.to_string(),
+            email_validation: EmailValidation::default(),
+            email_provider: EmailProvider::Resend,
+            email_retry: EmailRetry::default(),
+            drip_sequence: Vec::new(),
+            reminder_window: None,
+            access_products: Vec::new(),
+            discord_roles: None,
+        }
+    }
+
+
+    /// # with_payment_failed_email
+    /// Enables the "payment failed, update your card" email for this Organization.
+    ///
+    /// ## Arguments
+    /// - `payment_failed_email`: `EmailConfig` - The sender, subject and template of the email.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the payment failed email enabled.
+    ///
+    /// ## Examples
+    /// ```rust,ignore
+    /// let org = Organization::new("Acme Corp".to_string(), email_config)
+    ///     .with_payment_failed_email(payment_failed_email_config);
+    /// ```
+    pub fn with_payment_failed_email(
+        self,
+        payment_failed_email: EmailConfig
+    ) -> Organization {
+        self.with_email(EmailEvent::PaymentFailed, payment_failed_email)
+    }
+
+
+    /// # with_email
+    /// Enables the email of a lifecycle moment with its own sender, subject and template.
+    ///
+    /// ## Arguments
+    /// - `event`: `EmailEvent` - The moment the email is sent at.
+    /// - `email_config`: `EmailConfig` - The sender, subject and template of the email.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the email enabled.
+    ///
+    /// ## Examples
+    /// ```rust,ignore
+    /// let org = Organization::new("Acme Corp".to_string(), email_config)
+    ///     .with_email(EmailEvent::Receipt, receipt_email_config);
+    /// ```
+    pub fn with_email(
+        mut self,
+        event: EmailEvent,
+        email_config: EmailConfig
+    ) -> Organization {
+        self.emails.insert(event, email_config);
+
+        self
+    }
+
+
+    /// # without_email
+    /// Disables the email of a lifecycle moment, e.g. the welcome email.
+    ///
+    /// ## Arguments
+    /// - `event`: `EmailEvent` - The moment no email should be sent at.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the email disabled.
+    pub fn without_email(
+        mut self,
+        event: EmailEvent
+    ) -> Organization {
+        self.emails.remove(&event);
+
+        self
+    }
+
+
+    /// # email
+    /// The email sent at a lifecycle moment, `None` when it is disabled.
+    pub fn email(&self, event: EmailEvent) -> Option<&EmailConfig> {
+        self.emails.get(&event)
+    }
+
+
+    /// # with_entitlement_policies
+    /// Sets how refunds and disputes adjust the `paid` status of customers of this Organization.
+    ///
+    /// ## Arguments
+    /// - `refund_policy`: `RefundPolicy` - The policy applied on `charge.refunded`.
+    /// - `dispute_policy`: `DisputePolicy` - The policy applied on `charge.dispute.created` and `charge.dispute.closed`.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the policies set.
+    pub fn with_entitlement_policies(
+        mut self,
+        refund_policy: RefundPolicy,
+        dispute_policy: DisputePolicy
+    ) -> Organization {
+        self.refund_policy = refund_policy;
+        self.dispute_policy = dispute_policy;
+
+        self
+    }
+
+
+    /// # with_operator_email
+    /// Sends refund and dispute notifications of this Organization to `operator_email`.
+    ///
+    /// ## Arguments
+    /// - `operator_email`: `String` - The address of the operator.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with operator notifications enabled.
+    pub fn with_operator_email(
+        mut self,
+        operator_email: String
+    ) -> Organization {
+        self.operator_email = Some(operator_email);
+
+        self
+    }
+
+
+    /// # with_receipts
+    /// Turns the invoice or generated receipt on the welcome email of this Organization on or off.
+    ///
+    /// ## Arguments
+    /// - `attach_receipts`: `bool` - Whether receipts are attached.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with receipts turned on or off.
+    pub fn with_receipts(
+        mut self,
+        attach_receipts: bool
+    ) -> Organization {
+        self.attach_receipts = attach_receipts;
+
+        self
+    }
+
+
+    /// # with_email_validation
+    /// Sets how the addresses of customers of this Organization are checked before they are
+    /// stored or emailed.
+    ///
+    /// ## Arguments
+    /// - `email_validation`: `EmailValidation` - Whether MX records are looked up and what happens to invalid addresses.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the validation set.
+    pub fn with_email_validation(
+        mut self,
+        email_validation: EmailValidation
+    ) -> Organization {
+        self.email_validation = email_validation;
+
+        self
+    }
+
+
+    /// # with_drip_step
+    /// Appends a step to the drip sequence that starts when a customer purchases.
+    ///
+    /// ## Arguments
+    /// - `step`: `DripStep` - The name, delay and email of the step.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the step added to its sequence.
+    ///
+    /// ## Examples
+    /// ```rust,ignore
+    /// let org = Organization::new("Acme Corp".to_string(), email_config)
+    ///     .with_drip_step(DripStep::new("tips", Duration::from_secs(3 * 86400), tips_email_config));
+    /// ```
+    pub fn with_drip_step(
+        mut self,
+        step: DripStep
+    ) -> Organization {
+        self.drip_sequence.push(step);
+
+        self
+    }
+
+
+    /// # with_reminder_window
+    /// Scans for customers whose `end_time` is within `window` and sends them the renewal reminder.
+    ///
+    /// ## Arguments
+    /// - `window`: `Duration` - How long before the end of their period customers are reminded.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization whose customers are scanned by the scheduler.
+    ///
+    /// ## Examples
+    /// ```rust,ignore
+    /// let org = Organization::new("Acme Corp".to_string(), email_config)
+    ///     .with_reminder_window(Duration::from_secs(7 * 86400));
+    /// ```
+    pub fn with_reminder_window(
+        mut self,
+        window: Duration
+    ) -> Organization {
+        self.reminder_window = Some(window);
+
+        self
+    }
+
+
+    /// # with_access_product
+    /// Grants access for a limited time to the buyers of a product instead of for good.
+    ///
+    /// ## Arguments
+    /// - `product`: `AccessProduct` - The price or product id and how long a purchase grants access.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization whose buyers of the product lose access once it ends.
+    ///
+    /// ## Examples
+    /// ```rust,ignore
+    /// let org = Organization::new("Acme Corp".to_string(), email_config)
+    ///     .with_access_product(AccessProduct::new("price_1PthirtyDayPass", Duration::from_secs(30 * 86400)));
+    /// ```
+    pub fn with_access_product(
+        mut self,
+        product: AccessProduct
+    ) -> Organization {
+        self.access_products.push(product);
+
+        self
+    }
+
+
+    /// # drip_step
+    /// The step of the drip sequence with a name, `None` when it is not configured.
+    pub fn drip_step(&self, name: &str) -> Option<&DripStep> {
+        self.drip_sequence.iter().find(|step| step.name == name)
+    }
+
+
+    /// # with_email_provider
+    /// Sets the provider the emails of this Organization are sent with.
+    ///
+    /// ## Arguments
+    /// - `email_provider`: `EmailProvider` - The provider, Resend by default.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the provider set.
+    pub fn with_email_provider(
+        mut self,
+        email_provider: EmailProvider
+    ) -> Organization {
+        self.email_provider = email_provider;
+
+        self
+    }
+
+
+    /// # with_email_retry
+    /// Sets how often the emails of this Organization are attempted and which provider they fall
+    /// back to.
+    ///
+    /// ## Arguments
+    /// - `email_retry`: `EmailRetry` - The attempts, backoff and fallback provider.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the retries set.
+    pub fn with_email_retry(
+        mut self,
+        email_retry: EmailRetry
+    ) -> Organization {
+        self.email_retry = email_retry;
+
+        self
+    }
+
+
+    /// # with_default_locale
+    /// Sets the locale emails are rendered in for customers without a session locale or a known
+    /// billing country.
+    ///
+    /// ## Arguments
+    /// - `default_locale`: `&str` - A locale like `en` or `de`, ignored when it is not one.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with the default locale set.
+    pub fn with_default_locale(
+        mut self,
+        default_locale: &str
+    ) -> Organization {
+        if let Some(locale) = Locale::parse(default_locale) {
+            self.default_locale = locale.tag;
         }
+
+        self
     }
 
+
+    /// # locale
+    /// The locale of a customer of this Organization, see [`Locale::resolve`].
+    ///
+    /// ## Arguments
+    /// - `session_locale`: `Option<&str>` - The `locale` of the checkout session, if any.
+    /// - `country`: `Option<&str>` - The ISO code of the billing country, if known.
+    pub fn locale(
+        &self,
+        session_locale: Option<&str>,
+        country: Option<&str>
+    ) -> Locale {
+        Locale::resolve(session_locale, country, &self.default_locale)
+    }
+
+
+    /// # with_stripe_private_key
+    /// Retrieves the invoices of this Organization with its own Stripe account key.
+    ///
+    /// ## Arguments
+    /// - `stripe_private_key`: `Secret` - The `sk_` or `rk_` key of the Stripe account.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with its Stripe key set.
+    pub fn with_stripe_private_key(
+        mut self,
+        stripe_private_key: Secret
+    ) -> Organization {
+        self.stripe_private_key = Some(stripe_private_key);
+
+        self
+    }
+
+
+    /// # stripe_api_key
+    /// The private key of the Stripe account of this Organization, `STRIPE_PRIVATE_API_KEY` when it
+    /// has none of its own.
+    ///
+    /// ## Errors
+    /// - [`SecretError`] - The Organization has no key and `STRIPE_PRIVATE_API_KEY` is not set
+    pub fn stripe_api_key(&self) -> Result<Secret, SecretError> {
+        match &self.stripe_private_key {
+            Some(stripe_private_key) => Ok(stripe_private_key.clone()),
+            None => secret("STRIPE_PRIVATE_API_KEY"),
+        }
+    }
+
+
+    /// # with_discord_roles
+    /// Grants and revokes the role of this Organization with its own guild, role and bot.
+    ///
+    /// ## Arguments
+    /// - `discord_roles`: `DiscordRoles` - The bot and the guild role paid customers receive.
+    ///
+    /// ## Returns
+    /// - `Organization`: The Organization with its Discord settings set.
+    pub fn with_discord_roles(
+        mut self,
+        discord_roles: DiscordRoles
+    ) -> Organization {
+        self.discord_roles = Some(discord_roles);
+
+        self
+    }
+
+
+    /// # discord_roles
+    /// The role syncer of this Organization, `DISCORD_BOT_TOKEN`, `DISCORD_GUILD_ID` and
+    /// `DISCORD_ROLE_ID` when it has none of its own.
+    ///
+    /// ## Errors
+    /// - `String` - The Organization has no role syncer and the variables are not set
+    pub fn discord_roles(&self) -> Result<DiscordRoles, String> {
+        match &self.discord_roles {
+            Some(discord_roles) => Ok(discord_roles.clone()),
+            None => DiscordRoles::from_env(),
+        }
+    }
 }
@@ -1 +1,305 @@
 // ## Modeling an `Organization`
+//! ## Organization policies
+//!
+//! Decide how an `Organization` adjusts the `paid` entitlement of a customer when money flows back
+//!
+//! ### Policies
+//! - [`RefundPolicy`] - What happens to `paid` on `charge.refunded`
+//! - [`DisputePolicy`] - What happens to `paid` on `charge.dispute.created` and `charge.dispute.closed`
+//!
+//! ### Emails
+//! - [`EmailEvent`] - The moments an `Organization` can email its customers at
+//! - [`InvalidEmailPolicy`] - What happens to customer addresses that are not valid
+
+use std::fmt;
+use std::str::FromStr;
+
+
+/// ## Refund Policy
+/// This decides if a refund revokes the `paid` status of a customer
+///
+/// ### Policies
+/// - `keep` - Refunds never change `paid`
+/// - `revoke_on_full_refund` - Only a full refund sets `paid=false` (default)
+/// - `revoke_on_any_refund` - Partial and full refunds set `paid=false`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RefundPolicy {
+    Keep,
+    #[default]
+    RevokeOnFullRefund,
+    RevokeOnAnyRefund,
+}
+
+
+/// ## Dispute Policy
+/// This decides if a dispute revokes the `paid` status of a customer
+///
+/// ### Policies
+/// - `keep` - Disputes never change `paid`
+/// - `revoke_on_lost` - Only a lost dispute sets `paid=false` (default)
+/// - `revoke_on_open` - Opening a dispute sets `paid=false`, winning it sets `paid=true` again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisputePolicy {
+    Keep,
+    #[default]
+    RevokeOnLost,
+    RevokeOnOpen,
+}
+
+
+impl FromStr for RefundPolicy {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a string into a refund policy, unknown values are an error so a typo in
+    /// the config is not mistaken for the default
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::organization::model::RefundPolicy;
+    /// assert_eq!("revoke_on_any_refund".parse(), Ok(RefundPolicy::RevokeOnAnyRefund));
+    /// assert!("revoke_on_full".parse::<RefundPolicy>().is_err());
+    /// ```
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "keep" => Ok(RefundPolicy::Keep),
+            "revoke_on_full_refund" => Ok(RefundPolicy::RevokeOnFullRefund),
+            "revoke_on_any_refund" => Ok(RefundPolicy::RevokeOnAnyRefund),
+            _ => Err(format!(
+                "unknown refund policy `{}`, expected `keep`, `revoke_on_full_refund` or `revoke_on_any_refund`",
+                policy
+            )),
+        }
+    }
+}
+
+
+impl RefundPolicy {
+    /// ## paid_after_refund
+    /// Returns the `paid` status a customer should get after a refund
+    ///
+    /// ### Arguments
+    /// - `fully_refunded` - Whether the whole charge has been refunded
+    ///
+    /// ### Returns
+    /// `Some(false)` when the policy revokes access, `None` when `paid` should be left alone
+    pub fn paid_after_refund(&self, fully_refunded: bool) -> Option<bool> {
+        match (self, fully_refunded) {
+            (RefundPolicy::RevokeOnAnyRefund, _) => Some(false),
+            (RefundPolicy::RevokeOnFullRefund, true) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+
+impl FromStr for DisputePolicy {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a string into a dispute policy, unknown values are an error so a typo in
+    /// the config is not mistaken for the default
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::organization::model::DisputePolicy;
+    /// assert_eq!("revoke_on_open".parse(), Ok(DisputePolicy::RevokeOnOpen));
+    /// ```
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "keep" => Ok(DisputePolicy::Keep),
+            "revoke_on_lost" => Ok(DisputePolicy::RevokeOnLost),
+            "revoke_on_open" => Ok(DisputePolicy::RevokeOnOpen),
+            _ => Err(format!(
+                "unknown dispute policy `{}`, expected `keep`, `revoke_on_lost` or `revoke_on_open`",
+                policy
+            )),
+        }
+    }
+}
+
+
+impl DisputePolicy {
+    /// ## paid_after_dispute
+    /// Returns the `paid` status a customer should get after a dispute event
+    ///
+    /// ### Arguments
+    /// - `closed` - `false` for `charge.dispute.created`, `true` for `charge.dispute.closed`
+    /// - `status` - The status of the dispute, e.g. `needs_response`, `won` or `lost`
+    ///
+    /// ### Returns
+    /// `Some(paid)` when the policy changes access, `None` when `paid` should be left alone
+    pub fn paid_after_dispute(&self, closed: bool, status: &str) -> Option<bool> {
+        match (self, closed, status) {
+            (DisputePolicy::RevokeOnOpen, false, _) => Some(false),
+            (DisputePolicy::RevokeOnOpen, true, "won") => Some(true),
+            (DisputePolicy::RevokeOnOpen, true, "lost") => Some(false),
+            (DisputePolicy::RevokeOnLost, true, "lost") => Some(false),
+            _ => None,
+        }
+    }
+}
+
+
+/// ## Invalid Email Policy
+/// This decides what happens to a customer address that fails validation, it is never written
+/// to the customer data or emailed either way
+///
+/// ### Policies
+/// - `reject` - The address is dropped with a warning
+/// - `quarantine` - The address is stored in the quarantine table for review (default)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidEmailPolicy {
+    Reject,
+    #[default]
+    Quarantine,
+}
+
+
+impl FromStr for InvalidEmailPolicy {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert a string into an invalid email policy
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::organization::model::InvalidEmailPolicy;
+    /// assert_eq!("reject".parse(), Ok(InvalidEmailPolicy::Reject));
+    /// assert!("drop".parse::<InvalidEmailPolicy>().is_err());
+    /// ```
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "reject" => Ok(InvalidEmailPolicy::Reject),
+            "quarantine" => Ok(InvalidEmailPolicy::Quarantine),
+            _ => Err(format!("unknown invalid email policy `{}`, expected `reject` or `quarantine`", policy)),
+        }
+    }
+}
+
+
+/// ## Email Event
+/// The lifecycle moments an Organization can send an email at, each with its own template
+///
+/// ### Events
+/// - `welcome` - On `checkout.session.completed`, the first purchase
+/// - `receipt` - On every `charge.succeeded`
+/// - `trial_ending` - On `customer.subscription.trial_will_end`, before a trial converts
+/// - `renewal_reminder` - On `invoice.upcoming`, before a subscription renews
+/// - `payment_failed` - On `charge.failed` and `payment_intent.payment_failed`
+/// - `cancellation` - On `customer.subscription.deleted`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmailEvent {
+    Welcome,
+    Receipt,
+    TrialEnding,
+    RenewalReminder,
+    PaymentFailed,
+    Cancellation,
+}
+
+
+impl EmailEvent {
+    /// Every email event, in the order of a customer lifecycle
+    pub const ALL: [EmailEvent; 6] = [
+        EmailEvent::Welcome,
+        EmailEvent::Receipt,
+        EmailEvent::TrialEnding,
+        EmailEvent::RenewalReminder,
+        EmailEvent::PaymentFailed,
+        EmailEvent::Cancellation,
+    ];
+
+
+    /// ## as_str
+    /// The snake case name of the email event
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailEvent::Welcome => "welcome",
+            EmailEvent::Receipt => "receipt",
+            EmailEvent::TrialEnding => "trial_ending",
+            EmailEvent::RenewalReminder => "renewal_reminder",
+            EmailEvent::PaymentFailed => "payment_failed",
+            EmailEvent::Cancellation => "cancellation",
+        }
+    }
+
+
+    /// ## config_key
+    /// The key of the email event under `Email` in `stripe_discord.yaml`
+    pub fn config_key(&self) -> &'static str {
+        match self {
+            EmailEvent::Welcome => "Welcome",
+            EmailEvent::Receipt => "Receipt",
+            EmailEvent::TrialEnding => "TrialEnding",
+            EmailEvent::RenewalReminder => "RenewalReminder",
+            EmailEvent::PaymentFailed => "PaymentFailed",
+            EmailEvent::Cancellation => "Cancellation",
+        }
+    }
+
+
+    /// ## for_event_type
+    /// The email event a Stripe event type sends, if any
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::organization::model::EmailEvent;
+    /// assert_eq!(EmailEvent::for_event_type("charge.succeeded"), Some(EmailEvent::Receipt));
+    /// ```
+    pub fn for_event_type(event_type: &str) -> Option<Self> {
+        match event_type {
+            "checkout.session.completed" => Some(EmailEvent::Welcome),
+            "charge.succeeded" => Some(EmailEvent::Receipt),
+            "customer.subscription.trial_will_end" => Some(EmailEvent::TrialEnding),
+            "invoice.upcoming" => Some(EmailEvent::RenewalReminder),
+            "charge.failed" | "payment_intent.payment_failed" => Some(EmailEvent::PaymentFailed),
+            "customer.subscription.deleted" => Some(EmailEvent::Cancellation),
+            _ => None,
+        }
+    }
+
+
+    /// ## default_subject
+    /// The subject used when none is configured
+    pub fn default_subject(&self) -> &'static str {
+        match self {
+            EmailEvent::Welcome => "Welcome!",
+            EmailEvent::Receipt => "Your receipt",
+            EmailEvent::TrialEnding => "Your trial ends soon",
+            EmailEvent::RenewalReminder => "Your subscription renews soon",
+            EmailEvent::PaymentFailed => "Your payment failed",
+            EmailEvent::Cancellation => "Your subscription has been cancelled",
+        }
+    }
+}
+
+
+impl FromStr for EmailEvent {
+    type Err = String;
+
+    /// ## From String
+    /// This will convert the snake case name of an email event into an email event
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use stripe_discord::organization::model::EmailEvent;
+    /// assert_eq!("renewal_reminder".parse(), Ok(EmailEvent::RenewalReminder));
+    /// assert!("renewal".parse::<EmailEvent>().is_err());
+    /// ```
+    fn from_str(event: &str) -> Result<Self, Self::Err> {
+        EmailEvent::ALL
+            .into_iter()
+            .find(|email_event| email_event.as_str() == event)
+            .ok_or(format!("unknown email event `{}`", event))
+    }
+}
+
+
+impl fmt::Display for EmailEvent {
+    /// ## To String
+    /// This will write the snake case name of the email event
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -1,3 +1,187 @@
 //! ## Organization router
 //!
 //! Route the correct data points to the correct handlers based on their organization
+
+use crate::discord::roles::DiscordRoles;
+use crate::email::locale::EmailTranslation;
+use crate::email::address::EmailValidation;
+use crate::email::retry::EmailRetry;
+use crate::email::EmailProvider;
+use crate::organization::model::{DisputePolicy, EmailEvent, InvalidEmailPolicy, RefundPolicy};
+use crate::ConfigSetup;
+use crate::EmailConfig;
+use crate::EndpointConfigStripe;
+use crate::Organization;
+
+use std::time::Duration;
+
+
+/// # organization_from_config
+/// Builds the Organization webhooks are handled for, with the emails of every lifecycle moment,
+/// entitlement policies and operator address from `stripe_discord.yaml`.
+///
+/// ## Arguments
+/// - `config`: `&ConfigSetup` - The loaded config
+///
+/// ## Returns
+/// - `Organization`: The Organization to pass to the `EventHandler`
+///
+/// ## Example
+/// ```rust,ignore
+/// let organization: Organization = organization_from_config(&ConfigSetup::new());
+/// ```
+pub fn organization_from_config(config: &ConfigSetup) -> Organization {
+    // the welcome email defaults, `Email.Welcome` overrides them
+    let welcome_email: EmailConfig = EmailConfig::new(
+        "billing@xylex.cloud".to_string(),
+        "Welcome to Xylex Enterprise!".to_string(),
+        "https://xylex.ams3.cdn.digitaloceanspaces.com/email_templates/diamant_ai_new_sub.html".to_string(),
+    );
+
+    // build the organization
+    let mut organization: Organization = Organization::new(
+        "Xylex".to_string(),
+        welcome_email.clone()
+    );
+
+    // enable or disable the email of every lifecycle moment, the others default to the
+    // `Email.Sender` and the template named after the moment
+    for email in &config.emails {
+        let default: EmailConfig = match email.event {
+            EmailEvent::Welcome => welcome_email.clone(),
+            event => EmailConfig::new(
+                config.sender_email.clone(),
+                event.default_subject().to_string(),
+                event.as_str().to_string(),
+            ),
+        };
+
+        organization = match email.enabled {
+            true => organization.with_email(email.event, email.to_email_config(&default)),
+            false => organization.without_email(email.event),
+        };
+    }
+
+    // the welcome sender stays the default sender of the organization
+    organization.email_config = email_config_or(organization.email(EmailEvent::Welcome), &welcome_email);
+
+    // adjust `paid` on refunds and disputes by the configured policies, their names are checked
+    // when the config is loaded
+    organization = organization.with_entitlement_policies(
+        config.refund_policy.parse::<RefundPolicy>().unwrap_or_default(),
+        config.dispute_policy.parse::<DisputePolicy>().unwrap_or_default(),
+    );
+
+    if let Some(operator_email) = &config.operator_email {
+        organization = organization.with_operator_email(operator_email.clone());
+    }
+
+    organization = organization
+        .with_receipts(config.attach_receipts)
+        .with_default_locale(&config.default_locale)
+        .with_email_validation(EmailValidation {
+            check_mx: config.check_mx,
+            invalid: config.invalid_email_policy.parse::<InvalidEmailPolicy>().unwrap_or_default(),
+        })
+        .with_email_provider(EmailProvider::from_str(&config.email_provider))
+        .with_email_retry(EmailRetry {
+            max_attempts: config.email_max_attempts,
+            backoff: Duration::from_millis(config.email_backoff_ms),
+            max_backoff: Duration::from_millis(config.email_max_backoff_ms),
+            fallback: config.email_fallback_provider.as_deref().map(EmailProvider::from_str),
+            fallback_after: config.email_fallback_after,
+        });
+
+    // the drip sequence that follows a purchase, sent by the scheduler
+    for step in &config.drips {
+        organization = organization.with_drip_step(step.to_drip_step(&config.sender_email));
+    }
+
+    // the customers renewing within the window get the renewal reminder from the scheduler
+    if let Some(window_days) = config.renewal_reminder_window_days {
+        organization = organization.with_reminder_window(Duration::from_secs(window_days * 86400));
+    }
+
+    // purchases of these products grant access until the scheduler expires it
+    for product in &config.access_products {
+        organization = organization.with_access_product(product.clone());
+    }
+
+    organization
+}
+
+
+/// # organization_for_endpoint
+/// Builds the Organization the webhooks of an endpoint are handled for, named after the endpoint
+/// and sending its emails from the sender of the endpoint and its welcome email with the template
+/// of the endpoint. Which emails are enabled, their subjects, the other templates, the drip
+/// sequence, the time-boxed products, the policies and the operator address are taken from
+/// `base`, the plain-text alternative of the welcome email is generated from the template of the
+/// endpoint and only its translated subjects and preheaders are kept. Receipts follow
+/// `AttachReceipts` of the endpoint when it is set, invoices are retrieved with the private key of
+/// the endpoint when it has one and the Discord role is synced with the guild, role and bot of the
+/// endpoint when it has them. The customers are shared, so only `base` scans them for renewal
+/// reminders.
+///
+/// ## Arguments
+/// - `base`: `&Organization` - The Organization built by [`organization_from_config`]
+/// - `endpoint`: `&EndpointConfigStripe` - The endpoint from `stripe_discord.yaml`
+///
+/// ## Example
+/// ```rust,ignore
+/// let organization: Organization = organization_for_endpoint(&organization_from_config(&config), &endpoint);
+/// ```
+pub fn organization_for_endpoint(base: &Organization, endpoint: &EndpointConfigStripe) -> Organization {
+    let mut organization: Organization = base.clone();
+
+    organization.name = endpoint.name.clone();
+    organization.email_config = EmailConfig::new(
+        endpoint.sender_email.clone(),
+        base.email_config.subject.clone(),
+        endpoint.email_template_path.clone(),
+    );
+    organization.email_config.preheader = base.email_config.preheader.clone();
+
+    // keep the translated subjects of the welcome email, its template is the one of the endpoint
+    organization.email_config.translations = base.email_config.translations
+        .iter()
+        .map(|(locale, translation)| {
+            (locale.clone(), EmailTranslation { template_url: None, text_template_url: None, ..translation.clone() })
+        })
+        .collect();
+
+    for (event, email_config) in organization.emails.iter_mut() {
+        *email_config = match event {
+            EmailEvent::Welcome => organization.email_config.clone(),
+            _ => EmailConfig { sender_email: endpoint.sender_email.clone(), ..email_config.clone() },
+        };
+    }
+
+    // the drip sequence is shared, sent from the sender of the endpoint
+    for step in organization.drip_sequence.iter_mut() {
+        step.email_config.sender_email = endpoint.sender_email.clone();
+    }
+
+    organization.reminder_window = None;
+
+    if let Some(attach_receipts) = endpoint.attach_receipts {
+        organization = organization.with_receipts(attach_receipts);
+    }
+
+    organization.stripe_private_key = match endpoint.stripe_private_key.is_empty() {
+        true => None,
+        false => endpoint.stripe_private_key().ok(),
+    };
+
+    // endpoints without a guild, role and bot of their own sync the role from the environment
+    organization.discord_roles = DiscordRoles::from_endpoint(endpoint).ok();
+
+    organization
+}
+
+
+/// # email_config_or
+/// The email config when the email is enabled, `default` otherwise.
+fn email_config_or(email_config: Option<&EmailConfig>, default: &EmailConfig) -> EmailConfig {
+    email_config.cloned().unwrap_or(default.clone())
+}
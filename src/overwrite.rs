@@ -152,6 +152,25 @@ pub fn overwrite_stripe_customer_email_sent_column_name() -> String {
 }
 
 
+/// ## Overwrite `email_sent_reason` column name for the Stripe Customer data
+///
+/// This function will return the column name for why the last email was not sent in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the email sent reason to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_email_sent_reason_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_email_sent_reason: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_EMAIL_SENT_REASON_COLUMN_NAME") {
+            Ok(column_name_customer_email_sent_reason) => column_name_customer_email_sent_reason.clone(),
+            Err(_) => "email_sent_reason".to_string(),
+        };
+
+    column_name_customer_email_sent_reason
+}
+
+
 /// ### Overwrite `end_time` column name for the Stripe Customer data`
 ///
 /// This function will return the column name for the end time in Supabase for the Stripe Customer data
@@ -274,6 +293,120 @@ pub fn overwrite_stripe_customer_amount_total_column_name() -> String {
 }
 
 
+/// ### Overwrite `amount_total_minor` column name for the Stripe Customer data
+///
+/// This function will return the column name for the amount total in minor units in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the amount total in minor units to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_amount_total_minor_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_amount_total_minor: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_AMOUNT_TOTAL_MINOR_COLUMN_NAME") {
+            Ok(column_name_customer_amount_total_minor) => column_name_customer_amount_total_minor.clone(),
+            Err(_) => "amount_total_minor".to_string(),
+        };
+
+    column_name_customer_amount_total_minor
+}
+
+
+/// ### Overwrite `currency` column name for the Stripe Customer data
+///
+/// This function will return the column name for the currency of `amount_total` in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the currency to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_currency_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_currency: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_CURRENCY_COLUMN_NAME") {
+            Ok(column_name_customer_currency) => column_name_customer_currency.clone(),
+            Err(_) => "currency".to_string(),
+        };
+
+    column_name_customer_currency
+}
+
+
+/// ### Overwrite `access_status` column name for the Stripe Customer data
+///
+/// This function will return the column name for the status of time-boxed access in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the access status to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_access_status_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_access_status: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_ACCESS_STATUS_COLUMN_NAME") {
+            Ok(column_name_customer_access_status) => column_name_customer_access_status.clone(),
+            Err(_) => "access_status".to_string(),
+        };
+
+    column_name_customer_access_status
+}
+
+
+/// ### Overwrite `access_start_time` column name for the Stripe Customer data
+///
+/// This function will return the column name for the start of time-boxed access in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the access start time to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_access_start_time_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_access_start_time: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_ACCESS_START_TIME_COLUMN_NAME") {
+            Ok(column_name_customer_access_start_time) => column_name_customer_access_start_time.clone(),
+            Err(_) => "access_start_time".to_string(),
+        };
+
+    column_name_customer_access_start_time
+}
+
+
+/// ### Overwrite `access_end_time` column name for the Stripe Customer data
+///
+/// This function will return the column name for the end of time-boxed access in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the access end time to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_access_end_time_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_access_end_time: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_ACCESS_END_TIME_COLUMN_NAME") {
+            Ok(column_name_customer_access_end_time) => column_name_customer_access_end_time.clone(),
+            Err(_) => "access_end_time".to_string(),
+        };
+
+    column_name_customer_access_end_time
+}
+
+
+/// ### Overwrite `access_organization` column name for the Stripe Customer data
+///
+/// This function will return the column name for the Organization that granted time-boxed access in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the access organization to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_access_organization_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_access_organization: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_ACCESS_ORGANIZATION_COLUMN_NAME") {
+            Ok(column_name_customer_access_organization) => column_name_customer_access_organization.clone(),
+            Err(_) => "access_organization".to_string(),
+        };
+
+    column_name_customer_access_organization
+}
+
+
 /// ### Overwrite `payment_link` column name for the Stripe Customer data
 ///
 /// This function will return the column name for the payment link in Supabase for the Stripe Customer data
@@ -291,3 +424,298 @@ pub fn overwrite_stripe_customer_payment_link_column_name() -> String {
 
     column_name_customer_payment_link
 }
+
+
+/// ### Overwrite `decline_code` column name for the Stripe Customer data
+///
+/// This function will return the column name for the decline code of the last failed payment in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the decline code to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_decline_code_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_decline_code: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_DECLINE_CODE_COLUMN_NAME") {
+            Ok(column_name_customer_decline_code) => column_name_customer_decline_code.clone(),
+            Err(_) => "decline_code".to_string(),
+        };
+
+    column_name_customer_decline_code
+}
+
+
+/// ### Overwrite `decline_message` column name for the Stripe Customer data
+///
+/// This function will return the column name for the decline message of the last failed payment in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the decline message to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_decline_message_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_decline_message: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_DECLINE_MESSAGE_COLUMN_NAME") {
+            Ok(column_name_customer_decline_message) => column_name_customer_decline_message.clone(),
+            Err(_) => "decline_message".to_string(),
+        };
+
+    column_name_customer_decline_message
+}
+
+
+/// ### Overwrite `amount_refunded` column name for the Stripe Customer data
+///
+/// This function will return the column name for the total refunded amount in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the refunded amount to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_amount_refunded_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_amount_refunded: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_AMOUNT_REFUNDED_COLUMN_NAME") {
+            Ok(column_name_customer_amount_refunded) => column_name_customer_amount_refunded.clone(),
+            Err(_) => "amount_refunded".to_string(),
+        };
+
+    column_name_customer_amount_refunded
+}
+
+
+/// ### Overwrite `refund_status` column name for the Stripe Customer data
+///
+/// This function will return the column name for the refund status (`partial` or `full`) in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the refund status to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_refund_status_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_refund_status: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_REFUND_STATUS_COLUMN_NAME") {
+            Ok(column_name_customer_refund_status) => column_name_customer_refund_status.clone(),
+            Err(_) => "refund_status".to_string(),
+        };
+
+    column_name_customer_refund_status
+}
+
+
+/// ### Overwrite `dispute_status` column name for the Stripe Customer data
+///
+/// This function will return the column name for the dispute status in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the dispute status to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_dispute_status_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_dispute_status: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_DISPUTE_STATUS_COLUMN_NAME") {
+            Ok(column_name_customer_dispute_status) => column_name_customer_dispute_status.clone(),
+            Err(_) => "dispute_status".to_string(),
+        };
+
+    column_name_customer_dispute_status
+}
+
+
+/// ### Overwrite `dispute_reason` column name for the Stripe Customer data
+///
+/// This function will return the column name for the dispute reason in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the dispute reason to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_dispute_reason_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_dispute_reason: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_DISPUTE_REASON_COLUMN_NAME") {
+            Ok(column_name_customer_dispute_reason) => column_name_customer_dispute_reason.clone(),
+            Err(_) => "dispute_reason".to_string(),
+        };
+
+    column_name_customer_dispute_reason
+}
+
+
+
+/// ### Overwrite `discord_user_id` column name for the Stripe Customer data
+///
+/// This function will return the column name for the linked Discord user id in Supabase for the Stripe Customer data
+///
+/// ### Returns
+/// The column name for the Discord user id to use in Supabase for the Stripe Customer data
+pub fn overwrite_stripe_customer_discord_user_id_column_name() -> String {
+    dotenv().ok();
+
+    let column_name_customer_discord_user_id: String =
+        match var("OVERWRITE_STRIPE_CUSTOMER_DISCORD_USER_ID_COLUMN_NAME") {
+            Ok(column_name_customer_discord_user_id) => column_name_customer_discord_user_id.clone(),
+            Err(_) => "discord_user_id".to_string(),
+        };
+
+    column_name_customer_discord_user_id
+}
+
+/// ### Overwrite `stripe_customer_audit` table name for the Stripe Customer data
+///
+/// This function will return the table name for the refund and dispute audit trail in Supabase
+///
+/// ### Returns
+/// The table name for the audit trail to use in Supabase
+pub fn overwrite_stripe_customer_audit_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_CUSTOMER_AUDIT_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_customer_audit".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_email_quarantine` table name for the quarantined customer addresses
+///
+/// This function will return the table name for the customer addresses that failed validation in Supabase
+///
+/// ### Returns
+/// The table name for the email quarantine to use in Supabase
+pub fn overwrite_stripe_email_quarantine_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_EMAIL_QUARANTINE_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_email_quarantine".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_email_suppressions` table name for the email suppression list
+///
+/// This function will return the table name for the addresses that bounced or complained in Supabase
+///
+/// ### Returns
+/// The table name for the email suppression list to use in Supabase
+pub fn overwrite_stripe_email_suppressions_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_EMAIL_SUPPRESSIONS_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_email_suppressions".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_webhook_events` table name for the webhook event log
+///
+/// This function will return the table name for the log of received Stripe webhooks in Supabase
+///
+/// ### Returns
+/// The table name for the webhook event log to use in Supabase
+pub fn overwrite_stripe_webhook_events_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_WEBHOOK_EVENTS_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_webhook_events".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_api_keys` table name for the issued API keys
+///
+/// This function will return the table name for the hashed API keys in Supabase
+///
+/// ### Returns
+/// The table name for the API keys to use in Supabase
+pub fn overwrite_stripe_api_keys_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_API_KEYS_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_api_keys".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_email_log` table name for the log of sent emails
+///
+/// This function will return the table name for the delivery status of every email sent in Supabase
+///
+/// ### Returns
+/// The table name for the email log to use in Supabase
+pub fn overwrite_stripe_email_log_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_EMAIL_LOG_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_email_log".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_email_attempts` table name for the attempts of every email
+///
+/// This function will return the table name for the send attempts of emails in Supabase
+///
+/// ### Returns
+/// The table name for the email attempts to use in Supabase
+pub fn overwrite_stripe_email_attempts_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_EMAIL_ATTEMPTS_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_email_attempts".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_scheduled_emails` table name for the emails of drip sequences
+///
+/// This function will return the table name for the scheduled emails in Supabase
+///
+/// ### Returns
+/// The table name for the scheduled emails to use in Supabase
+pub fn overwrite_stripe_scheduled_emails_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_SCHEDULED_EMAILS_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_scheduled_emails".to_string(),
+    };
+
+    table_name
+}
+
+
+/// ### Overwrite `stripe_period_reminders` table name for the reminders of subscription periods
+///
+/// This function will return the table name for the period reminders in Supabase
+///
+/// ### Returns
+/// The table name for the period reminders to use in Supabase
+pub fn overwrite_stripe_period_reminders_table_name() -> String {
+    dotenv().ok();
+
+    let table_name: String = match var("OVERWRITE_STRIPE_PERIOD_REMINDERS_TABLE_NAME") {
+        Ok(table_name) => table_name.clone(),
+        Err(_) => "stripe_period_reminders".to_string(),
+    };
+
+    table_name
+}
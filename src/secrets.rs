@@ -0,0 +1,316 @@
+//! ## Resolving secrets
+//!
+//! The Stripe, Supabase, Resend and Discord secrets are resolved here instead of reading the
+//! environment all over the crate. A secret comes from one of three [`SecretSource`]s:
+//! - a literal value, e.g. `whsec_...` in the config
+//! - a named environment variable, `env:STRIPE_WEBHOOK_SECRET` in the config
+//! - a mounted file such as a Docker or Kubernetes secret, `file:/run/secrets/stripe_webhook_secret`
+//!
+//! Secrets read from the environment fall back to the file named by `<NAME>_FILE`, so
+//! `SUPABASE_KEY_FILE=/run/secrets/supabase_key` works wherever `SUPABASE_KEY` does.
+//!
+//! ### Startup validation
+//! [`validate_startup`] resolves every secret the configured providers need and checks its format
+//! before the server starts, so a missing or malformed secret fails the deploy instead of the
+//! first webhook.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let secret: Secret = secret("STRIPE_WEBHOOK_SECRET")?;
+//! verify_signature(raw_body, signature, secret.expose(), unix_now())?;
+//!
+//! // prints `Secret("***")`
+//! println!("{:?}", secret);
+//! ```
+
+use crate::email::EmailProvider;
+use crate::ConfigSetup;
+
+use dotenv::dotenv;
+use std::env::var;
+use std::error::Error;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use supabase_rs::SupabaseClient;
+
+
+/// The secrets the crate reads from the environment and the prefixes their values start with
+///
+/// ### Fields
+/// - `0` - The name of the environment variable
+/// - `1` - The prefixes a valid value starts with, empty when any value is accepted
+pub const SECRET_FORMATS: &[(&str, &[&str])] = &[
+    ("STRIPE_WEBHOOK_SECRET", &["whsec_"]),
+    ("STRIPE_PRIVATE_API_KEY", &["sk_", "rk_"]),
+    ("STRIPE_PUBLISH_KEY", &["pk_"]),
+    ("SUPABASE_URL", &["http://", "https://"]),
+    ("SUPABASE_KEY", &[]),
+    ("RESEND_API_KEY", &["re_"]),
+    ("RESEND_WEBHOOK_SECRET", &["whsec_"]),
+    ("SMTP_PASSWORD", &[]),
+    ("DISCORD_BOT_TOKEN", &[]),
+];
+
+
+/// ## Secret
+/// A resolved secret value, `Debug` never shows it
+///
+/// ### Usage example
+/// ```rust
+/// # use stripe_discord::secrets::Secret;
+/// let secret: Secret = Secret::new("whsec_test".to_string());
+///
+/// assert_eq!(secret.expose(), "whsec_test");
+/// assert_eq!(format!("{:?}", secret), "Secret(\"***\")");
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+
+impl Secret {
+    /// # new
+    /// Wraps a secret value.
+    pub fn new(value: String) -> Self {
+        Secret(value)
+    }
+
+    /// # expose
+    /// The secret value, only call this where the value is sent or compared.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+
+// secrets must never end up in the logs
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Secret").field(&"***").finish()
+    }
+}
+
+
+/// ## SecretSource
+/// Where a secret is read from
+///
+/// ### Variants
+/// - `Literal` - The value itself
+/// - `Env` - The name of the environment variable holding the value
+/// - `File` - The path of a file holding the value, trailing whitespace is dropped
+#[derive(Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    Literal(String),
+    Env(String),
+    File(PathBuf),
+}
+
+
+impl SecretSource {
+    /// # parse
+    /// Reads a source from a config value, `env:<NAME>` and `file:<path>` name a variable or a
+    /// file, anything else is the literal value.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::secrets::SecretSource;
+    /// assert_eq!(SecretSource::parse("env:STRIPE_WEBHOOK_SECRET"), SecretSource::Env("STRIPE_WEBHOOK_SECRET".to_string()));
+    /// assert_eq!(SecretSource::parse("whsec_test"), SecretSource::Literal("whsec_test".to_string()));
+    /// ```
+    pub fn parse(value: &str) -> Self {
+        if let Some(name) = value.strip_prefix("env:") {
+            return SecretSource::Env(name.trim().to_string());
+        }
+
+        if let Some(path) = value.strip_prefix("file:") {
+            return SecretSource::File(PathBuf::from(path.trim()));
+        }
+
+        SecretSource::Literal(value.to_string())
+    }
+
+    /// # from_env_name
+    /// The environment variable `name`, or the file named by `<name>_FILE` when only that is set.
+    ///
+    /// ## Arguments
+    /// - `name`: `&str` - The name of the environment variable, e.g. `SUPABASE_KEY`
+    pub fn from_env_name(name: &str) -> Self {
+        dotenv().ok();
+
+        if var(name).is_err() {
+            if let Ok(path) = var(format!("{}_FILE", name)) {
+                return SecretSource::File(PathBuf::from(path));
+            }
+        }
+
+        SecretSource::Env(name.to_string())
+    }
+
+    /// # resolve
+    /// Reads the secret from its source.
+    ///
+    /// ## Errors
+    /// - [`SecretError::NotSet`] - The environment variable is not set
+    /// - [`SecretError::Unreadable`] - The file could not be read
+    /// - [`SecretError::Empty`] - The value is empty
+    pub fn resolve(&self) -> Result<Secret, SecretError> {
+        let value: String = match self {
+            SecretSource::Literal(value) => value.clone(),
+            SecretSource::Env(name) => var(name).map_err(|_| SecretError::NotSet(name.clone()))?,
+            SecretSource::File(path) => read_to_string(path)
+                .map_err(|error| SecretError::Unreadable(path.display().to_string(), error.to_string()))?
+                .trim_end()
+                .to_string(),
+        };
+
+        if value.trim().is_empty() {
+            return Err(SecretError::Empty(self.describe()));
+        }
+
+        Ok(Secret::new(value))
+    }
+
+    /// # describe
+    /// Names the source without its value, for errors and logs.
+    pub fn describe(&self) -> String {
+        match self {
+            SecretSource::Literal(_) => "the literal value".to_string(),
+            SecretSource::Env(name) => name.clone(),
+            SecretSource::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+
+// literal sources hold the secret itself
+impl fmt::Debug for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::Literal(_) => f.debug_tuple("Literal").field(&"***").finish(),
+            SecretSource::Env(name) => f.debug_tuple("Env").field(name).finish(),
+            SecretSource::File(path) => f.debug_tuple("File").field(path).finish(),
+        }
+    }
+}
+
+
+/// ## SecretError
+/// The reasons a secret can not be used
+///
+/// ### Variants
+/// - `NotSet` - The environment variable is not set
+/// - `Unreadable` - The file could not be read, with its path and the io error
+/// - `Empty` - The value is empty
+/// - `Malformed` - The value does not start with any of the expected prefixes
+/// - `Endpoint` - A secret of the endpoint mounted at the route can not be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretError {
+    NotSet(String),
+    Unreadable(String, String),
+    Empty(String),
+    Malformed(String, String),
+    Endpoint(String, Box<SecretError>),
+}
+
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecretError::NotSet(name) => write!(f, "{} is not set", name),
+            SecretError::Unreadable(path, error) => write!(f, "failed to read the secret file {}: {}", path, error),
+            SecretError::Empty(source) => write!(f, "the secret from {} is empty", source),
+            SecretError::Malformed(name, expected) => write!(f, "{} must start with {}", name, expected),
+            SecretError::Endpoint(route, error) => write!(f, "endpoint {}: {}", route, error),
+        }
+    }
+}
+
+impl Error for SecretError {}
+
+
+/// # secret
+/// Resolves a secret from the environment variable `name` or the file under `<name>_FILE`.
+///
+/// ## Arguments
+/// - `name`: `&str` - The name of the environment variable, e.g. `RESEND_API_KEY`
+pub fn secret(name: &str) -> Result<Secret, SecretError> {
+    SecretSource::from_env_name(name).resolve()
+}
+
+
+/// # validate_format
+/// Checks that a secret starts with one of the prefixes of its format in [`SECRET_FORMATS`].
+///
+/// ## Arguments
+/// - `name`: `&str` - The name of the secret, e.g. `STRIPE_WEBHOOK_SECRET`
+/// - `secret`: `&Secret` - The resolved secret
+pub fn validate_format(name: &str, secret: &Secret) -> Result<(), SecretError> {
+    let prefixes: &[&str] = SECRET_FORMATS
+        .iter()
+        .find(|(format_name, _)| *format_name == name)
+        .map(|(_, prefixes)| *prefixes)
+        .unwrap_or_default();
+
+    if prefixes.is_empty() || prefixes.iter().any(|prefix| secret.expose().starts_with(prefix)) {
+        return Ok(());
+    }
+
+    Err(SecretError::Malformed(name.to_string(), prefixes.join(" or ")))
+}
+
+
+/// # supabase_client
+/// The Supabase client from the `SUPABASE_URL` and `SUPABASE_KEY` secrets.
+pub fn supabase_client() -> Result<SupabaseClient, SecretError> {
+    let supabase_url: Secret = secret("SUPABASE_URL")?;
+    let supabase_key: Secret = secret("SUPABASE_KEY")?;
+
+    Ok(SupabaseClient::new(
+        supabase_url.expose().to_string(),
+        supabase_key.expose().to_string(),
+    ))
+}
+
+
+/// # validate_startup
+/// Resolves and checks every secret the configured providers need, optional secrets are only
+/// checked when they are set. `STRIPE_WEBHOOK_SECRET` is always required since `/stripe_webhooks`
+/// is always mounted, and so is the webhook secret of every endpoint from the config.
+///
+/// ## Arguments
+/// - `config`: `&ConfigSetup` - The loaded config, decides which providers are used
+///
+/// ## Returns
+/// - `Vec<SecretError>` - Every problem found, empty when the secrets are usable
+pub fn validate_startup(config: &ConfigSetup) -> Vec<SecretError> {
+    let mut required: Vec<&str> = vec!["STRIPE_WEBHOOK_SECRET"];
+
+    if config.db_provider == "supabase" {
+        required.extend(["SUPABASE_URL", "SUPABASE_KEY"]);
+    }
+
+    if matches!(EmailProvider::from_str(&config.email_provider), EmailProvider::Resend) {
+        required.push("RESEND_API_KEY");
+    }
+
+    let endpoint_errors = config.endpoints.iter().flat_map(|endpoint| {
+        endpoint
+            .validate_secrets()
+            .into_iter()
+            .map(|error| SecretError::Endpoint(endpoint.endpoint_route.clone(), Box::new(error)))
+    });
+
+    SECRET_FORMATS
+        .iter()
+        .filter_map(|(name, _)| {
+            let resolved: Result<Secret, SecretError> = secret(name);
+
+            match resolved {
+                Ok(secret) => validate_format(name, &secret).err(),
+                Err(SecretError::NotSet(_)) if !required.contains(name) => None,
+                Err(error) => Some(error),
+            }
+        })
+        .chain(endpoint_errors)
+        .collect()
+}
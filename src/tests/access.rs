@@ -0,0 +1,357 @@
+//! ## Time-boxed access tests
+//!
+//! ### Table of contents
+//! - Reading `Entitlement.Products` from `stripe_discord.yaml` and matching the bought prices
+//! - Granting access on checkout and extending it with repeat purchases
+//! - Granting access on charges that were not made through Checkout
+//! - Revoking the Discord role and expiring the access once `access_end_time` has passed
+//! - Keeping passes apart from the periods of a subscription of the same customer
+//! - Revoking the role of the Organization that granted the access
+//!
+
+
+#[cfg(test)]
+mod timed_access {
+    use crate::background::scheduler::run_access_expiry;
+    use crate::discord::access::{access_duration, grant_access, grant_charge_access, AccessProduct};
+    use crate::discord::client::DiscordClient;
+    use crate::discord::roles::DiscordRoles;
+    use crate::events::test_event::TestEvent;
+    use crate::events::EventHandler;
+    use crate::organization::model::EmailEvent;
+    use crate::organization::router::organization_from_config;
+    use crate::tests::harness::Harness;
+    use crate::{ConfigSetup, CustomerId, EmailConfig, Organization};
+
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use supabase_rs::SupabaseClient;
+
+
+    /// A day in seconds
+    const DAY: i64 = 86400;
+
+    /// The price of the thirty day pass
+    const THIRTY_DAY_PASS: &str = "price_1PthirtyDayPass";
+
+
+    /// # organization
+    /// An Organization without the welcome email that sells a thirty day pass and a week of access
+    /// under the default test product.
+    fn organization() -> Organization {
+        Organization::new(
+            "Xylex".to_string(),
+            EmailConfig::new("billing@xylex.ai".to_string(), "Welcome!".to_string(), "welcome".to_string())
+        )
+            .without_email(EmailEvent::Welcome)
+            .with_access_product(AccessProduct::new(THIRTY_DAY_PASS, Duration::from_secs(30 * 86400)))
+            .with_access_product(AccessProduct::new("prod_test_default", Duration::from_secs(7 * 86400)))
+    }
+
+
+    /// # checkout
+    /// A completed checkout of an address for a price.
+    fn checkout(email: &str, price: &str) -> Value {
+        TestEvent::new("checkout.session.completed")
+            .unwrap()
+            .with_email(email.to_string())
+            .with_amount(1999)
+            .with_price(price.to_string())
+            .build()
+    }
+
+
+    /// # charge
+    /// A successful charge that creates the paid customer of an address.
+    fn charge(email: &str) -> Value {
+        TestEvent::new("charge.succeeded")
+            .unwrap()
+            .with_email(email.to_string())
+            .with_amount(1999)
+            .build()
+    }
+
+
+    /// # customer
+    /// The customer row of an address.
+    fn customer(harness: &Harness, email: &str) -> Value {
+        harness.rows("stripe_customer_data")
+            .into_iter()
+            .find(|row| row["email"] == email)
+            .expect("a customer row")
+    }
+
+
+    #[test]
+    /// # reads_access_products
+    /// Products are read by their price or product id, entries without `AccessDays` are skipped
+    /// and every matching line item grants its duration times its quantity.
+    fn reads_access_products() {
+        let value: Value = serde_yaml::from_str(r#"
+Entitlement:
+  Products:
+    - Price: price_1PthirtyDayPass
+      AccessDays: 30
+    - Product: prod_QweekendPass
+      AccessDays: 3
+    - Price: price_1PlifetimePass
+"#).unwrap();
+
+        let products: Vec<AccessProduct> = AccessProduct::from_config(&value);
+        assert_eq!(products, vec![
+            AccessProduct::new(THIRTY_DAY_PASS, Duration::from_secs(30 * 86400)),
+            AccessProduct::new("prod_QweekendPass", Duration::from_secs(3 * 86400)),
+        ]);
+
+        let config: ConfigSetup = ConfigSetup { access_products: products.clone(), ..ConfigSetup::default() };
+        assert_eq!(organization_from_config(&config).access_products, products);
+        assert!(organization_from_config(&ConfigSetup::default()).access_products.is_empty());
+
+        let line_items: Value = json!({ "data": [
+            { "price": { "id": THIRTY_DAY_PASS, "product": "prod_QthirtyDayPass" }, "quantity": 2 },
+            { "price": { "id": "price_1Pweekend", "product": { "id": "prod_QweekendPass" } } },
+            { "price": { "id": "price_1PlifetimePass", "product": "prod_Qlifetime" }, "quantity": 1 },
+        ] });
+        assert_eq!(access_duration(&line_items, &products), Some(Duration::from_secs(63 * 86400)));
+
+        let lifetime: Value = json!({ "data": [{ "price": { "id": "price_1PlifetimePass" }, "quantity": 1 }] });
+        assert_eq!(access_duration(&lifetime, &products), None);
+    }
+
+
+    #[tokio::test]
+    /// # grants_and_stacks_access
+    /// A checkout of a time-boxed product sets `access_start_time` and `access_end_time`, a repeat
+    /// purchase while the access is active extends `access_end_time` and line items that are not
+    /// expanded are retrieved
+    /// from Stripe. Buying before the charge arrived creates the customer, other products grant no
+    /// access.
+    async fn grants_and_stacks_access() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let email: &str = "jenny.rosen@example.com";
+
+        assert!(matches!(EventHandler::new(&charge(email), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+
+        let purchase: Value = checkout(email, THIRTY_DAY_PASS);
+        let purchased_at: i64 = purchase["created"].as_i64().unwrap();
+        assert!(matches!(EventHandler::new(&purchase, organization(), supabase.clone()).await, Ok(EventHandler::CheckoutSessionCompleted)));
+
+        let granted: Value = customer(&harness, email);
+        assert_eq!(granted["access_start_time"], purchased_at);
+        assert_eq!(granted["access_end_time"], purchased_at + 30 * DAY);
+        assert!(granted.get("end_time").is_none());
+        assert_eq!(granted["access_status"], "active");
+        assert_eq!(granted["paid"], true);
+
+        // ten days in, another pass adds thirty days to the end of the first
+        let repeat: Value = checkout(email, THIRTY_DAY_PASS);
+        let extended: Option<Result<i64, String>> = grant_access(&repeat["data"]["object"], email, purchased_at + 10 * DAY, &organization(), &supabase).await;
+        assert_eq!(extended, Some(Ok(purchased_at + 60 * DAY)));
+
+        // the session of the default test product carries no line items, Stripe has them
+        let mut week: Value = checkout(email, "price_test_default");
+        week["data"]["object"].as_object_mut().unwrap().remove("line_items");
+        let extended: Option<Result<i64, String>> = grant_access(&week["data"]["object"], email, purchased_at + 11 * DAY, &organization(), &supabase).await;
+        assert_eq!(extended, Some(Ok(purchased_at + 67 * DAY)));
+        assert!(harness.fakes.state.lock().unwrap().stripe_requests.iter().any(|request| request.ends_with("/line_items")));
+
+        let stacked: Value = customer(&harness, email);
+        assert_eq!(stacked["access_start_time"], purchased_at);
+        assert_eq!(stacked["access_end_time"], purchased_at + 67 * DAY);
+
+        let passes_only: Organization = Organization {
+            access_products: vec![AccessProduct::new(THIRTY_DAY_PASS, Duration::from_secs(30 * 86400))],
+            ..organization()
+        };
+        let lifetime: Value = checkout(email, "price_1PlifetimePass");
+        assert_eq!(grant_access(&lifetime["data"]["object"], email, purchased_at, &passes_only, &supabase).await, None);
+
+        let early: Value = checkout("amara@example.com", THIRTY_DAY_PASS);
+        let granted: Option<Result<i64, String>> = grant_access(&early["data"]["object"], "amara@example.com", purchased_at, &organization(), &supabase).await;
+        assert_eq!(granted, Some(Ok(purchased_at + 30 * DAY)));
+        assert_eq!(customer(&harness, "amara@example.com")["access_status"], "active");
+    }
+
+
+    #[tokio::test]
+    /// # grants_access_from_charges
+    /// A charge outside of Checkout grants the access of the price in its `metadata` or of the
+    /// lines of its invoice, stacked like checkouts. Charges of a checkout session and charges
+    /// that paid for nothing known grant no access.
+    async fn grants_access_from_charges() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let email: &str = "jenny.rosen@example.com";
+
+        let mut pass: Value = charge(email);
+        pass["data"]["object"]["metadata"] = json!({ "price": THIRTY_DAY_PASS });
+        let charged_at: i64 = pass["created"].as_i64().unwrap();
+        assert!(matches!(EventHandler::new(&pass, organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+
+        let granted: Value = customer(&harness, email);
+        assert_eq!(granted["access_start_time"], charged_at);
+        assert_eq!(granted["access_end_time"], charged_at + 30 * DAY);
+        assert_eq!(granted["access_status"], "active");
+        assert_eq!(granted["paid"], true);
+
+        // the invoice bought the default test product, a week on top of the pass
+        let mut invoiced: Value = charge(email);
+        invoiced["data"]["object"]["invoice"] = json!("in_test_oneOffInvoice");
+        assert_eq!(
+            grant_charge_access(&invoiced["data"]["object"], email, charged_at, &organization(), &supabase).await,
+            Some(Ok(charged_at + 37 * DAY))
+        );
+        assert!(harness.fakes.state.lock().unwrap().stripe_requests.contains(&"GET /v1/invoices/in_test_oneOffInvoice/lines".to_string()));
+
+        // checkout.session.completed grants the access of a checkout
+        let mut checkout_charge: Value = pass.clone();
+        checkout_charge["data"]["object"]["payment_intent"] = json!("pi_3PfixturePaymentIntent01");
+        assert_eq!(grant_charge_access(&checkout_charge["data"]["object"], email, charged_at, &organization(), &supabase).await, None);
+
+        let unknown: Value = charge("amara@example.com");
+        assert!(matches!(EventHandler::new(&unknown, organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+        assert!(customer(&harness, "amara@example.com").get("access_status").is_none());
+        assert_eq!(customer(&harness, email)["access_end_time"], charged_at + 37 * DAY);
+    }
+
+
+    #[tokio::test]
+    /// # expires_ended_access
+    /// Once `access_end_time` has passed the role is revoked, the customer is unpaid and the access is
+    /// `expired`, exactly once. Access bought again after it expired starts over, subscribers
+    /// without time-boxed access keep their role.
+    async fn expires_ended_access() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let email: &str = "jenny.rosen@example.com";
+
+        assert!(matches!(EventHandler::new(&charge(email), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+        assert!(matches!(EventHandler::new(&charge("kenji@example.com"), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+
+        let purchase: Value = checkout(email, THIRTY_DAY_PASS);
+        let purchased_at: i64 = purchase["created"].as_i64().unwrap();
+        let end_time: i64 = purchased_at + 30 * DAY;
+        assert_eq!(grant_access(&purchase["data"]["object"], email, purchased_at, &organization(), &supabase).await, Some(Ok(end_time)));
+
+        // the period of the subscription of kenji ends along with the pass, it renews instead
+        let subscription: Value = TestEvent::new("customer.subscription.updated")
+            .unwrap()
+            .with_email("kenji@example.com".to_string())
+            .build();
+        assert!(matches!(EventHandler::new(&subscription, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionUpdated)));
+        harness.patch_rows("stripe_customer_data", json!({ "discord_user_id": "80351110224678912" }));
+
+        let without_products: Organization = Organization { access_products: Vec::new(), ..organization() };
+        assert_eq!(run_access_expiry(&[without_products], end_time + DAY).await, Ok(0));
+        assert_eq!(run_access_expiry(&[organization()], end_time - 1).await, Ok(0));
+        assert!(harness.discord_requests().is_empty());
+
+        assert_eq!(run_access_expiry(&[organization()], end_time).await, Ok(1));
+        assert_eq!(run_access_expiry(&[organization()], end_time + DAY).await, Ok(0));
+
+        assert_eq!(
+            harness.discord_requests(),
+            vec!["DELETE /guilds/81384788765712384/members/80351110224678912/roles/41771983423143936"]
+        );
+
+        let expired: Value = customer(&harness, email);
+        assert_eq!(expired["paid"], false);
+        assert_eq!(expired["access_status"], "expired");
+        assert_eq!(customer(&harness, "kenji@example.com")["paid"], true);
+
+        let renewed: Option<Result<i64, String>> = grant_access(&purchase["data"]["object"], email, end_time + DAY, &organization(), &supabase).await;
+        assert_eq!(renewed, Some(Ok(end_time + 31 * DAY)));
+        assert_eq!(customer(&harness, email)["access_start_time"], end_time + DAY);
+        assert_eq!(customer(&harness, email)["paid"], true);
+    }
+
+
+    #[tokio::test]
+    /// # keeps_passes_apart_from_subscriptions
+    /// A customer with a subscription and a pass keeps the `end_time` of the period and the
+    /// `access_end_time` of the pass apart, only the period is scanned for reminders and the pass
+    /// ending before the period neither revokes the role nor unpays the customer.
+    async fn keeps_passes_apart_from_subscriptions() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let email: &str = "jenny.rosen@example.com";
+
+        assert!(matches!(EventHandler::new(&charge(email), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+
+        let subscription: Value = TestEvent::new("customer.subscription.updated")
+            .unwrap()
+            .with_email(email.to_string())
+            .build();
+        let period_end: i64 = subscription["data"]["object"]["current_period_end"].as_i64().unwrap();
+        assert!(matches!(EventHandler::new(&subscription, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionUpdated)));
+
+        // the pass was bought twenty days before the period ends and ends ten days before it
+        let purchase: Value = checkout(email, THIRTY_DAY_PASS);
+        let access_end: i64 = period_end - 10 * DAY;
+        assert_eq!(grant_access(&purchase["data"]["object"], email, access_end - 30 * DAY, &organization(), &supabase).await, Some(Ok(access_end)));
+
+        // another pass only customer is never scanned for a renewal reminder
+        assert_eq!(grant_access(&purchase["data"]["object"], "amara@example.com", access_end - 30 * DAY, &organization(), &supabase).await, Some(Ok(access_end)));
+
+        // the subscription renewing does not touch the pass
+        assert!(matches!(EventHandler::new(&subscription, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionUpdated)));
+
+        let both: Value = customer(&harness, email);
+        assert_eq!(both["end_time"], period_end);
+        assert_eq!(both["access_end_time"], access_end);
+
+        let ending: Vec<Value> = CustomerId::list_ending_between(access_end - DAY, period_end, supabase.clone()).await.unwrap();
+        assert_eq!(ending.iter().map(|record| record["email"].clone()).collect::<Vec<Value>>(), vec![json!(email)]);
+
+        harness.patch_rows("stripe_customer_data", json!({ "discord_user_id": "80351110224678912" }));
+        assert_eq!(run_access_expiry(&[organization()], access_end).await, Ok(2));
+
+        assert_eq!(
+            harness.discord_requests(),
+            vec!["DELETE /guilds/81384788765712384/members/80351110224678912/roles/41771983423143936"]
+        );
+
+        let subscribed: Value = customer(&harness, email);
+        assert_eq!(subscribed["access_status"], "expired");
+        assert_eq!(subscribed["paid"], true);
+        assert_eq!(subscribed["end_time"], period_end);
+        assert_eq!(customer(&harness, "amara@example.com")["paid"], false);
+    }
+
+
+    #[tokio::test]
+    /// # revokes_role_of_granting_organization
+    /// Access granted through an endpoint is revoked in the guild and role of that endpoint,
+    /// access of an Organization that is no longer configured with the environment.
+    async fn revokes_role_of_granting_organization() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        let acme: Organization = Organization { name: "Acme".to_string(), ..organization() }
+            .with_discord_roles(DiscordRoles {
+                client: DiscordClient::new("acme_bot_token".to_string()),
+                guild_id: "175928847299117063".to_string(),
+                role_id: "175928847299117064".to_string(),
+            });
+
+        let purchase: Value = checkout("jenny.rosen@example.com", THIRTY_DAY_PASS);
+        let purchased_at: i64 = purchase["created"].as_i64().unwrap();
+        let end_time: i64 = purchased_at + 30 * DAY;
+        assert_eq!(grant_access(&purchase["data"]["object"], "jenny.rosen@example.com", purchased_at, &acme, &supabase).await, Some(Ok(end_time)));
+        assert_eq!(customer(&harness, "jenny.rosen@example.com")["access_organization"], "Acme");
+
+        let gone: Organization = Organization { name: "Gone".to_string(), ..organization() };
+        assert_eq!(grant_access(&purchase["data"]["object"], "amara@example.com", purchased_at, &gone, &supabase).await, Some(Ok(end_time)));
+
+        harness.patch_rows("stripe_customer_data", json!({ "discord_user_id": "80351110224678912" }));
+        assert_eq!(run_access_expiry(&[organization(), acme], end_time).await, Ok(2));
+
+        let mut requests: Vec<String> = harness.discord_requests();
+        requests.sort();
+        assert_eq!(requests, vec![
+            "DELETE /guilds/175928847299117063/members/80351110224678912/roles/175928847299117064",
+            "DELETE /guilds/81384788765712384/members/80351110224678912/roles/41771983423143936",
+        ]);
+    }
+}
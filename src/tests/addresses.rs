@@ -0,0 +1,157 @@
+//! ## Email address tests
+//!
+//! ### Table of contents
+//! - Trimming addresses, lowercasing and IDNA converting their domain and rejecting invalid ones
+//! - Looking up mail servers with a mocked resolver and the DNS over HTTPS resolver
+//! - Quarantining or rejecting invalid customer addresses before anything is stored or emailed
+//!
+
+
+#[cfg(test)]
+mod email_addresses {
+    use crate::email::address::{CheckedEmail, DnsOverHttps, EmailAddressError, EmailValidation, MxResolver};
+    use crate::email::EmailAddress;
+    use crate::events::fixtures::fixture;
+    use crate::events::EventHandler;
+    use crate::organization::model::InvalidEmailPolicy;
+    use crate::tests::harness::Harness;
+    use crate::{EmailConfig, Organization};
+
+    use serde_json::{json, Value};
+    use supabase_rs::SupabaseClient;
+
+
+    /// # FakeResolver
+    /// Accepts mail for `example.com` only and fails every lookup of `down.example`.
+    struct FakeResolver;
+
+    impl MxResolver for FakeResolver {
+        async fn accepts_mail(&self, domain: &str) -> Result<bool, String> {
+            match domain {
+                "example.com" => Ok(true),
+                "down.example" => Err("the resolver is down".to_string()),
+                _ => Ok(false),
+            }
+        }
+    }
+
+
+    /// # email
+    /// The normalized address of a valid one.
+    fn email(raw: &str) -> String {
+        EmailAddress::parse(raw).expect("a valid email address").email
+    }
+
+
+    #[test]
+    /// # normalizes_addresses
+    /// Addresses are trimmed, their domain lowercased and converted to ASCII, the local part
+    /// keeps its case.
+    fn normalizes_addresses() {
+        assert_eq!(email("  Jane.Doe@Example.COM "), "Jane.Doe@example.com");
+        assert_eq!(email("<jane+stripe@example.com>"), "jane+stripe@example.com");
+        assert_eq!(email("jane@example.com."), "jane@example.com");
+        assert_eq!(email("jane@Bücher.de"), "jane@xn--bcher-kva.de");
+        assert_eq!(email("o'brien@mail.example.co.uk"), "o'brien@mail.example.co.uk");
+
+        assert_eq!(EmailAddress::parse("jane@Bücher.de").unwrap().domain(), "xn--bcher-kva.de");
+        assert!(EmailAddress { email: "jane@example.com".to_string() }.verify_email());
+        assert!(!EmailAddress { email: "jane@example".to_string() }.verify_email());
+    }
+
+
+    #[test]
+    /// # rejects_invalid_addresses
+    /// Missing, malformed and oversized addresses are refused with the reason why.
+    fn rejects_invalid_addresses() {
+        let error = |raw: &str| -> EmailAddressError { EmailAddress::parse(raw).unwrap_err() };
+
+        assert_eq!(error(""), EmailAddressError::Missing);
+        assert_eq!(error("unknown"), EmailAddressError::Missing);
+        assert_eq!(error("jane.example.com"), EmailAddressError::MissingAt);
+        assert_eq!(error(".jane@example.com"), EmailAddressError::LocalPart);
+        assert_eq!(error("jane..doe@example.com"), EmailAddressError::LocalPart);
+        assert_eq!(error("jane doe@example.com"), EmailAddressError::LocalPart);
+        assert_eq!(error(&format!("{}@example.com", "j".repeat(65))), EmailAddressError::LocalPart);
+        assert_eq!(error("jane@localhost"), EmailAddressError::Domain("localhost".to_string()));
+        assert_eq!(error("jane@-example.com"), EmailAddressError::Domain("-example.com".to_string()));
+        assert_eq!(error("jane@example..com"), EmailAddressError::Domain("example..com".to_string()));
+        assert_eq!(error("jane@192.168.0.1"), EmailAddressError::Domain("192.168.0.1".to_string()));
+        assert_eq!(error("jane@exa_mple.com"), EmailAddressError::Domain("exa_mple.com".to_string()));
+
+        let long_domain: String = format!("{}.com", vec!["a".repeat(60); 5].join("."));
+        assert_eq!(error(&format!("jane@{}", long_domain)), EmailAddressError::TooLong);
+    }
+
+
+    #[tokio::test]
+    /// # checks_mail_servers
+    /// With `check_mx` on, domains without mail servers are invalid and a failed lookup lets
+    /// the address through. The DNS over HTTPS resolver falls back on A records and honours
+    /// null MX records.
+    async fn checks_mail_servers() {
+        let validation: EmailValidation = EmailValidation { check_mx: true, invalid: InvalidEmailPolicy::Reject };
+
+        assert_eq!(validation.check_with("Jane@Example.com", &FakeResolver).await, CheckedEmail::Valid("Jane@example.com".to_string()));
+        assert_eq!(validation.check_with("jane@down.example", &FakeResolver).await, CheckedEmail::Valid("jane@down.example".to_string()));
+        assert_eq!(
+            validation.check_with("jane@nowhere.example", &FakeResolver).await,
+            CheckedEmail::Invalid(EmailAddressError::NoMailServer("nowhere.example".to_string()))
+        );
+        assert_eq!(validation.check_with("", &FakeResolver).await, CheckedEmail::Invalid(EmailAddressError::Missing));
+
+        let without_mx: EmailValidation = EmailValidation::default();
+        assert_eq!(without_mx.check_with("jane@nowhere.example", &FakeResolver).await, CheckedEmail::Valid("jane@nowhere.example".to_string()));
+
+        let harness: Harness = Harness::start().await;
+        let resolver: DnsOverHttps = DnsOverHttps { url: format!("{}/dns-query", harness.fakes.base_url) };
+
+        assert_eq!(resolver.accepts_mail("example.com").await, Ok(true));
+        assert_eq!(resolver.accepts_mail("a-only.example").await, Ok(true));
+        assert_eq!(resolver.accepts_mail("no-mail.example").await, Ok(false));
+        assert_eq!(resolver.accepts_mail("missing.example").await, Ok(false));
+
+        let unreachable: DnsOverHttps = DnsOverHttps { url: format!("{}/not-a-resolver", harness.fakes.base_url) };
+        assert!(unreachable.accepts_mail("example.com").await.is_err());
+    }
+
+
+    #[tokio::test]
+    /// # quarantines_invalid_emails
+    /// A charge with an invalid address stores no customer and sends no receipt, the address is
+    /// quarantined, or only dropped with `reject`. A valid address is stored normalized.
+    async fn quarantines_invalid_emails() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        let organization = |invalid: InvalidEmailPolicy| -> Organization {
+            Organization::new(
+                "Xylex".to_string(),
+                EmailConfig::new("billing@xylex.ai".to_string(), "Welcome!".to_string(), "welcome".to_string()),
+            )
+            .with_email_validation(EmailValidation { check_mx: false, invalid })
+        };
+
+        let mut charge: Value = fixture("charge.succeeded").expect("a charge fixture");
+        charge["data"]["object"]["billing_details"]["email"] = json!("jenny.rosen@example");
+        EventHandler::new(&charge, organization(InvalidEmailPolicy::Quarantine), supabase.clone()).await.expect("handled");
+        EventHandler::new(&charge, organization(InvalidEmailPolicy::Reject), supabase.clone()).await.expect("handled");
+
+        assert!(harness.rows("stripe_customer_data").is_empty());
+        assert!(harness.emails().is_empty());
+
+        let quarantined: Vec<Value> = harness.rows("stripe_email_quarantine");
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0]["email"], "jenny.rosen@example");
+        assert_eq!(quarantined[0]["reason"], "example is not a valid domain");
+        assert_eq!(quarantined[0]["event_type"], "charge.succeeded");
+        assert_eq!(quarantined[0]["event_id"], charge["id"]);
+
+        charge["data"]["object"]["billing_details"]["email"] = json!(" Jenny.Rosen@EXAMPLE.com ");
+        EventHandler::new(&charge, organization(InvalidEmailPolicy::Quarantine), supabase.clone()).await.expect("handled");
+
+        let rows: Vec<Value> = harness.rows("stripe_customer_data");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["email"], "Jenny.Rosen@example.com");
+    }
+}
@@ -0,0 +1,197 @@
+//! ## Admin API tests
+//!
+//! The admin routes run against the in-memory fakes of the [harness](../harness/index.html), the
+//! customer is created by replaying the `charge.succeeded` fixture.
+//!
+//! ### Table of contents
+//! - Rejecting requests without a key, with a revoked key or without the scope of the route
+//! - Searching and viewing a customer with its event history
+//! - Toggling paid, resending the welcome email and syncing the Discord role
+//! - Replaying a stored webhook event
+//!
+
+
+#[cfg(test)]
+mod customers {
+    use crate::auth::{key_id_of, Scope, ALL_SCOPES};
+    use crate::db::operations::api_key::ApiKey;
+    use crate::events::signature::unix_now;
+    use crate::events::test_event::TestEvent;
+    use crate::tests::harness::Harness;
+
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::LocalResponse;
+    use serde_json::{json, Value};
+    use supabase_rs::SupabaseClient;
+
+
+    /// The charge id of the fixture customer
+    const CUSTOMER_ID: &str = "ch_3PfixtureCharge01";
+
+
+    /// # admin_request
+    /// Sends an admin request with an API key with every scope and returns the status and JSON body.
+    async fn admin_request(harness: &Harness, method: &str, path: &str, body: Option<Value>) -> (Status, Value) {
+        let key: String = harness.issue_api_key(ALL_SCOPES.to_vec()).await;
+
+        keyed_request(harness, &key, method, path, body).await
+    }
+
+
+    /// # keyed_request
+    /// Sends an admin request with the given API key and returns the status and JSON body.
+    async fn keyed_request(harness: &Harness, key: &str, method: &str, path: &str, body: Option<Value>) -> (Status, Value) {
+        let authorization: Header<'static> = Header::new("Authorization", format!("Bearer {}", key));
+
+        let mut request = match method {
+            "GET" => harness.client.get(path.to_string()),
+            "PUT" => harness.client.put(path.to_string()),
+            _ => harness.client.post(path.to_string()),
+        }
+        .header(authorization);
+
+        if let Some(body) = body {
+            request = request.header(ContentType::JSON).body(body.to_string());
+        }
+
+        let response: LocalResponse = request.dispatch().await;
+        let status: Status = response.status();
+        let body: Value = response.into_json().await.unwrap_or(Value::Null);
+
+        (status, body)
+    }
+
+
+    #[tokio::test]
+    /// # rejects_unauthorized_keys
+    /// Missing, unknown and revoked keys get `401`, keys without the scope of the route get `403`.
+    async fn rejects_unauthorized_keys() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+
+        let path: String = format!("/admin/customers/{}", CUSTOMER_ID);
+
+        let missing: Status = harness.client.get(path.clone()).dispatch().await.status();
+        assert_eq!(missing, Status::Unauthorized);
+
+        let (unknown, _): (Status, Value) = keyed_request(&harness, "sdk_000000000000_not_a_key", "GET", &path, None).await;
+        assert_eq!(unknown, Status::Unauthorized);
+
+        let reader: String = harness.issue_api_key(vec![Scope::ReadCustomers]).await;
+
+        let (read, _): (Status, Value) = keyed_request(&harness, &reader, "GET", &path, None).await;
+        assert_eq!(read, Status::Ok);
+
+        let (write, _): (Status, Value) = keyed_request(&harness, &reader, "PUT", &format!("{}/paid", path), Some(json!({ "paid": false }))).await;
+        assert_eq!(write, Status::Forbidden);
+
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let revoked: Option<ApiKey> = ApiKey::revoke(key_id_of(&reader).unwrap(), unix_now(), supabase)
+            .await
+            .map_err(|error| error.to_string())
+            .unwrap();
+        assert!(revoked.unwrap().revoked_at.is_some());
+
+        let (after_revoke, _): (Status, Value) = keyed_request(&harness, &reader, "GET", &path, None).await;
+        assert_eq!(after_revoke, Status::Unauthorized);
+    }
+
+
+    #[tokio::test]
+    /// # searches_and_shows_customer
+    /// A customer is found by email and shown with its audit trail and webhook events.
+    async fn searches_and_shows_customer() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+        assert_eq!(harness.send_fixture("charge.refunded").await, Status::Ok);
+
+        // an event about someone else is never shown
+        let other: Value = TestEvent::new("customer.subscription.updated")
+            .unwrap()
+            .with_email("kenji@example.com".to_string())
+            .build();
+        assert_eq!(harness.send_event(&other).await, Status::Ok);
+
+        let (status, found): (Status, Value) = admin_request(&harness, "GET", "/admin/customers?email=jenny.rosen@example.com", None).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(found["customers"][0]["customer_id"], CUSTOMER_ID);
+
+        let (status, _): (Status, Value) = admin_request(&harness, "GET", "/admin/customers", None).await;
+        assert_eq!(status, Status::BadRequest);
+
+        let (status, shown): (Status, Value) = admin_request(&harness, "GET", &format!("/admin/customers/{}", CUSTOMER_ID), None).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(shown["customer"]["receipt_url"], "https://pay.stripe.com/receipts/payment/fixture_receipt_01");
+        assert_eq!(shown["audit"][0]["event_type"], "charge.refunded");
+
+        let event_types: Vec<&str> = shown["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|event| event["event_type"].as_str())
+            .collect();
+        assert!(event_types.contains(&"charge.succeeded"));
+        assert!(event_types.contains(&"charge.refunded"));
+        assert!(!event_types.contains(&"customer.subscription.updated"));
+
+        let (status, _): (Status, Value) = admin_request(&harness, "GET", "/admin/customers/ch_unknown", None).await;
+        assert_eq!(status, Status::NotFound);
+    }
+
+
+    #[tokio::test]
+    /// # edits_customer
+    /// Paid can be toggled, the welcome email resent and the Discord role synced once linked.
+    async fn edits_customer() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+
+        let paid_path: String = format!("/admin/customers/{}/paid", CUSTOMER_ID);
+        let (status, updated): (Status, Value) = admin_request(&harness, "PUT", &paid_path, Some(json!({ "paid": false }))).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(updated["customer"]["paid"], false);
+
+        let welcome_path: String = format!("/admin/customers/{}/welcome-email", CUSTOMER_ID);
+        let (status, sent): (Status, Value) = admin_request(&harness, "POST", &welcome_path, None).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(sent["message_id"], "email_1");
+        assert_eq!(harness.emails()[0]["subject"], "Welcome!");
+        assert_eq!(harness.rows("stripe_customer_data")[0]["email_sent"], true);
+
+        // the customer has not linked a Discord account yet
+        let sync_path: String = format!("/admin/customers/{}/discord-sync", CUSTOMER_ID);
+        let (status, _): (Status, Value) = admin_request(&harness, "POST", &sync_path, None).await;
+        assert_eq!(status, Status::UnprocessableEntity);
+
+        harness.patch_rows("stripe_customer_data", json!({ "discord_user_id": "80351110224678912" }));
+
+        let (status, synced): (Status, Value) = admin_request(&harness, "POST", &sync_path, None).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(synced["role"], "revoked");
+        assert_eq!(
+            harness.discord_requests(),
+            vec!["DELETE /guilds/81384788765712384/members/80351110224678912/roles/41771983423143936"]
+        );
+    }
+
+
+    #[tokio::test]
+    /// # replays_event
+    /// A stored event is processed again and its attempts go up.
+    async fn replays_event() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+
+        let (status, replayed): (Status, Value) = admin_request(&harness, "POST", "/admin/events/evt_3PfixtureChargeSucceeded/replay", None).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(replayed["outcome"], "handled");
+        assert_eq!(replayed["attempts"], 2);
+
+        let (status, _): (Status, Value) = admin_request(&harness, "POST", "/admin/events/evt_unknown/replay", None).await;
+        assert_eq!(status, Status::NotFound);
+    }
+}
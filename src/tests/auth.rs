@@ -0,0 +1,85 @@
+//! ## API key tests
+//!
+//! ### Table of contents
+//! - Generating, hashing and parsing keys
+//! - Reading keys from the config
+//! - Parsing the `keys` commands
+//!
+
+
+#[cfg(test)]
+mod api_keys {
+    use crate::auth::{authenticate, generate_api_key, hash_api_key, key_id_of, AuthenticatedKey, ConfiguredApiKey, Scope};
+    use crate::cli::Command;
+
+    use serde_json::{json, Value};
+
+
+    #[test]
+    /// # generates_keys
+    /// Keys carry their key id and only their hash is stored.
+    fn generates_keys() {
+        let (key_id, key): (String, String) = generate_api_key();
+        let (_, other_key): (String, String) = generate_api_key();
+
+        assert!(key.starts_with("sdk_"));
+        assert_eq!(key_id_of(&key), Some(key_id.as_str()));
+        assert_ne!(key, other_key);
+
+        assert_eq!(hash_api_key(&key).len(), 64);
+        assert_ne!(hash_api_key(&key), hash_api_key(&other_key));
+        assert_eq!(key_id_of("Bearer something"), None);
+    }
+
+
+    #[tokio::test]
+    /// # authenticates_configured_keys
+    /// Keys from the config are matched by hash, unknown scope names are dropped.
+    async fn authenticates_configured_keys() {
+        let (_, key): (String, String) = generate_api_key();
+
+        let config: Value = json!({
+            "Auth": {
+                "ApiKeys": [
+                    { "Name": "support", "Hash": hash_api_key(&key), "Scopes": ["customers:read", "customers:delete"] },
+                    { "Name": "no hash" }
+                ]
+            }
+        });
+
+        let configured: Vec<ConfiguredApiKey> = ConfiguredApiKey::from_config(&config);
+        assert_eq!(configured.len(), 1);
+        assert_eq!(configured[0].scopes, vec![Scope::ReadCustomers]);
+
+        // configured keys are found without a database
+        let authenticated: Option<AuthenticatedKey> = authenticate(&key, &configured, None).await.unwrap();
+        assert_eq!(authenticated.map(|key| key.name), Some("support".to_string()));
+
+        let (_, unknown_key): (String, String) = generate_api_key();
+        assert!(authenticate(&unknown_key, &configured, None).await.unwrap().is_none());
+    }
+
+
+    #[test]
+    /// # parses_key_commands
+    /// The `keys` subcommands need a known scope to issue a key.
+    fn parses_key_commands() {
+        let args = |line: &str| -> Vec<String> { line.split_whitespace().map(|arg| arg.to_string()).collect() };
+
+        assert_eq!(
+            Command::parse(&args("keys issue support --scope customers:read --scope events:replay --config")),
+            Ok(Command::KeysIssue {
+                name: "support".to_string(),
+                scopes: vec![Scope::ReadCustomers, Scope::ReplayEvents],
+                config: true,
+            })
+        );
+        assert_eq!(Command::parse(&args("keys list")), Ok(Command::KeysList));
+        assert_eq!(
+            Command::parse(&args("keys revoke 3f9a1c0b7d2e")),
+            Ok(Command::KeysRevoke { key_id: "3f9a1c0b7d2e".to_string() })
+        );
+        assert!(Command::parse(&args("keys issue support")).is_err());
+        assert!(Command::parse(&args("keys issue support --scope customers:delete")).is_err());
+    }
+}
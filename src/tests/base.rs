@@ -8,18 +8,13 @@
 //!
 
 
-use std::env;
 // use crate::Config;
 
 
 #[cfg(test)]
 mod environment {
-    // this will take all our external `lib` exports and take them into this `mod environment`
-    // context
-    //
-    //
-    use super::*;
     use dotenv::dotenv;
+    use std::env;
     // use crate::Config; 
     
    
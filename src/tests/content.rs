@@ -0,0 +1,84 @@
+//! ## Email content tests
+//!
+//! ### Table of contents
+//! - Generating the plain-text alternative from HTML
+//! - Adding the preheader
+//! - Rendering text templates
+//!
+
+
+#[cfg(test)]
+mod email_content {
+    use crate::email::content::{html_to_text, EmailContent};
+    use crate::EmailConfig;
+
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+
+    #[test]
+    /// # generates_plain_text
+    /// Paragraphs, line breaks, lists, links and entities survive, hidden elements do not.
+    fn generates_plain_text() {
+        let html: &str = "<html><head><title>Receipt</title><style>p { color: red; }</style></head><body>\
+            <h1>Thanks,   Jenny!</h1><p>Your order:<br>1 &times; Pro &amp; Support</p>\
+            <ul><li>Discord role</li><li class=\"item\">Priority support</li></ul>\
+            <!-- tracking --><p><a href=\"https://xylex.ai/login\">Log <b>in</b></a> or visit <a href=\"https://xylex.ai\">https://xylex.ai</a></p>\
+            </body></html>";
+
+        assert_eq!(
+            html_to_text(html),
+            "Thanks, Jenny!\n\nYour order:\n1 &times; Pro & Support\n\n- Discord role\n- Priority support\n\nLog in (https://xylex.ai/login) or visit https://xylex.ai"
+        );
+    }
+
+
+    #[test]
+    /// # adds_preheader
+    /// The preheader is hidden at the top of the body and kept out of the plain-text alternative.
+    fn adds_preheader() {
+        let hidden: &str = "<div style=\"display:none;max-height:0;overflow:hidden;opacity:0\">Your receipt &amp; invoice</div>";
+
+        let fragment: EmailContent = EmailContent::from_html("<p>Hi</p>".to_string()).with_preheader("Your receipt & invoice");
+        assert_eq!(fragment.html, format!("{}<p>Hi</p>", hidden));
+        assert_eq!(fragment.text, "Hi");
+
+        let document: EmailContent = EmailContent::from_html("<html><body class=\"main\"><p>Hi</p></body></html>".to_string())
+            .with_preheader("Your receipt & invoice");
+        assert_eq!(document.html, format!("<html><body class=\"main\">{}<p>Hi</p></body></html>", hidden));
+    }
+
+
+    #[tokio::test]
+    /// # renders_text_template
+    /// An explicit text template and the preheader are populated like the HTML template.
+    async fn renders_text_template() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("stripe-discord-content-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create the templates directory");
+        std::fs::write(dir.join("receipt.html"), "<p>Thanks {{FirstName}}</p>").expect("write the html template");
+        std::fs::write(dir.join("receipt.txt"), "Thanks {{FirstName}}, see you on Discord").expect("write the text template");
+
+        let mut placeholders: HashMap<String, String> = HashMap::new();
+        placeholders.insert("FirstName".to_string(), "Jenny".to_string());
+
+        let email_config: EmailConfig = EmailConfig::new(
+            "billing@xylex.ai".to_string(),
+            "Your receipt".to_string(),
+            format!("file://{}", dir.join("receipt.html").display()),
+        );
+
+        let generated: EmailContent = email_config.render(&placeholders).await.expect("rendered");
+        assert_eq!(generated, EmailContent { html: "<p>Thanks Jenny</p>".to_string(), text: "Thanks Jenny".to_string(), attachments: Vec::new() });
+
+        let rendered: EmailContent = email_config
+            .with_text_template(format!("file://{}", dir.join("receipt.txt").display()))
+            .with_preheader("Receipt for {{FirstName}}".to_string())
+            .render(&placeholders)
+            .await
+            .expect("rendered");
+        assert_eq!(rendered.text, "Thanks Jenny, see you on Discord");
+        assert!(rendered.html.contains(">Receipt for Jenny</div><p>Thanks Jenny</p>"));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,137 @@
+//! ## Email delivery tracking tests
+//!
+//! ### Table of contents
+//! - Reading delivery statuses from Resend events and SES notifications
+//! - Logging every sent email with its message id and customer
+//! - Moving logged emails along from the delivery webhooks, never back
+//!
+
+
+#[cfg(test)]
+mod email_deliveries {
+    use crate::auth::Scope;
+    use crate::db::operations::email_log::EmailStatus;
+    use crate::email::delivery::{resend_delivery, ses_delivery, DeliveryUpdate};
+    use crate::email::suppression::sign_resend_payload;
+    use crate::events::signature::unix_now;
+    use crate::tests::harness::Harness;
+
+    use rocket::http::{ContentType, Header, Status};
+    use serde_json::{json, Value};
+    use std::env;
+
+
+    /// The Resend webhook secret the tests sign with
+    const RESEND_SECRET: &str = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+
+
+    /// # update
+    /// The update of a status for a message id.
+    fn update(message_id: &str, status: EmailStatus) -> Option<DeliveryUpdate> {
+        Some(DeliveryUpdate { message_id: message_id.to_string(), status })
+    }
+
+
+    /// # post_resend_event
+    /// Posts a signed Resend event of a type for a message id.
+    async fn post_resend_event(harness: &Harness, event_type: &str, message_id: &str) -> Status {
+        let body: String = json!({
+            "type": event_type,
+            "created_at": "2024-04-24T23:06:40.000Z",
+            "data": { "email_id": message_id, "to": ["jenny.rosen@example.com"] },
+        }).to_string();
+
+        // signed and sent with the same timestamp, so a second passing in between does not matter
+        let timestamp: i64 = unix_now();
+        let signature: String = format!("v1,{}", sign_resend_payload(&body, "msg_1", timestamp, RESEND_SECRET));
+
+        harness.client
+            .post("/email_webhooks/resend")
+            .header(ContentType::JSON)
+            .header(Header::new("svix-id", "msg_1"))
+            .header(Header::new("svix-timestamp", timestamp.to_string()))
+            .header(Header::new("svix-signature", signature))
+            .body(body)
+            .dispatch()
+            .await
+            .status()
+    }
+
+
+    #[test]
+    /// # reads_delivery_statuses
+    /// Resend events and SES notifications map to a status of their message id, events without a
+    /// status or message id to nothing.
+    fn reads_delivery_statuses() {
+        let resend = |event_type: &str| resend_delivery(&json!({ "type": event_type, "data": { "email_id": "email_1" } }));
+
+        assert_eq!(resend("email.sent"), update("email_1", EmailStatus::Sent));
+        assert_eq!(resend("email.delivered"), update("email_1", EmailStatus::Delivered));
+        assert_eq!(resend("email.opened"), update("email_1", EmailStatus::Opened));
+        assert_eq!(resend("email.clicked"), update("email_1", EmailStatus::Opened));
+        assert_eq!(resend("email.bounced"), update("email_1", EmailStatus::Bounced));
+        assert_eq!(resend("email.complained"), update("email_1", EmailStatus::Complained));
+        assert_eq!(resend("email.delivery_delayed"), None);
+        assert_eq!(resend_delivery(&json!({ "type": "email.bounced", "data": { "email_id": "email_1", "bounce": { "type": "Transient" } } })), None);
+        assert_eq!(resend_delivery(&json!({ "type": "email.delivered", "data": {} })), None);
+
+        let ses = |message: Value| ses_delivery(&message);
+
+        assert_eq!(ses(json!({ "notificationType": "Delivery", "mail": { "messageId": "0100018f" } })), update("0100018f", EmailStatus::Delivered));
+        assert_eq!(ses(json!({ "eventType": "Open", "mail": { "messageId": "0100018f" } })), update("0100018f", EmailStatus::Opened));
+        assert_eq!(ses(json!({ "eventType": "Bounce", "bounce": { "bounceType": "Permanent" }, "mail": { "messageId": "0100018f" } })), update("0100018f", EmailStatus::Bounced));
+        assert_eq!(ses(json!({ "eventType": "Bounce", "bounce": { "bounceType": "Transient" }, "mail": { "messageId": "0100018f" } })), None);
+        assert_eq!(ses(json!({ "eventType": "Reject", "mail": { "messageId": "0100018f" } })), None);
+    }
+
+
+    #[tokio::test]
+    /// # tracks_sent_emails
+    /// The welcome email is logged as `sent` with its message id and customer, delivery webhooks
+    /// move it to `opened` and keep the first timestamp of every status.
+    async fn tracks_sent_emails() {
+        let harness: Harness = Harness::start().await;
+        env::set_var("RESEND_WEBHOOK_SECRET", RESEND_SECRET);
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+        assert_eq!(harness.send_fixture("checkout.session.completed").await, Status::Ok);
+
+        let customer: Value = harness.rows("stripe_customer_data").remove(0);
+        let logged: Vec<Value> = harness.rows("stripe_email_log");
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0]["message_id"], "email_1");
+        assert_eq!(logged[0]["template"], "welcome");
+        assert_eq!(logged[0]["subject"], "Welcome!");
+        assert_eq!(logged[0]["recipient"], "jenny.rosen@example.com");
+        assert_eq!(logged[0]["customer_id"], customer["customer_id"]);
+        assert_eq!(logged[0]["provider"], "resend");
+        assert_eq!(logged[0]["status"], "sent");
+        assert!(logged[0]["sent_at"].is_i64());
+
+        assert_eq!(post_resend_event(&harness, "email.opened", "email_1").await, Status::Ok);
+        harness.patch_rows("stripe_email_log", json!({ "opened_at": 1714000000 }));
+        assert_eq!(post_resend_event(&harness, "email.delivered", "email_1").await, Status::Ok);
+        assert_eq!(post_resend_event(&harness, "email.opened", "email_1").await, Status::Ok);
+        assert_eq!(post_resend_event(&harness, "email.delivered", "email_unknown").await, Status::Ok);
+
+        let logged: Value = harness.rows("stripe_email_log").remove(0);
+        assert_eq!(logged["status"], "opened");
+        assert_eq!(logged["opened_at"], 1714000000);
+        assert!(logged["delivered_at"].is_i64());
+        assert!(logged["bounced_at"].is_null());
+
+        let key: String = harness.issue_api_key(vec![Scope::ReadCustomers]).await;
+        let response = harness.client
+            .get(format!("/admin/customers/{}", customer["customer_id"].as_str().unwrap()))
+            .header(Header::new("Authorization", format!("Bearer {}", key)))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let body: Value = response.into_json().await.expect("a JSON body");
+        assert_eq!(body["emails"][0]["message_id"], "email_1");
+        assert_eq!(body["emails"][0]["status"], "opened");
+
+        env::remove_var("RESEND_WEBHOOK_SECRET");
+    }
+}
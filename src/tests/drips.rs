@@ -0,0 +1,203 @@
+//! ## Drip sequence tests
+//!
+//! ### Table of contents
+//! - Reading the steps under `Email.Drip` in `stripe_discord.yaml`
+//! - Scheduling the steps after checkout and sending them once they are due
+//! - Stopping the sequence on a refund or cancellation
+//!
+
+
+#[cfg(test)]
+mod drip_sequences {
+    use crate::background::scheduler::run_due_emails;
+    use crate::db::operations::scheduled_email::ScheduledEmail;
+    use crate::email::client::customer_placeholders;
+    use crate::email::drip::{schedule_drips, DripStep};
+    use crate::email::locale::Locale;
+    use crate::events::fixtures::fixture;
+    use crate::events::test_event::TestEvent;
+    use crate::events::EventHandler;
+    use crate::organization::router::organization_from_config;
+    use crate::tests::harness::Harness;
+    use crate::{ConfigSetup, DripStepConfig, EmailConfig, Organization};
+
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use supabase_rs::SupabaseClient;
+
+
+    /// When the checkout session fixture was completed
+    const PURCHASED_AT: i64 = 1714000002;
+
+    /// A day in seconds
+    const DAY: i64 = 86400;
+
+
+    /// # organization
+    /// An Organization that sends tips after three days and asks for feedback after two weeks,
+    /// with the templates served by the fake CDN.
+    fn organization(harness: &Harness) -> Organization {
+        let base_url: &str = &harness.fakes.base_url;
+        let email = |subject: &str, template: &str| -> EmailConfig {
+            EmailConfig::new("billing@xylex.ai".to_string(), subject.to_string(), format!("{}/templates/{}.html", base_url, template))
+        };
+
+        {
+            let mut state = harness.fakes.state.lock().expect("fake state lock");
+            state.templates.insert("/templates/tips.html".to_string(), "<p>Hi {{FirstName}}, three tips for {{ProductName}}</p>".to_string());
+            state.templates.insert("/templates/feedback.html".to_string(), "<p>Hi {{FirstName}}, how is it going?</p>".to_string());
+        }
+
+        Organization::new("Drips".to_string(), email("Welcome!", "welcome"))
+            .with_drip_step(DripStep::new("tips", Duration::from_secs(3 * 86400), email("Three tips", "tips")))
+            .with_drip_step(DripStep::new("feedback", Duration::from_secs(14 * 86400), email("How is it going?", "feedback")))
+    }
+
+
+    /// # schedule
+    /// Starts the sequence of the Organization for Jenny and a checkout session.
+    async fn schedule(organization: &Organization, checkout_session_id: &str, supabase: &SupabaseClient) -> Result<usize, String> {
+        let placeholders: HashMap<String, String> = customer_placeholders("Jenny Rosen", "jenny.rosen@example.com");
+        let locale: Locale = Locale::parse("en").expect("a valid locale");
+
+        schedule_drips(organization, checkout_session_id, "jenny.rosen@example.com", &locale, &placeholders, PURCHASED_AT, supabase).await
+    }
+
+
+    /// # column
+    /// One column of every row of a table.
+    fn column(rows: &[Value], column: &str) -> Vec<Value> {
+        rows.iter().map(|row| row[column].clone()).collect()
+    }
+
+
+    #[test]
+    /// # reads_drip_steps
+    /// Steps are read in order with their delays in days and hours, the sender defaults to
+    /// `Email.Sender` and entries without a subject or template are skipped.
+    fn reads_drip_steps() {
+        let value: Value = serde_yaml::from_str(r#"
+            Email:
+              Drip:
+                - Name: tips
+                  DelayDays: 3
+                  Subject: Three tips
+                  TemplateUrl: tips
+                - DelayDays: 14
+                  DelayHours: 2
+                  Sender: founder@xylex.ai
+                  Subject: How is it going?
+                  TemplateUrl: feedback
+                  Locales:
+                    de:
+                      Subject: Wie läuft es?
+                - Name: broken
+                  TemplateUrl: broken
+        "#).expect("valid yaml");
+
+        let drips: Vec<DripStepConfig> = DripStepConfig::from_config(&value);
+        assert_eq!(drips.len(), 2);
+        assert_eq!(drips[0].name, "tips");
+        assert_eq!(drips[0].delay_secs, 3 * 86400);
+        assert_eq!(drips[1].name, "feedback");
+        assert_eq!(drips[1].delay_secs, 14 * 86400 + 2 * 3600);
+
+        let config: ConfigSetup = ConfigSetup { drips, ..ConfigSetup::default() };
+        let organization: Organization = organization_from_config(&config);
+
+        let tips: &DripStep = organization.drip_step("tips").expect("the tips step");
+        assert_eq!(tips.delay, Duration::from_secs(3 * 86400));
+        assert_eq!(tips.email_config.sender_email, "test@example.com");
+        assert_eq!(tips.email_config.template_url, "tips");
+
+        let feedback: &DripStep = organization.drip_step("feedback").expect("the feedback step");
+        assert_eq!(feedback.email_config.sender_email, "founder@xylex.ai");
+        assert_eq!(feedback.email_config.localized(&Locale::parse("de").unwrap()).subject, "Wie läuft es?");
+
+        assert!(organization.drip_step("broken").is_none());
+    }
+
+
+    #[tokio::test]
+    /// # sends_due_drips
+    /// A checkout schedules every step once, each is sent when it is due with the placeholders of
+    /// the purchase and logged under its name.
+    async fn sends_due_drips() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let organization: Organization = organization(&harness);
+
+        let charge: Value = fixture("charge.succeeded").expect("a charge fixture");
+        let checkout: Value = fixture("checkout.session.completed").expect("a checkout fixture");
+        assert!(matches!(EventHandler::new(&charge, organization.clone(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+        assert!(matches!(EventHandler::new(&checkout, organization.clone(), supabase.clone()).await, Ok(EventHandler::CheckoutSessionCompleted)));
+        assert_eq!(schedule(&organization, "cs_test_fixtureCheckoutSession01", &supabase).await, Ok(0));
+
+        let scheduled: Vec<Value> = harness.rows("stripe_scheduled_emails");
+        assert_eq!(column(&scheduled, "step"), vec!["tips", "feedback"]);
+        assert_eq!(column(&scheduled, "send_at"), vec![PURCHASED_AT + 3 * DAY, PURCHASED_AT + 14 * DAY]);
+        assert_eq!(column(&scheduled, "status"), vec!["pending", "pending"]);
+        assert_eq!(scheduled[0]["organization"], "Drips");
+        assert_eq!(scheduled[0]["email"], "jenny.rosen@example.com");
+        assert_eq!(scheduled[0]["placeholders"]["FirstName"], "Jenny");
+
+        assert_eq!(run_due_emails(std::slice::from_ref(&organization), PURCHASED_AT + 3 * DAY - 1).await, Ok(0));
+        assert_eq!(run_due_emails(std::slice::from_ref(&organization), PURCHASED_AT + 3 * DAY).await, Ok(1));
+        assert_eq!(run_due_emails(std::slice::from_ref(&organization), PURCHASED_AT + 3 * DAY).await, Ok(0));
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[1]["subject"], "Three tips");
+        assert_eq!(emails[1]["to"], json!(["jenny.rosen@example.com"]));
+        assert!(emails[1]["html"].as_str().unwrap().contains("Hi Jenny, three tips for"));
+
+        let scheduled: Vec<Value> = harness.rows("stripe_scheduled_emails");
+        assert_eq!(column(&scheduled, "status"), vec!["sent", "pending"]);
+        assert_eq!(scheduled[0]["message_id"], "email_2");
+
+        assert_eq!(run_due_emails(std::slice::from_ref(&organization), PURCHASED_AT + 14 * DAY).await, Ok(1));
+        assert_eq!(harness.emails()[2]["subject"], "How is it going?");
+        assert_eq!(column(&harness.rows("stripe_email_log"), "template"), vec!["welcome", "tips", "feedback"]);
+    }
+
+
+    #[tokio::test]
+    /// # stops_drips
+    /// A refund and a cancelled subscription cancel the pending steps of the customer, steps that
+    /// are not configured anymore are skipped.
+    async fn stops_drips() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let organization: Organization = organization(&harness);
+
+        assert_eq!(schedule(&organization, "cs_refunded", &supabase).await, Ok(2));
+
+        let refund: Value = fixture("charge.refunded").expect("a refund fixture");
+        assert!(matches!(EventHandler::new(&refund, organization.clone(), supabase.clone()).await, Ok(EventHandler::ChargeRefunded)));
+
+        assert_eq!(schedule(&organization, "cs_cancelled", &supabase).await, Ok(2));
+
+        let deleted: Value = TestEvent::new("customer.subscription.deleted")
+            .unwrap()
+            .with_email("jenny.rosen@example.com".to_string())
+            .build();
+        assert!(matches!(EventHandler::new(&deleted, organization.clone(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionDeleted)));
+
+        let scheduled: Vec<Value> = harness.rows("stripe_scheduled_emails");
+        assert_eq!(column(&scheduled, "status"), vec!["cancelled"; 4]);
+        assert_eq!(column(&scheduled, "reason"), vec![
+            "charge.refunded",
+            "charge.refunded",
+            "customer.subscription.deleted",
+            "customer.subscription.deleted",
+        ]);
+
+        let gone: ScheduledEmail = ScheduledEmail::pending("Drips", "gone", "jenny.rosen@example.com", "cs_gone", "en", HashMap::new(), PURCHASED_AT, PURCHASED_AT);
+        gone.insert(supabase.clone()).await.expect("the scheduled email is stored");
+
+        assert_eq!(run_due_emails(&[organization], PURCHASED_AT + 15 * DAY).await, Ok(0));
+        assert!(harness.emails().is_empty());
+        assert_eq!(harness.rows("stripe_scheduled_emails")[4]["status"], "skipped");
+    }
+}
@@ -0,0 +1,220 @@
+//! ## Endpoint config tests
+//!
+//! ### Table of contents
+//! - Parsing Discord snowflakes
+//! - Reading and validating `Endpoints` from the config
+//! - Mounting an endpoint with its own secret and Organization
+//!
+
+
+#[cfg(test)]
+mod config_endpoints {
+    use crate::api::routes::build_rocket;
+    use crate::discord::snowflake::parse_snowflake;
+    use crate::events::fixtures::fixture;
+    use crate::discord::roles::DiscordRoles;
+    use crate::events::signature::{signature_header, unix_now};
+    use crate::organization::router::organization_for_endpoint;
+    use crate::tests::harness::{Harness, TEST_WEBHOOK_SECRET};
+    use crate::{ConfigError, ConfigSetup, EmailConfig, EndpointConfigStripe, Organization};
+
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::Client;
+    use serde_json::{json, Value};
+
+
+    /// The webhook secret of the mounted test endpoint
+    const ENDPOINT_SECRET: &str = "whsec_acme_endpoint_secret";
+
+
+    /// # config
+    /// Parses a `stripe_discord.yaml` document.
+    fn config(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).expect("valid YAML")
+    }
+
+
+    /// # acme_entry
+    /// A valid endpoint entry, with `changes` applied on top.
+    fn acme_entry(changes: Value) -> Value {
+        let mut entry: Value = json!({
+            "Route": "/stripe_webhooks/acme",
+            "Name": "Acme",
+            "SenderEmail": "billing@acme.example",
+            "EmailTemplatePath": "https://cdn.acme.example/welcome.html",
+            "Stripe": { "PublishKey": "pk_test_51Hx", "WebhookSecret": "whsec_acme" },
+        });
+
+        for (key, value) in changes.as_object().expect("an object of changes") {
+            entry[key] = value.clone();
+        }
+
+        entry
+    }
+
+
+    #[test]
+    /// # parses_snowflakes
+    /// Snowflakes are accepted as strings and integers, anything else is rejected.
+    fn parses_snowflakes() {
+        assert_eq!(parse_snowflake(&json!("81384788765712384")), Ok(81384788765712384));
+        assert_eq!(parse_snowflake(&json!(41771983423143936_i64)), Ok(41771983423143936));
+
+        for invalid in [json!("1234"), json!("8138478876571238x"), json!(""), json!(-5), json!(true), json!("99999999999999999999")] {
+            assert!(parse_snowflake(&invalid).is_err(), "{} is not a snowflake", invalid);
+        }
+    }
+
+
+    #[test]
+    /// # reads_endpoints
+    /// Every entry under `Endpoints` is read, optional fields fall back to their defaults, and the
+    /// Organization of an endpoint syncs the role with its own Discord settings.
+    fn reads_endpoints() {
+        let endpoints: Vec<EndpointConfigStripe> = EndpointConfigStripe::from_config(&config(r#"
+Endpoints:
+  - Route: /stripe_webhooks/acme
+    Name: Acme
+    SenderEmail: billing@acme.example
+    EmailTemplatePath: https://cdn.acme.example/welcome.html
+    ReplaceKeysWithEnvNames: true
+    Stripe:
+      PublishKey: pk_live_51Hx
+      WebhookSecret: ACME_STRIPE_WEBHOOK_SECRET
+      PrivateKey: ACME_STRIPE_PRIVATE_API_KEY
+    Discord:
+      ClientId: "1089273409192386560"
+      GuildId: "81384788765712384"
+      RoleId: 41771983423143936
+      BotToken: ACME_DISCORD_BOT_TOKEN
+  - Route: /webhooks/globex
+    SenderEmail: billing@globex.example
+    EmailTemplatePath: ./email/templates/globex.html
+    Stripe:
+      WebhookSecret: file:/run/secrets/globex_webhook_secret
+"#)).unwrap();
+
+        assert_eq!(endpoints.len(), 2);
+
+        assert_eq!(endpoints[0].name, "Acme");
+        assert_eq!(endpoints[0].discord_guild_id, 81384788765712384);
+        assert_eq!(endpoints[0].discord_role_id, 41771983423143936);
+        assert_eq!(endpoints[0].discord_client_id, "1089273409192386560");
+        assert!(endpoints[0].replace_keys_with_env_names);
+
+        assert_eq!(endpoints[1].name, "/webhooks/globex");
+        assert_eq!(endpoints[1].discord_guild_id, 0);
+        assert_eq!(endpoints[1].stripe_private_key, "");
+        assert!(!endpoints[1].replace_keys_with_env_names);
+
+        assert!(EndpointConfigStripe::from_config(&config("Api:\n  Port: 8080\n")).unwrap().is_empty());
+
+        std::env::set_var("ACME_DISCORD_BOT_TOKEN", "acme_bot_token");
+        let base: Organization = Organization::new(
+            "Fixture".to_string(),
+            EmailConfig::new("billing@example.com".to_string(), "Welcome!".to_string(), "welcome".to_string())
+        );
+
+        let roles: DiscordRoles = organization_for_endpoint(&base, &endpoints[0]).discord_roles.expect("the roles of Acme");
+        assert_eq!(roles.guild_id, "81384788765712384");
+        assert_eq!(roles.role_id, "41771983423143936");
+        assert_eq!(roles.client.bot_token, "acme_bot_token");
+        assert!(organization_for_endpoint(&base, &endpoints[1]).discord_roles.is_none());
+    }
+
+
+    #[test]
+    /// # rejects_invalid_endpoints
+    /// Bad routes, snowflakes, prefixes and incomplete Discord settings name the endpoint.
+    fn rejects_invalid_endpoints() {
+        let invalid: Vec<Value> = vec![
+            acme_entry(json!({ "Route": "stripe_webhooks/acme" })),
+            acme_entry(json!({ "Route": "/stripe_webhooks/<org>" })),
+            acme_entry(json!({ "Route": "/stripe_webhooks" })),
+            acme_entry(json!({ "Route": "/admin/webhooks" })),
+            acme_entry(json!({ "SenderEmail": "billing" })),
+            acme_entry(json!({ "EmailTemplatePath": "" })),
+            acme_entry(json!({ "Stripe": { "WebhookSecret": "whsec_acme", "PublishKey": "sk_test_51Hx" } })),
+            acme_entry(json!({ "Stripe": { "PublishKey": "pk_test_51Hx" } })),
+            acme_entry(json!({ "Discord": { "GuildId": "1234", "RoleId": "41771983423143936", "BotToken": "token" } })),
+            acme_entry(json!({ "Discord": { "GuildId": "81384788765712384", "RoleId": "41771983423143936" } })),
+        ];
+
+        for entry in invalid {
+            let error: ConfigError = EndpointConfigStripe::from_config(&json!({ "Endpoints": [entry.clone()] }))
+                .expect_err(&format!("{} is invalid", entry));
+
+            assert!(matches!(error, ConfigError::InvalidEndpoint(ref route, _) if *route == entry["Route"]), "{}", error);
+        }
+
+        let duplicate: Result<Vec<EndpointConfigStripe>, ConfigError> = EndpointConfigStripe::from_config(
+            &json!({ "Endpoints": [acme_entry(json!({})), acme_entry(json!({ "Name": "Acme again" }))] })
+        );
+        assert!(matches!(duplicate, Err(ConfigError::InvalidEndpoint(..))));
+    }
+
+
+    #[tokio::test]
+    /// # mounts_endpoint_routes
+    /// An endpoint only accepts webhooks signed with its own secret and sends its own welcome email.
+    async fn mounts_endpoint_routes() {
+        let harness: Harness = Harness::start().await;
+
+        let organization: Organization = Organization::new(
+            "Fixture".to_string(),
+            EmailConfig::new(
+                "billing@example.com".to_string(),
+                "Welcome!".to_string(),
+                format!("{}/templates/missing.html", harness.fakes.base_url),
+            ),
+        );
+
+        let entry: Value = acme_entry(json!({
+            "Stripe": { "WebhookSecret": ENDPOINT_SECRET },
+            "EmailTemplatePath": format!("{}/templates/welcome.html", harness.fakes.base_url),
+        }));
+        let endpoints: Vec<EndpointConfigStripe> = EndpointConfigStripe::from_config(&json!({ "Endpoints": [entry] })).unwrap();
+
+        let config: ConfigSetup = ConfigSetup { endpoints, ..ConfigSetup::default() };
+
+        let client: Client = Client::untracked(build_rocket(organization, &config))
+            .await
+            .expect("valid rocket instance");
+
+        let post = |event: &Value, secret: Option<&str>| {
+            let body: String = event.to_string();
+            let mut request = client
+                .post("/stripe_webhooks/acme")
+                .header(ContentType::JSON)
+                .body(body.clone());
+
+            if let Some(secret) = secret {
+                request = request.header(Header::new("Stripe-Signature", signature_header(&body, unix_now(), secret)));
+            }
+
+            request.dispatch()
+        };
+
+        let charge: Value = fixture("charge.succeeded").unwrap();
+        let checkout: Value = fixture("checkout.session.completed").unwrap();
+
+        // unsigned or signed with the secret of `/stripe_webhooks`
+        assert_eq!(post(&charge, None).await.status(), Status::BadRequest);
+        assert_eq!(post(&charge, Some(TEST_WEBHOOK_SECRET)).await.status(), Status::BadRequest);
+        assert!(harness.rows("stripe_webhook_events").is_empty());
+
+        assert_eq!(post(&charge, Some(ENDPOINT_SECRET)).await.status(), Status::Ok);
+        harness.wait_until_processed(charge["id"].as_str().unwrap()).await;
+
+        assert_eq!(post(&checkout, Some(ENDPOINT_SECRET)).await.status(), Status::Ok);
+        harness.wait_until_processed(checkout["id"].as_str().unwrap()).await;
+
+        assert_eq!(harness.rows("stripe_webhook_events").len(), 2);
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0]["from"], "billing@acme.example");
+        assert_eq!(emails[0]["subject"], "Welcome!");
+        assert_eq!(emails[0]["html"], "<p>Welcome aboard!</p>");
+    }
+}
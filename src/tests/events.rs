@@ -0,0 +1,236 @@
+//! ## Event unwrapping tests
+//!
+//! ### Table of contents
+//! - Unwrapping `charge.failed` objects
+//! - Unwrapping `payment_intent.payment_failed` objects
+//! - Populating email template placeholders, escaped in HTML
+//! - Unwrapping `charge.refunded` and `charge.dispute.*` objects
+//! - Refund and dispute entitlement policies, unknown names rejected
+//!
+
+
+#[cfg(test)]
+mod payment_failed {
+    use crate::email::templates::template::{populate_html_placeholders, populate_placeholders};
+    use crate::events::{ChargeFailed, PaymentIntentPaymentFailed};
+
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+
+    #[test]
+    /// # charge_failed_from_object
+    /// Unwraps a declined `charge.failed` object into a `ChargeFailed`.
+    fn charge_failed_from_object() {
+        let object: Value = json!({
+            "id": "ch_3PA1",
+            "billing_details": {
+                "email": "floris@xylex.ai",
+                "name": "Floris Xylex",
+                "address": { "country": "NL" }
+            },
+            "failure_code": "card_declined",
+            "failure_message": "Your card has insufficient funds.",
+            "outcome": { "reason": "insufficient_funds", "seller_message": "The bank returned the decline code `insufficient_funds`." },
+            "payment_intent": "pi_3PA1"
+        });
+
+        let charge_failed: ChargeFailed = ChargeFailed::from_object(&object);
+
+        assert_eq!(charge_failed.email, "floris@xylex.ai");
+        assert_eq!(charge_failed.name, "Floris Xylex");
+        assert_eq!(charge_failed.country, "NL");
+        assert_eq!(charge_failed.decline_code, "insufficient_funds");
+        assert_eq!(charge_failed.decline_message, "Your card has insufficient funds.");
+        assert_eq!(charge_failed.payment_intent, Some("pi_3PA1".to_string()));
+        assert!(!charge_failed.paid_status);
+    }
+
+
+    #[test]
+    /// # charge_failed_falls_back
+    /// Falls back to `failure_code` and `"unknown"` when the charge has no outcome.
+    fn charge_failed_falls_back() {
+        let object: Value = json!({
+            "failure_code": "expired_card",
+            "payment_intent": null
+        });
+
+        let charge_failed: ChargeFailed = ChargeFailed::from_object(&object);
+
+        assert_eq!(charge_failed.email, "unknown");
+        assert_eq!(charge_failed.decline_code, "expired_card");
+        assert_eq!(charge_failed.decline_message, "unknown");
+        assert_eq!(charge_failed.payment_intent, None);
+    }
+
+
+    #[test]
+    /// # payment_intent_payment_failed_from_object
+    /// Unwraps the `last_payment_error` of a `payment_intent.payment_failed` object.
+    fn payment_intent_payment_failed_from_object() {
+        let object: Value = json!({
+            "id": "pi_3PA1",
+            "created": 1714000000,
+            "receipt_email": "receipt@xylex.ai",
+            "last_payment_error": {
+                "code": "card_declined",
+                "decline_code": "stolen_card",
+                "message": "Your card was declined.",
+                "payment_method": {
+                    "billing_details": {
+                        "email": null,
+                        "name": "Floris Xylex",
+                        "address": { "country": "DE" }
+                    }
+                }
+            }
+        });
+
+        let payment_failed: PaymentIntentPaymentFailed = PaymentIntentPaymentFailed::from_object(&object);
+
+        assert_eq!(payment_failed.email, "receipt@xylex.ai");
+        assert_eq!(payment_failed.name, "Floris Xylex");
+        assert_eq!(payment_failed.country, "DE");
+        assert_eq!(payment_failed.created_ad, 1714000000);
+        assert_eq!(payment_failed.decline_code, "stolen_card");
+        assert_eq!(payment_failed.decline_message, "Your card was declined.");
+    }
+
+
+    #[test]
+    /// # populate_placeholders_replaces_known
+    /// Replaces known placeholders and leaves unknown ones untouched.
+    fn populate_placeholders_replaces_known() {
+        let mut placeholders: HashMap<String, String> = HashMap::new();
+        placeholders.insert("FirstName".to_string(), "Floris".to_string());
+        placeholders.insert("DeclineMessage".to_string(), "Your card was declined.".to_string());
+
+        let html: String = populate_placeholders(
+            "<p>Hi {{FirstName}}, {{DeclineMessage}} {{ProductName}}</p>",
+            &placeholders
+        );
+
+        assert_eq!(html, "<p>Hi Floris, Your card was declined. {{ProductName}}</p>");
+    }
+
+
+    #[test]
+    /// # populate_placeholders_escapes_values
+    /// Values are escaped in HTML, kept as they are in text and never expanded again.
+    fn populate_placeholders_escapes_values() {
+        let mut placeholders: HashMap<String, String> = HashMap::new();
+        placeholders.insert("FirstName".to_string(), "<script>alert(1)</script>{{Email}}".to_string());
+        placeholders.insert("Email".to_string(), "jenny@example.com".to_string());
+
+        let html: String = populate_html_placeholders("<p>Hi {{FirstName}}</p>", &placeholders);
+        assert_eq!(html, "<p>Hi &lt;script&gt;alert(1)&lt;/script&gt;{{Email}}</p>");
+
+        let text: String = populate_placeholders("Hi {{FirstName}}", &placeholders);
+        assert_eq!(text, "Hi <script>alert(1)</script>{{Email}}");
+    }
+}
+
+
+#[cfg(test)]
+mod refunds_and_disputes {
+    use crate::config::policy;
+    use crate::events::{ChargeDispute, ChargeRefunded};
+    use crate::organization::model::{DisputePolicy, RefundPolicy};
+    use crate::ConfigError;
+
+    use serde_json::{json, Value};
+
+
+    #[test]
+    /// # charge_refunded_from_object
+    /// Unwraps a partially refunded charge.
+    fn charge_refunded_from_object() {
+        let object: Value = json!({
+            "id": "ch_3PA1",
+            "amount": 5000,
+            "amount_refunded": 2000,
+            "refunded": false,
+            "billing_details": { "email": "floris@xylex.ai" },
+            "refunds": { "data": [ { "id": "re_1", "reason": "requested_by_customer" } ] }
+        });
+
+        let charge_refunded: ChargeRefunded = ChargeRefunded::from_object(&object);
+
+        assert_eq!(charge_refunded.customer_id, "ch_3PA1");
+        assert_eq!(charge_refunded.email, "floris@xylex.ai");
+        assert_eq!(charge_refunded.amount_refunded, 2000);
+        assert!(!charge_refunded.fully_refunded);
+        assert_eq!(charge_refunded.refund_reason, "requested_by_customer");
+    }
+
+
+    #[test]
+    /// # charge_dispute_from_object
+    /// Unwraps a dispute and takes the customer id from the disputed charge.
+    fn charge_dispute_from_object() {
+        let object: Value = json!({
+            "id": "dp_1",
+            "charge": "ch_3PA1",
+            "amount": 5000,
+            "status": "needs_response",
+            "reason": "fraudulent",
+            "evidence": { "customer_email_address": "floris@xylex.ai" }
+        });
+
+        let dispute: ChargeDispute = ChargeDispute::from_object(&object);
+
+        assert_eq!(dispute.dispute_id, "dp_1");
+        assert_eq!(dispute.customer_id, "ch_3PA1");
+        assert_eq!(dispute.email, "floris@xylex.ai");
+        assert_eq!(dispute.status, "needs_response");
+        assert_eq!(dispute.reason, "fraudulent");
+    }
+
+
+    #[test]
+    /// # refund_policies
+    /// Only the configured refunds revoke `paid`.
+    fn refund_policies() {
+        assert_eq!(RefundPolicy::Keep.paid_after_refund(true), None);
+        assert_eq!(RefundPolicy::RevokeOnFullRefund.paid_after_refund(false), None);
+        assert_eq!(RefundPolicy::RevokeOnFullRefund.paid_after_refund(true), Some(false));
+        assert_eq!(RefundPolicy::RevokeOnAnyRefund.paid_after_refund(false), Some(false));
+    }
+
+
+    #[test]
+    /// # dispute_policies
+    /// Opening, winning and losing disputes under every policy.
+    fn dispute_policies() {
+        let revoke_on_open: DisputePolicy = DisputePolicy::RevokeOnOpen;
+        assert_eq!(revoke_on_open.paid_after_dispute(false, "needs_response"), Some(false));
+        assert_eq!(revoke_on_open.paid_after_dispute(true, "won"), Some(true));
+        assert_eq!(revoke_on_open.paid_after_dispute(true, "lost"), Some(false));
+
+        let revoke_on_lost: DisputePolicy = DisputePolicy::RevokeOnLost;
+        assert_eq!(revoke_on_lost.paid_after_dispute(false, "needs_response"), None);
+        assert_eq!(revoke_on_lost.paid_after_dispute(true, "won"), None);
+        assert_eq!(revoke_on_lost.paid_after_dispute(true, "lost"), Some(false));
+
+        assert_eq!(DisputePolicy::Keep.paid_after_dispute(true, "lost"), None);
+    }
+
+
+    #[test]
+    /// # rejects_unknown_policies
+    /// Policy names are checked when the config is loaded, a typo is an error instead of the
+    /// default and a missing name is the default.
+    fn rejects_unknown_policies() {
+        assert_eq!("revoke_on_any_refund".parse(), Ok(RefundPolicy::RevokeOnAnyRefund));
+        assert_eq!("keep".parse(), Ok(DisputePolicy::Keep));
+        assert!("nonsense".parse::<DisputePolicy>().is_err());
+
+        let config: Value = json!({ "Entitlement": { "Refund": "revoke_on_full" } });
+        let error: ConfigError = policy::<RefundPolicy>(&config, "Refund", "revoke_on_full_refund").unwrap_err();
+        assert!(matches!(error, ConfigError::InvalidValue(ref key, _) if key == "Entitlement.Refund"), "{}", error);
+        assert!(error.to_string().contains("revoke_on_full_refund"), "{}", error);
+
+        assert_eq!(policy::<DisputePolicy>(&json!({}), "Dispute", "revoke_on_lost").unwrap(), "revoke_on_lost");
+    }
+}
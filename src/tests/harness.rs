@@ -0,0 +1,843 @@
+//! ## Fixture replay harness
+//!
+//! Feeds Stripe fixtures, signed with a test secret, through the real Rocket endpoint while
+//! Supabase, Resend and Discord are replaced by an in-memory fake, so the side effects of the
+//! `EventHandler` can be checked offline and without any real secrets.
+//!
+//! ### How it works
+//! [`FakeServices`] is a tiny HTTP server on a random local port that answers like the Supabase
+//! REST API (`/rest/v1/<table>`), the Resend API (`/emails`, `/domains`), the Discord API
+//! (`/discord/...`), the Stripe API (`/stripe/...`), a template CDN
+//! (`/templates/<name>.html`, with `ETag`s) and a DNS over HTTPS resolver (`/dns-query`) and
+//! keeps everything it receives in memory.
+//! [`Harness::start`] points `SUPABASE_URL`, `RESEND_API_URL`, `DISCORD_API_URL` and
+//! `STRIPE_API_URL` at it and sets `STRIPE_WEBHOOK_SECRET` to [`TEST_WEBHOOK_SECRET`].
+//! [`FakeSmtp`] is a plain-text SMTP server for emails sent over `smtp`.
+//!
+//! ### Usage example
+//! ```rust
+//! let harness: Harness = Harness::start().await;
+//!
+//! assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+//! assert_eq!(harness.rows("stripe_customer_data")[0]["paid"], true);
+//! ```
+//!
+//! ### Notes
+//! - The environment is process wide, a [`Harness`] holds a lock so harness tests run one at a time
+//! - `checkout.session.completed` waits 6 seconds before sending the welcome email, like in production
+
+use crate::api::routes::build_rocket;
+use crate::auth::{issue_api_key, Scope};
+use crate::db::operations::api_key::ApiKey;
+use crate::events::fixtures::fixture;
+use crate::events::signature::{signature_header, unix_now};
+use crate::organization::model::{DisputePolicy, RefundPolicy};
+use crate::{ConfigSetup, EmailConfig, Organization};
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use supabase_rs::SupabaseClient;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard};
+
+
+/// The webhook secret fixtures are signed with
+pub const TEST_WEBHOOK_SECRET: &str = "whsec_fixture_test_secret";
+
+/// The welcome email template served by the fake
+pub const WELCOME_TEMPLATE: &str = "<p>Welcome aboard!</p>";
+
+/// The private Stripe key the fake Stripe API accepts
+pub const TEST_STRIPE_PRIVATE_KEY: &str = "sk_test_fixture_private_key";
+
+/// The invoice PDF served by the fake Stripe
+pub const INVOICE_PDF: &str = "%PDF-1.4 fixture invoice";
+
+/// The payment failed email template served by the fake
+pub const PAYMENT_FAILED_TEMPLATE: &str = "<p>Hi {{FirstName}}, your payment failed: {{DeclineMessage}}</p>";
+
+/// The `(table, column)` pairs with a unique constraint in the schema, the fake answers `409`
+/// when a plain insert repeats one
+const UNIQUE_COLUMNS: [(&str, &str); 1] = [("stripe_webhook_events", "event_id")];
+
+/// Harness tests change the process environment, so only one may run at a time
+static ENVIRONMENT: AsyncMutex<()> = AsyncMutex::const_new(());
+
+
+/// ## FakeState
+/// Everything the fake services received
+///
+/// ### Fields
+/// - `tables` - The Supabase rows by table name
+/// - `emails` - The bodies posted to Resend
+/// - `discord_requests` - The method and path of every Discord call
+/// - `stripe_requests` - The method and path of every Stripe call
+/// - `templates` - Templates served by the CDN besides the welcome and payment failed ones, by path
+/// - `templates_down` - Whether the CDN answers every template request with a 503
+/// - `template_responses` - The status of every template response
+/// - `email_failures` - The statuses the next calls to Resend fail with, in order
+/// - `email_idempotency_keys` - The `Idempotency-Key` of every call to Resend, failed ones included
+/// - `tables_down` - The Supabase tables that answer every request with a 503
+#[derive(Debug, Default)]
+pub struct FakeState {
+    pub tables: HashMap<String, Vec<Value>>,
+    pub emails: Vec<Value>,
+    pub discord_requests: Vec<String>,
+    pub stripe_requests: Vec<String>,
+    pub templates: HashMap<String, String>,
+    pub templates_down: bool,
+    pub template_responses: Vec<u16>,
+    pub email_failures: Vec<u16>,
+    pub email_idempotency_keys: Vec<String>,
+    pub tables_down: Vec<String>,
+}
+
+
+/// ## FakeServices
+/// An in-memory Supabase, Resend and Discord on a random local port
+#[derive(Debug, Clone)]
+pub struct FakeServices {
+    pub base_url: String,
+    pub state: Arc<Mutex<FakeState>>,
+}
+
+
+impl FakeServices {
+    /// # start
+    /// Binds a random local port and serves the fakes on the current runtime.
+    pub async fn start() -> Self {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.expect("bind a local port");
+        let base_url: String = format!("http://{}", listener.local_addr().expect("bound address"));
+        let state: Arc<Mutex<FakeState>> = Arc::new(Mutex::new(FakeState::default()));
+
+        let served_state: Arc<Mutex<FakeState>> = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, served_state.clone()));
+            }
+        });
+
+        FakeServices { base_url, state }
+    }
+}
+
+
+/// ## Harness
+/// The Rocket endpoint wired to [`FakeServices`]
+pub struct Harness {
+    pub fakes: FakeServices,
+    pub client: Client,
+    _environment: AsyncMutexGuard<'static, ()>,
+}
+
+
+impl Harness {
+    /// # start
+    /// Starts the fakes, points the environment at them and builds the Rocket endpoint for an
+    /// Organization with a welcome email with receipts, payment failed email and operator address.
+    pub async fn start() -> Self {
+        let environment: AsyncMutexGuard<'static, ()> = ENVIRONMENT.lock().await;
+        let fakes: FakeServices = FakeServices::start().await;
+
+        env::set_var("SUPABASE_URL", &fakes.base_url);
+        env::set_var("SUPABASE_KEY", "fake_supabase_key");
+        env::set_var("RESEND_API_URL", &fakes.base_url);
+        env::set_var("RESEND_API_KEY", "re_fake");
+        env::set_var("DISCORD_API_URL", format!("{}/discord", fakes.base_url));
+        env::set_var("DISCORD_BOT_TOKEN", "fake_bot_token");
+        env::set_var("STRIPE_API_URL", format!("{}/stripe", fakes.base_url));
+        env::set_var("STRIPE_PRIVATE_API_KEY", TEST_STRIPE_PRIVATE_KEY);
+        env::set_var("STRIPE_WEBHOOK_SECRET", TEST_WEBHOOK_SECRET);
+        env::set_var("DISCORD_GUILD_ID", "81384788765712384");
+        env::set_var("DISCORD_ROLE_ID", "41771983423143936");
+
+        let organization: Organization = Organization::new(
+            "Fixture".to_string(),
+            EmailConfig::new(
+                "billing@example.com".to_string(),
+                "Welcome!".to_string(),
+                format!("{}/templates/welcome.html", fakes.base_url),
+            ),
+        )
+        .with_payment_failed_email(EmailConfig::new(
+            "billing@example.com".to_string(),
+            "Your payment failed".to_string(),
+            format!("{}/templates/payment_failed.html", fakes.base_url),
+        ))
+        .with_entitlement_policies(RefundPolicy::RevokeOnFullRefund, DisputePolicy::RevokeOnLost)
+        .with_operator_email("ops@example.com".to_string())
+        .with_receipts(true);
+
+        let client: Client = Client::untracked(build_rocket(organization, &ConfigSetup::default()))
+            .await
+            .expect("valid rocket instance");
+
+        Harness { fakes, client, _environment: environment }
+    }
+
+
+    /// # send_fixture
+    /// Signs the fixture of an event type and posts it to `/stripe_webhooks`.
+    pub async fn send_fixture(&self, event_type: &str) -> Status {
+        let event: Value = fixture(event_type).expect("a fixture exists for the event type");
+
+        self.send_event(&event).await
+    }
+
+
+    /// # send_event
+    /// Signs an event with [`TEST_WEBHOOK_SECRET`], posts it to `/stripe_webhooks` and waits until
+    /// it is processed in the background.
+    pub async fn send_event(&self, event: &Value) -> Status {
+        let body: String = event.to_string();
+        let signature: String = signature_header(&body, unix_now(), TEST_WEBHOOK_SECRET);
+
+        let status: Status = self.client
+            .post("/stripe_webhooks")
+            .header(ContentType::JSON)
+            .header(Header::new("Stripe-Signature", signature))
+            .body(body)
+            .dispatch()
+            .await
+            .status();
+
+        self.wait_until_processed(event["id"].as_str().unwrap_or_default()).await;
+
+        status
+    }
+
+
+    /// # wait_until_processed
+    /// Waits until the stored event with this id is no longer `pending`, events that were never
+    /// stored are not waited for.
+    pub async fn wait_until_processed(&self, event_id: &str) {
+        for _ in 0..600 {
+            let pending: bool = self.rows("stripe_webhook_events")
+                .iter()
+                .any(|row| row["event_id"] == event_id && row["outcome"] == "pending");
+
+            if !pending {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("event {} was not processed in time", event_id);
+    }
+
+
+    /// # rows
+    /// The rows of a table in the fake Supabase.
+    pub fn rows(&self, table: &str) -> Vec<Value> {
+        let state = self.fakes.state.lock().expect("fake state lock");
+
+        state.tables.get(table).cloned().unwrap_or_default()
+    }
+
+
+    /// # issue_api_key
+    /// Issues an API key into the fake Supabase and returns the key.
+    pub async fn issue_api_key(&self, scopes: Vec<Scope>) -> String {
+        let supabase: SupabaseClient = SupabaseClient::new(self.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        let issued: Result<(ApiKey, String), String> = issue_api_key("harness".to_string(), scopes, supabase)
+            .await
+            .map_err(|error| error.to_string());
+
+        issued.expect("API key issued").1
+    }
+
+
+    /// # patch_rows
+    /// Merges changes into every row of a table in the fake Supabase, e.g. to link a Discord account.
+    pub fn patch_rows(&self, table: &str, changes: Value) {
+        let mut state = self.fakes.state.lock().expect("fake state lock");
+
+        for row in state.tables.entry(table.to_string()).or_default() {
+            merge_into(row, &changes);
+        }
+    }
+
+
+    /// # discord_requests
+    /// The method and path of every call to the fake Discord.
+    pub fn discord_requests(&self) -> Vec<String> {
+        self.fakes.state.lock().expect("fake state lock").discord_requests.clone()
+    }
+
+
+    /// # emails
+    /// The emails posted to the fake Resend.
+    pub fn emails(&self) -> Vec<Value> {
+        self.fakes.state.lock().expect("fake state lock").emails.clone()
+    }
+
+
+    /// # email_idempotency_keys
+    /// The `Idempotency-Key` of every call to the fake Resend.
+    pub fn email_idempotency_keys(&self) -> Vec<String> {
+        self.fakes.state.lock().expect("fake state lock").email_idempotency_keys.clone()
+    }
+
+
+    /// # fail_emails
+    /// Makes the next calls to the fake Resend fail with these statuses, in order.
+    pub fn fail_emails(&self, statuses: Vec<u16>) {
+        self.fakes.state.lock().expect("fake state lock").email_failures = statuses;
+    }
+
+
+    /// # take_down_table
+    /// Makes every request to a table of the fake Supabase fail with a 503.
+    pub fn take_down_table(&self, table: &str) {
+        self.fakes.state.lock().expect("fake state lock").tables_down.push(table.to_string());
+    }
+}
+
+
+/// ## FakeSmtp
+/// A plain-text SMTP server on a random local port that accepts every email
+///
+/// ### Fields
+/// - `port` - The local port it listens on
+/// - `messages` - The `DATA` of every email it accepted
+#[derive(Debug, Clone)]
+pub struct FakeSmtp {
+    pub port: u16,
+    pub messages: Arc<Mutex<Vec<String>>>,
+}
+
+
+impl FakeSmtp {
+    /// # start
+    /// Binds a random local port and points `SMTP_HOST`, `SMTP_PORT` and `SMTP_TLS` at it.
+    pub async fn start() -> Self {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.expect("bind a local port");
+        let port: u16 = listener.local_addr().expect("bound address").port();
+        let messages: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+
+        let served_messages: Arc<Mutex<Vec<String>>> = messages.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_smtp(stream, served_messages.clone()));
+            }
+        });
+
+        env::set_var("SMTP_HOST", "127.0.0.1");
+        env::set_var("SMTP_PORT", port.to_string());
+        env::set_var("SMTP_TLS", "none");
+
+        FakeSmtp { port, messages }
+    }
+
+
+    /// # messages
+    /// The emails the server accepted.
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().expect("fake smtp lock").clone()
+    }
+}
+
+
+/// # handle_smtp
+/// Talks SMTP on one connection, replying `250` to every command and keeping the `DATA`.
+async fn handle_smtp(mut stream: TcpStream, messages: Arc<Mutex<Vec<String>>>) {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk: [u8; 4096] = [0; 4096];
+    let mut in_data: bool = false;
+
+    if stream.write_all(b"220 fake ESMTP\r\n").await.is_err() {
+        return;
+    }
+
+    loop {
+        // the data ends with a lone dot, commands with a line break
+        let end: &[u8] = if in_data { b"\r\n.\r\n" } else { b"\r\n" };
+
+        let position: usize = match buffer.windows(end.len()).position(|window| window == end) {
+            Some(position) => position,
+            None => match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(read) => {
+                    buffer.extend_from_slice(&chunk[..read]);
+                    continue;
+                },
+            },
+        };
+
+        let line: String = String::from_utf8_lossy(&buffer[..position]).to_string();
+        buffer.drain(..position + end.len());
+
+        let command: String = line.to_uppercase();
+        let reply: &[u8] = if in_data {
+            in_data = false;
+            messages.lock().expect("fake smtp lock").push(line);
+            b"250 queued\r\n"
+        } else if command.starts_with("EHLO") {
+            b"250 fake\r\n"
+        } else if command.starts_with("DATA") {
+            in_data = true;
+            b"354 go ahead\r\n"
+        } else if command.starts_with("QUIT") {
+            let _ = stream.write_all(b"221 bye\r\n").await;
+            return;
+        } else {
+            b"250 ok\r\n"
+        };
+
+        if stream.write_all(reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+
+/// # handle_connection
+/// Reads one HTTP request, answers it and closes the connection.
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<FakeState>>) {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk: [u8; 4096] = [0; 4096];
+
+    // read until the end of the headers
+    let header_end: usize = loop {
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+    };
+
+    let head: String = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+
+    let request_line: Vec<&str> = lines.next().unwrap_or_default().split_whitespace().collect();
+    let (method, target): (&str, &str) = match request_line.as_slice() {
+        [method, target, ..] => (method, target),
+        _ => return,
+    };
+
+    // repeated headers are joined like HTTP allows, supabase sends `Prefer` twice on upserts
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        headers
+            .entry(name.trim().to_lowercase())
+            .and_modify(|joined| *joined = format!("{}, {}", joined, value.trim()))
+            .or_insert_with(|| value.trim().to_string());
+    }
+
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + content_length {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+    }
+
+    let body: String = String::from_utf8_lossy(&buffer[header_end..]).to_string();
+
+    let (status, response): (u16, String) = route(method, target, &headers, &body, &state);
+
+    let etag: String = match (status, target.starts_with("/templates/")) {
+        (200, true) => format!("ETag: {}\r\n", template_etag(&response)),
+        _ => String::new(),
+    };
+
+    let reply: String = format!(
+        "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        etag,
+        response.len(),
+        response
+    );
+
+    let _ = stream.write_all(reply.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+
+/// # route
+/// Answers a request like Supabase, Resend or Discord would.
+fn route(
+    method: &str,
+    target: &str,
+    headers: &HashMap<String, String>,
+    body: &str,
+    state: &Arc<Mutex<FakeState>>,
+) -> (u16, String) {
+    let (path, query): (&str, &str) = target.split_once('?').unwrap_or((target, ""));
+    let mut state = state.lock().expect("fake state lock");
+
+    if let Some(table) = path.strip_prefix("/rest/v1/") {
+        return supabase(method, table, query, headers, body, &mut state);
+    }
+
+    if let Some(stripe_path) = path.strip_prefix("/stripe") {
+        state.stripe_requests.push(format!("{} {}", method, stripe_path));
+
+        return stripe(method, stripe_path, query, headers);
+    }
+
+    if path.starts_with("/templates/") {
+        return template(path, headers, &mut state);
+    }
+
+    if let Some(discord_path) = path.strip_prefix("/discord") {
+        state.discord_requests.push(format!("{} {}", method, discord_path));
+
+        return match (method, discord_path) {
+            ("GET", "/users/@me") => (200, json!({ "id": "1", "username": "fixture-bot" }).to_string()),
+            _ => (204, String::new()),
+        };
+    }
+
+    if (method, path) == ("POST", "/emails") {
+        state.email_idempotency_keys.push(headers.get("idempotency-key").cloned().unwrap_or_default());
+    }
+
+    match (method, path) {
+        ("GET", "/dns-query") => dns(query),
+        ("POST", "/emails") if !state.email_failures.is_empty() => {
+            let status: u16 = state.email_failures.remove(0);
+
+            (status, json!({ "name": "fake_failure", "message": format!("failed with {}", status) }).to_string())
+        },
+        ("POST", "/emails") => {
+            let email: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+            state.emails.push(email);
+
+            (200, json!({ "id": format!("email_{}", state.emails.len()) }).to_string())
+        },
+        ("GET", "/domains") => (200, json!({ "data": [] }).to_string()),
+        _ => (404, json!({ "message": "not found" }).to_string()),
+    }
+}
+
+
+/// # dns
+/// Answers like the JSON API of a DNS over HTTPS resolver: `missing.example` does not exist,
+/// `no-mail.example` has a null MX record, `a-only.example` only an A record and every other
+/// domain an MX record.
+fn dns(query: &str) -> (u16, String) {
+    let name: &str = query_param(query, "name").unwrap_or_default();
+    let record_type: &str = query_param(query, "type").unwrap_or_default();
+
+    let answer: Value = match (name, record_type) {
+        ("missing.example", _) => return (200, json!({ "Status": 3 }).to_string()),
+        ("no-mail.example", "MX") => json!([{ "name": name, "type": 15, "data": "0 ." }]),
+        ("a-only.example", "MX") => json!([]),
+        ("a-only.example", "A") => json!([{ "name": name, "type": 1, "data": "192.0.2.1" }]),
+        (_, "MX") => json!([{ "name": name, "type": 15, "data": format!("10 mx.{}.", name) }]),
+        _ => json!([]),
+    };
+
+    (200, json!({ "Status": 0, "Answer": answer }).to_string())
+}
+
+
+/// # supabase
+/// The subset of the PostgREST API `supabase_rs` uses: filtered selects, inserts, upserts,
+/// updates and deletes.
+fn supabase(
+    method: &str,
+    table: &str,
+    query: &str,
+    headers: &HashMap<String, String>,
+    body: &str,
+    state: &mut FakeState,
+) -> (u16, String) {
+    if state.tables_down.iter().any(|down| down == table) {
+        return (503, json!({ "message": format!("{} is unavailable", table) }).to_string());
+    }
+
+    let filters: Vec<(String, String, String)> = parse_filters(query);
+    let rows: &mut Vec<Value> = state.tables.entry(table.to_string()).or_default();
+
+    match method {
+        "GET" => {
+            let limit: usize = query_param(query, "limit").and_then(|limit| limit.parse().ok()).unwrap_or(usize::MAX);
+
+            let selected: Vec<Value> = rows
+                .iter()
+                .filter(|row| matches_filters(row, &filters))
+                .take(limit)
+                .cloned()
+                .collect();
+
+            (200, Value::Array(selected).to_string())
+        },
+        "POST" => {
+            let merge: bool = headers.get("prefer").is_some_and(|prefer| prefer.contains("merge-duplicates"));
+            let new_row: Value = normalize_id(serde_json::from_str(body).unwrap_or(json!({})));
+
+            let conflict: bool = !merge && UNIQUE_COLUMNS.iter().any(|(unique_table, column)| {
+                *unique_table == table && rows.iter().any(|row| row.get(*column).is_some() && row.get(*column) == new_row.get(*column))
+            });
+
+            if conflict {
+                return (409, json!({ "code": "23505", "message": "duplicate key value violates unique constraint" }).to_string());
+            }
+
+            let existing: Option<&mut Value> = rows
+                .iter_mut()
+                .find(|row| merge && row.get("id").is_some() && row.get("id") == new_row.get("id"));
+
+            match existing {
+                Some(existing) => merge_into(existing, &new_row),
+                None => rows.push(new_row.clone()),
+            }
+
+            (201, Value::Array(vec![new_row]).to_string())
+        },
+        "PATCH" => {
+            let changes: Value = normalize_id(serde_json::from_str(body).unwrap_or(json!({})));
+
+            for row in rows.iter_mut().filter(|row| matches_filters(row, &filters)) {
+                merge_into(row, &changes);
+            }
+
+            (204, String::new())
+        },
+        "DELETE" => {
+            rows.retain(|row| !matches_filters(row, &filters));
+
+            (204, String::new())
+        },
+        _ => (405, String::new()),
+    }
+}
+
+
+/// # parse_filters
+/// Parses `column=op.value` pairs, parameters without an operator such as `select` are skipped.
+fn parse_filters(query: &str) -> Vec<(String, String, String)> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter_map(|(column, filter)| {
+            let (operator, value) = filter.split_once('.')?;
+
+            matches!(operator, "eq" | "neq" | "gt" | "gte" | "lt" | "lte")
+                .then(|| (decode(column), operator.to_string(), decode(value)))
+        })
+        .collect()
+}
+
+
+/// # query_param
+/// The value of a plain query parameter.
+fn query_param<'q>(query: &'q str, name: &str) -> Option<&'q str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+
+/// # matches_filters
+/// Whether a row passes every filter, numbers are compared numerically.
+fn matches_filters(row: &Value, filters: &[(String, String, String)]) -> bool {
+    filters.iter().all(|(column, operator, expected)| {
+        let actual: String = match &row[column.as_str()] {
+            Value::String(text) => text.clone(),
+            Value::Null => "null".to_string(),
+            other => other.to_string(),
+        };
+
+        let ordering = match (actual.parse::<f64>(), expected.parse::<f64>()) {
+            (Ok(actual), Ok(expected)) => actual.partial_cmp(&expected),
+            _ => Some(actual.as_str().cmp(expected.as_str())),
+        };
+
+        match operator.as_str() {
+            "eq" => actual == *expected,
+            "neq" => actual != *expected,
+            "gt" => ordering.is_some_and(|ordering| ordering.is_gt()),
+            "gte" => ordering.is_some_and(|ordering| ordering.is_ge()),
+            "lt" => ordering.is_some_and(|ordering| ordering.is_lt()),
+            "lte" => ordering.is_some_and(|ordering| ordering.is_le()),
+            _ => false,
+        }
+    })
+}
+
+
+/// # normalize_id
+/// Stores numeric ids as numbers, `supabase_rs` sends them as strings on upserts.
+fn normalize_id(mut row: Value) -> Value {
+    if let Some(id) = row.get("id").and_then(|id| id.as_str()).and_then(|id| id.parse::<i64>().ok()) {
+        row["id"] = json!(id);
+    }
+
+    row
+}
+
+
+/// # merge_into
+/// Overwrites the columns of a row with the given changes.
+fn merge_into(row: &mut Value, changes: &Value) {
+    if let (Some(row), Some(changes)) = (row.as_object_mut(), changes.as_object()) {
+        for (column, value) in changes {
+            row.insert(column.clone(), value.clone());
+        }
+    }
+}
+
+
+/// # decode
+/// Percent-decodes a query component.
+fn decode(component: &str) -> String {
+    let bytes: &[u8] = component.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index: usize = 0;
+
+    while index < bytes.len() {
+        let escaped: Option<u8> = (bytes[index] == b'%' && index + 2 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[index + 1..index + 3]).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            },
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+
+/// # stripe
+/// Answers like the Stripe API, invoices are served with an `invoice_pdf` on the fake itself,
+/// every customer is the fixture customer and every checkout session and invoice bought the
+/// default test price. Only the payment intent of the checkout fixture has a checkout session.
+fn stripe(method: &str, path: &str, query: &str, headers: &HashMap<String, String>) -> (u16, String) {
+    let host: &str = headers.get("host").map(|host| host.as_str()).unwrap_or_default();
+
+    if path.starts_with("/v1/") && headers.get("authorization") != Some(&format!("Bearer {}", TEST_STRIPE_PRIVATE_KEY)) {
+        return (401, json!({ "error": { "message": "Invalid API Key provided" } }).to_string());
+    }
+
+    if let Some(customer_id) = path.strip_prefix("/v1/customers/") {
+        let customer: Value = json!({
+            "id": customer_id,
+            "object": "customer",
+            "email": "jenny.rosen@example.com",
+            "name": "Jenny Rosen",
+        });
+
+        return (200, customer.to_string());
+    }
+
+    if let Some(session_id) = path.strip_prefix("/v1/checkout/sessions/").and_then(|path| path.strip_suffix("/line_items")) {
+        let line_items: Value = json!({
+            "object": "list",
+            "has_more": false,
+            "url": format!("/v1/checkout/sessions/{}/line_items", session_id),
+            "data": [{
+                "id": "li_fixture",
+                "object": "item",
+                "price": { "id": "price_test_default", "object": "price", "product": "prod_test_default" },
+                "quantity": 1,
+            }],
+        });
+
+        return (200, line_items.to_string());
+    }
+
+    if path == "/v1/checkout/sessions" {
+        let sessions: Vec<Value> = match query_param(query, "payment_intent") {
+            Some(payment_intent) if payment_intent == "pi_3PfixturePaymentIntent01" => {
+                vec![json!({ "id": "cs_test_fixtureCheckoutSession01", "object": "checkout.session", "payment_intent": payment_intent })]
+            },
+            _ => Vec::new(),
+        };
+
+        return (200, json!({ "object": "list", "has_more": false, "url": "/v1/checkout/sessions", "data": sessions }).to_string());
+    }
+
+    if let Some(invoice_id) = path.strip_prefix("/v1/invoices/").and_then(|path| path.strip_suffix("/lines")) {
+        let lines: Value = json!({
+            "object": "list",
+            "has_more": false,
+            "url": format!("/v1/invoices/{}/lines", invoice_id),
+            "data": [{
+                "id": "il_fixture",
+                "object": "line_item",
+                "price": { "id": "price_test_default", "object": "price", "product": "prod_test_default" },
+                "quantity": 1,
+            }],
+        });
+
+        return (200, lines.to_string());
+    }
+
+    if let Some(invoice_id) = path.strip_prefix("/v1/invoices/") {
+        let invoice: Value = json!({
+            "id": invoice_id,
+            "object": "invoice",
+            "number": "FIXTURE-0001",
+            "invoice_pdf": format!("http://{}/stripe/invoices/{}.pdf", host, invoice_id),
+        });
+
+        return (200, invoice.to_string());
+    }
+
+    match (method, path.starts_with("/invoices/")) {
+        ("GET", true) => (200, INVOICE_PDF.to_string()),
+        _ => (404, json!({ "error": { "message": "not found" } }).to_string()),
+    }
+}
+
+
+/// # template
+/// Answers like a CDN, with a 304 when the `If-None-Match` matches the template.
+fn template(path: &str, headers: &HashMap<String, String>, state: &mut FakeState) -> (u16, String) {
+    let html: Option<String> = match path {
+        _ if state.templates.contains_key(path) => state.templates.get(path).cloned(),
+        "/templates/welcome.html" => Some(WELCOME_TEMPLATE.to_string()),
+        "/templates/payment_failed.html" => Some(PAYMENT_FAILED_TEMPLATE.to_string()),
+        _ => None,
+    };
+
+    let (status, response): (u16, String) = match html {
+        _ if state.templates_down => (503, "service unavailable".to_string()),
+        Some(html) if headers.get("if-none-match") == Some(&template_etag(&html)) => (304, String::new()),
+        Some(html) => (200, html),
+        None => (404, "not found".to_string()),
+    };
+
+    state.template_responses.push(status);
+
+    (status, response)
+}
+
+
+/// # template_etag
+/// The `ETag` the fake CDN serves a template with.
+fn template_etag(html: &str) -> String {
+    let digest: String = hex::encode(Sha256::digest(html.as_bytes()));
+
+    format!("\"{}\"", &digest[..16])
+}
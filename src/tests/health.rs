@@ -0,0 +1,68 @@
+//! ## Health tests
+//!
+//! ### Table of contents
+//! - Bounding a dependency check by its timeout
+//! - Deciding readiness from the individual checks
+//! - Reusing the handle of the Sled database across probes
+//!
+
+
+#[cfg(test)]
+mod readiness {
+    use crate::api::health::{run_check, sled_database, CheckStatus, DependencyCheck, Readiness};
+
+    use std::time::Duration;
+
+
+    #[tokio::test]
+    /// # slow_check_times_out
+    /// A check that does not finish within the limit is reported down.
+    async fn slow_check_times_out() {
+        let check: DependencyCheck = run_check("database", Duration::from_millis(20), async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok((CheckStatus::Up, None))
+        }).await;
+
+        assert_eq!(check.status, CheckStatus::Down);
+        assert_eq!(check.detail, Some("timed out after 20ms".to_string()));
+    }
+
+
+    #[tokio::test]
+    /// # skipped_checks_do_not_block_readiness
+    /// Only checks that are down make the service not ready.
+    async fn skipped_checks_do_not_block_readiness() {
+        let up: DependencyCheck = run_check("email", Duration::from_secs(1), async {
+            Ok((CheckStatus::Up, Some("resend".to_string())))
+        }).await;
+        let skipped: DependencyCheck = run_check("discord", Duration::from_secs(1), async {
+            Ok((CheckStatus::Skipped, None))
+        }).await;
+        let down: DependencyCheck = run_check("database", Duration::from_secs(1), async {
+            Err("SUPABASE_KEY is not set".to_string())
+        }).await;
+
+        assert!(Readiness::from_checks(vec![up.clone(), skipped.clone()]).ready);
+        assert!(!Readiness::from_checks(vec![up, skipped, down]).ready);
+    }
+
+
+    #[test]
+    /// # reuses_sled_handle
+    /// Every probe gets the handle opened by the first one, sled would refuse a second open of the
+    /// locked directory.
+    fn reuses_sled_handle() {
+        let path: String = std::env::temp_dir()
+            .join(format!("stripe_discord_readyz_{}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        let first: sled::Db = sled_database(&path).expect("sled opens");
+        first.insert("probe", "up").expect("sled writes");
+
+        let second: sled::Db = sled_database(&path).expect("sled is reused");
+        assert_eq!(second.get("probe").expect("sled reads").as_deref(), Some(&b"up"[..]));
+
+        std::fs::remove_dir_all(&path).ok();
+    }
+}
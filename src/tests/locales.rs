@@ -0,0 +1,155 @@
+//! ## Localized email tests
+//!
+//! ### Table of contents
+//! - Picking the locale from the session locale, the billing country or the default
+//! - Writing amounts and dates per locale
+//! - Translating subjects and templates from the config and the templates directory
+//! - Sending a receipt in the locale of the billing country
+//!
+
+
+#[cfg(test)]
+mod email_locales {
+    use crate::email::locale::{EmailTranslation, Locale};
+    use crate::email::templates::source::TemplateSource;
+    use crate::events::fixtures::fixture;
+    use crate::events::EventHandler;
+    use crate::organization::model::EmailEvent;
+    use crate::tests::harness::Harness;
+    use crate::utils::money::Money;
+    use crate::{EmailConfig, EmailEventConfig, Organization};
+
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+    use supabase_rs::SupabaseClient;
+
+
+    /// # locale
+    /// A locale that is known to parse.
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).expect("a valid locale")
+    }
+
+
+    #[test]
+    /// # resolves_locales
+    /// The session locale wins unless it is `auto`, then the billing country, then the default.
+    fn resolves_locales() {
+        assert_eq!(Locale::parse("pt_br"), Some(locale("pt-BR")));
+        assert_eq!(Locale::parse("DE"), Some(locale("de")));
+        assert_eq!(Locale::parse("auto"), None);
+        assert_eq!(Locale::parse("english"), None);
+        assert_eq!(Locale::parse("de-1996"), None);
+
+        assert_eq!(Locale::resolve(Some("fr"), Some("DE"), "en"), locale("fr"));
+        assert_eq!(Locale::resolve(Some("auto"), Some("DE"), "en"), locale("de"));
+        assert_eq!(Locale::resolve(None, Some("br"), "en"), locale("pt-BR"));
+        assert_eq!(Locale::resolve(None, Some("JP"), "nl"), locale("nl"));
+        assert_eq!(Locale::resolve(None, None, "not a locale"), locale("en"));
+
+        assert_eq!(locale("pt-BR").candidates(), vec!["pt-BR", "pt"]);
+        assert_eq!(locale("de").candidates(), vec!["de"]);
+
+        let organization: Organization = Organization::new(
+            "Xylex".to_string(),
+            EmailConfig::new("billing@xylex.ai".to_string(), "Welcome!".to_string(), "welcome".to_string()),
+        )
+        .with_default_locale("it");
+        assert_eq!(organization.locale(None, None), locale("it"));
+    }
+
+
+    #[test]
+    /// # formats_per_locale
+    /// Amounts get the separators and symbol position of the locale, dates its order.
+    fn formats_per_locale() {
+        assert_eq!(Money::new(123456, "eur").format(&locale("en")), "€1,234.56");
+        assert_eq!(Money::new(1999, "usd").format(&locale("en-US")), "$19.99");
+        assert_eq!(Money::new(1234567, "chf").format(&locale("en")), "12,345.67 CHF");
+        assert_eq!(Money::new(123456, "eur").format(&locale("de")), "1.234,56 €");
+        assert_eq!(Money::new(123456, "eur").format(&locale("nl")), "€ 1.234,56");
+        assert_eq!(Money::new(123456, "eur").format(&locale("fr")), "1 234,56 €");
+        assert_eq!(Money::new(-500, "gbp").format(&locale("pt-BR")), "-5,00 £");
+
+        assert_eq!(locale("en").format_date(1714000000), "April 24, 2024");
+        assert_eq!(locale("de").format_date(1714000000), "24.04.2024");
+        assert_eq!(locale("nl").format_date(1714000000), "24-04-2024");
+        assert_eq!(locale("es").format_date(1714000000), "24/04/2024");
+        assert_eq!(locale("ja").format_date(1714000000), "April 24, 2024");
+    }
+
+
+    #[tokio::test]
+    /// # translates_emails
+    /// Translations are read from `Locales`, the most specific one wins, and named templates are
+    /// looked up in the directory of the locale first.
+    async fn translates_emails() {
+        let value: Value = json!({
+            "Email": {
+                "Receipt": {
+                    "TemplateUrl": "receipt",
+                    "Locales": {
+                        "pt": { "Subject": "O seu recibo", "TemplateUrl": "recibo" },
+                        "pt-BR": { "Subject": "Seu recibo" },
+                        "not a locale": { "Subject": "skipped" },
+                    },
+                },
+            }
+        });
+
+        let emails: Vec<EmailEventConfig> = EmailEventConfig::from_config(&value);
+        let receipt: &EmailEventConfig = emails.iter().find(|email| email.event == EmailEvent::Receipt).unwrap();
+        assert_eq!(receipt.translations.len(), 2);
+
+        let default: EmailConfig = EmailConfig::new("billing@xylex.ai".to_string(), "Your receipt".to_string(), "receipt".to_string());
+        let email_config: EmailConfig = receipt.to_email_config(&default);
+
+        let brazilian: EmailConfig = email_config.localized(&locale("pt-BR"));
+        assert_eq!(brazilian.subject, "Seu recibo");
+        assert_eq!(brazilian.template_url, "recibo");
+        assert_eq!(brazilian.locale, Some(locale("pt-BR")));
+
+        let english: EmailConfig = email_config.localized(&locale("en"));
+        assert_eq!(english.subject, "Your receipt");
+        assert_eq!(english.template_url, "receipt");
+
+        let dir: PathBuf = std::env::temp_dir().join(format!("stripe-discord-locales-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("de")).expect("create the locale directory");
+        std::fs::write(dir.join("de").join("welcome.html"), "<p>Willkommen</p>").expect("write the german template");
+        std::fs::write(dir.join("welcome.html"), "<p>Welcome</p>").expect("write the template");
+
+        let welcome: TemplateSource = TemplateSource::parse("welcome");
+        assert_eq!(welcome.load_localized(&dir, &locale("de-AT").candidates()).await.unwrap(), "<p>Willkommen</p>");
+        assert_eq!(welcome.load_localized(&dir, &locale("fr").candidates()).await.unwrap(), "<p>Welcome</p>");
+        assert!(TemplateSource::parse("receipt").load_localized(&dir, &["de".to_string()]).await.unwrap().contains("{{ReceiptNumber}}"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+
+    #[tokio::test]
+    /// # sends_localized_receipt
+    /// A charge billed in Germany sends the German subject with German amounts and dates.
+    async fn sends_localized_receipt() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        let receipt: EmailConfig = EmailConfig::new("billing@xylex.ai".to_string(), "Your receipt".to_string(), "receipt".to_string())
+            .with_translation("de", EmailTranslation { subject: Some("Ihre Quittung".to_string()), ..EmailTranslation::default() });
+
+        let organization: Organization = Organization::new(
+            "Xylex".to_string(),
+            EmailConfig::new("billing@xylex.ai".to_string(), "Welcome!".to_string(), "welcome".to_string()),
+        )
+        .with_email(EmailEvent::Receipt, receipt);
+
+        let mut charge: Value = fixture("charge.succeeded").expect("a charge fixture");
+        charge["data"]["object"]["billing_details"]["address"]["country"] = json!("DE");
+        EventHandler::new(&charge, organization, supabase).await.expect("handled");
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0]["subject"], "Ihre Quittung");
+        assert!(emails[0]["text"].as_str().unwrap().contains("50,00 € for Xylex on 24.04.2024"));
+    }
+}
@@ -0,0 +1,68 @@
+//! ## Logging tests
+//!
+//! ### Table of contents
+//! - Redacting emails, names and secrets
+//! - Redacting webhook payloads
+//!
+
+
+#[cfg(test)]
+mod redact {
+    use crate::log::redact::{redact_email, redact_name, redact_payload, redact_secret};
+
+    use serde_json::{json, Value};
+
+
+    #[test]
+    /// # redacts_values
+    /// Keeps just enough of a value to recognise it.
+    fn redacts_values() {
+        assert_eq!(redact_email("floris@xylex.ai"), "f***@xylex.ai");
+        assert_eq!(redact_email("not-an-email"), "***");
+        assert_eq!(redact_name("Floris Xylex"), "F*** X***");
+        assert_eq!(redact_name(""), "***");
+        assert_eq!(redact_secret("sk_live_51H8abc"), "sk_live_***");
+        assert_eq!(redact_secret("abc123"), "***");
+
+        // underscores in the secret itself never keep more than the prefix
+        assert_eq!(redact_secret("sk_live_51H8_abc_def"), "sk_live_***");
+        assert_eq!(redact_secret("re_123_abc_456"), "re_***");
+        assert_eq!(redact_secret("supabasekey_abc"), "***");
+        assert_eq!(redact_secret("abc123_def_ghi"), "abc123_***");
+    }
+
+
+    #[test]
+    /// # redacts_payload
+    /// Redacts nested personal data and secrets of a Stripe event but keeps everything else.
+    fn redacts_payload() {
+        let event: Value = json!({
+            "id": "evt_1",
+            "type": "charge.succeeded",
+            "data": {
+                "object": {
+                    "amount": 5000,
+                    "client_secret": "pi_1_secret_abc",
+                    "billing_details": {
+                        "email": "floris@xylex.ai",
+                        "name": "Floris Xylex",
+                        "phone": "+31600000000",
+                        "address": { "country": "NL", "line1": "Damrak 1" }
+                    }
+                }
+            }
+        });
+
+        let redacted: Value = redact_payload(&event);
+        let object: &Value = &redacted["data"]["object"];
+
+        assert_eq!(redacted["id"], "evt_1");
+        assert_eq!(object["amount"], 5000);
+        assert_eq!(object["client_secret"], "pi_***");
+        assert_eq!(object["billing_details"]["email"], "f***@xylex.ai");
+        assert_eq!(object["billing_details"]["name"], "F*** X***");
+        assert_eq!(object["billing_details"]["phone"], "***");
+        assert_eq!(object["billing_details"]["address"]["country"], "NL");
+        assert_eq!(object["billing_details"]["address"]["line1"], "***");
+    }
+}
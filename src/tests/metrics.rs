@@ -0,0 +1,52 @@
+//! ## Metrics tests
+//!
+//! ### Table of contents
+//! - Rendering observed metrics in the Prometheus text format
+//! - Labelling unhandled event types as `other`
+//!
+
+
+#[cfg(test)]
+mod prometheus {
+    use crate::metrics::{
+        observe_db_operation,
+        observe_discord_request,
+        observe_email,
+        observe_webhook,
+        render_metrics
+    };
+
+
+    #[test]
+    /// # renders_observed_metrics
+    /// Every observed metric shows up with its labels.
+    fn renders_observed_metrics() {
+        observe_webhook("charge.succeeded", "handled");
+        observe_email("resend", false);
+        observe_discord_request(Some(429));
+        observe_discord_request(None);
+        drop(observe_db_operation("update_paid"));
+
+        let rendered: String = render_metrics();
+
+        assert!(rendered.contains(r#"stripe_webhooks_total{event_type="charge.succeeded",outcome="handled"}"#));
+        assert!(rendered.contains(r#"emails_sent_total{provider="resend",result="failed"}"#));
+        assert!(rendered.contains(r#"discord_api_requests_total{status="429"}"#));
+        assert!(rendered.contains(r#"discord_api_requests_total{status="error"}"#));
+        assert!(rendered.contains(r#"db_operation_duration_seconds_count{operation="update_paid"}"#));
+        assert!(rendered.contains("background_tasks_in_flight"));
+    }
+
+
+    #[test]
+    /// # bounds_event_type_label
+    /// Event types we do not handle share the `other` label, whatever the sender made up.
+    fn bounds_event_type_label() {
+        observe_webhook("made.up.type_1714000000", "ignored");
+
+        let rendered: String = render_metrics();
+
+        assert!(rendered.contains(r#"stripe_webhooks_total{event_type="other",outcome="ignored"}"#));
+        assert!(!rendered.contains("made.up.type_1714000000"));
+    }
+}
@@ -1,3 +1,28 @@
 //! This module contains all the tests for the Stripe.
 
+pub mod access;
+pub mod addresses;
+pub mod admin;
+pub mod auth;
 pub mod base;
+pub mod content;
+pub mod deliveries;
+pub mod drips;
+pub mod endpoints;
+pub mod events;
+#[cfg(test)]
+pub mod harness;
+pub mod health;
+pub mod locales;
+pub mod log;
+pub mod metrics;
+pub mod money;
+pub mod webhooks;
+pub mod receipts;
+pub mod reminders;
+pub mod replay;
+pub mod retries;
+pub mod routing;
+pub mod secrets;
+pub mod suppressions;
+pub mod templates;
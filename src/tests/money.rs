@@ -0,0 +1,111 @@
+//! ## Money tests
+//!
+//! ### Table of contents
+//! - Currency exponents for zero, two and three decimal currencies
+//! - Writing amounts as decimals, numbers and per locale
+//! - Storing `amount_total` and refunds in minor units of the charged currency
+//! - Reading legacy `amount_total` rows in major units
+//!
+
+
+#[cfg(test)]
+mod amounts {
+    use crate::email::locale::Locale;
+    use crate::events::fixtures::fixture;
+    use crate::tests::harness::Harness;
+    use crate::utils::money::Money;
+    use crate::CustomerId;
+
+    use rocket::http::Status;
+    use serde_json::{json, Value};
+
+
+    #[test]
+    /// # handles_exponents
+    /// Zero decimal currencies have no fraction, three decimal ones three digits of it.
+    fn handles_exponents() {
+        assert_eq!(Money::new(1999, "USD"), Money::new(1999, "usd"));
+        assert_eq!(Money::new(1999, "usd").exponent(), 2);
+        assert_eq!(Money::new(1999, "jpy").exponent(), 0);
+        assert_eq!(Money::new(1999, "kwd").exponent(), 3);
+        assert_eq!(Money::new(1999, "mga").exponent(), 0);
+
+        // Stripe sends these in hundredths although ISO 4217 gives them no minor unit
+        assert_eq!(Money::new(100000, "isk").to_decimal(), "1000.00");
+        assert_eq!(Money::new(100000, "ugx").to_decimal(), "1000.00");
+
+        assert_eq!(Money::new(1999, "usd").to_decimal(), "19.99");
+        assert_eq!(Money::new(5, "eur").to_decimal(), "0.05");
+        assert_eq!(Money::new(-250, "eur").to_decimal(), "-2.50");
+        assert_eq!(Money::new(1999, "jpy").to_decimal(), "1999");
+        assert_eq!(Money::new(1250, "kwd").to_decimal(), "1.250");
+
+        assert_eq!(Money::new(1999, "usd").to_string(), "19.99 USD");
+        assert_eq!(Money::new(1250, "bhd").to_string(), "1.250 BHD");
+    }
+
+
+    #[test]
+    /// # formats_currencies
+    /// Locales write the digits of every currency with their own separators.
+    fn formats_currencies() {
+        let english: Locale = Locale::parse("en").unwrap();
+        let german: Locale = Locale::parse("de").unwrap();
+
+        assert_eq!(Money::new(123456, "jpy").format(&english), "¥123,456");
+        assert_eq!(Money::new(123456, "jpy").format(&german), "123.456 ¥");
+        assert_eq!(Money::new(1234567, "kwd").format(&english), "1,234.567 KWD");
+        assert_eq!(Money::new(1234567, "kwd").format(&german), "1.234,567 KWD");
+
+        let invoice: Value = json!({ "amount_due": 4900, "currency": "GBP" });
+        assert_eq!(Money::from_stripe(&invoice, "amount_due"), Money::new(4900, "gbp"));
+        assert_eq!(Money::from_stripe(&json!({}), "amount_due"), Money::new(0, "usd"));
+    }
+
+
+    #[tokio::test]
+    /// # stores_amount_total
+    /// A yen charge is stored as whole yen, not divided by 100, and so are its refunds on the
+    /// customer and in the audit trail.
+    async fn stores_amount_total() {
+        let harness: Harness = Harness::start().await;
+
+        let mut charge: Value = fixture("charge.succeeded").expect("a charge fixture");
+        charge["data"]["object"]["currency"] = json!("jpy");
+        charge["data"]["object"]["amount_captured"] = json!(4500);
+        assert_eq!(harness.send_event(&charge).await, Status::Ok);
+
+        let rows: Vec<Value> = harness.rows("stripe_customer_data");
+        assert_eq!(rows[0]["amount_total_minor"], json!(4500));
+        assert!(rows[0].get("amount_total").is_none());
+        assert_eq!(rows[0]["currency"], "jpy");
+
+        let mut refund: Value = charge.clone();
+        refund["id"] = json!("evt_refund_jpy");
+        refund["type"] = json!("charge.refunded");
+        refund["data"]["object"]["amount_refunded"] = json!(1500);
+        assert_eq!(harness.send_event(&refund).await, Status::Ok);
+
+        let rows: Vec<Value> = harness.rows("stripe_customer_data");
+        assert_eq!(rows[0]["amount_refunded"], json!(1500));
+
+        let audit: Vec<Value> = harness.rows("stripe_customer_audit");
+        assert_eq!(audit[0]["amount"], json!(1500));
+        assert_eq!(audit[0]["currency"], "jpy");
+    }
+
+
+    #[test]
+    /// # reads_legacy_amount_total
+    /// Rows from before `amount_total_minor` keep major units in `amount_total`, also whole ones,
+    /// and rows without a currency were charged in `usd`.
+    fn reads_legacy_amount_total() {
+        assert_eq!(CustomerId::amount_total_of(&json!({ "amount_total": 19.99 })), Some(Money::new(1999, "usd")));
+        assert_eq!(CustomerId::amount_total_of(&json!({ "amount_total": 20 })), Some(Money::new(2000, "usd")));
+        assert_eq!(CustomerId::amount_total_of(&json!({ "amount_total": 4500.0, "currency": "jpy" })), Some(Money::new(4500, "jpy")));
+
+        let migrated: Value = json!({ "amount_total": 19.99, "amount_total_minor": 2500, "currency": "eur" });
+        assert_eq!(CustomerId::amount_total_of(&migrated), Some(Money::new(2500, "eur")));
+        assert_eq!(CustomerId::amount_total_of(&json!({ "currency": "eur" })), None);
+    }
+}
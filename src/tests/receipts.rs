@@ -0,0 +1,144 @@
+//! ## Receipt attachment tests
+//!
+//! ### Table of contents
+//! - Formatting payment dates
+//! - Reading receipts from charges and checkout sessions and rendering them as PDF
+//! - Attaching the Stripe invoice PDF or a generated receipt
+//!
+
+
+#[cfg(test)]
+mod receipt_pdfs {
+    use crate::email::attachments::{receipt_attachments, EmailAttachment};
+    use crate::email::receipt::Receipt;
+    use crate::events::fixtures::fixture;
+    use crate::tests::harness::{Harness, INVOICE_PDF};
+    use crate::utils::format::format_date;
+    use crate::{EmailConfig, Organization};
+
+    use serde_json::{json, Value};
+
+
+    /// # organization
+    /// An Organization with receipts turned on or off.
+    fn organization(attach_receipts: bool) -> Organization {
+        Organization::new(
+            "Xylex".to_string(),
+            EmailConfig::new("billing@xylex.ai".to_string(), "Welcome!".to_string(), "welcome".to_string()),
+        )
+        .with_receipts(attach_receipts)
+    }
+
+
+    /// # object
+    /// The `data.object` of a fixture.
+    fn object(event_type: &str) -> Value {
+        fixture(event_type).expect("a fixture exists for the event type")["data"]["object"].clone()
+    }
+
+
+    #[test]
+    /// # formats_dates
+    /// Unix timestamps become UTC dates, leap days included.
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1714000000), "2024-04-24");
+        assert_eq!(format_date(951825600), "2000-02-29");
+        assert_eq!(format_date(1735689599), "2024-12-31");
+    }
+
+
+    #[test]
+    /// # renders_charge_receipt
+    /// A charge becomes a receipt whose PDF has a valid cross-reference table.
+    fn renders_charge_receipt() {
+        let receipt: Receipt = Receipt::from_charge(&object("charge.succeeded"), "Xylex");
+
+        assert_eq!(receipt.receipt_number, "ch_3PfixtureCharge01");
+        assert_eq!(receipt.product, "Xylex");
+        assert_eq!(receipt.amount, 5000);
+        assert_eq!(receipt.filename(), "receipt-ch_3PfixtureCharge01.pdf");
+        assert_eq!(receipt.lines(), vec![
+            "Receipt number: ch_3PfixtureCharge01",
+            "Date paid: 2024-04-24",
+            "Billed to: Jenny Rosen <jenny.rosen@example.com>",
+            "",
+            "Product: Xylex",
+            "Amount paid: 50.00 EUR",
+        ]);
+
+        let pdf: String = String::from_utf8(receipt.to_pdf()).expect("the receipt is ASCII");
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        assert!(pdf.contains("(Receipt from Xylex) Tj"));
+        assert!(pdf.contains("(Amount paid: 50.00 EUR) Tj"));
+
+        // every entry of the cross-reference table points at its object
+        let startxref: usize = pdf.rsplit("startxref\n").next().and_then(|tail| tail.lines().next()).and_then(|offset| offset.parse().ok()).expect("startxref");
+        assert!(pdf[startxref..].starts_with("xref\n0 7\n"));
+
+        for (index, entry) in pdf[startxref..].lines().skip(3).take(6).enumerate() {
+            let offset: usize = entry[..10].parse().expect("an offset");
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", index + 1)));
+        }
+    }
+
+
+    #[test]
+    /// # reads_checkout_receipt
+    /// Checkout sessions use their line items or metadata as the product, PDF text is escaped.
+    fn reads_checkout_receipt() {
+        let mut session: Value = object("checkout.session.completed");
+
+        let receipt: Receipt = Receipt::from_checkout_session(&session, "Xylex");
+        assert_eq!(receipt.receipt_number, "pi_3PfixturePaymentIntent01");
+        assert_eq!(receipt.customer_name, "Jenny Rosen");
+        assert_eq!(receipt.product, "Xylex");
+        assert_eq!(receipt.created_at, 1713999980);
+
+        session["metadata"] = json!({ "product": "Pro (yearly)" });
+        assert_eq!(Receipt::from_checkout_session(&session, "Xylex").product, "Pro (yearly)");
+
+        session["line_items"] = json!({ "data": [{ "description": "Diamant AI Pro – €" }] });
+        let receipt: Receipt = Receipt::from_checkout_session(&session, "Xylex");
+        assert_eq!(receipt.product, "Diamant AI Pro – €");
+
+        let pdf: String = String::from_utf8(receipt.to_pdf()).expect("the receipt is ASCII");
+        assert!(pdf.contains("(Product: Diamant AI Pro ? ?) Tj"));
+
+        session["metadata"] = json!({ "product": "Pro (yearly)" });
+        session["line_items"] = Value::Null;
+        let pdf: String = String::from_utf8(Receipt::from_checkout_session(&session, "Xylex").to_pdf()).expect("ASCII");
+        assert!(pdf.contains("(Product: Pro \\(yearly\\)) Tj"));
+    }
+
+
+    #[tokio::test]
+    /// # attaches_receipts
+    /// The invoice PDF is attached when the session has an invoice, a generated receipt otherwise
+    /// or when the invoice can not be retrieved, and nothing when receipts are off.
+    async fn attaches_receipts() {
+        let harness: Harness = Harness::start().await;
+        let mut session: Value = object("checkout.session.completed");
+
+        assert!(receipt_attachments(&session, &organization(false)).await.is_empty());
+
+        let generated: Vec<EmailAttachment> = receipt_attachments(&session, &organization(true)).await;
+        assert_eq!(generated.len(), 1);
+        assert_eq!(generated[0].filename, "receipt-pi_3PfixturePaymentIntent01.pdf");
+        assert_eq!(generated[0].content_type, "application/pdf");
+        assert!(generated[0].content.starts_with(b"%PDF-1.4"));
+
+        session["invoice"] = json!("in_1PfixtureInvoice01");
+        let invoice: Vec<EmailAttachment> = receipt_attachments(&session, &organization(true)).await;
+        assert_eq!(invoice, vec![EmailAttachment::pdf("invoice-FIXTURE-0001.pdf".to_string(), INVOICE_PDF.as_bytes().to_vec())]);
+
+        let stripe_requests: Vec<String> = harness.fakes.state.lock().unwrap().stripe_requests.clone();
+        assert_eq!(stripe_requests, vec!["GET /v1/invoices/in_1PfixtureInvoice01", "GET /invoices/in_1PfixtureInvoice01.pdf"]);
+
+        // a key of another account is rejected, the generated receipt is attached instead
+        let other_account: Organization = organization(true).with_stripe_private_key(crate::secrets::Secret::new("sk_test_other".to_string()));
+        let fallback: Vec<EmailAttachment> = receipt_attachments(&session, &other_account).await;
+        assert_eq!(fallback[0].filename, "receipt-pi_3PfixturePaymentIntent01.pdf");
+    }
+}
@@ -0,0 +1,291 @@
+//! ## Renewal and trial ending reminder tests
+//!
+//! ### Table of contents
+//! - Reading `TrialEnding` and the reminder window from `stripe_discord.yaml`
+//! - Sending one reminder per period, from the webhooks or the scan of `end_time`
+//! - Settling periods that do not renew without a reminder
+//! - Walking the subscription fixtures through their trial, renewal and cancellation
+//!
+
+
+#[cfg(test)]
+mod period_reminders {
+    use crate::background::scheduler::run_reminder_scan;
+    use crate::events::fixtures::fixture;
+    use crate::db::operations::period_reminder::PeriodReminder;
+    use crate::events::test_event::TestEvent;
+    use crate::events::EventHandler;
+    use crate::organization::model::EmailEvent;
+    use crate::organization::router::{organization_for_endpoint, organization_from_config};
+    use crate::tests::harness::Harness;
+    use crate::{ConfigSetup, CustomerId, EmailConfig, EndpointConfigStripe, Organization};
+
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use supabase_rs::SupabaseClient;
+
+
+    /// A day in seconds
+    const DAY: i64 = 86400;
+
+
+    /// # organization
+    /// An Organization with the welcome email off, the renewal reminder and trial ending emails on
+    /// and customers renewing within a week reminded by the scan.
+    fn organization() -> Organization {
+        let email = |subject: &str, template: &str| -> EmailConfig {
+            EmailConfig::new("billing@xylex.ai".to_string(), subject.to_string(), template.to_string())
+        };
+
+        Organization::new("Xylex".to_string(), email("Welcome!", "welcome"))
+            .without_email(EmailEvent::Welcome)
+            .with_email(EmailEvent::RenewalReminder, email("Xylex renews soon", "renewal_reminder"))
+            .with_email(EmailEvent::TrialEnding, email("Your Xylex trial ends soon", "trial_ending"))
+            .with_reminder_window(Duration::from_secs(7 * 86400))
+    }
+
+
+    /// # subscription
+    /// A subscription event for an address, with a period that does or does not renew.
+    fn subscription(event_type: &str, email: &str, cancel_at_period_end: bool) -> Value {
+        let mut event: Value = TestEvent::new(event_type)
+            .unwrap()
+            .with_email(email.to_string())
+            .with_amount(1999)
+            .with_currency("usd".to_string())
+            .build();
+
+        event["data"]["object"]["cancel_at_period_end"] = json!(cancel_at_period_end);
+
+        event
+    }
+
+
+    /// # charge
+    /// A successful charge that creates the paid customer of an address.
+    fn charge(email: &str, amount: i64, currency: &str) -> Value {
+        TestEvent::new("charge.succeeded")
+            .unwrap()
+            .with_email(email.to_string())
+            .with_amount(amount)
+            .with_currency(currency.to_string())
+            .build()
+    }
+
+
+    /// # handle_fixture
+    /// Handles the fixture of an event type for the Organization.
+    async fn handle_fixture(supabase: &SupabaseClient, event_type: &str) -> Result<EventHandler, String> {
+        EventHandler::new(&fixture(event_type).unwrap(), organization(), supabase.clone()).await
+    }
+
+
+    /// # customer
+    /// The customer row of an address.
+    fn customer(harness: &Harness, email: &str) -> Value {
+        harness.rows("stripe_customer_data")
+            .into_iter()
+            .find(|row| row["email"] == email)
+            .expect("a customer row")
+    }
+
+
+    #[test]
+    /// # reads_reminder_config
+    /// `trial_will_end` sends `TrialEnding`, `WindowDays` sets the window of the scan and only the
+    /// base Organization scans the shared customers. Stored amounts are read back in the minor
+    /// units of their currency.
+    fn reads_reminder_config() {
+        assert_eq!(EmailEvent::for_event_type("customer.subscription.trial_will_end"), Some(EmailEvent::TrialEnding));
+        assert_eq!(EmailEvent::TrialEnding.config_key(), "TrialEnding");
+        assert_eq!("trial_ending".parse(), Ok(EmailEvent::TrialEnding));
+
+        let config: ConfigSetup = ConfigSetup { renewal_reminder_window_days: Some(7), ..ConfigSetup::default() };
+        let base: Organization = organization_from_config(&config);
+        assert_eq!(base.reminder_window, Some(Duration::from_secs(7 * 86400)));
+        assert_eq!(organization_from_config(&ConfigSetup::default()).reminder_window, None);
+
+        let endpoint: EndpointConfigStripe = EndpointConfigStripe {
+            endpoint_route: "/stripe_webhooks/diamant".to_string(),
+            name: "Diamant".to_string(),
+            sender_email: "billing@diamant.ai".to_string(),
+            stripe_publish_key: String::new(),
+            stripe_webhook_secret: String::new(),
+            stripe_private_key: String::new(),
+            email_template_path: "welcome".to_string(),
+            discord_client_id: String::new(),
+            discord_application_id: String::new(),
+            discord_role_id: 0,
+            discord_guild_id: 0,
+            discord_bot_token: String::new(),
+            replace_keys_with_env_names: false,
+            attach_receipts: None,
+        };
+        assert_eq!(organization_for_endpoint(&base, &endpoint).reminder_window, None);
+    }
+
+
+    #[tokio::test]
+    /// # sends_one_reminder_per_period
+    /// The trial ending email settles the period, so the upcoming invoice of the same renewal and
+    /// the scan skip it, but not the periods after it. The scan reminds the other paid customers
+    /// renewing within the window once, with the amount they paid.
+    async fn sends_one_reminder_per_period() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        assert!(matches!(EventHandler::new(&charge("jane@example.com", 1999, "usd"), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+        assert!(matches!(EventHandler::new(&charge("kenji@example.com", 1999, "jpy"), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+
+        let trial: Value = subscription("customer.subscription.trial_will_end", "jane@example.com", false);
+        let trial_end: i64 = trial["data"]["object"]["trial_end"].as_i64().unwrap();
+        assert!(matches!(EventHandler::new(&trial, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionTrialWillEnd)));
+        assert_eq!(customer(&harness, "jane@example.com")["end_time"], trial_end);
+
+        // Stripe attempts the first payment an hour after the trial ends
+        let upcoming: Value = json!({
+            "id": "evt_test_upcoming",
+            "type": "invoice.upcoming",
+            "created": trial_end - DAY,
+            "data": { "object": {
+                "object": "invoice",
+                "customer_email": "jane@example.com",
+                "customer_name": "Jane Doe",
+                "amount_due": 1999,
+                "currency": "usd",
+                "next_payment_attempt": trial_end + 3600,
+            } },
+        });
+        assert!(matches!(EventHandler::new(&upcoming, organization(), supabase.clone()).await, Ok(EventHandler::InvoiceUpcoming)));
+
+        let renewal: Value = subscription("customer.subscription.created", "kenji@example.com", false);
+        let renewal_end: i64 = renewal["data"]["object"]["current_period_end"].as_i64().unwrap();
+        assert!(matches!(EventHandler::new(&renewal, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionCreated)));
+
+        // both bounds apply, periods that ended before the window are not scanned
+        let ending: Vec<Value> = CustomerId::list_ending_between(renewal_end + DAY, renewal_end + 2 * DAY, supabase.clone()).await.unwrap();
+        assert!(ending.is_empty());
+        assert!(PeriodReminder::find_for_period("jane@example.com", trial_end + 30 * DAY, supabase.clone()).await.unwrap().is_none());
+
+        assert_eq!(run_reminder_scan(&[organization()], renewal_end - 8 * DAY).await, Ok(0));
+        assert_eq!(run_reminder_scan(&[organization()], renewal_end - 3 * DAY).await, Ok(1));
+        assert_eq!(run_reminder_scan(&[organization()], renewal_end - 2 * DAY).await, Ok(0));
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 2);
+
+        assert_eq!(emails[0]["subject"], "Your Xylex trial ends soon");
+        assert_eq!(emails[0]["to"], json!(["jane@example.com"]));
+        assert!(emails[0]["text"].as_str().unwrap().contains("after that $19.99 will be charged"));
+
+        assert_eq!(emails[1]["subject"], "Xylex renews soon");
+        assert_eq!(emails[1]["to"], json!(["kenji@example.com"]));
+        assert!(emails[1]["text"].as_str().unwrap().contains("and ¥ 1.999 will be charged"));
+
+        let reminders: Vec<Value> = harness.rows("stripe_period_reminders");
+        assert_eq!(reminders.len(), 2);
+        assert_eq!(reminders[0]["template"], "trial_ending");
+        assert_eq!(reminders[0]["source"], trial["id"]);
+        assert_eq!(reminders[0]["period_end"], trial_end);
+        assert_eq!(reminders[1]["template"], "renewal_reminder");
+        assert_eq!(reminders[1]["source"], "scan");
+        assert_eq!(reminders[1]["status"], "sent");
+        assert_eq!(reminders[1]["message_id"], "email_2");
+    }
+
+
+    #[tokio::test]
+    /// # skips_periods_without_renewal
+    /// A subscription that cancels at the end of its period gets no reminder from the scan or an
+    /// upcoming invoice, and neither do unpaid customers or an Organization without a window.
+    async fn skips_periods_without_renewal() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        assert!(matches!(EventHandler::new(&charge("jane@example.com", 1999, "usd"), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+        assert!(matches!(EventHandler::new(&charge("kenji@example.com", 1999, "jpy"), organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+
+        let cancelling: Value = subscription("customer.subscription.updated", "jane@example.com", true);
+        let period_end: i64 = cancelling["data"]["object"]["current_period_end"].as_i64().unwrap();
+        assert!(matches!(EventHandler::new(&cancelling, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionUpdated)));
+
+        let upcoming: Value = json!({
+            "id": "evt_test_upcoming",
+            "type": "invoice.upcoming",
+            "created": period_end - 3 * DAY,
+            "data": { "object": {
+                "object": "invoice",
+                "customer_email": "jane@example.com",
+                "amount_due": 1999,
+                "currency": "usd",
+                "period_end": period_end,
+            } },
+        });
+        assert!(matches!(EventHandler::new(&upcoming, organization(), supabase.clone()).await, Ok(EventHandler::InvoiceUpcoming)));
+
+        let unpaid: Value = subscription("customer.subscription.updated", "kenji@example.com", false);
+        assert!(matches!(EventHandler::new(&unpaid, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionUpdated)));
+        CustomerId::update_payment_failed_by_email(
+            "kenji@example.com".to_string(),
+            "insufficient_funds".to_string(),
+            "Your card has insufficient funds.".to_string(),
+            supabase.clone()
+        ).await.expect("the failed payment is stored");
+
+        let without_window: Organization = Organization { reminder_window: None, ..organization() };
+        assert_eq!(run_reminder_scan(&[without_window, organization()], period_end - 3 * DAY).await, Ok(0));
+        assert!(harness.emails().is_empty());
+
+        let reminders: Vec<Value> = harness.rows("stripe_period_reminders");
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0]["status"], "skipped");
+        assert_eq!(reminders[0]["reason"], "cancel_at_period_end");
+        assert_eq!(reminders[0]["email"], "jane@example.com");
+    }
+
+
+    #[tokio::test]
+    /// # handles_subscription_fixtures
+    /// The subscription fixtures store the end of the trial and of the paid period, the trial
+    /// ending email and the renewal reminder are each sent once for their period and the
+    /// cancellation leaves the stored period alone.
+    async fn handles_subscription_fixtures() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+        let email: &str = "jenny.rosen@example.com";
+
+        assert!(matches!(handle_fixture(&supabase, "charge.succeeded").await, Ok(EventHandler::ChargeSucceeded)));
+
+        assert!(matches!(handle_fixture(&supabase, "customer.subscription.created").await, Ok(EventHandler::CustomerSubscriptionCreated)));
+        assert_eq!(customer(&harness, email)["end_time"], 1715704800);
+
+        assert!(matches!(handle_fixture(&supabase, "customer.subscription.trial_will_end").await, Ok(EventHandler::CustomerSubscriptionTrialWillEnd)));
+        assert!(matches!(handle_fixture(&supabase, "customer.subscription.trial_will_end").await, Ok(EventHandler::CustomerSubscriptionTrialWillEnd)));
+
+        assert!(matches!(handle_fixture(&supabase, "customer.subscription.updated").await, Ok(EventHandler::CustomerSubscriptionUpdated)));
+        assert_eq!(customer(&harness, email)["end_time"], 1718383200);
+
+        // the renewal is the next payment attempt, an hour after the period ends
+        assert!(matches!(handle_fixture(&supabase, "invoice.upcoming").await, Ok(EventHandler::InvoiceUpcoming)));
+        assert_eq!(customer(&harness, email)["end_time"], 1718386800);
+
+        assert!(matches!(handle_fixture(&supabase, "customer.subscription.deleted").await, Ok(EventHandler::CustomerSubscriptionDeleted)));
+        assert_eq!(customer(&harness, email)["end_time"], 1718386800);
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0]["subject"], "Your Xylex trial ends soon");
+        assert_eq!(emails[0]["to"], json!([email]));
+        assert_eq!(emails[1]["subject"], "Xylex renews soon");
+        assert!(emails[1]["text"].as_str().unwrap().contains("renews on 14-06-2024 and € 50,00 will be charged"));
+
+        let reminders: Vec<Value> = harness.rows("stripe_period_reminders");
+        assert_eq!(reminders.len(), 2);
+        assert_eq!(reminders[0]["template"], "trial_ending");
+        assert_eq!(reminders[0]["source"], "evt_1PfixtureTrialWillEnd");
+        assert_eq!(reminders[0]["period_end"], 1715704800);
+        assert_eq!(reminders[1]["template"], "renewal_reminder");
+        assert_eq!(reminders[1]["source"], "evt_1PfixtureInvoiceUpcoming");
+        assert_eq!(reminders[1]["period_end"], 1718386800);
+    }
+}
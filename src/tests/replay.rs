@@ -0,0 +1,284 @@
+//! ## Fixture replay tests
+//!
+//! Every fixture is signed and sent through the Rocket endpoint, the side effects are checked on
+//! the in-memory fakes of the [harness](../harness/index.html).
+//!
+//! ### Table of contents
+//! - Rejecting unsigned webhooks and events without an id
+//! - Storing every fixture in the event log
+//! - Purchases, failed payments, refunds and disputes
+//! - Escaping what Stripe sends in operator notifications
+//! - Handling concurrent deliveries of the same event once
+//! - Failing refunds when the database is down, so they can be replayed
+//! - Welcome emails after checkout
+//! - Synthetic test events
+//!
+
+
+#[cfg(test)]
+mod fixtures {
+    use crate::events::fixtures::{fixture, FIXTURE_EVENT_TYPES};
+    use crate::events::test_event::TestEvent;
+    use crate::tests::harness::Harness;
+
+    use rocket::http::{ContentType, Status};
+    use serde_json::{json, Value};
+
+
+    /// # customer
+    /// The single customer row every fixture describes.
+    fn customer(harness: &Harness) -> Value {
+        let rows: Vec<Value> = harness.rows("stripe_customer_data");
+        assert_eq!(rows.len(), 1, "expected exactly one customer row: {:?}", rows);
+
+        rows[0].clone()
+    }
+
+
+    #[tokio::test]
+    /// # rejects_unsigned_webhooks
+    /// Webhooks without a valid signature or without an event id are rejected before anything is
+    /// stored.
+    async fn rejects_unsigned_webhooks() {
+        let harness: Harness = Harness::start().await;
+
+        let status: Status = harness.client
+            .post("/stripe_webhooks")
+            .header(ContentType::JSON)
+            .body(fixture("charge.succeeded").unwrap().to_string())
+            .dispatch()
+            .await
+            .status();
+
+        assert_eq!(status, Status::BadRequest);
+
+        let mut without_id: Value = fixture("charge.succeeded").unwrap();
+        without_id.as_object_mut().unwrap().remove("id");
+        assert_eq!(harness.send_event(&without_id).await, Status::BadRequest);
+
+        assert!(harness.rows("stripe_webhook_events").is_empty());
+        assert!(harness.rows("stripe_customer_data").is_empty());
+    }
+
+
+    #[tokio::test]
+    /// # stores_every_fixture
+    /// Every fixture but checkout is accepted, stored and processed exactly once, redeliveries included.
+    async fn stores_every_fixture() {
+        let harness: Harness = Harness::start().await;
+
+        let event_types: Vec<&str> = FIXTURE_EVENT_TYPES
+            .into_iter()
+            .filter(|event_type| *event_type != "checkout.session.completed")
+            .collect();
+
+        for event_type in &event_types {
+            assert_eq!(harness.send_fixture(event_type).await, Status::Ok, "{}", event_type);
+        }
+
+        // Stripe redelivering an event that was already handled
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+
+        let events: Vec<Value> = harness.rows("stripe_webhook_events");
+        assert_eq!(events.len(), event_types.len());
+
+        for event in events {
+            assert_eq!(event["verified"], true);
+            assert_eq!(event["attempts"], 1, "{}", event["event_type"]);
+            assert_eq!(event["outcome"], "handled", "{}", event["event_type"]);
+            assert!(event["headers"]["stripe-signature"].as_str().unwrap().starts_with("t="));
+        }
+    }
+
+
+    #[tokio::test]
+    /// # records_purchase
+    /// A succeeded charge creates a paid customer record.
+    async fn records_purchase() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+
+        let customer: Value = customer(&harness);
+        assert_eq!(customer["customer_id"], "ch_3PfixtureCharge01");
+        assert_eq!(customer["email"], "jenny.rosen@example.com");
+        assert_eq!(customer["name"], "Jenny Rosen");
+        assert_eq!(customer["country"], "NL");
+        assert_eq!(customer["paid"], true);
+    }
+
+
+    #[tokio::test]
+    /// # records_failed_payment
+    /// A failed payment marks the customer unpaid and sends the populated payment failed email.
+    async fn records_failed_payment() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+        assert_eq!(harness.send_fixture("payment_intent.payment_failed").await, Status::Ok);
+
+        let customer: Value = customer(&harness);
+        assert_eq!(customer["paid"], false);
+        assert_eq!(customer["decline_code"], "insufficient_funds");
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0]["to"][0], "jenny.rosen@example.com");
+        assert_eq!(emails[0]["subject"], "Your payment failed");
+        assert_eq!(emails[0]["html"], "<p>Hi Jenny, your payment failed: Your card has insufficient funds.</p>");
+        assert_eq!(emails[0]["text"], "Hi Jenny, your payment failed: Your card has insufficient funds.");
+    }
+
+
+    #[tokio::test]
+    /// # records_refund_and_dispute
+    /// A full refund and a lost dispute revoke access and the Discord role, are audited and notify
+    /// the operator.
+    async fn records_refund_and_dispute() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+        harness.patch_rows("stripe_customer_data", json!({ "discord_user_id": "80351110224678912" }));
+        assert_eq!(harness.send_fixture("charge.refunded").await, Status::Ok);
+
+        let customer_after_refund: Value = customer(&harness);
+        assert_eq!(customer_after_refund["paid"], false);
+        assert_eq!(customer_after_refund["refund_status"], "full");
+        assert_eq!(
+            harness.discord_requests(),
+            vec!["DELETE /guilds/81384788765712384/members/80351110224678912/roles/41771983423143936"]
+        );
+
+        assert_eq!(harness.send_fixture("charge.dispute.created").await, Status::Ok);
+        assert_eq!(harness.send_fixture("charge.dispute.closed").await, Status::Ok);
+
+        let customer_after_dispute: Value = customer(&harness);
+        assert_eq!(customer_after_dispute["dispute_status"], "lost");
+        assert_eq!(customer_after_dispute["dispute_reason"], "fraudulent");
+        assert_eq!(harness.discord_requests().len(), 2);
+
+        let audit: Vec<Value> = harness.rows("stripe_customer_audit");
+        let audited: Vec<&str> = audit.iter().filter_map(|entry| entry["event_type"].as_str()).collect();
+        assert_eq!(audited, vec!["charge.refunded", "charge.dispute.created", "charge.dispute.closed"]);
+
+        let operator_emails: usize = harness.emails()
+            .iter()
+            .filter(|email| email["to"][0] == "ops@example.com")
+            .count();
+        assert_eq!(operator_emails, 3);
+    }
+
+
+    #[tokio::test]
+    /// # escapes_operator_notification
+    /// The refund reason is escaped in the operator email, a refund without an email reads as
+    /// `unknown`.
+    async fn escapes_operator_notification() {
+        let harness: Harness = Harness::start().await;
+
+        let mut refund: Value = fixture("charge.refunded").expect("a refund fixture");
+        refund["data"]["object"]["billing_details"]["email"] = json!(null);
+        refund["data"]["object"]["receipt_email"] = json!(null);
+        refund["data"]["object"]["refunds"]["data"][0]["reason"] = json!("<img src=x onerror=alert(1)>");
+        assert_eq!(harness.send_event(&refund).await, Status::Ok);
+
+        let emails: Vec<Value> = harness.emails();
+        let notification: &Value = emails.iter().find(|email| email["to"][0] == "ops@example.com").expect("an operator email");
+
+        assert!(notification["subject"].as_str().unwrap().ends_with("full refund for unknown"), "{}", notification["subject"]);
+
+        let html: &str = notification["html"].as_str().unwrap();
+        assert!(html.contains("Reason: &lt;img src=x onerror=alert(1)&gt;"), "{}", html);
+        assert!(html.contains("(unknown)"), "{}", html);
+        assert!(!html.contains("<img"), "{}", html);
+    }
+
+
+    #[tokio::test]
+    /// # handles_concurrent_deliveries
+    /// Two deliveries of the same event that arrive together store and handle it once.
+    async fn handles_concurrent_deliveries() {
+        let harness: Harness = Harness::start().await;
+        let charge: Value = fixture("charge.succeeded").expect("a charge fixture");
+
+        let (first, second) = tokio::join!(harness.send_event(&charge), harness.send_event(&charge));
+        assert_eq!((first, second), (Status::Ok, Status::Ok));
+
+        let events: Vec<Value> = harness.rows("stripe_webhook_events");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["attempts"], 1);
+        assert_eq!(events[0]["outcome"], "handled");
+
+        customer(&harness);
+    }
+
+
+    #[tokio::test]
+    /// # fails_refund_without_database
+    /// A refund that can not be audited is acknowledged, then recorded as `failed` with the error so
+    /// it can be replayed.
+    async fn fails_refund_without_database() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+
+        harness.take_down_table("stripe_customer_audit");
+        assert_eq!(harness.send_fixture("charge.refunded").await, Status::Ok);
+
+        let events: Vec<Value> = harness.rows("stripe_webhook_events");
+        let refund: &Value = events.iter().find(|event| event["event_type"] == "charge.refunded").expect("a stored refund");
+        assert_eq!(refund["outcome"], "failed");
+        assert!(refund["last_error"].as_str().unwrap_or_default().contains("audit trail"), "{}", refund["last_error"]);
+    }
+
+
+    #[tokio::test]
+    /// # sends_welcome_email
+    /// Checkout sends the welcome email and marks it as sent on the customer.
+    async fn sends_welcome_email() {
+        let harness: Harness = Harness::start().await;
+
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+        assert_eq!(harness.send_fixture("checkout.session.completed").await, Status::Ok);
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0]["to"][0], "jenny.rosen@example.com");
+        assert_eq!(emails[0]["subject"], "Welcome!");
+        assert_eq!(emails[0]["html"], "<p>Welcome aboard!</p>");
+        assert_eq!(emails[0]["text"], "Welcome aboard!");
+        assert_eq!(emails[0]["attachments"][0]["filename"], "receipt-pi_3PfixturePaymentIntent01.pdf");
+
+        assert_eq!(customer(&harness)["email_sent"], true);
+    }
+
+
+    #[tokio::test]
+    /// # handles_test_events
+    /// Synthetic events are handled like the fixtures they are built from, a created subscription
+    /// stores the end of its period on the customer.
+    async fn handles_test_events() {
+        let harness: Harness = Harness::start().await;
+
+        let charge: Value = TestEvent::new("charge.succeeded")
+            .unwrap()
+            .with_email("jane@example.com".to_string())
+            .with_amount(1999)
+            .build();
+
+        assert_eq!(harness.send_event(&charge).await, Status::Ok);
+
+        let customer: Value = customer(&harness);
+        assert_eq!(customer["customer_id"], charge["data"]["object"]["id"]);
+        assert_eq!(customer["email"], "jane@example.com");
+        assert_eq!(customer["paid"], true);
+
+        let subscription: Value = TestEvent::new("customer.subscription.created")
+            .unwrap()
+            .with_email("jane@example.com".to_string())
+            .build();
+        assert_eq!(harness.send_event(&subscription).await, Status::Ok);
+        assert_eq!(harness.rows("stripe_webhook_events")[1]["outcome"], "handled");
+        assert_eq!(self::customer(&harness)["end_time"], subscription["data"]["object"]["current_period_end"]);
+    }
+}
@@ -0,0 +1,230 @@
+//! ## Email retry and fallback tests
+//!
+//! ### Table of contents
+//! - Doubling the backoff and switching to the fallback provider
+//! - Retrying transient failures and stopping at permanent ones
+//! - Sending the same idempotency key with every attempt
+//! - Falling back to SMTP when Resend keeps failing or rejects the API key
+//! - Sending with the provider the Organization was configured with
+//! - Recording every attempt of the welcome email
+//!
+
+
+#[cfg(test)]
+mod email_retries {
+    use crate::email::client::{deliver_email, Delivery};
+    use crate::email::content::EmailContent;
+    use crate::email::retry::EmailRetry;
+    use crate::email::EmailProvider;
+    use crate::organization::router::organization_from_config;
+    use crate::tests::harness::{FakeSmtp, Harness};
+    use crate::{ConfigSetup, EmailConfig, Organization};
+
+    use rocket::http::Status;
+    use serde_json::Value;
+    use std::env;
+    use std::time::Duration;
+
+
+    /// # organization
+    /// An Organization that retries without waiting.
+    fn organization(fallback: Option<EmailProvider>) -> Organization {
+        Organization::new(
+            "Retries".to_string(),
+            EmailConfig::new("billing@example.com".to_string(), "Welcome!".to_string(), "welcome".to_string()),
+        )
+        .with_email_retry(EmailRetry {
+            backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            fallback,
+            ..EmailRetry::default()
+        })
+    }
+
+
+    /// # deliver
+    /// Delivers a welcome email to Jenny for email log row `7`.
+    async fn deliver(organization: &Organization) -> Delivery {
+        let content: EmailContent = EmailContent::from_html("<p>Welcome Jenny</p>".to_string());
+
+        deliver_email(organization, vec!["jenny.rosen@example.com".to_string()], "Welcome!", &content, Some(7)).await
+    }
+
+
+    /// # column
+    /// One column of every row of a table.
+    fn column(rows: &[Value], column: &str) -> Vec<Value> {
+        rows.iter().map(|row| row[column].clone()).collect()
+    }
+
+
+    #[test]
+    /// # backs_off_and_falls_back
+    /// The backoff doubles up to its cap and the fallback provider takes over after
+    /// `fallback_after` failures, never without a fallback.
+    fn backs_off_and_falls_back() {
+        let retry: EmailRetry = EmailRetry::default();
+
+        assert_eq!(retry.backoff(1), Duration::from_millis(500));
+        assert_eq!(retry.backoff(2), Duration::from_millis(1000));
+        assert_eq!(retry.backoff(4), Duration::from_millis(4000));
+        assert_eq!(retry.backoff(5), Duration::from_millis(5000));
+        assert_eq!(retry.backoff(u32::MAX), Duration::from_millis(5000));
+        assert_eq!(retry.provider(EmailProvider::Resend, 3), EmailProvider::Resend);
+
+        let retry: EmailRetry = EmailRetry { fallback: Some(EmailProvider::Smtp), ..EmailRetry::default() };
+
+        assert_eq!(retry.provider(EmailProvider::Resend, 0), EmailProvider::Resend);
+        assert_eq!(retry.provider(EmailProvider::Resend, 1), EmailProvider::Resend);
+        assert_eq!(retry.provider(EmailProvider::Resend, 2), EmailProvider::Smtp);
+    }
+
+
+    #[tokio::test]
+    /// # retries_transient_failures
+    /// A `503` from Resend is attempted again and both attempts are recorded, a `422` is not.
+    async fn retries_transient_failures() {
+        let harness: Harness = Harness::start().await;
+
+        harness.fail_emails(vec![503]);
+        let delivery: Delivery = deliver(&organization(None)).await;
+
+        assert_eq!(delivery, Delivery { sent: Ok("email_1".to_string()), provider: EmailProvider::Resend, attempts: 2 });
+
+        let attempts: Vec<Value> = harness.rows("stripe_email_attempts");
+        assert_eq!(column(&attempts, "attempt"), vec![1, 2]);
+        assert_eq!(column(&attempts, "status"), vec!["failed", "sent"]);
+        assert_eq!(column(&attempts, "error_kind"), vec![Value::from("transient"), Value::Null]);
+        assert_eq!(attempts[1]["message_id"], "email_1");
+        assert_eq!(attempts[1]["email_log_id"], 7);
+        assert_eq!(attempts[1]["recipient"], "jenny.rosen@example.com");
+        assert_eq!(harness.email_idempotency_keys(), vec!["email-log-7", "email-log-7"]);
+
+        harness.fail_emails(vec![422, 503]);
+        let delivery: Delivery = deliver(&organization(None)).await;
+
+        assert_eq!(delivery.attempts, 1);
+        assert!(delivery.sent.is_err());
+        assert_eq!(harness.rows("stripe_email_attempts")[2]["error_kind"], "permanent");
+        assert_eq!(harness.rows("stripe_email_attempts").len(), 3);
+
+        harness.fail_emails(vec![503, 503, 503, 503]);
+        let delivery: Delivery = deliver(&organization(None)).await;
+
+        assert_eq!(delivery.attempts, 4);
+        assert!(delivery.sent.is_err());
+        assert_eq!(harness.emails().len(), 1);
+    }
+
+
+    #[tokio::test]
+    /// # falls_back_to_smtp
+    /// Once Resend failed twice the email goes out over SMTP with a message id of its own.
+    async fn falls_back_to_smtp() {
+        let harness: Harness = Harness::start().await;
+        let smtp: FakeSmtp = FakeSmtp::start().await;
+
+        harness.fail_emails(vec![503, 500]);
+        let delivery: Delivery = deliver(&organization(Some(EmailProvider::Smtp))).await;
+
+        assert_eq!(delivery.provider, EmailProvider::Smtp);
+        assert_eq!(delivery.attempts, 3);
+
+        let message_id: String = delivery.sent.expect("sent over smtp");
+        assert!(message_id.ends_with("@example.com"));
+
+        let messages: Vec<String> = smtp.messages();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Subject: Welcome!"));
+        assert!(messages[0].contains(&format!("Message-ID: <{}>", message_id)));
+        assert!(messages[0].contains("Welcome Jenny"));
+
+        let attempts: Vec<Value> = harness.rows("stripe_email_attempts");
+        assert_eq!(column(&attempts, "provider"), vec!["resend", "resend", "smtp"]);
+        assert_eq!(attempts[2]["message_id"], message_id.as_str());
+
+        for name in ["SMTP_HOST", "SMTP_PORT", "SMTP_TLS"] {
+            env::remove_var(name);
+        }
+    }
+
+
+    #[tokio::test]
+    /// # falls_back_on_rejected_api_key
+    /// A rejected API key is not attempted again, SMTP takes over right away and without a
+    /// fallback sending stops.
+    async fn falls_back_on_rejected_api_key() {
+        let harness: Harness = Harness::start().await;
+
+        harness.fail_emails(vec![401]);
+        let delivery: Delivery = deliver(&organization(None)).await;
+
+        assert_eq!(delivery.attempts, 1);
+        assert!(delivery.sent.is_err());
+        assert_eq!(harness.rows("stripe_email_attempts")[0]["error_kind"], "unauthorized");
+
+        let smtp: FakeSmtp = FakeSmtp::start().await;
+
+        harness.fail_emails(vec![403]);
+        let delivery: Delivery = deliver(&organization(Some(EmailProvider::Smtp))).await;
+
+        assert_eq!(delivery.provider, EmailProvider::Smtp);
+        assert_eq!(delivery.attempts, 2);
+        assert!(delivery.sent.is_ok());
+        assert_eq!(smtp.messages().len(), 1);
+
+        let attempts: Vec<Value> = harness.rows("stripe_email_attempts");
+        assert_eq!(column(&attempts, "provider"), vec!["resend", "resend", "smtp"]);
+
+        for name in ["SMTP_HOST", "SMTP_PORT", "SMTP_TLS"] {
+            env::remove_var(name);
+        }
+    }
+
+
+    #[tokio::test]
+    /// # sends_with_organization_provider
+    /// `Email.Provider` is read into the Organization once, emails go out with it.
+    async fn sends_with_organization_provider() {
+        let harness: Harness = Harness::start().await;
+        let smtp: FakeSmtp = FakeSmtp::start().await;
+
+        let config: ConfigSetup = ConfigSetup { email_provider: "smtp".to_string(), ..ConfigSetup::default() };
+        assert_eq!(organization_from_config(&config).email_provider, EmailProvider::Smtp);
+        assert_eq!(organization_from_config(&ConfigSetup::default()).email_provider, EmailProvider::Resend);
+
+        let delivery: Delivery = deliver(&organization(None).with_email_provider(EmailProvider::Smtp)).await;
+
+        assert_eq!(delivery.provider, EmailProvider::Smtp);
+        assert!(delivery.sent.is_ok());
+        assert_eq!(smtp.messages().len(), 1);
+        assert!(harness.emails().is_empty());
+
+        for name in ["SMTP_HOST", "SMTP_PORT", "SMTP_TLS"] {
+            env::remove_var(name);
+        }
+    }
+
+
+    #[tokio::test]
+    /// # retries_the_welcome_email
+    /// A welcome email Resend failed once still goes out, the email log keeps the attempts.
+    async fn retries_the_welcome_email() {
+        let harness: Harness = Harness::start().await;
+
+        harness.fail_emails(vec![502]);
+        assert_eq!(harness.send_fixture("charge.succeeded").await, Status::Ok);
+        assert_eq!(harness.send_fixture("checkout.session.completed").await, Status::Ok);
+
+        assert_eq!(harness.rows("stripe_customer_data")[0]["email_sent"], true);
+
+        let logged: Value = harness.rows("stripe_email_log").remove(0);
+        assert_eq!(logged["status"], "sent");
+        assert_eq!(logged["attempts"], 2);
+        assert_eq!(logged["provider"], "resend");
+
+        let attempts: Vec<Value> = harness.rows("stripe_email_attempts");
+        assert_eq!(column(&attempts, "status"), vec!["failed", "sent"]);
+        assert_eq!(attempts[0]["email_log_id"], logged["id"]);
+    }
+}
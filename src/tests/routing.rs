@@ -0,0 +1,168 @@
+//! ## Per-event email routing tests
+//!
+//! ### Table of contents
+//! - Mapping Stripe event types to the email sent for them
+//! - Enabling and configuring every email under `Email` in `stripe_discord.yaml`
+//! - Sending the receipt, renewal reminder and cancellation emails and skipping disabled ones
+//!
+
+
+#[cfg(test)]
+mod email_routing {
+    use crate::email::client::send_welcome_email;
+    use crate::email::locale::Locale;
+    use crate::events::test_event::TestEvent;
+    use crate::events::fixtures::fixture;
+    use crate::events::EventHandler;
+    use crate::organization::model::EmailEvent;
+    use crate::tests::harness::Harness;
+    use crate::{EmailConfig, EmailEventConfig, Organization};
+
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use supabase_rs::SupabaseClient;
+
+
+    /// # organization
+    /// An Organization with the welcome email off and the receipt, renewal reminder and
+    /// cancellation emails on, sent with the embedded templates.
+    fn organization() -> Organization {
+        let email = |subject: &str, template: &str| -> EmailConfig {
+            EmailConfig::new("billing@xylex.ai".to_string(), subject.to_string(), template.to_string())
+        };
+
+        Organization::new("Xylex".to_string(), email("Welcome!", "welcome"))
+            .without_email(EmailEvent::Welcome)
+            .with_email(EmailEvent::Receipt, email("Your Xylex receipt", "receipt"))
+            .with_email(EmailEvent::RenewalReminder, email("Xylex renews soon", "renewal_reminder"))
+            .with_email(EmailEvent::Cancellation, email("Xylex cancelled", "cancellation"))
+            .with_receipts(true)
+    }
+
+
+    #[test]
+    /// # maps_event_types
+    /// Every lifecycle moment has an event type, a config key and a default subject.
+    fn maps_event_types() {
+        assert_eq!(EmailEvent::for_event_type("checkout.session.completed"), Some(EmailEvent::Welcome));
+        assert_eq!(EmailEvent::for_event_type("charge.succeeded"), Some(EmailEvent::Receipt));
+        assert_eq!(EmailEvent::for_event_type("invoice.upcoming"), Some(EmailEvent::RenewalReminder));
+        assert_eq!(EmailEvent::for_event_type("charge.failed"), Some(EmailEvent::PaymentFailed));
+        assert_eq!(EmailEvent::for_event_type("payment_intent.payment_failed"), Some(EmailEvent::PaymentFailed));
+        assert_eq!(EmailEvent::for_event_type("customer.subscription.deleted"), Some(EmailEvent::Cancellation));
+        assert_eq!(EmailEvent::for_event_type("customer.subscription.created"), None);
+
+        for event in EmailEvent::ALL {
+            assert_eq!(event.to_string().parse(), Ok(event));
+            assert!(!event.default_subject().is_empty());
+        }
+
+        assert_eq!(EmailEvent::RenewalReminder.config_key(), "RenewalReminder");
+        assert!("renewal".parse::<EmailEvent>().is_err());
+    }
+
+
+    #[test]
+    /// # reads_email_config
+    /// Emails are enabled by `Enabled` or by having a `TemplateUrl`, only the welcome email is on
+    /// without config, and unset fields fall back to the defaults of the event.
+    fn reads_email_config() {
+        let defaults: Vec<EmailEventConfig> = EmailEventConfig::from_config(&Value::Null);
+        let enabled: Vec<EmailEvent> = defaults.iter().filter(|email| email.enabled).map(|email| email.event).collect();
+        assert_eq!(enabled, vec![EmailEvent::Welcome]);
+
+        let value: Value = json!({
+            "Email": {
+                "Welcome": { "Enabled": false },
+                "Receipt": { "TemplateUrl": "receipt", "Subject": "Your receipt from Xylex" },
+                "Cancellation": { "Enabled": true, "Sender": "support@xylex.ai" },
+                "PaymentFailed": { "Enabled": false, "TemplateUrl": "payment_failed" },
+            }
+        });
+
+        let emails: Vec<EmailEventConfig> = EmailEventConfig::from_config(&value);
+        let email = |event: EmailEvent| -> &EmailEventConfig { emails.iter().find(|email| email.event == event).unwrap() };
+
+        assert!(!email(EmailEvent::Welcome).enabled);
+        assert!(email(EmailEvent::Receipt).enabled);
+        assert!(!email(EmailEvent::RenewalReminder).enabled);
+        assert!(!email(EmailEvent::PaymentFailed).enabled);
+        assert!(email(EmailEvent::Cancellation).enabled);
+
+        let default: EmailConfig = EmailConfig::new(
+            "billing@xylex.ai".to_string(),
+            EmailEvent::Cancellation.default_subject().to_string(),
+            EmailEvent::Cancellation.as_str().to_string(),
+        );
+
+        let cancellation: EmailConfig = email(EmailEvent::Cancellation).to_email_config(&default);
+        assert_eq!(cancellation.sender_email, "support@xylex.ai");
+        assert_eq!(cancellation.subject, "Your subscription has been cancelled");
+        assert_eq!(cancellation.template_url, "cancellation");
+
+        let receipt: EmailConfig = email(EmailEvent::Receipt).to_email_config(&default);
+        assert_eq!(receipt.subject, "Your receipt from Xylex");
+        assert_eq!(receipt.sender_email, "billing@xylex.ai");
+    }
+
+
+    #[tokio::test]
+    /// # routes_lifecycle_emails
+    /// A successful charge sends the receipt with the generated PDF, an upcoming invoice the
+    /// renewal reminder and a deleted subscription the cancellation, to the email of the Stripe
+    /// customer when the subscription has none. The disabled welcome email is not sent.
+    async fn routes_lifecycle_emails() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        let charge: Value = fixture("charge.succeeded").expect("a charge fixture");
+        assert!(matches!(EventHandler::new(&charge, organization(), supabase.clone()).await, Ok(EventHandler::ChargeSucceeded)));
+
+        let upcoming: Value = json!({
+            "id": "evt_test_upcoming",
+            "type": "invoice.upcoming",
+            "created": 1714000000,
+            "data": { "object": {
+                "object": "invoice",
+                "customer_email": "jane@example.com",
+                "customer_name": "Jane Doe",
+                "amount_due": 1999,
+                "currency": "usd",
+                "next_payment_attempt": 1716592000,
+            } },
+        });
+        assert!(matches!(EventHandler::new(&upcoming, organization(), supabase.clone()).await, Ok(EventHandler::InvoiceUpcoming)));
+
+        let mut deleted: Value = TestEvent::new("customer.subscription.deleted").unwrap().build();
+        deleted["data"]["object"]["metadata"] = json!({});
+        assert!(matches!(EventHandler::new(&deleted, organization(), supabase.clone()).await, Ok(EventHandler::CustomerSubscriptionDeleted)));
+
+        let emails: Vec<Value> = harness.emails();
+        assert_eq!(emails.len(), 3);
+
+        assert_eq!(emails[0]["subject"], "Your Xylex receipt");
+        assert_eq!(emails[0]["from"], "billing@xylex.ai");
+        assert_eq!(emails[0]["to"], json!(["jenny.rosen@example.com"]));
+        assert!(emails[0]["html"].as_str().unwrap().contains("€ 50,00 for Xylex on 24-04-2024"));
+        assert!(emails[0]["html"].as_str().unwrap().contains("https://pay.stripe.com/receipts/payment/fixture_receipt_01"));
+        assert_eq!(emails[0]["attachments"][0]["filename"], "receipt-ch_3PfixtureCharge01.pdf");
+
+        assert_eq!(emails[1]["subject"], "Xylex renews soon");
+        assert_eq!(emails[1]["to"], json!(["jane@example.com"]));
+        assert!(emails[1]["text"].as_str().unwrap().contains("Hi Jane,"));
+        assert!(emails[1]["text"].as_str().unwrap().contains("renews on May 24, 2024 and $19.99"));
+
+        assert_eq!(emails[2]["subject"], "Xylex cancelled");
+        assert_eq!(emails[2]["to"], json!(["jenny.rosen@example.com"]));
+        assert!(emails[2]["text"].as_str().unwrap().contains("Hi Jenny,"));
+
+        let stripe_requests: Vec<String> = harness.fakes.state.lock().unwrap().stripe_requests.clone();
+        assert_eq!(stripe_requests.len(), 1);
+        assert!(stripe_requests[0].starts_with("GET /v1/customers/cus_test_"));
+
+        let locale: Locale = Locale::parse("en").unwrap();
+        let welcome: Result<String, String> = send_welcome_email(organization(), "jane@example.com".to_string(), &locale, &HashMap::new(), Vec::new()).await;
+        assert_eq!(welcome, Err("the welcome email is disabled".to_string()));
+        assert_eq!(harness.emails().len(), 3);
+    }
+}
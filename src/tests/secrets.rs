@@ -0,0 +1,168 @@
+//! ## Secret tests
+//!
+//! ### Table of contents
+//! - Resolving secrets from literals, env vars and files
+//! - Redacting secrets in `Debug` output
+//! - Resolving the secrets of an endpoint config
+//! - Validating the secrets at startup
+//!
+
+
+#[cfg(test)]
+mod sources {
+    use crate::secrets::{secret, validate_startup, Secret, SecretError, SecretSource};
+    use crate::tests::harness::Harness;
+    use crate::{ConfigSetup, EndpointConfigStripe};
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+
+    /// # secret_file
+    /// Writes a secret file the way Docker mounts them, with a trailing newline.
+    fn secret_file(name: &str, value: &str) -> PathBuf {
+        let path: PathBuf = env::temp_dir().join(format!("stripe_discord_{}_{}", name, std::process::id()));
+        fs::write(&path, format!("{}\n", value)).expect("the secret file is written");
+
+        path
+    }
+
+
+    /// # endpoint_config
+    /// An endpoint config with the given secret fields.
+    fn endpoint_config(webhook_secret: &str, private_key: &str, bot_token: &str, replace_keys_with_env_names: bool) -> EndpointConfigStripe {
+        EndpointConfigStripe {
+            endpoint_route: "/stripe_webhooks/acme".to_string(),
+            name: "Acme".to_string(),
+            sender_email: "billing@acme.example".to_string(),
+            stripe_publish_key: "pk_test_51Hx".to_string(),
+            stripe_webhook_secret: webhook_secret.to_string(),
+            stripe_private_key: private_key.to_string(),
+            email_template_path: "./email/templates/welcome.html".to_string(),
+            discord_client_id: "1234".to_string(),
+            discord_application_id: "1234".to_string(),
+            discord_role_id: 41771983423143936,
+            discord_guild_id: 81384788765712384,
+            discord_bot_token: bot_token.to_string(),
+            replace_keys_with_env_names,
+            attach_receipts: None,
+        }
+    }
+
+
+    #[test]
+    /// # resolves_sources
+    /// Literal values, `env:` and `file:` sources and the `<NAME>_FILE` fallback all resolve.
+    fn resolves_sources() {
+        env::set_var("SECRETS_TEST_ENV_VALUE", "whsec_from_env");
+        let path: PathBuf = secret_file("webhook_secret", "whsec_from_file");
+        env::set_var("SECRETS_TEST_MOUNTED_FILE", &path);
+
+        assert_eq!(SecretSource::parse("whsec_literal").resolve().unwrap().expose(), "whsec_literal");
+        assert_eq!(SecretSource::parse("env:SECRETS_TEST_ENV_VALUE").resolve().unwrap().expose(), "whsec_from_env");
+        assert_eq!(
+            SecretSource::parse(&format!("file:{}", path.display())).resolve().unwrap().expose(),
+            "whsec_from_file"
+        );
+        assert_eq!(secret("SECRETS_TEST_MOUNTED").unwrap().expose(), "whsec_from_file");
+
+        assert_eq!(secret("SECRETS_TEST_UNSET"), Err(SecretError::NotSet("SECRETS_TEST_UNSET".to_string())));
+        assert!(matches!(SecretSource::parse("").resolve(), Err(SecretError::Empty(_))));
+        assert!(matches!(SecretSource::parse("file:/nonexistent/secret").resolve(), Err(SecretError::Unreadable(..))));
+
+        fs::remove_file(path).ok();
+    }
+
+
+    #[test]
+    /// # redacts_debug_output
+    /// Neither secrets, literal sources nor configs print secret values.
+    fn redacts_debug_output() {
+        let secret: Secret = Secret::new("whsec_do_not_log".to_string());
+        assert_eq!(format!("{:?}", secret), "Secret(\"***\")");
+        assert!(!format!("{:?}", SecretSource::parse("whsec_do_not_log")).contains("do_not_log"));
+        assert_eq!(format!("{:?}", SecretSource::parse("env:STRIPE_WEBHOOK_SECRET")), "Env(\"STRIPE_WEBHOOK_SECRET\")");
+
+        let endpoint: String = format!("{:?}", endpoint_config("whsec_do_not_log", "sk_test_do_not_log", "MTk4NjIyNDgzNDcxOTI1MjQ4.bot", false));
+        assert!(!endpoint.contains("do_not_log"));
+        assert!(!endpoint.contains("MTk4NjIyNDgzNDcxOTI1MjQ4"));
+        assert!(endpoint.contains("81384788765712384"));
+
+        let config: ConfigSetup = ConfigSetup {
+            supabase_key: Secret::new("supabase_do_not_log".to_string()),
+            ..ConfigSetup::default()
+        };
+        assert!(!format!("{:?}", config).contains("do_not_log"));
+    }
+
+
+    #[test]
+    /// # resolves_endpoint_secrets
+    /// With `replace_keys_with_env_names` the fields name env vars, and formats are checked.
+    fn resolves_endpoint_secrets() {
+        env::set_var("SECRETS_TEST_ENDPOINT_WEBHOOK", "whsec_endpoint");
+        env::set_var("SECRETS_TEST_ENDPOINT_PRIVATE", "sk_test_endpoint");
+        env::set_var("SECRETS_TEST_ENDPOINT_BOT_TOKEN", "MTk4NjIyNDgzNDcxOTI1MjQ4.bot");
+
+        let by_name: EndpointConfigStripe = endpoint_config("SECRETS_TEST_ENDPOINT_WEBHOOK", "SECRETS_TEST_ENDPOINT_PRIVATE", "SECRETS_TEST_ENDPOINT_BOT_TOKEN", true);
+        assert_eq!(by_name.stripe_webhook_secret().unwrap().expose(), "whsec_endpoint");
+        assert_eq!(by_name.stripe_private_key().unwrap().expose(), "sk_test_endpoint");
+        assert!(by_name.validate_secrets().is_empty());
+
+        let literal: EndpointConfigStripe = endpoint_config("SECRETS_TEST_ENDPOINT_WEBHOOK", "env:SECRETS_TEST_ENDPOINT_PRIVATE", "file:/nonexistent/bot_token", false);
+        assert_eq!(literal.stripe_private_key().unwrap().expose(), "sk_test_endpoint");
+        assert_eq!(
+            literal.stripe_webhook_secret(),
+            Err(SecretError::Malformed("STRIPE_WEBHOOK_SECRET".to_string(), "whsec_".to_string()))
+        );
+        assert_eq!(literal.validate_secrets().len(), 2);
+    }
+
+
+    #[tokio::test]
+    /// # validates_startup_secrets
+    /// The secrets of the configured providers, `STRIPE_WEBHOOK_SECRET` and the webhook secret of
+    /// every endpoint are required, malformed optional ones are reported.
+    async fn validates_startup_secrets() {
+        let _harness: Harness = Harness::start().await;
+
+        // supabase and resend by default
+        let config: ConfigSetup = ConfigSetup::default();
+
+        env::remove_var("STRIPE_PRIVATE_API_KEY");
+        env::remove_var("STRIPE_PUBLISH_KEY");
+        assert_eq!(validate_startup(&config), Vec::<SecretError>::new());
+
+        env::set_var("STRIPE_PRIVATE_API_KEY", "pk_test_wrong_key");
+        env::remove_var("RESEND_API_KEY");
+
+        let errors: Vec<SecretError> = validate_startup(&config);
+        assert_eq!(errors, vec![
+            SecretError::Malformed("STRIPE_PRIVATE_API_KEY".to_string(), "sk_ or rk_".to_string()),
+            SecretError::NotSet("RESEND_API_KEY".to_string()),
+        ]);
+
+        env::remove_var("STRIPE_PRIVATE_API_KEY");
+        env::set_var("RESEND_API_KEY", "re_fake");
+
+        // the default route and every endpoint verify webhooks with their own secret
+        env::remove_var("STRIPE_WEBHOOK_SECRET");
+        let with_endpoint: ConfigSetup = ConfigSetup {
+            endpoints: vec![endpoint_config("SECRETS_TEST_UNSET_WEBHOOK", "", "", true)],
+            ..ConfigSetup::default()
+        };
+
+        assert_eq!(validate_startup(&with_endpoint), vec![
+            SecretError::NotSet("STRIPE_WEBHOOK_SECRET".to_string()),
+            SecretError::Endpoint(
+                "/stripe_webhooks/acme".to_string(),
+                Box::new(SecretError::NotSet("SECRETS_TEST_UNSET_WEBHOOK".to_string()))
+            ),
+        ]);
+        assert_eq!(
+            SecretError::Endpoint("/stripe_webhooks/acme".to_string(), Box::new(SecretError::NotSet("X".to_string()))).to_string(),
+            "endpoint /stripe_webhooks/acme: X is not set"
+        );
+    }
+}
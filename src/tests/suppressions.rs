@@ -0,0 +1,314 @@
+//! ## Email suppression tests
+//!
+//! ### Table of contents
+//! - Verifying the Svix signature of Resend webhooks
+//! - Verifying the signature of SNS messages
+//! - Reading hard bounces and complaints from Resend events and SES notifications
+//! - Ingesting Resend and SES webhooks into the suppression list
+//! - Skipping suppressed addresses and marking `email_sent` with the reason
+//!
+
+
+#[cfg(test)]
+mod email_suppressions {
+    use crate::api::email_webhooks::SIGNING_CERTIFICATES;
+    use crate::db::operations::suppression::Suppression;
+    use crate::email::suppression::{is_sns_url, resend_suppressions, ses_suppressions, sign_resend_payload, sns_string_to_sign, verify_resend_signature, verify_sns_signature, EmailWebhookError};
+    use crate::events::fixtures::fixture;
+    use crate::events::signature::unix_now;
+    use crate::events::EventHandler;
+    use crate::organization::model::EmailEvent;
+    use crate::tests::harness::Harness;
+    use crate::{EmailConfig, Organization};
+
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use openssl::sign::Signer;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+    use rocket::http::{ContentType, Header, Status};
+    use serde_json::{json, Value};
+    use std::env;
+    use std::sync::LazyLock;
+    use supabase_rs::SupabaseClient;
+
+
+    /// The Resend webhook secret the tests sign with, `whsec_` and a base64 key
+    const RESEND_SECRET: &str = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+
+
+    /// The `SigningCertURL` of the fixture SNS messages
+    const SNS_CERTIFICATE_URL: &str = "https://sns.us-east-1.amazonaws.com/SimpleNotificationService-fixture.pem";
+
+
+    /// The key and self-signed PEM certificate the fixture SNS messages are signed with
+    static SNS_SIGNING_KEY: LazyLock<(PKey<Private>, Vec<u8>)> = LazyLock::new(|| {
+        let key: PKey<Private> = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "sns.amazonaws.com").unwrap();
+        let name = name.build();
+
+        let mut certificate = X509Builder::new().unwrap();
+        certificate.set_version(2).unwrap();
+        certificate.set_subject_name(&name).unwrap();
+        certificate.set_issuer_name(&name).unwrap();
+        certificate.set_pubkey(&key).unwrap();
+        certificate.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        certificate.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        certificate.sign(&key, MessageDigest::sha256()).unwrap();
+
+        (key, certificate.build().to_pem().unwrap())
+    });
+
+
+    /// # sign_sns
+    /// Signs an SNS message like SNS does with `SignatureVersion` 2, and makes the webhook find
+    /// the fixture certificate at its `SigningCertURL`.
+    fn sign_sns(mut envelope: Value) -> Value {
+        let (key, certificate) = &*SNS_SIGNING_KEY;
+        SIGNING_CERTIFICATES.lock().unwrap().insert(SNS_CERTIFICATE_URL.to_string(), certificate.clone());
+
+        envelope["SignatureVersion"] = json!("2");
+        envelope["SigningCertURL"] = json!(SNS_CERTIFICATE_URL);
+
+        let mut signer: Signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer.update(sns_string_to_sign(&envelope).unwrap().as_bytes()).unwrap();
+        envelope["Signature"] = json!(BASE64.encode(signer.sign_to_vec().unwrap()));
+
+        envelope
+    }
+
+
+    /// # resend_bounce
+    /// A Resend `email.bounced` event for one recipient.
+    fn resend_bounce(to: &str, bounce_type: &str) -> Value {
+        json!({
+            "type": "email.bounced",
+            "created_at": "2024-04-24T23:06:40.000Z",
+            "data": {
+                "email_id": "4ef9a417-02e9-4d39-ad75-9611e0fcc33c",
+                "to": [to],
+                "bounce": { "type": bounce_type, "subType": "General", "message": "The recipient does not exist" },
+            },
+        })
+    }
+
+
+    /// # ses_notification
+    /// An unsigned SNS notification of the fixture topic carrying an SES message.
+    fn ses_notification(message: &Value) -> Value {
+        json!({
+            "Type": "Notification",
+            "MessageId": "22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324",
+            "TopicArn": "arn:aws:sns:us-east-1:123456789012:ses-feedback",
+            "Message": message.to_string(),
+            "Timestamp": "2024-04-24T23:06:40.000Z",
+        })
+    }
+
+
+    #[test]
+    /// # verifies_resend_signatures
+    /// Only signatures of the secret over the id, a recent timestamp and the exact body pass.
+    fn verifies_resend_signatures() {
+        let body: &str = r#"{"type":"email.bounced"}"#;
+        let now: i64 = 1714000000;
+        let signature: String = format!("v1,{}", sign_resend_payload(body, "msg_1", now, RESEND_SECRET));
+
+        assert_eq!(verify_resend_signature(body, "msg_1", "1714000000", &signature, RESEND_SECRET, now), Ok(()));
+        assert_eq!(verify_resend_signature(body, "msg_1", "1714000000", &format!("v1,bm9wZQ== {}", signature), RESEND_SECRET, now), Ok(()));
+        assert_eq!(verify_resend_signature(body, "msg_2", "1714000000", &signature, RESEND_SECRET, now), Err(EmailWebhookError::NoMatchingSignature));
+        assert_eq!(verify_resend_signature("{}", "msg_1", "1714000000", &signature, RESEND_SECRET, now), Err(EmailWebhookError::NoMatchingSignature));
+        assert_eq!(verify_resend_signature(body, "msg_1", "1714000000", &signature, "whsec_b3RoZXI=", now), Err(EmailWebhookError::NoMatchingSignature));
+        assert_eq!(verify_resend_signature(body, "msg_1", "1714000000", &signature, RESEND_SECRET, now + 301), Err(EmailWebhookError::TimestampOutsideTolerance));
+        assert_eq!(verify_resend_signature(body, "msg_1", "yesterday", &signature, RESEND_SECRET, now), Err(EmailWebhookError::MalformedHeader));
+        assert_eq!(verify_resend_signature(body, "msg_1", "1714000000", "v2,abc", RESEND_SECRET, now), Err(EmailWebhookError::MalformedHeader));
+    }
+
+
+    #[test]
+    /// # verifies_sns_signatures
+    /// Only messages signed with the key of the certificate pass, over exactly the signed fields,
+    /// and certificates are only read from SNS urls.
+    fn verifies_sns_signatures() {
+        let signed: Value = sign_sns(ses_notification(&json!({ "notificationType": "Delivery" })));
+        let certificate: &[u8] = &SNS_SIGNING_KEY.1;
+
+        assert_eq!(
+            sns_string_to_sign(&signed).unwrap(),
+            format!(
+                "Message\n{}\nMessageId\n22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324\nTimestamp\n2024-04-24T23:06:40.000Z\nTopicArn\narn:aws:sns:us-east-1:123456789012:ses-feedback\nType\nNotification\n",
+                signed["Message"].as_str().unwrap()
+            )
+        );
+        assert_eq!(verify_sns_signature(&signed, certificate), Ok(()));
+
+        let mut tampered: Value = signed.clone();
+        tampered["TopicArn"] = json!("arn:aws:sns:us-east-1:123456789012:other");
+        assert!(matches!(verify_sns_signature(&tampered, certificate), Err(EmailWebhookError::InvalidSignature(_))));
+
+        let mut unknown_version: Value = signed.clone();
+        unknown_version["SignatureVersion"] = json!("3");
+        assert!(matches!(verify_sns_signature(&unknown_version, certificate), Err(EmailWebhookError::InvalidSignature(_))));
+        assert!(matches!(verify_sns_signature(&signed, b"not a certificate"), Err(EmailWebhookError::InvalidSignature(_))));
+
+        assert!(is_sns_url(SNS_CERTIFICATE_URL));
+        assert!(!is_sns_url("http://sns.us-east-1.amazonaws.com/cert.pem"));
+        assert!(!is_sns_url("https://sns.us-east-1.amazonaws.com.example.com/cert.pem"));
+        assert!(!is_sns_url("https://sns.us-east-1.amazonaws.com@example.com/cert.pem"));
+        assert!(!is_sns_url("https://example.com/sns.us-east-1.amazonaws.com"));
+    }
+
+
+    #[test]
+    /// # reads_bounces_and_complaints
+    /// Hard bounces and complaints suppress their normalized recipients, soft bounces and other
+    /// events nothing.
+    fn reads_bounces_and_complaints() {
+        let bounced: Vec<Suppression> = resend_suppressions(&resend_bounce("Jane@Example.COM", "Permanent"), 1714000000);
+        assert_eq!(bounced.len(), 1);
+        assert_eq!(bounced[0].email, "Jane@example.com");
+        assert_eq!(bounced[0].reason, "bounce");
+        assert_eq!(bounced[0].source, "resend");
+        assert_eq!(bounced[0].detail, "Permanent/General");
+        assert_eq!(bounced[0].provider_event_id, "4ef9a417-02e9-4d39-ad75-9611e0fcc33c");
+
+        assert!(resend_suppressions(&resend_bounce("jane@example.com", "Transient"), 0).is_empty());
+        assert!(resend_suppressions(&json!({ "type": "email.delivered", "data": { "to": ["jane@example.com"] } }), 0).is_empty());
+
+        let complained: Vec<Suppression> = resend_suppressions(&json!({ "type": "email.complained", "data": { "to": ["jane@example.com"] } }), 0);
+        assert_eq!(complained[0].reason, "complaint");
+
+        let ses_bounce: Value = json!({
+            "notificationType": "Bounce",
+            "bounce": {
+                "bounceType": "Permanent",
+                "bounceSubType": "NoEmail",
+                "feedbackId": "0100018f-feedback",
+                "bouncedRecipients": [{ "emailAddress": "jane@example.com" }, { "emailAddress": "not an address" }],
+            },
+        });
+        let bounced: Vec<Suppression> = ses_suppressions(&ses_bounce, 0);
+        assert_eq!(bounced.len(), 1);
+        assert_eq!(bounced[0].detail, "Permanent/NoEmail");
+        assert_eq!(bounced[0].source, "ses");
+
+        let soft_bounce: Value = json!({ "eventType": "Bounce", "bounce": { "bounceType": "Transient", "bouncedRecipients": [{ "emailAddress": "jane@example.com" }] } });
+        assert!(ses_suppressions(&soft_bounce, 0).is_empty());
+
+        let ses_complaint: Value = json!({ "eventType": "Complaint", "complaint": { "complaintFeedbackType": "abuse", "complainedRecipients": [{ "emailAddress": "jane@example.com" }] } });
+        assert_eq!(ses_suppressions(&ses_complaint, 0)[0].reason, "complaint");
+    }
+
+
+    #[tokio::test]
+    /// # ingests_provider_webhooks
+    /// Signed Resend webhooks and signed SES notifications of the configured topic fill the
+    /// suppression list once per address, anything else is rejected.
+    async fn ingests_provider_webhooks() {
+        let harness: Harness = Harness::start().await;
+        env::set_var("RESEND_WEBHOOK_SECRET", RESEND_SECRET);
+        env::set_var("SES_SNS_TOPIC_ARN", "arn:aws:sns:us-east-1:123456789012:ses-feedback");
+
+        // signed and sent with the same timestamp, so a second passing in between does not matter
+        let timestamp: i64 = unix_now();
+
+        let post_resend = |body: String, signature: String| {
+            harness.client
+                .post("/email_webhooks/resend")
+                .header(ContentType::JSON)
+                .header(Header::new("svix-id", "msg_1"))
+                .header(Header::new("svix-timestamp", timestamp.to_string()))
+                .header(Header::new("svix-signature", signature))
+                .body(body)
+                .dispatch()
+        };
+
+        let body: String = resend_bounce("jenny.rosen@example.com", "Permanent").to_string();
+        let signature: String = format!("v1,{}", sign_resend_payload(&body, "msg_1", timestamp, RESEND_SECRET));
+        assert_eq!(post_resend(body.clone(), signature.clone()).await.status(), Status::Ok);
+        assert_eq!(post_resend(body.clone(), signature).await.status(), Status::Ok);
+        assert_eq!(post_resend(body, "v1,bm9wZQ==".to_string()).await.status(), Status::BadRequest);
+
+        let post_ses = |body: String| {
+            harness.client
+                .post("/email_webhooks/ses")
+                .header(ContentType::Plain)
+                .body(body)
+                .dispatch()
+        };
+
+        let complaint: Value = ses_notification(&json!({
+            "notificationType": "Complaint",
+            "complaint": { "complainedRecipients": [{ "emailAddress": "jane@example.com" }], "feedbackId": "0100018f-feedback" },
+        }));
+        assert_eq!(post_ses(sign_sns(complaint.clone()).to_string()).await.status(), Status::Ok);
+        assert_eq!(post_ses(complaint.to_string()).await.status(), Status::Unauthorized);
+
+        let mut tampered: Value = sign_sns(complaint.clone());
+        tampered["Message"] = json!(json!({ "notificationType": "Complaint", "complaint": { "complainedRecipients": [{ "emailAddress": "jenny.rosen@example.com" }] } }).to_string());
+        assert_eq!(post_ses(tampered.to_string()).await.status(), Status::Unauthorized);
+
+        let mut elsewhere: Value = sign_sns(complaint.clone());
+        elsewhere["SigningCertURL"] = json!(format!("{}/cert.pem", harness.fakes.base_url));
+        assert_eq!(post_ses(elsewhere.to_string()).await.status(), Status::Unauthorized);
+
+        let mut other_topic: Value = complaint.clone();
+        other_topic["TopicArn"] = json!("arn:aws:sns:us-east-1:123456789012:other");
+        assert_eq!(post_ses(sign_sns(other_topic).to_string()).await.status(), Status::Forbidden);
+
+        let confirmation: Value = json!({
+            "Type": "SubscriptionConfirmation",
+            "MessageId": "165545c9-2a5c-472c-8df2-7ff2be2b3b1b",
+            "Token": "2336412f37fb687f5d51e6e2425c464de12884",
+            "TopicArn": "arn:aws:sns:us-east-1:123456789012:ses-feedback",
+            "Message": "You have chosen to subscribe to the topic.",
+            "SubscribeURL": format!("{}/confirm", harness.fakes.base_url),
+            "Timestamp": "2024-04-24T23:06:40.000Z",
+        });
+        assert_eq!(post_ses(sign_sns(confirmation).to_string()).await.status(), Status::BadRequest);
+
+        let suppressions: Vec<Value> = harness.rows("stripe_email_suppressions");
+        assert_eq!(suppressions.len(), 2);
+        assert_eq!(suppressions[0]["email"], "jenny.rosen@example.com");
+        assert_eq!(suppressions[0]["reason"], "bounce");
+        assert_eq!(suppressions[1]["email"], "jane@example.com");
+        assert_eq!(suppressions[1]["reason"], "complaint");
+
+        env::remove_var("RESEND_WEBHOOK_SECRET");
+        env::remove_var("SES_SNS_TOPIC_ARN");
+        assert_eq!(post_ses(sign_sns(complaint).to_string()).await.status(), Status::Unauthorized);
+    }
+
+
+    #[tokio::test]
+    /// # skips_suppressed_addresses
+    /// No receipt is sent to a suppressed address, the customer gets `email_sent=false` with the
+    /// reason instead.
+    async fn skips_suppressed_addresses() {
+        let harness: Harness = Harness::start().await;
+        let supabase: SupabaseClient = SupabaseClient::new(harness.fakes.base_url.clone(), "fake_supabase_key".to_string());
+
+        let suppression: Suppression = resend_suppressions(&resend_bounce("jenny.rosen@example.com", "Permanent"), 1714000000).remove(0);
+        assert!(suppression.insert(supabase.clone()).await.unwrap());
+
+        let organization: Organization = Organization::new(
+            "Xylex".to_string(),
+            EmailConfig::new("billing@xylex.ai".to_string(), "Welcome!".to_string(), "welcome".to_string()),
+        )
+        .with_email(EmailEvent::Receipt, EmailConfig::new("billing@xylex.ai".to_string(), "Your receipt".to_string(), "receipt".to_string()));
+
+        let charge: Value = fixture("charge.succeeded").expect("a charge fixture");
+        EventHandler::new(&charge, organization, supabase).await.expect("handled");
+
+        assert!(harness.emails().is_empty());
+
+        let customer: Value = harness.rows("stripe_customer_data").remove(0);
+        assert_eq!(customer["email_sent"], false);
+        assert_eq!(customer["email_sent_reason"], "suppressed after a bounce (Permanent/General)");
+    }
+}
@@ -0,0 +1,91 @@
+//! ## Email template source tests
+//!
+//! ### Table of contents
+//! - Parsing template sources from a `template_url`
+//! - Loading templates from files, the templates directory and the embedded defaults
+//! - Revalidating remote templates with their `ETag` and surviving a CDN outage
+//!
+
+
+#[cfg(test)]
+mod template_sources {
+    use crate::email::templates::source::{embedded, TemplateError, TemplateSource};
+    use crate::tests::harness::FakeServices;
+
+    use std::path::{Path, PathBuf};
+
+
+    /// # templates_dir
+    /// A fresh templates directory with a custom welcome template.
+    fn templates_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(format!("stripe-discord-templates-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create the templates directory");
+        std::fs::write(dir.join("welcome.html"), "<p>Custom welcome</p>").expect("write the welcome template");
+
+        dir
+    }
+
+
+    /// # parses_template_sources
+    /// Urls, file paths, names and embedded names map to their sources.
+    #[test]
+    fn parses_template_sources() {
+        assert_eq!(TemplateSource::parse("https://cdn.example.com/welcome.html"), TemplateSource::Http("https://cdn.example.com/welcome.html".to_string()));
+        assert_eq!(TemplateSource::parse("http://localhost/welcome.html"), TemplateSource::Http("http://localhost/welcome.html".to_string()));
+        assert_eq!(TemplateSource::parse("file:///srv/welcome.html"), TemplateSource::File(PathBuf::from("/srv/welcome.html")));
+        assert_eq!(TemplateSource::parse("./email/templates/welcome.html"), TemplateSource::File(PathBuf::from("./email/templates/welcome.html")));
+        assert_eq!(TemplateSource::parse("welcome"), TemplateSource::Named("welcome".to_string()));
+        assert_eq!(TemplateSource::parse("payment_failed"), TemplateSource::Named("payment_failed".to_string()));
+        assert_eq!(TemplateSource::parse("embedded:welcome"), TemplateSource::Embedded("welcome".to_string()));
+    }
+
+
+    /// # loads_local_templates
+    /// Named templates come from the directory first and the embedded defaults second.
+    #[tokio::test]
+    async fn loads_local_templates() {
+        let dir: PathBuf = templates_dir("local");
+        let file_url: String = format!("file://{}", dir.join("welcome.html").display());
+
+        assert_eq!(TemplateSource::parse("welcome").load(&dir).await, Ok("<p>Custom welcome</p>".to_string()));
+        assert_eq!(TemplateSource::parse(&file_url).load(Path::new("/nonexistent")).await, Ok("<p>Custom welcome</p>".to_string()));
+        assert_eq!(TemplateSource::parse("payment_failed").load(&dir).await, Ok(embedded("payment_failed").expect("embedded default")));
+        assert_eq!(TemplateSource::parse("embedded:welcome").load(&dir).await, Ok(embedded("welcome").expect("embedded default")));
+
+        assert!(matches!(TemplateSource::parse("unknown").load(&dir).await, Err(TemplateError::NotFound(_))));
+        assert!(matches!(TemplateSource::parse("embedded:unknown").load(&dir).await, Err(TemplateError::NotFound(_))));
+        assert!(matches!(TemplateSource::parse("file:///nonexistent/welcome.html").load(&dir).await, Err(TemplateError::NotFound(_))));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+
+    /// # revalidates_remote_templates
+    /// Remote templates are revalidated with their `ETag` and served from the cache during an outage,
+    /// or as the embedded default of their name when they were never downloaded.
+    #[tokio::test]
+    async fn revalidates_remote_templates() {
+        let fakes: FakeServices = FakeServices::start().await;
+        let source: TemplateSource = TemplateSource::parse(&format!("{}/templates/cdn.html", fakes.base_url));
+        let dir: &Path = Path::new("/nonexistent");
+
+        fakes.state.lock().unwrap().templates.insert("/templates/cdn.html".to_string(), "<p>v1</p>".to_string());
+
+        assert_eq!(source.load(dir).await, Ok("<p>v1</p>".to_string()));
+        assert_eq!(source.load(dir).await, Ok("<p>v1</p>".to_string()));
+        assert_eq!(fakes.state.lock().unwrap().template_responses, vec![200, 304]);
+
+        fakes.state.lock().unwrap().templates.insert("/templates/cdn.html".to_string(), "<p>v2</p>".to_string());
+        assert_eq!(source.load(dir).await, Ok("<p>v2</p>".to_string()));
+
+        fakes.state.lock().unwrap().templates_down = true;
+        assert_eq!(source.load(dir).await, Ok("<p>v2</p>".to_string()));
+        assert_eq!(fakes.state.lock().unwrap().template_responses, vec![200, 304, 200, 503]);
+
+        let uncached: TemplateSource = TemplateSource::parse(&format!("{}/templates/welcome.html?v=2", fakes.base_url));
+        assert_eq!(uncached.load(dir).await, Ok(embedded("welcome").expect("embedded default")));
+
+        let without_default: TemplateSource = TemplateSource::parse(&format!("{}/templates/spring_sale.html", fakes.base_url));
+        assert!(matches!(without_default.load(dir).await, Err(TemplateError::Download(_, _))));
+    }
+}
@@ -0,0 +1,179 @@
+//! ## Webhook event log tests
+//!
+//! ### Table of contents
+//! - Verifying Stripe signatures
+//! - Building a stored event from a received webhook
+//! - Parsing the event log commands
+//!
+
+
+#[cfg(test)]
+mod signature {
+    use crate::events::signature::{signature_header, sign_payload, verify_signature, SignatureError};
+
+    const SECRET: &str = "whsec_test";
+    const PAYLOAD: &str = r#"{"id":"evt_1","type":"charge.succeeded"}"#;
+    const NOW: i64 = 1_714_000_000;
+
+
+    #[test]
+    /// # accepts_valid_signature
+    /// A header signed with the secret verifies, also next to a signature of a rolled secret.
+    fn accepts_valid_signature() {
+        let header: String = signature_header(PAYLOAD, NOW, SECRET);
+        assert_eq!(verify_signature(PAYLOAD, &header, SECRET, NOW + 10), Ok(()));
+
+        let rolled: String = format!("t={},v1={},v1={}", NOW, sign_payload(PAYLOAD, NOW, "whsec_old"), sign_payload(PAYLOAD, NOW, SECRET));
+        assert_eq!(verify_signature(PAYLOAD, &rolled, SECRET, NOW), Ok(()));
+    }
+
+
+    #[test]
+    /// # rejects_invalid_signatures
+    /// Tampered bodies, other secrets, stale timestamps and malformed headers are rejected.
+    fn rejects_invalid_signatures() {
+        let header: String = signature_header(PAYLOAD, NOW, SECRET);
+
+        assert_eq!(
+            verify_signature(r#"{"id":"evt_2","type":"charge.succeeded"}"#, &header, SECRET, NOW),
+            Err(SignatureError::NoMatchingSignature)
+        );
+        assert_eq!(
+            verify_signature(PAYLOAD, &header, "whsec_other", NOW),
+            Err(SignatureError::NoMatchingSignature)
+        );
+        assert_eq!(
+            verify_signature(PAYLOAD, &header, SECRET, NOW + 301),
+            Err(SignatureError::TimestampOutsideTolerance)
+        );
+        assert_eq!(
+            verify_signature(PAYLOAD, "v1=abc", SECRET, NOW),
+            Err(SignatureError::MalformedHeader)
+        );
+        assert_eq!(
+            verify_signature(PAYLOAD, &format!("t={}", NOW), SECRET, NOW),
+            Err(SignatureError::MalformedHeader)
+        );
+    }
+}
+
+
+#[cfg(test)]
+mod event_log {
+    use crate::cli::Command;
+    use crate::db::operations::webhook_event::{WebhookEvent, WebhookOutcome};
+
+    use serde_json::json;
+
+
+    #[test]
+    /// # builds_pending_event
+    /// The id, type and customer are read from the raw body, which is kept as received.
+    fn builds_pending_event() {
+        let raw_body: String = r#"{"id":"evt_1", "type":"charge.refunded"}"#.to_string();
+        let event: WebhookEvent = WebhookEvent::new(raw_body.clone(), json!({"stripe-signature": "t=1,v1=abc"}), 1_714_000_000).unwrap();
+
+        assert_eq!(event.event_id, "evt_1");
+        assert_eq!(event.event_type, "charge.refunded");
+        assert_eq!(event.raw_body, raw_body);
+        assert_eq!(event.attempts, 0);
+        assert_eq!(event.outcome.parse(), Ok(WebhookOutcome::Pending));
+        assert!(serde_json::to_value(&event).unwrap().get("id").is_none());
+        assert_eq!(event.customer_id, None);
+
+        let charge: String = r#"{"id":"evt_2","type":"charge.succeeded","data":{"object":{"object":"charge","id":"ch_1","billing_details":{"email":" Jenny.Rosen@Example.com"}}}}"#.to_string();
+        let event: WebhookEvent = WebhookEvent::new(charge, json!({}), 1_714_000_000).unwrap();
+        assert_eq!(event.customer_id.as_deref(), Some("ch_1"));
+        assert_eq!(event.customer_email.as_deref(), Some("jenny.rosen@example.com"));
+
+        let dispute: String = r#"{"id":"evt_3","type":"charge.dispute.created","data":{"object":{"object":"dispute","id":"dp_1","charge":"ch_1"}}}"#.to_string();
+        assert_eq!(WebhookEvent::new(dispute, json!({}), 1_714_000_000).unwrap().customer_id.as_deref(), Some("ch_1"));
+    }
+
+
+    #[test]
+    /// # parses_event_commands
+    /// The event log subcommands and their flags.
+    fn parses_event_commands() {
+        let args = |line: &str| -> Vec<String> { line.split_whitespace().map(|arg| arg.to_string()).collect() };
+
+        assert_eq!(Command::parse(&[]), Ok(Command::Serve));
+        assert_eq!(
+            Command::parse(&args("events list --outcome failed --limit 5")),
+            Ok(Command::EventsList { event_type: None, outcome: Some(WebhookOutcome::Failed), limit: 5 })
+        );
+        assert_eq!(
+            Command::parse(&args("events replay evt_1")),
+            Ok(Command::EventsReplay { event_id: "evt_1".to_string() })
+        );
+        assert!(Command::parse(&args("events list --limit")).is_err());
+        assert!(Command::parse(&args("events delete evt_1")).is_err());
+    }
+}
+
+
+#[cfg(test)]
+mod test_events {
+    use crate::cli::test_event::webhook_url;
+    use crate::cli::Command;
+    use crate::events::test_event::TestEvent;
+    use crate::ConfigSetup;
+
+    use serde_json::Value;
+
+
+    #[test]
+    /// # builds_events_with_options
+    /// The email, amount and price end up where the handlers read them, every event gets fresh ids.
+    fn builds_events_with_options() {
+        let checkout: TestEvent = TestEvent::new("checkout.session.completed")
+            .unwrap()
+            .with_email("jane@example.com".to_string())
+            .with_amount(1999)
+            .with_currency("USD".to_string())
+            .with_price("price_monthly".to_string());
+
+        let first: Value = checkout.build();
+        let second: Value = checkout.build();
+        let session: &Value = &first["data"]["object"];
+
+        assert_eq!(first["type"], "checkout.session.completed");
+        assert_ne!(first["id"], second["id"]);
+        assert_eq!(session["customer_details"]["email"], "jane@example.com");
+        assert_eq!(session["amount_total"], 1999);
+        assert_eq!(session["currency"], "usd");
+        assert_eq!(session["line_items"]["data"][0]["price"]["id"], "price_monthly");
+
+        let charge: Value = TestEvent::new("charge.succeeded").unwrap().with_amount(1999).build();
+        assert_eq!(charge["data"]["object"]["amount_captured"], 1999);
+        assert!(charge["data"]["object"]["id"].as_str().unwrap().starts_with("ch_test_"));
+
+        let deleted: Value = TestEvent::new("customer.subscription.deleted").unwrap().build();
+        assert_eq!(deleted["data"]["object"]["status"], "canceled");
+        assert_eq!(deleted["data"]["object"]["items"]["data"][0]["price"]["recurring"]["interval"], "month");
+
+        assert!(TestEvent::new("invoice.paid").is_err());
+    }
+
+
+    #[test]
+    /// # parses_send_test_event
+    /// The flags of `send-test-event` and the default webhook url.
+    fn parses_send_test_event() {
+        let args = |line: &str| -> Vec<String> { line.split_whitespace().map(|arg| arg.to_string()).collect() };
+
+        assert_eq!(
+            Command::parse(&args("send-test-event charge.succeeded --email jane@example.com --amount 1999 --url http://localhost:4242/stripe_webhooks")),
+            Ok(Command::SendTestEvent {
+                test_event: TestEvent::new("charge.succeeded").unwrap().with_email("jane@example.com".to_string()).with_amount(1999),
+                url: Some("http://localhost:4242/stripe_webhooks".to_string()),
+            })
+        );
+        assert!(Command::parse(&args("send-test-event charge.succeeded --amount 19.99")).is_err());
+        assert!(Command::parse(&args("send-test-event invoice.paid")).is_err());
+
+        if std::env::var("PORT").is_err() {
+            assert_eq!(webhook_url(&ConfigSetup::default()), "http://127.0.0.1:8080/stripe_webhooks");
+        }
+    }
+}
@@ -26,7 +26,7 @@
 /// Returns `true` if the two email addresses are not the same, otherwise returns `false`.
 ///
 /// ## Examples
-/// ```
+/// ```rust,ignore
 /// let email_1 = "user1@example.com";
 /// let email_2 = "user2@example.com";
 /// assert!(are_emails_unique(email_1, email_2)); // This will pass because emails are unique
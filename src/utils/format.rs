@@ -1,24 +1,45 @@
-/// Format the total amount to a readable format
-/// 
+/// Format a unix timestamp as a `YYYY-MM-DD` date in UTC
+///
 /// ### Arguments
-/// - `amount` - The amount to format
-/// 
-/// ### Returns
-/// The formatted amount
-/// 
+/// - `timestamp` - The seconds since the unix epoch
+///
 /// ### Example
 /// ```rust
-/// let amount: i64 = 1000;
-/// 
-/// let formatted_amount: f64 = format_total_amount(amount).await;
-/// println!("Formatted amount: {:?}", formatted_amount);
-/// // Formatted amount: 10.0
+/// # use stripe_discord::utils::format::format_date;
+/// assert_eq!(format_date(1714000000), "2024-04-24");
 /// ```
-/// 
-pub async fn format_total_amount(
-    amount: i64
-) -> f64 {
-    let amount: f64 = amount as f64 / 100.0;
+pub fn format_date(
+    timestamp: i64
+) -> String {
+    let (year, month, day): (i64, i64, i64) = civil_date(timestamp);
 
-    amount
-}
\ No newline at end of file
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The year, month and day of a unix timestamp in UTC
+///
+/// ### Arguments
+/// - `timestamp` - The seconds since the unix epoch
+///
+/// ### Example
+/// ```rust
+/// # use stripe_discord::utils::format::civil_date;
+/// assert_eq!(civil_date(1714000000), (2024, 4, 24));
+/// ```
+pub fn civil_date(
+    timestamp: i64
+) -> (i64, i64, i64) {
+    // days to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days: i64 = timestamp.div_euclid(86_400) + 719_468;
+    let era: i64 = days.div_euclid(146_097);
+    let day_of_era: i64 = days.rem_euclid(146_097);
+    let year_of_era: i64 = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year: i64 = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
@@ -3,6 +3,7 @@
 //! belong in their own `niche`
 //!
 //! ### Table of contents  
+//! - `money` - Amounts in minor units of their currency
 //!
 //!
 //! ### Variants
@@ -17,4 +18,5 @@
 //!
 
 pub mod check;
-pub mod format;
\ No newline at end of file
+pub mod format;
+pub mod money;
\ No newline at end of file
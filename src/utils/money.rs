@@ -0,0 +1,204 @@
+//! ## Money
+//!
+//! Stripe sends every amount in minor units of its currency: cents for `usd`, but yen for `jpy`,
+//! which has no minor unit, and fils for `kwd`, which has a thousand of them. [`Money`] keeps the
+//! amount as an integer with its ISO 4217 currency and only turns it into a decimal when it is
+//! written, so no amount is ever rounded by floating point arithmetic.
+//!
+//! ### Usage example
+//! ```rust,ignore
+//! let money: Money = Money::from_stripe(&charge, "amount_captured");
+//! assert_eq!(money.to_decimal(), "50.00");
+//! assert_eq!(money.format(&Locale::parse("de").unwrap()), "50,00 €");
+//! ```
+
+use crate::email::locale::Locale;
+
+use serde_json::Value;
+use std::fmt;
+
+
+/// Currencies Stripe sends without a minor unit. `isk` and `ugx` have exponent 0 in ISO 4217 but
+/// Stripe still sends them in hundredths, so they are not listed here.
+const ZERO_DECIMAL_CURRENCIES: &[&str] = &[
+    "bif", "clp", "djf", "gnf", "jpy", "kmf", "krw", "mga", "pyg",
+    "rwf", "uyi", "vnd", "vuv", "xaf", "xof", "xpf",
+];
+
+/// Currencies with a thousand minor units, ISO 4217 exponent 3
+const THREE_DECIMAL_CURRENCIES: &[&str] = &["bhd", "iqd", "jod", "kwd", "lyd", "omr", "tnd"];
+
+
+/// ## Money
+/// An amount in the minor units of its currency
+///
+/// ### Fields
+/// - `minor_units` - The amount as Stripe sends it, e.g. `1999` for 19.99 USD or 1999 JPY
+/// - `currency` - The lowercase ISO 4217 currency code, e.g. `usd`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Money {
+    pub minor_units: i64,
+    pub currency: String,
+}
+
+
+impl Money {
+    /// # new
+    /// An amount in minor units of a currency, the code is stored lowercase like Stripe does.
+    pub fn new(minor_units: i64, currency: &str) -> Self {
+        Money {
+            minor_units,
+            currency: currency.trim().to_ascii_lowercase(),
+        }
+    }
+
+    /// # from_stripe
+    /// Reads an amount and the `currency` next to it from a Stripe object, `0` and `usd` when
+    /// they are missing.
+    ///
+    /// ## Arguments
+    /// - `object`: `&Value` - A charge, checkout session, invoice or dispute
+    /// - `amount_key`: `&str` - The key of the amount, e.g. `amount_captured` or `amount_total`
+    pub fn from_stripe(object: &Value, amount_key: &str) -> Self {
+        Money::new(
+            object[amount_key].as_i64().unwrap_or(0),
+            object["currency"].as_str().unwrap_or("usd"),
+        )
+    }
+
+    /// # from_major
+    /// An amount in major units of a currency, rounded to its minor units. Only for rows written
+    /// before amounts were stored in minor units, like the legacy `amount_total` column.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::utils::money::Money;
+    /// assert_eq!(Money::from_major(19.99, "usd"), Money::new(1999, "usd"));
+    /// assert_eq!(Money::from_major(20.0, "usd"), Money::new(2000, "usd"));
+    /// assert_eq!(Money::from_major(1999.0, "jpy"), Money::new(1999, "jpy"));
+    /// ```
+    pub fn from_major(amount: f64, currency: &str) -> Self {
+        let money: Money = Money::new(0, currency);
+        let minor_units: f64 = amount * 10f64.powi(money.exponent() as i32);
+
+        Money { minor_units: minor_units.round() as i64, ..money }
+    }
+
+    /// # exponent
+    /// The number of decimals of the currency: 0 for `jpy`, 3 for `kwd`, 2 for the rest.
+    pub fn exponent(&self) -> u32 {
+        match self.currency.as_str() {
+            currency if ZERO_DECIMAL_CURRENCIES.contains(&currency) => 0,
+            currency if THREE_DECIMAL_CURRENCIES.contains(&currency) => 3,
+            _ => 2,
+        }
+    }
+
+    /// # to_decimal
+    /// The amount in major units with the decimals of the currency.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::utils::money::Money;
+    /// assert_eq!(Money::new(1999, "usd").to_decimal(), "19.99");
+    /// assert_eq!(Money::new(1999, "jpy").to_decimal(), "1999");
+    /// assert_eq!(Money::new(1250, "kwd").to_decimal(), "1.250");
+    /// ```
+    pub fn to_decimal(&self) -> String {
+        let (units, fraction): (String, String) = self.parts();
+        let sign: &str = if self.minor_units < 0 { "-" } else { "" };
+
+        match fraction.is_empty() {
+            true => format!("{}{}", sign, units),
+            false => format!("{}{}.{}", sign, units, fraction),
+        }
+    }
+
+    /// # format
+    /// Writes the amount the way the locale does, see [locale](../../email/locale/index.html).
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use stripe_discord::email::locale::Locale;
+    /// # use stripe_discord::utils::money::Money;
+    /// assert_eq!(Money::new(123456, "eur").format(&Locale::parse("de").unwrap()), "1.234,56 €");
+    /// assert_eq!(Money::new(123456, "jpy").format(&Locale::parse("en").unwrap()), "¥123,456");
+    /// ```
+    pub fn format(&self, locale: &Locale) -> String {
+        let (group, decimal): (&str, &str) = match locale.language() {
+            "de" | "nl" | "es" | "it" | "pt" => (".", ","),
+            "fr" => (" ", ","),
+            _ => (",", "."),
+        };
+
+        let (units, fraction): (String, String) = self.parts();
+        let sign: &str = if self.minor_units < 0 { "-" } else { "" };
+
+        let number: String = match fraction.is_empty() {
+            true => format!("{}{}", sign, group_digits(&units, group)),
+            false => format!("{}{}{}{}", sign, group_digits(&units, group), decimal, fraction),
+        };
+
+        let symbol: String = self.symbol();
+
+        match locale.language() {
+            "de" | "fr" | "es" | "it" | "pt" => format!("{} {}", number, symbol),
+            "nl" => format!("{} {}", symbol, number),
+            // currencies without a symbol are written after the amount, like `1,234.56 CHF`
+            _ if symbol == self.currency.to_ascii_uppercase() => format!("{} {}", number, symbol),
+            _ => format!("{}{}", symbol, number),
+        }
+    }
+
+    /// # symbol
+    /// The symbol of the currency, its uppercase code when it has no common symbol.
+    pub fn symbol(&self) -> String {
+        match self.currency.as_str() {
+            "eur" => "€".to_string(),
+            "usd" => "$".to_string(),
+            "gbp" => "£".to_string(),
+            "jpy" => "¥".to_string(),
+            _ => self.currency.to_ascii_uppercase(),
+        }
+    }
+
+    /// # parts
+    /// The whole major units and the zero padded fraction of the absolute amount.
+    fn parts(&self) -> (String, String) {
+        let exponent: u32 = self.exponent();
+        let minor_units: u64 = self.minor_units.unsigned_abs();
+        let scale: u64 = 10_u64.pow(exponent);
+
+        let fraction: String = match exponent {
+            0 => String::new(),
+            _ => format!("{:0width$}", minor_units % scale, width = exponent as usize),
+        };
+
+        ((minor_units / scale).to_string(), fraction)
+    }
+}
+
+
+impl fmt::Display for Money {
+    /// Writes the amount with its uppercase code, like `19.99 USD`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.to_decimal(), self.currency.to_ascii_uppercase())
+    }
+}
+
+
+/// # group_digits
+/// Writes whole units with a separator between every group of three digits.
+fn group_digits(digits: &str, separator: &str) -> String {
+    let mut grouped: String = String::new();
+
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push_str(separator);
+        }
+
+        grouped.push(digit);
+    }
+
+    grouped
+}